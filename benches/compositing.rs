@@ -0,0 +1,80 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use paintdesk::paint_app::canvas::Canvas;
+use paintdesk::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use paintdesk::paint_app::data_types::{Color, PixelPos};
+use paintdesk::paint_app::fast_overlap::overlap_layers;
+use paintdesk::paint_app::utils::pixel_overlap;
+
+const LAYERS: usize = 10;
+
+// layers with a mix of opaque, transparent and partly covered pixels
+fn stack(width: u32, height: u32) -> Vec<FlatCanvasLayer> {
+    (0..LAYERS).map(|layer| {
+        let mut result = FlatCanvasLayer::new(width, height);
+        result.iter_pixels_mut().for_each(|(pos, color)| {
+            let i = pos.x.wrapping_mul(31) ^ pos.y.wrapping_mul(17) ^ (layer as u32 * 101);
+            *color = Color::new(i as u8, (i >> 3) as u8, (i >> 5) as u8, (i >> 2) as u8);
+        });
+        result
+    }).collect()
+}
+
+// what apply_to_canvas did before the fast path, one pixel at a time through the trait
+fn scalar(stack: &[FlatCanvasLayer], target: &mut FlatCanvasLayer) {
+    let (width, height) = target.get_size();
+    for layer in stack.iter().rev() {
+        for y in 0..height {
+            for x in 0..width {
+                let pos = PixelPos { x, y };
+                target.set_pixel(pos, pixel_overlap(layer.get_pixel(pos), target.get_pixel(pos)));
+            }
+        }
+    }
+}
+
+fn fast(stack: &[FlatCanvasLayer], target: &mut FlatCanvasLayer) {
+    for layer in stack.iter().rev() {
+        overlap_layers(layer, target);
+    }
+}
+
+fn bench_compositing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("stack of 10 layers");
+    group.sample_size(10);
+    for (name, width, height) in [("1080p", 1920, 1080), ("4K", 3840, 2160)] {
+        let stack = stack(width, height);
+        let mut target = FlatCanvasLayer::new(width, height);
+        group.bench_with_input(BenchmarkId::new("scalar", name), &stack, |b, stack| {
+            b.iter(|| scalar(black_box(stack), &mut target));
+        });
+        group.bench_with_input(BenchmarkId::new("fast", name), &stack, |b, stack| {
+            b.iter(|| fast(black_box(stack), &mut target));
+        });
+    }
+    group.finish();
+}
+
+// what the app does after every change, toggling a layer recomposites the whole stack
+fn bench_display_canvas(c: &mut Criterion) {
+    let mut group = c.benchmark_group("display canvas with 10 layers");
+    group.sample_size(10);
+    for (name, width, height) in [("1080p", 1920, 1080), ("4K", 3840, 2160)] {
+        let mut canvas = Canvas::new(width, height);
+        for layer in stack(width, height).iter().rev() {
+            canvas.add_raster_layer(layer);
+        }
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut config = canvas.get_canvas_layers_config();
+                let bottom = config.entries.last_mut().unwrap();
+                bottom.visible = !bottom.visible;
+                canvas.set_canvas_layers_config(config);
+                black_box(canvas.get_draw_layer());
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_compositing, bench_display_canvas);
+criterion_main!(benches);
@@ -0,0 +1,104 @@
+// cuts the slices of a project out without the editor, for build scripts:
+// paintdesk-slice <project> <folder> [options]
+use paintdesk::paint_app::canvas::Canvas;
+use paintdesk::paint_app::canvas_layer::FlatCanvasLayer;
+use paintdesk::paint_app::image_io::decode_image;
+use paintdesk::paint_app::ora::read_ora;
+use paintdesk::paint_app::psd::read_psd;
+use paintdesk::paint_app::slices::{slice_files, AtlasFormat, SliceExport, SliceGrid, SliceSet};
+
+const USAGE: &str = "usage: paintdesk-slice <project> <folder> [options]
+
+the project is an .ora file saved with its slices, a .psd or an image
+options:
+  --slices <file>              slices from a text file instead of the project
+  --grid <width> <height> [<margin> <spacing>]
+                               cut a grid instead of the project's grid
+  --keep-empty                 also write grid cells that are fully transparent
+  --atlas aseprite|texturepacker
+                               one packed png with json instead of a png per slice
+  --name <name>                of the atlas files, atlas by default
+  --padding <pixels>           between the slices of the atlas, 1 by default";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Err(err) = run(&args) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run(args: &[String]) -> Result<(), String> {
+    let [project, folder, options @ ..] = args else { return Err(USAGE.to_string()) };
+    let (layer, mut slices) = read_project(project)?;
+    let mut atlas = None;
+    let mut name = "atlas".to_string();
+    let mut padding = 1;
+    let mut keep_empty = false;
+
+    let mut options = options.iter();
+    let number = |value: Option<&String>, option: &str| value.and_then(|value| value.parse::<u32>().ok()).ok_or(format!("{} needs a number\n\n{}", option, USAGE));
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--slices" => {
+                let path = options.next().ok_or(format!("--slices needs a file\n\n{}", USAGE))?;
+                let text = std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+                slices = SliceSet::from_text(&text).map_err(|err| format!("{}: {}", path, err))?;
+            }
+            "--grid" => {
+                let mut grid = SliceGrid::new(number(options.next(), option)?, number(options.next(), option)?);
+                // margin and spacing go together
+                if let Some(margin) = options.clone().next().and_then(|value| value.parse::<u32>().ok()) {
+                    options.next();
+                    grid.margin = margin;
+                    grid.spacing = number(options.next(), option)?;
+                }
+                slices.grid = Some(grid);
+            }
+            "--keep-empty" => keep_empty = true,
+            "--atlas" => atlas = Some(match options.next().map(String::as_str) {
+                Some("aseprite") => AtlasFormat::Aseprite,
+                Some("texturepacker") => AtlasFormat::TexturePacker,
+                _ => return Err(format!("--atlas is aseprite or texturepacker\n\n{}", USAGE))
+            }),
+            "--name" => name = options.next().ok_or(format!("--name needs a name\n\n{}", USAGE))?.clone(),
+            "--padding" => padding = number(options.next(), option)?,
+            other => return Err(format!("unknown option {}\n\n{}", other, USAGE))
+        }
+    }
+    if keep_empty {
+        slices.grid.iter_mut().for_each(|grid| grid.skip_empty = false);
+    }
+    if slices.is_empty() {
+        return Err(format!("{} has no slices, add them in the editor or use --grid or --slices", project));
+    }
+
+    let export = match atlas {
+        Some(format) => SliceExport::Atlas { format, padding },
+        None => SliceExport::Separate
+    };
+    let files = slice_files(&layer, &slices, export, &name)?;
+    paintdesk::paint_app::image_io::write_files(folder, &files)?;
+    println!("wrote {} files to {}", files.len(), folder);
+    Ok(())
+}
+
+// the visible layers merged, with the slices saved in the project
+#[cfg(not(target_arch = "wasm32"))]
+fn read_project(path: &str) -> Result<(FlatCanvasLayer, SliceSet), String> {
+    let bytes = std::fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+    let lowercase = path.to_lowercase();
+    let document = match lowercase.rsplit('.').next() {
+        Some("ora") => read_ora(&bytes),
+        Some("psd") => read_psd(&bytes),
+        _ => return decode_image(&bytes).map(|layer| (layer, SliceSet::new())).map_err(|err| format!("{}: {}", path, err))
+    }.map_err(|err| format!("{}: {}", path, err))?;
+    document.warnings.iter().for_each(|warning| eprintln!("{}: {}", path, warning));
+    let slices = document.slices.clone();
+    Ok((Canvas::from_document(document).get_merged_layer(), slices))
+}
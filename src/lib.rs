@@ -0,0 +1 @@
+pub mod paint_app;
@@ -1,16 +1,52 @@
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::time::Instant;
 use eframe::egui;
 use eframe::epaint::textures::TextureOptions;
-use egui::{Button, Color32, ColorImage, PointerButton, Pos2, Rect, Sense, Vec2, menu, WidgetText};
+use egui::{Button, Color32, ColorImage, PointerButton, Pos2, Rect, Sense, Vec2, menu};
 use paint_app::size_window::SizeWindow;
-use crate::paint_app::canvas::{Canvas, CanvasLayerEntry, LayerConfig, LayerId, LineTool, PaintTool, PixelPencil};
+use paint_app::gradient_tool::GradientTool;
+use paint_app::color_picker_tool::ColorPickerTool;
+use paint_app::palette::{Palette, PaletteFormat, PalettePanel};
+use paint_app::indexed::median_cut;
+use paint_app::indexed_window::{IndexedWindow, PaletteSource};
+use paint_app::export_window::{ExportFormat, ExportWindow};
+use paint_app::document_export::{encode_pdf, encode_svg, export_nodes};
+use paint_app::image_io::{decode_image, encode_animated_gif, encode_apng, encode_indexed_png, encode_png, write_file, write_files};
+use paint_app::raster_formats::{encode_raster, flatten, RasterFormat};
+use paint_app::sprite_sheet::build_sprite_sheet;
+use paint_app::timeline_panel::{TimelineCommand, TimelinePanel};
+use paint_app::slices::slice_files;
+use paint_app::slices_window::{draw_slices_overlay, SlicesWindow};
+use paint_app::symmetry::SymmetryMode;
+use paint_app::adjustments::{Adjustment, AdjustmentLayer};
+use paint_app::adjustment_window::AdjustmentWindow;
+use paint_app::filters::Filter;
+use paint_app::filter_window::FilterWindow;
+use paint_app::new_document_window::NewDocumentWindow;
+use paint_app::utils::BlendSpace;
+use paint_app::actions::{action_id, ActionRegistry, Shortcut};
+use paint_app::command_palette::CommandPalette;
+use paint_app::tool_registry::ToolRegistry;
+use paint_app::shortcuts_window::ShortcutsWindow;
+use paint_app::file_warnings_window::FileWarningsWindow;
+use paint_app::ora::{encode_ora, read_ora};
+use paint_app::psd::read_psd;
+use paint_app::pen_tool::PenTool;
+use paint_app::paths_panel::{PathCommand, PathsPanel};
+use paint_app::vector_layer::{ShapeKind, VectorLayer, VectorShape};
+use crate::paint_app::canvas::{Canvas, CanvasLayersConfig, GroupBlend, LayerConfig, LayerId, LayerKind, LayerOperation, LineTool, PixelPencil};
+use crate::paint_app::text_layer::TextAlign;
+use crate::paint_app::text_tool::TextTool;
 use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
 use crate::paint_app::data_types::*;
 use egui_dnd::*;
 
 
-mod paint_app;
+use paintdesk::paint_app;
+
+// holding alt temporarily switches to this tool
+const COLOR_PICKER_TOOL: &str = "color_picker";
 
 #[cfg(not(target_arch = "wasm32"))]
 fn main() {
@@ -41,11 +77,38 @@ struct AppContext {
     frame_times: Vec<f32>,
     tool_button_started: bool,
     primary_button: bool,
+    secondary_button: bool,
+    alt_key: bool,
+    stroke_tool: &'static str,
     canvas: Canvas,
     global_params: GlobalParams,
-    paint_tools: HashMap<u32, Box<dyn PaintTool>>,
-    selected_paint_tool: u32,
-    size_dialog: SizeWindow
+    paint_tools: ToolRegistry,
+    size_dialog: SizeWindow,
+    show_symmetry_toolbar: bool,
+    show_slices: bool,
+    indexed_dialog: IndexedWindow,
+    export_dialog: ExportWindow,
+    slices_dialog: SlicesWindow,
+    adjustment_dialog: AdjustmentWindow,
+    // the adjustment layer the dialog edits and its settings before, for cancelling
+    edited_adjustment_layer: Option<(LayerId, AdjustmentLayer)>,
+    filter_dialog: FilterWindow,
+    new_document_dialog: NewDocumentWindow,
+    actions: ActionRegistry,
+    command_palette: CommandPalette,
+    shortcuts_dialog: ShortcutsWindow,
+    file_warnings_dialog: FileWarningsWindow,
+    palette_panel: PalettePanel,
+    paths_panel: PathsPanel,
+    timeline_panel: TimelinePanel,
+    // layer and mask thumbnails with the layer revision they were made from
+    thumbnails: HashMap<(LayerId, bool), (u64, egui::TextureHandle)>,
+    // why the last edit was refused or what failed and when, in seconds
+    status_message: Option<(String, f64)>,
+    // when this frame started, in seconds
+    frame_time: f64,
+    // saved once the mouse is let go, not for every step of a slider
+    tool_options_changed: bool,
 }
 
 impl AppContext {
@@ -59,18 +122,163 @@ impl AppContext {
             frame_times: Vec::new(),
             tool_button_started: false,
             primary_button: false,
+            secondary_button: false,
+            alt_key: false,
+            stroke_tool: "",
             canvas: Canvas::new(w, h),
             global_params: GlobalParams::new(),
-            paint_tools: HashMap::new(),
-            selected_paint_tool: 1,
-            size_dialog: SizeWindow::new()
+            paint_tools: ToolRegistry::new(),
+            size_dialog: SizeWindow::new(),
+            show_symmetry_toolbar: true,
+            show_slices: true,
+            indexed_dialog: IndexedWindow::new(),
+            export_dialog: ExportWindow::new(),
+            slices_dialog: SlicesWindow::new(),
+            adjustment_dialog: AdjustmentWindow::new(),
+            edited_adjustment_layer: None,
+            filter_dialog: FilterWindow::new(),
+            new_document_dialog: NewDocumentWindow::new(),
+            actions: ActionRegistry::new(),
+            command_palette: CommandPalette::new(),
+            shortcuts_dialog: ShortcutsWindow::new(),
+            file_warnings_dialog: FileWarningsWindow::new(),
+            palette_panel: PalettePanel::new(),
+            paths_panel: PathsPanel::new(),
+            timeline_panel: TimelinePanel::new(),
+            thumbnails: HashMap::new(),
+            status_message: None,
+            frame_time: 0.0,
+            tool_options_changed: false,
         };
-        app.paint_tools.insert(1, Box::new(PixelPencil::new()));
-        app.paint_tools.insert(2, Box::new(LineTool::new()));
+        app.global_params.symmetry.center = (w as f32 / 2.0, h as f32 / 2.0);
+        app.paint_tools.register(Box::new(PixelPencil::new()));
+        app.paint_tools.register(Box::new(LineTool::new()));
+        app.paint_tools.register(Box::new(PenTool::new()));
+        app.paint_tools.register(Box::new(ColorPickerTool::new()));
+        app.paint_tools.register(Box::new(GradientTool::new()));
+        app.paint_tools.register(Box::new(TextTool::new()));
+
+        app.register_actions();
+        // what couldn't be read from the settings files is listed, the rest is still used
+        let mut warnings = Vec::new();
+        if let Err(err) = app.actions.load() {
+            warnings.extend(err.lines().map(|line| format!("shortcuts: {}", line)));
+        }
+        if let Err(err) = app.paint_tools.load() {
+            warnings.extend(err.lines().map(|line| format!("tool options: {}", line)));
+        }
+        app.file_warnings_dialog.open_with("Some settings couldn't be loaded".to_string(), warnings);
 
         app
     }
 
+    // everything in the menus and the tool box, the ids are what run_action matches on
+    fn register_actions(&mut self) {
+        use egui::Key;
+        let actions = &mut self.actions;
+        actions.register("file.new", "New...", &[Shortcut::command(Key::N)]);
+        actions.register("file.export", "Export...", &[Shortcut::command_shift(Key::E)]);
+        actions.register("file.size", "Size...", &[]);
+        actions.register("file.slices", "Slices...", &[]);
+        actions.register("edit.undo", "Undo", &[Shortcut::command(Key::Z)]);
+        actions.register("edit.redo", "Redo", &[Shortcut::command(Key::Y), Shortcut::command_shift(Key::Z)]);
+        actions.register("edit.shortcuts", "Keyboard shortcuts...", &[]);
+        actions.register("view.command_palette", "Command palette...", &[Shortcut::command_shift(Key::P)]);
+        actions.register("view.symmetry_toolbar", "Symmetry toolbar", &[]);
+        actions.register("view.slices", "Slices", &[]);
+        actions.register("color.swap", "Swap colors", &[Shortcut::new(Key::X)]);
+        for adjustment in Adjustment::all_defaults() {
+            actions.register(&action_id("adjustment", adjustment.name()), &format!("{}...", adjustment.name()), &[]);
+        }
+        for adjustment in Adjustment::all_defaults() {
+            let name = format!("New adjustment layer: {}...", adjustment.name());
+            actions.register(&action_id("adjustment_layer", adjustment.name()), &name, &[]);
+        }
+        for filter in Filter::all_defaults() {
+            actions.register(&action_id("filter", filter.name()), &format!("{}...", filter.name()), &[]);
+        }
+        actions.register("layer.new_vector", "New vector layer", &[]);
+        actions.register("layer.rasterize", "Rasterize layer", &[]);
+        actions.register("image.indexed", "Indexed color...", &[]);
+        actions.register("image.rgba", "RGBA color", &[]);
+        actions.register("image.linear_blending", "Linear light blending", &[]);
+        actions.register("animation.new_frame", "New frame", &[]);
+        actions.register("animation.duplicate_frame", "Duplicate frame", &[]);
+        actions.register("animation.previous_frame", "Previous frame", &[Shortcut::new(Key::PageUp)]);
+        actions.register("animation.next_frame", "Next frame", &[Shortcut::new(Key::PageDown)]);
+        actions.register("animation.play", "Play animation", &[]);
+        self.paint_tools.register_actions(actions);
+    }
+
+    fn run_action(&mut self, id: &str) {
+        match id {
+            "file.new" => self.new_document_dialog.open = true,
+            "file.export" => {
+                self.export_dialog.open = true;
+                // the canvas or the secondary color may have changed since
+                self.export_dialog.estimated_size = None;
+            }
+            "file.size" => {
+                self.size_dialog.width = self.canvas.get_size().0;
+                self.size_dialog.height = self.canvas.get_size().1;
+                self.size_dialog.open = true;
+            }
+            "file.slices" => self.slices_dialog.open = true,
+            "edit.undo" => self.canvas.undo(),
+            "edit.redo" => self.canvas.redo(),
+            "edit.shortcuts" => self.shortcuts_dialog.open = true,
+            "view.command_palette" => self.command_palette.open(),
+            "view.symmetry_toolbar" => self.show_symmetry_toolbar = !self.show_symmetry_toolbar,
+            "view.slices" => self.show_slices = !self.show_slices,
+            "color.swap" => self.global_params.swap_colors(),
+            "layer.new_vector" => {
+                self.canvas.add_vector_layer(VectorLayer::new());
+            }
+            "layer.rasterize" => self.canvas.rasterize_active_layer(),
+            "image.indexed" => self.indexed_dialog.open = true,
+            "image.rgba" => self.canvas.convert_to_rgba(),
+            "image.linear_blending" => self.canvas.set_blend_space(match self.canvas.get_blend_space() {
+                BlendSpace::Srgb => BlendSpace::Linear,
+                BlendSpace::Linear => BlendSpace::Srgb
+            }),
+            "animation.new_frame" => self.run_timeline_command(TimelineCommand::Add(false)),
+            "animation.duplicate_frame" => self.run_timeline_command(TimelineCommand::Add(true)),
+            "animation.previous_frame" | "animation.next_frame" => {
+                let (count, active) = (self.canvas.get_frame_count(), self.canvas.get_active_frame());
+                let index = match id {
+                    "animation.previous_frame" => (active + count - 1) % count,
+                    _ => (active + 1) % count
+                };
+                self.run_timeline_command(TimelineCommand::Select(index));
+            }
+            "animation.play" => self.timeline_panel.playing = !self.timeline_panel.playing,
+            _ if id.starts_with("tool.") => {
+                if !self.paint_tools.select(&id["tool.".len()..]) {
+                    self.show_status(format!("there is no tool {}", &id["tool.".len()..]));
+                }
+            }
+            _ => {
+                if let Some(adjustment) = Adjustment::all_defaults().into_iter().find(|adjustment| action_id("adjustment", adjustment.name()) == id) {
+                    self.edited_adjustment_layer = None;
+                    self.adjustment_dialog.open_with(adjustment, self.canvas.get_active_histogram());
+                } else if let Some(adjustment) = Adjustment::all_defaults().into_iter().find(|adjustment| action_id("adjustment_layer", adjustment.name()) == id) {
+                    let adjustment_layer = AdjustmentLayer::new(adjustment);
+                    let layer_id = self.canvas.add_adjustment_layer(adjustment_layer.clone());
+                    self.open_adjustment_layer_editor(layer_id, adjustment_layer);
+                } else if let Some(filter) = Filter::all_defaults().into_iter().find(|filter| action_id("filter", filter.name()) == id) {
+                    self.filter_dialog.open_with(filter);
+                } else {
+                    self.show_status(format!("there is no action {}", id));
+                }
+            }
+        }
+    }
+
+    // shown in the status bar for a few seconds
+    fn show_status(&mut self, message: String) {
+        self.status_message = Some((message, self.frame_time));
+    }
+
     fn get_fps(&mut self) -> f32 {
         //let now = Instant::now();
         let delta_time = 1f32;//now.duration_since(self.start_time).as_secs_f32();
@@ -94,12 +302,7 @@ impl AppContext {
                 *take_input = false;
             }
 
-            //Fill tool box
-            for (key, value) in self.paint_tools.iter() {
-                if ui.selectable_label(self.selected_paint_tool == *key, value.get_name()).clicked() {
-                    self.selected_paint_tool = *key;
-                }
-            }
+            self.paint_tools.tool_box(ui, &self.actions);
 
             ui.separator();
 
@@ -111,6 +314,32 @@ impl AppContext {
             let mut color_secondary = self.global_params.secondary_color.to_color32();
             ui.color_edit_button_srgba(&mut color_secondary);
             self.global_params.secondary_color = Color::from_color32(&color_secondary);
+
+            let swap = Button::new("Swap").shortcut_text(self.actions.shortcut_text(ctx, "color.swap").unwrap_or_default());
+            if ui.add(swap).clicked() {
+                self.global_params.swap_colors();
+            }
+
+            if let Some(palette) = self.canvas.get_indexed_palette() {
+                ui.separator();
+                ui.heading("Document palette");
+                let mut changed = None;
+                ui.horizontal_wrapped(|ui| {
+                    for (i, color) in palette.colors.iter().enumerate() {
+                        let mut color32 = color.to_color32();
+                        if ui.color_edit_button_srgba(&mut color32).changed() {
+                            changed = Some((i, Color::from_color32(&color32)));
+                        }
+                    }
+                });
+                if let Some((i, color)) = changed {
+                    self.canvas.set_indexed_palette_color(i, color);
+                }
+            }
+
+            ui.separator();
+
+            self.palette_panel.show(ui, &mut self.global_params);
         });
     }
 
@@ -119,39 +348,79 @@ impl AppContext {
             let mut checked = false;
             ui.vertical(|ui| {
                 ui.heading("Tool");
-                ui.label("Current tool extra settings");
+                self.tool_options_changed |= self.paint_tools.settings_ui(ui);
+                self.draw_text_layer_settings(ui);
+                self.draw_vector_layer_settings(ui);
+                self.draw_adjustment_layer_settings(ui);
                 ui.spacing();
                 ui.separator();
                 
                 ui.heading("Layers");
 
                 let mut canvas_layers_config = self.canvas.get_canvas_layers_config();
-                let layers = &mut canvas_layers_config.entries;
-                let active_layer_id =  &mut canvas_layers_config.active_layer_id;
+                self.update_thumbnails(ui.ctx(), &canvas_layers_config);
+                let thumbnails = &self.thumbnails;
+                let mut rows = canvas_layers_config.rows();
+                let mut active_layer_id = canvas_layers_config.active_layer_id;
 
                 ui.vertical(|ui| {
-                    dnd(ui, "2dnd_example2")
-                        .show_vec(layers, |ui, item, handle, _state| {
+                    let response = dnd(ui, "2dnd_example2")
+                        .show(rows.iter_mut(), |ui, row, handle, _state| {
+                            let Some(item) = canvas_layers_config.find_mut(row.id) else { return };
                             handle.ui(ui, |ui| {
                                 ui.horizontal(|ui| {
                                     ui.horizontal(|ui| {
-                                        ui.label(format!("layer: {}", item.id.0));
+                                        ui.add_space(row.depth as f32 * 16.0);
+                                        if let Some((_, texture)) = thumbnails.get(&(item.id, false)) {
+                                            ui.image(texture);
+                                        }
+                                        match item.kind {
+                                            LayerKind::Raster | LayerKind::Text | LayerKind::Vector | LayerKind::Adjustment => ui.label(&item.name),
+                                            LayerKind::Group => {
+                                                let arrow = if item.collapsed { "▶" } else { "▼" };
+                                                if ui.small_button(arrow).clicked() {
+                                                    item.collapsed = !item.collapsed;
+                                                }
+                                                ui.label(&item.name)
+                                            }
+                                        };
                                         ui.checkbox(&mut item.visible, "visible");
+                                        ui.checkbox(&mut item.clipped, "clip");
+                                        if let (Some((_, texture)), Some(enabled)) = (thumbnails.get(&(item.id, true)), &mut item.mask_enabled) {
+                                            ui.image(texture);
+                                            ui.checkbox(enabled, "mask");
+                                        }
+                                        ui.toggle_value(&mut item.locks.all, "🔒").on_hover_text("Lock all");
+                                        ui.toggle_value(&mut item.locks.alpha, "α").on_hover_text("Lock alpha, painting keeps transparency");
+                                        ui.toggle_value(&mut item.locks.position, "pos").on_hover_text("Lock position");
                                         // tickbox
-                                        let mut active = *active_layer_id == item.id;
+                                        let mut active = active_layer_id == item.id;
                                         ui.checkbox(&mut active, "active");
                                         if active {
-                                            *active_layer_id = item.id;
+                                            active_layer_id = item.id;
                                         }
                                     });
                                     //ui.image(&texture_id);
                                 });
                             });
                         });
+                    // the parent of the dropped layer depends on where it ends up
+                    if let Some(update) = response.final_update() {
+                        response.update_vec(&mut rows);
+                        let index = if update.to > update.from { update.to - 1 } else { update.to };
+                        canvas_layers_config.move_row(&rows, index);
+                    }
                 });
+                canvas_layers_config.active_layer_id = active_layer_id;
 
                 self.canvas.set_canvas_layers_config(canvas_layers_config);
 
+                self.draw_mask_settings(ui);
+                self.draw_group_settings(ui);
+
+                ui.separator();
+                self.draw_paths_panel(ui);
+
 
                 //TODO: add real layer list
                 //let mut checked = false;
@@ -162,6 +431,373 @@ impl AppContext {
         });
     }
 
+    fn draw_paths_panel(&mut self, ui: &mut egui::Ui) {
+        let stroke_tools = self.paint_tools.iter()
+            .filter(|tool| tool.path_stroke().is_some())
+            .map(|tool| (tool.info().id, tool.info().name))
+            .collect::<Vec<_>>();
+        let command = self.paths_panel.show(ui, self.canvas.get_paths(), self.canvas.get_active_path(), &stroke_tools);
+        match command {
+            Some(PathCommand::Select(index)) => self.canvas.set_active_path(index),
+            Some(PathCommand::Rename(index, name)) => self.canvas.rename_path(index, name),
+            Some(PathCommand::Delete(index)) => self.canvas.delete_path(index),
+            Some(PathCommand::Stroke(index, tool)) => {
+                if let Some(stroke) = self.paint_tools.get(tool).and_then(|tool| tool.path_stroke()) {
+                    self.canvas.stroke_path(index, stroke, self.global_params.primary_color);
+                }
+            }
+            Some(PathCommand::Fill(index)) => self.canvas.fill_path(index, self.global_params.primary_color, true),
+            Some(PathCommand::ToMask(index)) => self.canvas.path_to_mask(index),
+            None => {}
+        }
+    }
+
+    fn draw_timeline_panel(&mut self, ctx: &egui::Context) {
+        egui::TopBottomPanel::bottom("timeline_panel").show(ctx, |ui| {
+            let command = self.timeline_panel.show(ui, &self.canvas.get_frame_durations(), self.canvas.get_active_frame());
+            if let Some(command) = command {
+                self.run_timeline_command(command);
+            }
+        });
+        self.canvas.set_onion_skin(self.timeline_panel.shown_onion_skin());
+    }
+
+    fn run_timeline_command(&mut self, command: TimelineCommand) {
+        match command {
+            TimelineCommand::Select(index) => self.canvas.set_active_frame(index),
+            TimelineCommand::Add(duplicate) => self.canvas.add_frame(duplicate),
+            TimelineCommand::Delete(index) => self.canvas.delete_frame(index),
+            TimelineCommand::Move(from, to) => self.canvas.move_frame(from, to),
+            TimelineCommand::SetDuration(index, duration) => self.canvas.set_frame_duration(index, duration),
+        }
+        // the layers of the frame are other layers
+        self.thumbnails.clear();
+    }
+
+    fn draw_vector_layer_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(mut vector) = self.canvas.get_active_vector().cloned() else { return };
+
+        ui.separator();
+        ui.heading("Vector layer");
+        let mut remove = None;
+        for (i, shape) in vector.shapes.iter_mut().enumerate() {
+            egui::CollapsingHeader::new(shape.name().to_string()).id_source(("vector_shape", i)).show(ui, |ui| {
+                let (min, max) = shape.bounds();
+                // empty paths have no bounds
+                if min.x <= max.x {
+                    let (mut position, mut size) = (min, max - min);
+                    let mut moved = false;
+                    ui.horizontal(|ui| {
+                        ui.label("Position");
+                        moved |= ui.add(egui::DragValue::new(&mut position.x)).changed();
+                        moved |= ui.add(egui::DragValue::new(&mut position.y)).changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Size");
+                        moved |= ui.add(egui::DragValue::new(&mut size.x).clamp_range(0.0..=f32::MAX)).changed();
+                        moved |= ui.add(egui::DragValue::new(&mut size.y).clamp_range(0.0..=f32::MAX)).changed();
+                    });
+                    if moved {
+                        shape.set_bounds(position, position + size);
+                    }
+                }
+                if let ShapeKind::Rectangle { corner_radius, .. } = &mut shape.kind {
+                    ui.horizontal(|ui| {
+                        ui.label("Corner radius");
+                        ui.add(egui::DragValue::new(corner_radius).speed(0.5).clamp_range(0.0..=f32::MAX));
+                    });
+                }
+                let primary = self.global_params.primary_color;
+                for (label, paint) in [("Fill", &mut shape.fill), ("Stroke", &mut shape.stroke)] {
+                    ui.horizontal(|ui| {
+                        let mut enabled = paint.is_some();
+                        ui.checkbox(&mut enabled, label);
+                        let mut color = paint.unwrap_or(primary).to_color32();
+                        ui.color_edit_button_srgba(&mut color);
+                        *paint = enabled.then(|| Color::from_color32(&color));
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Stroke width");
+                    ui.add(egui::DragValue::new(&mut shape.stroke_width).speed(0.25).clamp_range(0.0..=500.0));
+                });
+                if ui.small_button("Remove").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            vector.shapes.remove(i);
+        }
+
+        // new shapes fill the middle of the canvas
+        let size = self.canvas.get_size();
+        let (min, max) = (glam::Vec2::new(size.0 as f32, size.1 as f32) * 0.25, glam::Vec2::new(size.0 as f32, size.1 as f32) * 0.75);
+        let color = self.global_params.primary_color;
+        ui.horizontal(|ui| {
+            if ui.button("Add rectangle").clicked() {
+                vector.shapes.push(VectorShape::rectangle(min, max, color));
+            }
+            if ui.button("Add ellipse").clicked() {
+                vector.shapes.push(VectorShape::ellipse(min, max, color));
+            }
+            let active_path = self.canvas.get_active_path().and_then(|i| self.canvas.get_paths().get(i));
+            if ui.add_enabled(active_path.is_some(), Button::new("Add path")).on_disabled_hover_text("Select a path first").clicked() {
+                vector.shapes.extend(active_path.map(|path| VectorShape::path(path.clone(), color)));
+            }
+        });
+
+        if ui.button("Rasterize layer").clicked() {
+            self.canvas.rasterize_active_layer();
+        } else if self.canvas.get_active_vector() != Some(&vector) {
+            self.canvas.set_active_vector(vector);
+        }
+    }
+
+    fn draw_text_layer_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(mut text) = self.canvas.get_active_text().cloned() else { return };
+
+        ui.separator();
+        ui.heading("Text layer");
+        ui.text_edit_multiline(&mut text.text);
+
+        egui::ComboBox::from_label("Font")
+            .selected_text(match text.font_name.is_empty() {
+                true => "Default".to_string(),
+                false => text.font_name.clone()
+            })
+            .show_ui(ui, |ui| {
+                for name in self.canvas.get_fonts().names() {
+                    ui.selectable_value(&mut text.font_name, name.to_string(), name);
+                }
+            });
+        ui.label("Drop a .ttf or .otf file to load a font");
+
+        ui.horizontal(|ui| {
+            ui.label("Size");
+            ui.add(egui::DragValue::new(&mut text.size).speed(0.5).clamp_range(1.0..=1000.0));
+            let mut color = text.color.to_color32();
+            ui.color_edit_button_srgba(&mut color);
+            text.color = Color::from_color32(&color);
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut text.align, TextAlign::Left, "Left");
+            ui.radio_value(&mut text.align, TextAlign::Center, "Center");
+            ui.radio_value(&mut text.align, TextAlign::Right, "Right");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Line spacing");
+            ui.add(egui::DragValue::new(&mut text.line_spacing).speed(0.05).clamp_range(0.1..=10.0));
+        });
+        ui.checkbox(&mut text.anti_alias, "Anti-aliasing");
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            ui.add(egui::DragValue::new(&mut text.position.x));
+            ui.add(egui::DragValue::new(&mut text.position.y));
+        });
+
+        if ui.button("Rasterize layer").clicked() {
+            self.canvas.rasterize_active_layer();
+        } else if self.canvas.get_active_text() != Some(&text) {
+            self.canvas.set_active_text(text);
+        }
+    }
+
+    // only layers whose revision changed get a new texture
+    fn update_thumbnails(&mut self, ctx: &egui::Context, config: &CanvasLayersConfig) {
+        let ids = config.flatten().iter().map(|(entry, _)| entry.id).collect::<Vec<_>>();
+        self.thumbnails.retain(|(id, _), _| ids.contains(id));
+        for id in ids {
+            let Some(revision) = self.canvas.get_layer_revision(id) else { continue };
+            for mask in [false, true] {
+                if self.thumbnails.get(&(id, mask)).is_some_and(|(cached, _)| *cached == revision) {
+                    continue;
+                }
+                let thumbnail = match mask {
+                    false => self.canvas.get_layer_thumbnail(id, 24),
+                    true => self.canvas.get_mask_thumbnail(id, 24)
+                };
+                match thumbnail {
+                    Some(thumbnail) => {
+                        let name = format!("thumbnail {} {}", id.0, mask);
+                        let texture = ctx.load_texture(name, layer_image(&thumbnail), TextureOptions::NEAREST);
+                        self.thumbnails.insert((id, mask), (revision, texture));
+                    }
+                    None => {
+                        self.thumbnails.remove(&(id, mask));
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw_mask_settings(&mut self, ui: &mut egui::Ui) {
+        let config = self.canvas.get_canvas_layers_config();
+        let Some(active) = config.find(config.active_layer_id) else { return };
+
+        ui.horizontal(|ui| {
+            match active.mask_enabled {
+                None => {
+                    if ui.button("Add mask").clicked() {
+                        self.canvas.add_active_mask();
+                    }
+                }
+                Some(_) => {
+                    if ui.button("Apply mask").clicked() {
+                        self.canvas.apply_active_mask();
+                    }
+                    if ui.button("Delete mask").clicked() {
+                        self.canvas.delete_active_mask();
+                    }
+                }
+            }
+        });
+        if active.mask_enabled.is_some() {
+            let mut edit_mask = self.canvas.get_edit_mask();
+            ui.horizontal(|ui| {
+                ui.label("Paint on");
+                ui.radio_value(&mut edit_mask, false, "Layer");
+                ui.radio_value(&mut edit_mask, true, "Mask");
+            });
+            self.canvas.set_edit_mask(edit_mask);
+        }
+    }
+
+    fn draw_group_settings(&mut self, ui: &mut egui::Ui) {
+        let active_group = self.canvas.get_active_group().copied();
+        ui.horizontal(|ui| {
+            if ui.button("Group").clicked() {
+                self.canvas.group_active_layer();
+            }
+            if active_group.is_some() && ui.button("Ungroup").clicked() {
+                self.canvas.ungroup_active_layer();
+            }
+        });
+        let Some(mut group) = active_group else { return };
+        let id = self.canvas.get_canvas_layers_config().active_layer_id;
+
+        ui.separator();
+        ui.heading("Group");
+        ui.add(egui::Slider::new(&mut group.opacity, 0.0..=1.0).text("Opacity"));
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut group.blend, GroupBlend::PassThrough, "Pass through");
+            ui.radio_value(&mut group.blend, GroupBlend::Isolated, "Isolated");
+        });
+        self.canvas.set_group(id, group);
+    }
+
+    fn draw_adjustment_layer_settings(&mut self, ui: &mut egui::Ui) {
+        let Some(mut adjustment_layer) = self.canvas.get_active_adjustment().cloned() else { return };
+        let id = self.canvas.get_canvas_layers_config().active_layer_id;
+
+        ui.separator();
+        ui.heading("Adjustment layer");
+        ui.label(adjustment_layer.adjustment.name());
+        ui.add(egui::Slider::new(&mut adjustment_layer.opacity, 0.0..=1.0).text("Opacity"));
+        if ui.button("Edit...").clicked() {
+            self.open_adjustment_layer_editor(id, adjustment_layer.clone());
+        }
+        self.canvas.set_adjustment_layer(id, adjustment_layer);
+    }
+
+    fn open_adjustment_layer_editor(&mut self, id: LayerId, adjustment_layer: AdjustmentLayer) {
+        self.adjustment_dialog.open_with(adjustment_layer.adjustment.clone(), self.canvas.get_active_histogram());
+        self.edited_adjustment_layer = Some((id, adjustment_layer));
+    }
+
+    // for new and opened documents, the old layers and their editors go away
+    fn replace_canvas(&mut self, canvas: Canvas) {
+        let (width, height) = canvas.get_size();
+        self.canvas = canvas;
+        self.global_params.symmetry.center = (width as f32 / 2.0, height as f32 / 2.0);
+        self.edited_adjustment_layer = None;
+        self.thumbnails.clear();
+    }
+
+    // the file for the export dialog settings, with what got lost on the way
+    fn export_bytes(&self) -> Result<(Vec<u8>, Vec<String>), String> {
+        let size = self.canvas.get_size();
+        let merged = self.canvas.get_merged_layer();
+        let bytes = match (self.export_dialog.format, self.canvas.get_indexed_palette()) {
+            (ExportFormat::IndexedPng, Some(palette)) => encode_indexed_png(&merged, &palette.colors),
+            (ExportFormat::Svg, _) => encode_svg(&export_nodes(self.canvas.get_layers()), size).map(String::into_bytes),
+            (ExportFormat::Pdf, _) => Ok(encode_pdf(&export_nodes(self.canvas.get_layers()), size)),
+            (ExportFormat::Ora, _) => return encode_ora(self.canvas.get_layers(), &merged, self.canvas.get_slices()),
+            (ExportFormat::Raster(format), _) => encode_raster(&merged, format, &self.export_dialog.options, self.global_params.secondary_color),
+            (ExportFormat::IndexedPng, None) => encode_png(&merged),
+            (ExportFormat::AnimatedGif, _) => {
+                let background = self.global_params.secondary_color;
+                let frames = self.animation_frames().into_iter().map(|(layer, duration)| (flatten(&layer, background, true), duration)).collect::<Vec<_>>();
+                encode_animated_gif(&frames)
+            }
+            (ExportFormat::Apng, _) => encode_apng(&self.animation_frames()),
+            (ExportFormat::SpriteSheet, _) => {
+                let frames = self.animation_frames().into_iter().map(|(layer, _)| layer).collect::<Vec<_>>();
+                build_sprite_sheet(&frames, self.export_dialog.sprite_columns).and_then(|sheet| encode_png(&sheet))
+            }
+        };
+        bytes.map(|bytes| (bytes, Vec::new()))
+    }
+
+    // the merged layers of every frame with their durations
+    fn animation_frames(&self) -> Vec<(FlatCanvasLayer, u32)> {
+        let durations = self.canvas.get_frame_durations();
+        durations.into_iter().enumerate()
+            .filter_map(|(i, duration)| self.canvas.get_frame_merged_layer(i).map(|layer| (layer, duration)))
+            .collect()
+    }
+
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped_files = ctx.input(|s| s.raw.dropped_files.clone());
+        for file in dropped_files {
+            // web gives the bytes, native gives the path
+            let name = file.path.as_ref()
+                .and_then(|path| path.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or(file.name.clone());
+            let bytes = match (&file.bytes, &file.path) {
+                (Some(bytes), _) => Some(bytes.to_vec()),
+                (None, Some(path)) => std::fs::read(path).ok(),
+                _ => None
+            };
+            let Some(bytes) = bytes else { continue };
+
+            let lowercase = name.to_lowercase();
+            if lowercase.ends_with(".ttf") || lowercase.ends_with(".otf") {
+                if let Err(err) = self.canvas.load_font(name, bytes) {
+                    self.show_status(format!("couldn't load the font {}", err));
+                }
+            } else if RasterFormat::detect(&bytes).is_some() {
+                match decode_image(&bytes) {
+                    Ok(layer) => {
+                        self.canvas.add_raster_layer(&layer);
+                    }
+                    Err(err) => self.show_status(format!("couldn't open {}: {}", name, err))
+                }
+            } else if lowercase.ends_with(".ora") || lowercase.ends_with(".psd") {
+                let document = match lowercase.ends_with(".ora") {
+                    true => read_ora(&bytes),
+                    false => read_psd(&bytes)
+                };
+                match document {
+                    Ok(document) => {
+                        self.file_warnings_dialog.open_with(format!("Opened {}", name), document.warnings.clone());
+                        self.replace_canvas(Canvas::from_document(document));
+                    }
+                    Err(err) => self.show_status(format!("couldn't open {}: {}", name, err))
+                }
+            } else if PaletteFormat::detect(&name, &bytes).is_some() {
+                match Palette::import(&name, &bytes) {
+                    Ok((palette, warnings)) => {
+                        self.palette_panel.palette = palette;
+                        self.file_warnings_dialog.open_with(format!("Imported {}", name), warnings);
+                    }
+                    Err(err) => self.show_status(format!("couldn't import the palette {}: {}", name, err))
+                }
+            }
+        }
+    }
+
     fn draw_center(&mut self, ctx: &egui::Context, take_input: bool) -> egui::InnerResponse<()> {
         egui::CentralPanel::default().show(ctx, |ui| {
 
@@ -192,24 +828,34 @@ impl AppContext {
 
             let mut middle_button = false;
             let mut current = Pos2::new(0f32, 0f32);
-            let mut ctrl_key = false;
-            let mut z_key = false;
-            let mut y_key = false;
 
             if input {
                 ctx.input(|s| {
                     middle_button = s.pointer.button_down(PointerButton::Middle);
                     self.primary_button = s.pointer.button_down(PointerButton::Primary);
+                    self.secondary_button = s.pointer.button_down(PointerButton::Secondary);
+                    self.alt_key = s.modifiers.alt;
                     current = s.pointer.latest_pos().unwrap_or_default();
-                    ctrl_key = s.modifiers.ctrl;
-                    z_key = s.key_pressed(egui::Key::Z);
-                    y_key = s.key_pressed(egui::Key::Y);
                 });
             }
 
             let mut image_rect: Rect = Rect::from_two_pos(Pos2::new(0f32, 0f32), Pos2::new(0f32, 0f32));
+            let symmetry_axes = self.global_params.symmetry.axes(size);
             let scroll_area = egui::ScrollArea::both().drag_to_scroll(middle_button).show(ui, |ui| {
                 image_rect = ui.image(&texture).rect;
+
+                let to_screen = |(x, y): (f32, f32)| image_rect.min + Vec2::new(x, y);
+                let axis_stroke = egui::Stroke::new(1.0, Color32::from_rgba_unmultiplied(0, 160, 255, 180));
+                let painter = ui.painter_at(image_rect);
+                for (start, end) in symmetry_axes {
+                    painter.line_segment([to_screen(start), to_screen(end)], axis_stroke);
+                }
+                if let Some(tool) = self.paint_tools.get(self.paint_tools.selected()) {
+                    self.canvas.draw_tool_overlay(tool, &painter, &to_screen);
+                }
+                if self.show_slices || self.slices_dialog.open {
+                    draw_slices_overlay(&painter, self.canvas.get_slices(), size, &to_screen);
+                }
             });
             self.global_params.cursor_in_canvas = scroll_area.inner_rect.contains(current);
             current.x -= image_rect.min.x;
@@ -223,11 +869,13 @@ impl AppContext {
                 };
             }
 
-            if ctrl_key && z_key {
-                self.canvas.undo();
-            }
-            if ctrl_key && y_key {
-                self.canvas.redo();
+            // don't steal the shortcuts from text fields
+            let text_focused = ctx.wants_keyboard_input();
+            if input && !text_focused {
+                let pressed = ctx.input_mut(|s| self.actions.consume_pressed(s));
+                for id in pressed {
+                    self.run_action(&id);
+                }
             }
 
             //ui.label(format!("drawing:{} origin:{},{} current:{},{}", drawing, origin.x, origin.y, current.x, current.y));
@@ -239,25 +887,167 @@ impl AppContext {
 
         if self.size_dialog.open {
             dialog_opened = true;
-            self.size_dialog.show_size_window(ctx);
+            if self.size_dialog.show_size_window(ctx) {
+                let dialog = &self.size_dialog;
+                let (old_width, old_height) = self.canvas.get_size();
+                self.canvas.set_size(dialog.width, dialog.height, dialog.keep_horizontal, dialog.keep_vertical);
+                let (width, height) = self.canvas.get_size();
+                let symmetry = &mut self.global_params.symmetry;
+                symmetry.center = (symmetry.center.0 * width as f32 / old_width as f32, symmetry.center.1 * height as f32 / old_height as f32);
+            }
+        }
+
+        if self.indexed_dialog.open {
+            dialog_opened = true;
+            if self.indexed_dialog.show_indexed_window(ctx) {
+                let mut palette = match self.indexed_dialog.palette_source {
+                    PaletteSource::Generate => {
+                        let merged = self.canvas.get_merged_layer();
+                        let mut palette = Palette::new("Indexed");
+                        palette.colors = median_cut(merged.get_data().iter().copied(), self.indexed_dialog.colors);
+                        palette
+                    }
+                    PaletteSource::PalettePanel => self.palette_panel.palette.clone()
+                };
+                palette.colors.iter_mut().for_each(|color| color.alpha = 255);
+                palette.colors.truncate(255);
+                if palette.colors.is_empty() {
+                    palette.colors.push(Color::black());
+                }
+                self.canvas.convert_to_indexed(palette, self.indexed_dialog.dither);
+            }
+        }
+
+        if self.adjustment_dialog.open {
+            dialog_opened = true;
+            let result = self.adjustment_dialog.show_adjustment_window(ctx);
+            let adjustment = self.adjustment_dialog.adjustment.clone();
+            if let Some((id, original)) = self.edited_adjustment_layer.clone() {
+                // adjustment layers are edited in place
+                let edited = AdjustmentLayer { adjustment, ..original.clone() };
+                match result {
+                    Some(false) => self.canvas.set_adjustment_layer(id, original),
+                    _ => self.canvas.set_adjustment_layer(id, edited)
+                }
+                if result.is_some() {
+                    self.edited_adjustment_layer = None;
+                }
+            } else {
+                let operation = LayerOperation::Adjustment(adjustment);
+                match result {
+                    Some(true) => self.canvas.apply_operation(&operation),
+                    Some(false) => self.canvas.set_operation_preview(None),
+                    None => self.canvas.set_operation_preview(Some(operation))
+                }
+            }
+        }
+
+        if self.filter_dialog.open {
+            dialog_opened = true;
+            let result = self.filter_dialog.show_filter_window(ctx);
+            let operation = LayerOperation::Filter(self.filter_dialog.filter.clone(), self.filter_dialog.edge_mode);
+            match result {
+                Some(true) => self.canvas.apply_operation(&operation),
+                Some(false) => self.canvas.set_operation_preview(None),
+                None if self.filter_dialog.live_preview => self.canvas.set_operation_preview(Some(operation)),
+                None => self.canvas.set_operation_preview(None)
+            }
+        }
+
+        if self.new_document_dialog.open {
+            dialog_opened = true;
+            if self.new_document_dialog.show_new_document_window(ctx) {
+                let dialog = &self.new_document_dialog;
+                let mut canvas = Canvas::with_format(dialog.width, dialog.height, dialog.format);
+                canvas.set_blend_space(dialog.blend_space);
+                self.replace_canvas(canvas);
+            }
+        }
+
+        if self.command_palette.open {
+            dialog_opened = true;
+            if let Some(id) = self.command_palette.show_command_palette(ctx, &self.actions) {
+                self.run_action(&id);
+            }
+        }
+
+        if self.shortcuts_dialog.open {
+            dialog_opened = true;
+            if self.shortcuts_dialog.show_shortcuts_window(ctx, &mut self.actions) {
+                if let Err(err) = self.actions.save() {
+                    self.show_status(format!("couldn't save the shortcuts: {}", err));
+                }
+            }
+        }
+
+        if self.export_dialog.open {
+            dialog_opened = true;
+            if self.export_dialog.estimated_size.is_none() {
+                self.export_dialog.estimated_size = Some(self.export_bytes().map(|(bytes, _)| bytes.len()));
+            }
+            if self.export_dialog.show_export_window(ctx, self.canvas.get_indexed_palette().is_some(), self.canvas.get_frame_count()) {
+                let bytes = self.export_bytes().map(|(bytes, warnings)| {
+                    self.file_warnings_dialog.open_with(format!("Exported {}", self.export_dialog.path), warnings);
+                    bytes
+                });
+                if let Err(err) = bytes.and_then(|bytes| write_file(&self.export_dialog.path, &bytes)) {
+                    self.show_status(format!("couldn't export: {}", err));
+                }
+            }
+        }
+
+        if self.slices_dialog.open {
+            dialog_opened = true;
+            let mut slices = self.canvas.get_slices().clone();
+            let confirmed = self.slices_dialog.show_slices_window(ctx, &mut slices, self.canvas.get_size());
+            if slices != *self.canvas.get_slices() {
+                self.canvas.set_slices(slices);
+            }
+            if confirmed {
+                let dialog = &self.slices_dialog;
+                let files = slice_files(&self.canvas.get_merged_layer(), self.canvas.get_slices(), dialog.export, &dialog.name);
+                if let Err(err) = files.and_then(|files| write_files(&dialog.folder, &files)) {
+                    self.show_status(format!("couldn't export the slices: {}", err));
+                }
+            }
+        }
+
+        if self.file_warnings_dialog.open {
+            self.file_warnings_dialog.show_file_warnings_window(ctx);
         }
 
         dialog_opened
     }
 
     fn handle_tool_events(&mut self) {
-        match self.paint_tools.get_mut(&self.selected_paint_tool) {
+        let button_down = self.primary_button || self.secondary_button;
+        if !self.tool_button_started {
+            // alt picks colors with any tool, the tool is kept until the stroke ends
+            self.stroke_tool = match self.alt_key {
+                true => COLOR_PICKER_TOOL,
+                false => self.paint_tools.selected()
+            };
+        }
+
+        let selected_tool = self.paint_tools.selected();
+        match self.paint_tools.get_mut(self.stroke_tool) {
             Some(value) => {
                 let contains = self.global_params.cursor_in_canvas;
-                if contains && !self.tool_button_started && self.primary_button {
-                    self.canvas.stroke_start(&self.global_params, value.as_mut());
+                if contains && !self.tool_button_started && button_down {
+                    // alt-click only means secondary when the color picker is the selected tool
+                    let alt_secondary = self.alt_key && selected_tool == COLOR_PICKER_TOOL;
+                    self.global_params.secondary_stroke = self.secondary_button || alt_secondary;
+                    if self.stroke_tool != COLOR_PICKER_TOOL {
+                        self.palette_panel.add_recent_color(self.global_params.stroke_color());
+                    }
+                    self.canvas.stroke_start(&mut self.global_params, value);
                     self.tool_button_started = true;
                 } else {
                     if self.tool_button_started {
-                        if contains && self.primary_button {
-                            self.canvas.stroke_update(&self.global_params, value.as_mut());
+                        if contains && button_down {
+                            self.canvas.stroke_update(&mut self.global_params, value);
                         } else {
-                            self.canvas.stroke_end(&self.global_params, value.as_mut());
+                            self.canvas.stroke_end(&mut self.global_params, value);
                             self.tool_button_started = false;
                         }
                     }
@@ -271,21 +1061,43 @@ impl AppContext {
 
     fn draw_panel_bottom(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
-            let pos = self.global_params.current_pixel;
-            match pos {
-                Some(value) => {
-                    ui.label(format!("{} x {}", value.x, value.y));
+            ui.horizontal(|ui| {
+                let pos = self.global_params.current_pixel;
+                match pos {
+                    Some(value) => {
+                        ui.label(format!("{} x {}", value.x, value.y));
+                    }
+                    None => {}
+
                 }
-                None => {}
-        
-            }
+                // shown for a few seconds
+                let now = ctx.input(|s| s.time);
+                if let Some((message, time)) = &self.status_message {
+                    if now - time < 4.0 {
+                        ui.colored_label(Color32::LIGHT_RED, message);
+                    }
+                }
+            });
         });
     }
 
     fn draw_panel_top(&mut self, ctx: &egui::Context) {
+        let run = Cell::new(None);
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
+            let actions = &self.actions;
+            let action_button = |ui: &mut egui::Ui, id: &str, label: Option<String>| {
+                if action_button(ui, actions, id, label) {
+                    run.set(Some(id.to_string()));
+                }
+            };
+            let action_toggle = |ui: &mut egui::Ui, id: &str, checked: bool| {
+                if action_toggle(ui, actions, id, checked) {
+                    run.set(Some(id.to_string()));
+                }
+            };
             menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
+                    action_button(ui, "file.new", None);
                     if ui.button("Open (TODO)").clicked() {
                         ui.close_menu();
                         //TODO: open file
@@ -296,23 +1108,14 @@ impl AppContext {
                         //TODO: save file
                         //This will be handled differently for web and native
                     }
-                    if ui.button("Size...").clicked() {
-                        ui.close_menu();
-                        self.size_dialog.width = self.canvas.get_size().0;
-                        self.size_dialog.height = self.canvas.get_size().1;
-                        self.size_dialog.open = true;
-                    }
+                    action_button(ui, "file.export", None);
+                    action_button(ui, "file.size", None);
+                    action_button(ui, "file.slices", None);
                 });
 
                 ui.menu_button("Edit", |ui| {
-                    if ui.button("Undo").clicked() {
-                        ui.close_menu();
-                        self.canvas.undo();
-                    }
-                    if ui.button("Redo").clicked() {
-                        ui.close_menu();
-                        self.canvas.redo();
-                    }
+                    action_button(ui, "edit.undo", None);
+                    action_button(ui, "edit.redo", None);
 
                     ui.separator();
 
@@ -325,17 +1128,101 @@ impl AppContext {
                         ui.close_menu();
                         //TODO: open resize dialog
                     }
+
+                    ui.separator();
+
+                    action_button(ui, "edit.shortcuts", None);
+                });
+
+                ui.menu_button("View", |ui| {
+                    action_toggle(ui, "view.symmetry_toolbar", self.show_symmetry_toolbar);
+                    action_toggle(ui, "view.slices", self.show_slices);
+                    action_button(ui, "view.command_palette", None);
+                });
+
+                ui.menu_button("Layer", |ui| {
+                    action_button(ui, "layer.new_vector", None);
+                    action_button(ui, "layer.rasterize", None);
+                });
+
+                ui.menu_button("Animation", |ui| {
+                    action_button(ui, "animation.new_frame", None);
+                    action_button(ui, "animation.duplicate_frame", None);
+                    action_button(ui, "animation.previous_frame", None);
+                    action_button(ui, "animation.next_frame", None);
+                    action_button(ui, "animation.play", None);
+                });
+
+                ui.menu_button("Adjustments", |ui| {
+                    for adjustment in Adjustment::all_defaults() {
+                        action_button(ui, &action_id("adjustment", adjustment.name()), None);
+                    }
+                    ui.separator();
+                    ui.menu_button("New adjustment layer", |ui| {
+                        for adjustment in Adjustment::all_defaults() {
+                            let label = format!("{}...", adjustment.name());
+                            action_button(ui, &action_id("adjustment_layer", adjustment.name()), Some(label));
+                        }
+                    });
+                });
+
+                ui.menu_button("Filters", |ui| {
+                    for filter in Filter::all_defaults() {
+                        action_button(ui, &action_id("filter", filter.name()), None);
+                    }
+                });
+
+                ui.menu_button("Image", |ui| {
+                    let indexed = self.canvas.get_indexed_palette().is_some();
+                    action_toggle(ui, "image.indexed", indexed);
+                    action_toggle(ui, "image.rgba", !indexed);
+                    ui.separator();
+                    ui.label(format!("Bit depth: {}", self.canvas.get_pixel_format().name()));
+                    action_toggle(ui, "image.linear_blending", self.canvas.get_blend_space() == BlendSpace::Linear);
                 });
             });
+
+            if self.show_symmetry_toolbar {
+                self.draw_symmetry_toolbar(ui);
+            }
+        });
+        if let Some(id) = run.into_inner() {
+            self.run_action(&id);
+        }
+    }
+
+    fn draw_symmetry_toolbar(&mut self, ui: &mut egui::Ui) {
+        let size = self.canvas.get_size();
+        let symmetry = &mut self.global_params.symmetry;
+        ui.horizontal(|ui| {
+            ui.label("Symmetry:");
+            ui.selectable_value(&mut symmetry.mode, SymmetryMode::Off, "Off");
+            ui.selectable_value(&mut symmetry.mode, SymmetryMode::Vertical, "Vertical");
+            ui.selectable_value(&mut symmetry.mode, SymmetryMode::Horizontal, "Horizontal");
+            ui.selectable_value(&mut symmetry.mode, SymmetryMode::Both, "Both");
+            ui.selectable_value(&mut symmetry.mode, SymmetryMode::Radial, "Radial");
+            if symmetry.mode == SymmetryMode::Radial {
+                ui.add(egui::DragValue::new(&mut symmetry.radial_count).speed(0.1).clamp_range(2..=64));
+            }
+            ui.separator();
+            ui.label("Center:");
+            ui.add(egui::DragValue::new(&mut symmetry.center.0).speed(1.0).clamp_range(0.0..=size.0 as f32));
+            ui.add(egui::DragValue::new(&mut symmetry.center.1).speed(1.0).clamp_range(0.0..=size.1 as f32));
+            if ui.button("Reset").clicked() {
+                symmetry.center = (size.0 as f32 / 2.0, size.1 as f32 / 2.0);
+            }
         });
     }
 }
 
 impl eframe::App for AppContext {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.frame_time = ctx.input(|s| s.time);
 
         let mut take_input: bool = true;
 
+        self.handle_dropped_files(ctx);
+
         self.draw_panel_top(ctx);
 
         self.draw_panel_left(ctx, &mut take_input);
@@ -343,11 +1230,24 @@ impl eframe::App for AppContext {
         self.draw_panel_right(ctx);
 
         self.draw_panel_bottom(ctx);
+
+        self.draw_timeline_panel(ctx);
         
         self.draw_center(ctx, take_input);
 
         self.handle_tool_events();
 
+        if let Some(reason) = self.canvas.take_blocked_reason() {
+            self.show_status(reason);
+        }
+
+        if self.tool_options_changed && !ctx.input(|i| i.pointer.any_down()) {
+            self.tool_options_changed = false;
+            if let Err(err) = self.paint_tools.save() {
+                self.show_status(format!("couldn't save the tool options: {}", err));
+            }
+        }
+
         //ctx.request_repaint();
     }
 }
@@ -362,12 +1262,29 @@ impl eframe::App for AppContext {
 //    }
 //}
 
-impl Into<WidgetText> for CanvasLayerEntry {
-    fn into(self) -> WidgetText {
-        let layer_str = self.id.0.to_string();
+// a menu item for an action with its shortcut, the action's name is used without a label
+fn action_button(ui: &mut egui::Ui, actions: &ActionRegistry, id: &str, label: Option<String>) -> bool {
+    action_menu_item(ui, actions, id, label, false)
+}
+
+// highlighted while checked, for settings that are switched on and off
+fn action_toggle(ui: &mut egui::Ui, actions: &ActionRegistry, id: &str, checked: bool) -> bool {
+    action_menu_item(ui, actions, id, None, checked)
+}
 
-        // layer : layer_str
-        let final_str = format!("layer : {}", layer_str);
-        WidgetText::from(final_str)
+fn action_menu_item(ui: &mut egui::Ui, actions: &ActionRegistry, id: &str, label: Option<String>, checked: bool) -> bool {
+    let Some(action) = actions.get(id) else { return false };
+    let text = label.unwrap_or(action.name.clone());
+    let button = Button::new(text).selected(checked).shortcut_text(actions.shortcut_text(ui.ctx(), id).unwrap_or_default());
+    let clicked = ui.add(button).clicked();
+    if clicked {
+        ui.close_menu();
     }
+    clicked
+}
+
+fn layer_image(layer: &FlatCanvasLayer) -> ColorImage {
+    let (width, height) = layer.get_size();
+    let pixels = layer.get_data().iter().map(|color| color.to_color32()).collect();
+    ColorImage { size: [width as usize, height as usize], pixels }
 }
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fmt;
+use eframe::egui;
+use egui::{InputState, Key, KeyboardShortcut, Modifiers};
+use crate::paint_app::config::{read_config, write_config};
+
+const CONFIG_FILE: &str = "shortcuts.cfg";
+
+// keys that can be bound, the names are what egui calls them and what goes in the config file
+const BINDABLE_KEYS: [Key; 65] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    Key::Escape, Key::Tab, Key::Backspace, Key::Enter, Key::Space, Key::Insert, Key::Delete,
+    Key::Home, Key::End, Key::PageUp, Key::PageDown, Key::Minus, Key::PlusEquals, Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight,
+];
+
+// command is ctrl, or cmd on mac (web keyboards included)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    pub command: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub key: Key,
+}
+
+impl Shortcut {
+    pub fn new(key: Key) -> Shortcut {
+        Shortcut { command: false, alt: false, shift: false, key }
+    }
+
+    pub fn command(key: Key) -> Shortcut {
+        Shortcut { command: true, ..Shortcut::new(key) }
+    }
+
+    pub fn command_shift(key: Key) -> Shortcut {
+        Shortcut { command: true, shift: true, ..Shortcut::new(key) }
+    }
+
+    pub fn from_event(modifiers: Modifiers, key: Key) -> Shortcut {
+        Shortcut {
+            command: modifiers.command || modifiers.ctrl || modifiers.mac_cmd,
+            alt: modifiers.alt,
+            shift: modifiers.shift,
+            key
+        }
+    }
+
+    pub fn to_keyboard_shortcut(&self) -> KeyboardShortcut {
+        let mut modifiers = Modifiers::NONE;
+        modifiers.command = self.command;
+        modifiers.alt = self.alt;
+        modifiers.shift = self.shift;
+        KeyboardShortcut::new(modifiers, self.key)
+    }
+
+    // "Ctrl+Shift+Z", cmd and option work as well
+    pub fn parse(text: &str) -> Option<Shortcut> {
+        let mut parts = text.split('+').map(|part| part.trim()).collect::<Vec<_>>();
+        let key_name = parts.pop()?;
+        let key = BINDABLE_KEYS.into_iter().find(|key| key.name().eq_ignore_ascii_case(key_name))?;
+        let mut result = Shortcut::new(key);
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "ctrl" | "cmd" | "command" => result.command = true,
+                "alt" | "option" => result.alt = true,
+                "shift" => result.shift = true,
+                _ => return None
+            }
+        }
+        Some(result)
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let modifiers = [(self.command, "Ctrl+"), (self.alt, "Alt+"), (self.shift, "Shift+")];
+        for (_, name) in modifiers.iter().filter(|(active, _)| *active) {
+            write!(f, "{}", name)?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Action {
+    // stable, used in the config file. "edit.undo", "tool.pencil"
+    pub id: String,
+    pub name: String,
+    pub shortcuts: Vec<Shortcut>,
+    pub default_shortcuts: Vec<Shortcut>,
+}
+
+// every menu item and tool, in the order they were registered
+pub struct ActionRegistry {
+    actions: Vec<Action>,
+    index: HashMap<String, usize>,
+}
+
+impl ActionRegistry {
+    pub fn new() -> ActionRegistry {
+        ActionRegistry {
+            actions: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, id: &str, name: &str, shortcuts: &[Shortcut]) {
+        let action = Action {
+            id: id.to_string(),
+            name: name.to_string(),
+            shortcuts: shortcuts.to_vec(),
+            default_shortcuts: shortcuts.to_vec(),
+        };
+        match self.index.get(id) {
+            Some(i) => self.actions[*i] = action,
+            None => {
+                self.index.insert(id.to_string(), self.actions.len());
+                self.actions.push(action);
+            }
+        }
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Action> {
+        self.index.get(id).map(|i| &self.actions[*i])
+    }
+
+    pub fn set_shortcuts(&mut self, id: &str, shortcuts: Vec<Shortcut>) {
+        if let Some(i) = self.index.get(id) {
+            self.actions[*i].shortcuts = shortcuts;
+        }
+    }
+
+    pub fn reset(&mut self, id: &str) {
+        if let Some(i) = self.index.get(id) {
+            let action = &mut self.actions[*i];
+            action.shortcuts = action.default_shortcuts.clone();
+        }
+    }
+
+    pub fn reset_all(&mut self) {
+        self.actions.iter_mut().for_each(|action| action.shortcuts = action.default_shortcuts.clone());
+    }
+
+    // the first shortcut as menus show it, ctrl becomes cmd on mac
+    pub fn shortcut_text(&self, ctx: &egui::Context, id: &str) -> Option<String> {
+        let shortcut = self.get(id)?.shortcuts.first()?;
+        Some(ctx.format_shortcut(&shortcut.to_keyboard_shortcut()))
+    }
+
+    pub fn bound_to(&self, shortcut: Shortcut) -> Vec<&Action> {
+        self.actions.iter().filter(|action| action.shortcuts.contains(&shortcut)).collect()
+    }
+
+    // shortcuts used by more than one action, with those actions
+    pub fn conflicts(&self) -> Vec<(Shortcut, Vec<&Action>)> {
+        let mut result: Vec<(Shortcut, Vec<&Action>)> = Vec::new();
+        for action in self.actions.iter() {
+            for shortcut in action.shortcuts.iter() {
+                match result.iter_mut().find(|(other, _)| other == shortcut) {
+                    Some((_, actions)) => actions.push(action),
+                    None => result.push((*shortcut, vec![action]))
+                }
+            }
+        }
+        result.retain(|(_, actions)| actions.len() > 1);
+        result
+    }
+
+    // takes the key presses of this frame, on a conflict the action registered first wins
+    pub fn consume_pressed(&self, input: &mut InputState) -> Vec<String> {
+        let mut result = Vec::new();
+        for action in self.actions.iter() {
+            for shortcut in action.shortcuts.iter() {
+                if input.consume_shortcut(&shortcut.to_keyboard_shortcut()) && !result.contains(&action.id) {
+                    result.push(action.id.clone());
+                }
+            }
+        }
+        result
+    }
+
+    // best match first, actions that don't match are left out
+    pub fn search(&self, query: &str) -> Vec<&Action> {
+        let mut matches = self.actions.iter()
+            .filter_map(|action| fuzzy_score(query, &action.name).map(|score| (score, action)))
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|(score, _)| -score);
+        matches.into_iter().map(|(_, action)| action).collect()
+    }
+
+    // one line per action that isn't on its defaults: "edit.redo = Ctrl+Y, Ctrl+Shift+Z"
+    pub fn to_config(&self) -> String {
+        let mut result = String::new();
+        for action in self.actions.iter().filter(|action| action.shortcuts != action.default_shortcuts) {
+            let shortcuts = action.shortcuts.iter().map(|shortcut| shortcut.to_string()).collect::<Vec<_>>();
+            result += &format!("{} = {}\n", action.id, shortcuts.join(", "));
+        }
+        result
+    }
+
+    // lines that can't be read are skipped and reported, ids that aren't registered are ignored
+    pub fn load_config(&mut self, text: &str) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((id, value)) = line.split_once('=') else {
+                errors.push(format!("line {}: expected id = shortcuts", number + 1));
+                continue;
+            };
+            let shortcuts = value.split(',')
+                .map(|text| text.trim())
+                .filter(|text| !text.is_empty())
+                .map(|text| Shortcut::parse(text).ok_or(text))
+                .collect::<Result<Vec<_>, _>>();
+            match shortcuts {
+                Ok(shortcuts) => self.set_shortcuts(id.trim(), shortcuts),
+                Err(text) => errors.push(format!("line {}: unknown shortcut {}", number + 1, text))
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("\n"))
+        }
+    }
+
+    pub fn load(&mut self) -> Result<(), String> {
+        match read_config(CONFIG_FILE)? {
+            Some(text) => self.load_config(&text),
+            None => Ok(())
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        write_config(CONFIG_FILE, &self.to_config())
+    }
+}
+
+impl Default for ActionRegistry {
+    fn default() -> ActionRegistry {
+        ActionRegistry::new()
+    }
+}
+
+// the "Pencil" action of a group "tool" gets the id "tool.pencil"
+pub fn action_id(group: &str, name: &str) -> String {
+    let name = name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+    format!("{}.{}", group, name)
+}
+
+// every character of the query has to show up in order. matches that are next to each
+// other or at the start of a word score higher, shorter texts win ties
+pub fn fuzzy_score(query: &str, text: &str) -> Option<i32> {
+    let query = query.chars().filter(|c| !c.is_whitespace()).map(|c| c.to_ascii_lowercase()).collect::<Vec<_>>();
+    let text = text.chars().map(|c| c.to_ascii_lowercase()).collect::<Vec<_>>();
+    let Some(first) = query.first() else { return Some(-(text.len() as i32)) };
+    // matching is greedy, so every place the first character shows up is tried
+    let best = (0..text.len())
+        .filter(|start| text[*start] == *first)
+        .filter_map(|start| score_from(&query, &text, start))
+        .max()?;
+    Some(best * 100 - text.len() as i32)
+}
+
+fn score_from(query: &[char], text: &[char], start: usize) -> Option<i32> {
+    let mut score = 0;
+    let mut position = start;
+    let mut previous = None;
+    for c in query {
+        let found = position + text[position..].iter().position(|other| other == c)?;
+        score += match found {
+            0 => 8,
+            _ if !text[found - 1].is_alphanumeric() => 8,
+            _ if previous == Some(found - 1) => 5,
+            _ => 1
+        };
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortcut_text() {
+        let redo = Shortcut::command_shift(Key::Z);
+        assert_eq!(redo.to_string(), "Ctrl+Shift+Z");
+        assert_eq!(Shortcut::parse("Ctrl+Shift+Z"), Some(redo));
+        assert_eq!(Shortcut::parse("cmd + shift + z"), Some(redo));
+        assert_eq!(Shortcut::parse("Alt+PageUp"), Some(Shortcut { alt: true, ..Shortcut::new(Key::PageUp) }));
+        assert_eq!(Shortcut::parse("Hyper+Z"), None);
+        assert_eq!(Shortcut::parse("Ctrl+"), None);
+    }
+
+    #[test]
+    fn test_config_and_conflicts() {
+        let mut registry = ActionRegistry::new();
+        registry.register("edit.undo", "Undo", &[Shortcut::command(Key::Z)]);
+        registry.register("edit.redo", "Redo", &[Shortcut::command(Key::Y), Shortcut::command_shift(Key::Z)]);
+        registry.register("color.swap", "Swap colors", &[Shortcut::new(Key::X)]);
+        assert_eq!(registry.to_config(), "");
+        assert!(registry.conflicts().is_empty());
+
+        registry.set_shortcuts("color.swap", vec![Shortcut::command(Key::Z)]);
+        registry.set_shortcuts("edit.undo", vec![]);
+        let config = registry.to_config();
+        assert_eq!(config, "edit.undo = \ncolor.swap = Ctrl+Z\n");
+
+        let mut loaded = ActionRegistry::new();
+        loaded.register("edit.undo", "Undo", &[Shortcut::command(Key::Z)]);
+        loaded.register("color.swap", "Swap colors", &[Shortcut::new(Key::X)]);
+        assert!(loaded.load_config(&config).is_ok());
+        assert!(loaded.get("edit.undo").unwrap().shortcuts.is_empty());
+        assert_eq!(loaded.get("color.swap").unwrap().shortcuts, vec![Shortcut::command(Key::Z)]);
+
+        // the bad line is reported, the good one still applies
+        assert!(loaded.load_config("edit.undo = Ctrl+Q\ncolor.swap = Ctrl+Nope").is_err());
+        assert_eq!(loaded.get("edit.undo").unwrap().shortcuts, vec![Shortcut::command(Key::Q)]);
+
+        loaded.set_shortcuts("edit.undo", vec![Shortcut::command(Key::Z)]);
+        let conflicts = loaded.conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].0, Shortcut::command(Key::Z));
+        assert_eq!(conflicts[0].1.iter().map(|action| action.id.as_str()).collect::<Vec<_>>(), ["edit.undo", "color.swap"]);
+
+        loaded.reset_all();
+        assert!(loaded.conflicts().is_empty());
+    }
+
+    #[test]
+    fn test_search() {
+        let mut registry = ActionRegistry::new();
+        for name in ["Gaussian blur", "Box blur", "Brightness / Contrast", "Undo"] {
+            registry.register(&action_id("filter", name), name, &[]);
+        }
+        assert_eq!(registry.get("filter.brightness_contrast").unwrap().name, "Brightness / Contrast");
+        let names = |query: &str| registry.search(query).iter().map(|action| action.name.clone()).collect::<Vec<_>>();
+        assert_eq!(names("bb"), ["Box blur"]);
+        assert_eq!(names("blur")[..2], ["Box blur", "Gaussian blur"]);
+        assert_eq!(names("bc"), ["Brightness / Contrast"]);
+        assert!(names("xyz").is_empty());
+        assert_eq!(names("").len(), 4);
+    }
+}
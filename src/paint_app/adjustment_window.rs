@@ -0,0 +1,205 @@
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, Sense, Slider, Stroke, Vec2};
+use crate::paint_app::adjustments::{curve_lookup_table, Adjustment, Levels};
+
+pub struct AdjustmentWindow {
+    pub open: bool,
+    pub adjustment: Adjustment,
+    // red, green, blue and luminance of the active layer when the window was opened
+    pub histogram: Option<[[u32; 256]; 4]>,
+    // 0 is the master channel, then red, green and blue
+    levels_channel: usize,
+    dragged_point: Option<usize>,
+}
+
+const CURVE_SIZE: f32 = 256.0;
+
+impl AdjustmentWindow {
+    pub fn new() -> AdjustmentWindow {
+        AdjustmentWindow {
+            open: false,
+            adjustment: Adjustment::Invert,
+            histogram: None,
+            levels_channel: 0,
+            dragged_point: None,
+        }
+    }
+
+    pub fn open_with(&mut self, adjustment: Adjustment, histogram: Option<[[u32; 256]; 4]>) {
+        self.adjustment = adjustment;
+        self.histogram = histogram;
+        self.levels_channel = 0;
+        self.dragged_point = None;
+        self.open = true;
+    }
+
+    // returns Some(true) when applied and Some(false) when cancelled
+    pub fn show_adjustment_window(&mut self, ctx: &egui::Context) -> Option<bool> {
+        let mut result = None;
+        let mut open = self.open;
+        egui::Window::new(self.adjustment.name().to_string())
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.settings_ui(ui);
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("OK").clicked() {
+                        result = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        result = Some(false);
+                    }
+                });
+            });
+        if !open {
+            result = Some(false);
+        }
+        if result.is_some() {
+            self.open = false;
+        }
+        result
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        let histogram = self.histogram;
+        match &mut self.adjustment {
+            Adjustment::BrightnessContrast { brightness, contrast } => {
+                ui.add(Slider::new(brightness, -1.0..=1.0).text("Brightness"));
+                ui.add(Slider::new(contrast, -1.0..=1.0).text("Contrast"));
+            }
+            Adjustment::Levels { master, channels } => {
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.levels_channel, 0, "RGB");
+                    ui.selectable_value(&mut self.levels_channel, 1, "Red");
+                    ui.selectable_value(&mut self.levels_channel, 2, "Green");
+                    ui.selectable_value(&mut self.levels_channel, 3, "Blue");
+                });
+                // the master channel shows the luminance histogram
+                let histogram_channel = match self.levels_channel {
+                    0 => 3,
+                    channel => channel - 1
+                };
+                if let Some(histogram) = histogram {
+                    let (rect, _) = ui.allocate_exact_size(Vec2::new(CURVE_SIZE, 100.0), Sense::hover());
+                    draw_histogram(ui, rect, &histogram[histogram_channel]);
+                }
+                let levels: &mut Levels = match self.levels_channel {
+                    0 => master,
+                    channel => &mut channels[channel - 1]
+                };
+                ui.label("Input");
+                ui.add(Slider::new(&mut levels.input_black, 0..=254).text("Black"));
+                ui.add(Slider::new(&mut levels.input_white, 1..=255).text("White"));
+                ui.add(Slider::new(&mut levels.gamma, 0.1..=10.0).logarithmic(true).text("Gamma"));
+                ui.label("Output");
+                ui.add(Slider::new(&mut levels.output_black, 0..=255).text("Black"));
+                ui.add(Slider::new(&mut levels.output_white, 0..=255).text("White"));
+                if levels.input_white <= levels.input_black {
+                    levels.input_white = levels.input_black + 1;
+                }
+            }
+            Adjustment::Curves { points } => {
+                curve_editor(ui, points, &mut self.dragged_point, histogram.as_ref().map(|histogram| &histogram[3]));
+                ui.label("Drag to add or move points, right click removes them");
+            }
+            Adjustment::HueSaturation { hue, saturation, lightness } => {
+                ui.add(Slider::new(hue, -180.0..=180.0).text("Hue"));
+                ui.add(Slider::new(saturation, -1.0..=1.0).text("Saturation"));
+                ui.add(Slider::new(lightness, -1.0..=1.0).text("Lightness"));
+            }
+            Adjustment::Invert | Adjustment::Desaturate => {
+                ui.label("No settings");
+            }
+            Adjustment::Posterize { levels } => {
+                ui.add(Slider::new(levels, 2..=32).text("Levels"));
+            }
+            Adjustment::Threshold { level } => {
+                if let Some(histogram) = histogram {
+                    let (rect, _) = ui.allocate_exact_size(Vec2::new(CURVE_SIZE, 100.0), Sense::hover());
+                    draw_histogram(ui, rect, &histogram[3]);
+                    let x = rect.min.x + *level as f32 / 255.0 * rect.width();
+                    ui.painter().line_segment([Pos2::new(x, rect.min.y), Pos2::new(x, rect.max.y)], Stroke::new(1.0, Color32::RED));
+                }
+                ui.add(Slider::new(level, 0..=255).text("Level"));
+            }
+        }
+    }
+}
+
+impl Default for AdjustmentWindow {
+    fn default() -> AdjustmentWindow {
+        AdjustmentWindow::new()
+    }
+}
+
+fn draw_histogram(ui: &egui::Ui, rect: Rect, histogram: &[u32; 256]) {
+    ui.painter().rect_filled(rect, 0.0, Color32::from_gray(30));
+    let max = *histogram.iter().max().unwrap_or(&1).max(&1) as f32;
+    let bar_width = rect.width() / 256.0;
+    histogram.iter().enumerate().for_each(|(i, count)| {
+        let height = *count as f32 / max * rect.height();
+        let x = rect.min.x + i as f32 * bar_width;
+        let bar = Rect::from_min_max(Pos2::new(x, rect.max.y - height), Pos2::new(x + bar_width, rect.max.y));
+        ui.painter().rect_filled(bar, 0.0, Color32::from_gray(140));
+    });
+}
+
+fn curve_editor(ui: &mut egui::Ui, points: &mut Vec<(f32, f32)>, dragged_point: &mut Option<usize>, histogram: Option<&[u32; 256]>) {
+    let (rect, response) = ui.allocate_exact_size(Vec2::splat(CURVE_SIZE), Sense::click_and_drag());
+    let to_screen = |(x, y): (f32, f32)| Pos2::new(rect.min.x + x * rect.width(), rect.max.y - y * rect.height());
+    let from_screen = |pos: Pos2| (
+        ((pos.x - rect.min.x) / rect.width()).clamp(0.0, 1.0),
+        ((rect.max.y - pos.y) / rect.height()).clamp(0.0, 1.0),
+    );
+    let point_at = |points: &Vec<(f32, f32)>, pos: Pos2| points.iter().position(|point| to_screen(*point).distance(pos) < 8.0);
+
+    if let Some(pointer) = response.interact_pointer_pos() {
+        if response.drag_started() {
+            *dragged_point = point_at(points, pointer).or_else(|| {
+                let new_point = from_screen(pointer);
+                let index = points.iter().position(|point| point.0 > new_point.0).unwrap_or(points.len());
+                points.insert(index, new_point);
+                Some(index)
+            });
+        }
+        if let (true, Some(index)) = (response.dragged(), *dragged_point) {
+            let (mut x, y) = from_screen(pointer);
+            // end points keep their x, the others stay between their neighbours
+            if index == 0 || index == points.len() - 1 {
+                x = points[index].0;
+            } else {
+                x = x.clamp(points[index - 1].0 + 0.01, points[index + 1].0 - 0.01);
+            }
+            points[index] = (x, y);
+        }
+        if response.secondary_clicked() {
+            if let Some(index) = point_at(points, pointer).filter(|index| *index > 0 && *index < points.len() - 1) {
+                points.remove(index);
+            }
+        }
+    }
+    if response.drag_released() {
+        *dragged_point = None;
+    }
+
+    match histogram {
+        Some(histogram) => draw_histogram(ui, rect, histogram),
+        None => ui.painter().rect_filled(rect, 0.0, Color32::from_gray(30))
+    }
+    for i in 1..4 {
+        let t = i as f32 / 4.0;
+        let grid_stroke = Stroke::new(1.0, Color32::from_gray(70));
+        ui.painter().line_segment([to_screen((t, 0.0)), to_screen((t, 1.0))], grid_stroke);
+        ui.painter().line_segment([to_screen((0.0, t)), to_screen((1.0, t))], grid_stroke);
+    }
+    let lut = curve_lookup_table(points);
+    let curve = lut.iter().enumerate().map(|(i, value)| to_screen((i as f32 / 255.0, *value as f32 / 255.0))).collect();
+    ui.painter().add(egui::Shape::line(curve, Stroke::new(1.5, Color32::WHITE)));
+    points.iter().for_each(|point| {
+        ui.painter().circle_filled(to_screen(*point), 4.0, Color32::LIGHT_BLUE);
+    });
+}
@@ -0,0 +1,421 @@
+use crate::paint_app::canvas_layer::FlatCanvasLayer;
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub input_black: u8,
+    pub input_white: u8,
+    pub gamma: f32,
+    pub output_black: u8,
+    pub output_white: u8,
+}
+
+impl Levels {
+    pub fn new() -> Levels {
+        Levels {
+            input_black: 0,
+            input_white: 255,
+            gamma: 1.0,
+            output_black: 0,
+            output_white: 255,
+        }
+    }
+
+    pub fn map(&self, value: u8) -> u8 {
+        let range = (self.input_white as f32 - self.input_black as f32).max(1.0);
+        let t = ((value as f32 - self.input_black as f32) / range).clamp(0.0, 1.0);
+        let t = t.powf(1.0 / self.gamma.max(0.01));
+        let output = self.output_black as f32 + t * (self.output_white as f32 - self.output_black as f32);
+        (output + 0.5).clamp(0.0, 255.0) as u8
+    }
+}
+
+impl Default for Levels {
+    fn default() -> Levels {
+        Levels::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Adjustment {
+    // both in -1..1
+    BrightnessContrast { brightness: f32, contrast: f32 },
+    // master applies to all channels after the red, green and blue ones
+    Levels { master: Levels, channels: [Levels; 3] },
+    // control points in 0..1, sorted by x, the first and last ones are the end points
+    Curves { points: Vec<(f32, f32)> },
+    // hue in degrees, saturation and lightness in -1..1
+    HueSaturation { hue: f32, saturation: f32, lightness: f32 },
+    Invert,
+    Desaturate,
+    Posterize { levels: u8 },
+    Threshold { level: u8 },
+}
+
+impl Adjustment {
+    pub fn name(&self) -> &str {
+        match self {
+            Adjustment::BrightnessContrast { .. } => "Brightness / Contrast",
+            Adjustment::Levels { .. } => "Levels",
+            Adjustment::Curves { .. } => "Curves",
+            Adjustment::HueSaturation { .. } => "Hue / Saturation",
+            Adjustment::Invert => "Invert",
+            Adjustment::Desaturate => "Desaturate",
+            Adjustment::Posterize { .. } => "Posterize",
+            Adjustment::Threshold { .. } => "Threshold",
+        }
+    }
+
+    pub fn all_defaults() -> Vec<Adjustment> {
+        vec![
+            Adjustment::BrightnessContrast { brightness: 0.0, contrast: 0.0 },
+            Adjustment::Levels { master: Levels::new(), channels: [Levels::new(); 3] },
+            Adjustment::Curves { points: vec![(0.0, 0.0), (1.0, 1.0)] },
+            Adjustment::HueSaturation { hue: 0.0, saturation: 0.0, lightness: 0.0 },
+            Adjustment::Invert,
+            Adjustment::Desaturate,
+            Adjustment::Posterize { levels: 4 },
+            Adjustment::Threshold { level: 128 },
+        ]
+    }
+
+    // per channel lookup tables for the adjustments that work on channels independently
+    pub fn lookup_tables(&self) -> Option<[[u8; 256]; 3]> {
+        let from_fn = |f: &dyn Fn(u8) -> u8| {
+            let mut lut = [0u8; 256];
+            lut.iter_mut().enumerate().for_each(|(i, value)| *value = f(i as u8));
+            lut
+        };
+        match self {
+            Adjustment::BrightnessContrast { brightness, contrast } => {
+                // contrast pivots around the middle gray
+                let factor = (1.0 + contrast.clamp(-1.0, 1.0)) / (1.0 - contrast.clamp(-1.0, 0.99));
+                let lut = from_fn(&|value| {
+                    let value = value as f32 / 255.0 + brightness;
+                    let value = (value - 0.5) * factor + 0.5;
+                    (value * 255.0 + 0.5).clamp(0.0, 255.0) as u8
+                });
+                Some([lut; 3])
+            }
+            Adjustment::Levels { master, channels } => {
+                Some([0, 1, 2].map(|channel| from_fn(&|value| master.map(channels[channel].map(value)))))
+            }
+            Adjustment::Curves { points } => {
+                let lut = curve_lookup_table(points);
+                Some([lut; 3])
+            }
+            Adjustment::Invert => Some([from_fn(&|value| 255 - value); 3]),
+            Adjustment::Posterize { levels } => {
+                let steps = (*levels).max(2) as f32 - 1.0;
+                Some([from_fn(&|value| ((value as f32 / 255.0 * steps).round() / steps * 255.0 + 0.5) as u8); 3])
+            }
+            _ => None
+        }
+    }
+}
+
+// the color with the adjustment applied, alpha is kept
+pub fn adjust_pixel(color: Color, adjustment: &Adjustment, luts: Option<&[[u8; 256]; 3]>) -> Color {
+    if let Some(luts) = luts {
+        return Color::new(luts[0][color.red as usize], luts[1][color.green as usize], luts[2][color.blue as usize], color.alpha);
+    }
+    match adjustment {
+        Adjustment::Desaturate => {
+            let gray = luminance(color);
+            Color::new(gray, gray, gray, color.alpha)
+        }
+        Adjustment::Threshold { level } => {
+            let value = if luminance(color) >= *level { 255 } else { 0 };
+            Color::new(value, value, value, color.alpha)
+        }
+        Adjustment::HueSaturation { hue, saturation, lightness } => {
+            let (h, s, l) = rgb_to_hsl(color);
+            let h = (h + hue / 360.0).rem_euclid(1.0);
+            let s = (s * (1.0 + saturation)).clamp(0.0, 1.0);
+            let l = match *lightness >= 0.0 {
+                true => l + (1.0 - l) * lightness,
+                false => l * (1.0 + lightness)
+            };
+            let mut result = hsl_to_rgb(h, s, l.clamp(0.0, 1.0));
+            result.alpha = color.alpha;
+            result
+        }
+        _ => color
+    }
+}
+
+// adjust_pixel for pixels with more than 8 bits, the lookup tables are interpolated
+pub fn adjust_rgba(rgba: [f32; 4], adjustment: &Adjustment, luts: Option<&[[u8; 256]; 3]>) -> [f32; 4] {
+    if let Some(luts) = luts {
+        let sample = |lut: &[u8; 256], value: f32| {
+            let x = value.clamp(0.0, 1.0) * 255.0;
+            let i = (x as usize).min(254);
+            let t = x - i as f32;
+            (lut[i] as f32 * (1.0 - t) + lut[i + 1] as f32 * t) / 255.0
+        };
+        return [sample(&luts[0], rgba[0]), sample(&luts[1], rgba[1]), sample(&luts[2], rgba[2]), rgba[3]];
+    }
+    match adjustment {
+        Adjustment::Desaturate => {
+            let gray = luminance_rgb(rgba);
+            [gray, gray, gray, rgba[3]]
+        }
+        Adjustment::Threshold { level } => {
+            let value = if luminance_rgb(rgba) * 255.0 >= *level as f32 { 1.0 } else { 0.0 };
+            [value, value, value, rgba[3]]
+        }
+        Adjustment::HueSaturation { hue, saturation, lightness } => {
+            let (h, s, l) = hsl_from_rgb([rgba[0], rgba[1], rgba[2]]);
+            let h = (h + hue / 360.0).rem_euclid(1.0);
+            let s = (s * (1.0 + saturation)).clamp(0.0, 1.0);
+            let l = match *lightness >= 0.0 {
+                true => l + (1.0 - l) * lightness,
+                false => l * (1.0 + lightness)
+            };
+            let [r, g, b] = rgb_from_hsl(h, s, l.clamp(0.0, 1.0));
+            [r, g, b, rgba[3]]
+        }
+        _ => rgba
+    }
+}
+
+pub fn apply_adjustment<P: Pixel>(layer: &mut FlatCanvasLayer<P>, adjustment: &Adjustment) {
+    let luts = adjustment.lookup_tables();
+    layer.iter_pixels_mut().for_each(|(_, pixel)| *pixel = match P::FORMAT {
+        PixelFormat::Rgba8 => P::from_color(adjust_pixel(pixel.to_color(), adjustment, luts.as_ref())),
+        _ => P::from_rgba(adjust_rgba(pixel.to_rgba(), adjustment, luts.as_ref()))
+    });
+}
+
+// a non destructive adjustment, applied to everything below it in the layer stack
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdjustmentLayer {
+    pub adjustment: Adjustment,
+    // in 0..1, mixes between the pixels below and the adjusted ones
+    pub opacity: f32,
+}
+
+impl AdjustmentLayer {
+    pub fn new(adjustment: Adjustment) -> AdjustmentLayer {
+        AdjustmentLayer {
+            adjustment,
+            opacity: 1.0,
+        }
+    }
+
+    // straight rgba in 0..1, luts are the adjustment's lookup_tables, computed once per layer
+    pub fn apply_to_rgba(&self, rgba: [f32; 4], luts: Option<&[[u8; 256]; 3]>) -> [f32; 4] {
+        let adjusted = adjust_rgba(rgba, &self.adjustment, luts);
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        [0, 1, 2, 3].map(|i| adjusted[i] * opacity + rgba[i] * (1.0 - opacity))
+    }
+}
+
+pub fn luminance(color: Color) -> u8 {
+    ((color.red as u32 * 299 + color.green as u32 * 587 + color.blue as u32 * 114) / 1000) as u8
+}
+
+fn luminance_rgb(rgba: [f32; 4]) -> f32 {
+    rgba[0] * 0.299 + rgba[1] * 0.587 + rgba[2] * 0.114
+}
+
+// red, green, blue and luminance, transparent pixels are skipped
+pub fn histogram(layer: &FlatCanvasLayer) -> [[u32; 256]; 4] {
+    let mut result = [[0u32; 256]; 4];
+    layer.get_data().iter().filter(|color| color.alpha > 0).for_each(|color| {
+        result[0][color.red as usize] += 1;
+        result[1][color.green as usize] += 1;
+        result[2][color.blue as usize] += 1;
+        result[3][luminance(*color) as usize] += 1;
+    });
+    result
+}
+
+// monotone cubic interpolation through the points, so the curve never overshoots
+pub fn curve_lookup_table(points: &[(f32, f32)]) -> [u8; 256] {
+    let mut lut = [0u8; 256];
+    if points.len() < 2 {
+        lut.iter_mut().enumerate().for_each(|(i, value)| *value = i as u8);
+        return lut;
+    }
+
+    let n = points.len();
+    let slopes = (0..n - 1).map(|i| {
+        let dx = (points[i + 1].0 - points[i].0).max(1e-6);
+        (points[i + 1].1 - points[i].1) / dx
+    }).collect::<Vec<_>>();
+    let mut tangents = vec![0f32; n];
+    tangents[0] = slopes[0];
+    tangents[n - 1] = slopes[n - 2];
+    for i in 1..n - 1 {
+        tangents[i] = match slopes[i - 1] * slopes[i] <= 0.0 {
+            true => 0.0,
+            false => (slopes[i - 1] + slopes[i]) / 2.0
+        };
+    }
+    // fritsch-carlson limit
+    for i in 0..n - 1 {
+        if slopes[i] == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+        let a = tangents[i] / slopes[i];
+        let b = tangents[i + 1] / slopes[i];
+        let length = (a * a + b * b).sqrt();
+        if length > 3.0 {
+            tangents[i] = 3.0 * a / length * slopes[i];
+            tangents[i + 1] = 3.0 * b / length * slopes[i];
+        }
+    }
+
+    lut.iter_mut().enumerate().for_each(|(i, value)| {
+        let x = i as f32 / 255.0;
+        let y = if x <= points[0].0 {
+            points[0].1
+        } else if x >= points[n - 1].0 {
+            points[n - 1].1
+        } else {
+            let k = points.iter().position(|point| point.0 > x).unwrap_or(n - 1) - 1;
+            let (x0, y0) = points[k];
+            let (x1, y1) = points[k + 1];
+            let h = (x1 - x0).max(1e-6);
+            let t = (x - x0) / h;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[k]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[k + 1]
+        };
+        *value = (y.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+    });
+    lut
+}
+
+// h, s and l in 0..1
+pub fn rgb_to_hsl(color: Color) -> (f32, f32, f32) {
+    hsl_from_rgb([color.red as f32 / 255.0, color.green as f32 / 255.0, color.blue as f32 / 255.0])
+}
+
+pub fn hsl_to_rgb(h: f32, s: f32, l: f32) -> Color {
+    let to_u8 = |value: f32| (value * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+    let [r, g, b] = rgb_from_hsl(h, s, l);
+    Color::new(to_u8(r), to_u8(g), to_u8(b), 255)
+}
+
+// the same with rgb in 0..1
+pub fn hsl_from_rgb([r, g, b]: [f32; 3]) -> (f32, f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let l = (max + min) / 2.0;
+    if max == min {
+        return (0.0, 0.0, l);
+    }
+    let d = max - min;
+    let s = if l > 0.5 { d / (2.0 - max - min) } else { d / (max + min) };
+    let h = if max == r {
+        (g - b) / d + if g < b { 6.0 } else { 0.0 }
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    (h / 6.0, s, l)
+}
+
+pub fn rgb_from_hsl(h: f32, s: f32, l: f32) -> [f32; 3] {
+    if s == 0.0 {
+        return [l; 3];
+    }
+    let q = if l < 0.5 { l * (1.0 + s) } else { l + s - l * s };
+    let p = 2.0 * l - q;
+    let hue_to_rgb = |t: f32| {
+        let t = t.rem_euclid(1.0);
+        if t < 1.0 / 6.0 {
+            p + (q - p) * 6.0 * t
+        } else if t < 0.5 {
+            q
+        } else if t < 2.0 / 3.0 {
+            p + (q - p) * (2.0 / 3.0 - t) * 6.0
+        } else {
+            p
+        }
+    };
+    [hue_to_rgb(h + 1.0 / 3.0), hue_to_rgb(h), hue_to_rgb(h - 1.0 / 3.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas_layer::CanvasLayer;
+
+    fn single_pixel(color: Color, adjustment: &Adjustment) -> Color {
+        let mut layer = FlatCanvasLayer::<Color>::new(1, 1);
+        layer.fill(color);
+        apply_adjustment(&mut layer, adjustment);
+        layer.get_pixel(PixelPos { x: 0, y: 0 })
+    }
+
+    #[test]
+    fn test_simple_adjustments() {
+        let color = Color::new(200, 100, 50, 128);
+        assert_eq!(single_pixel(color, &Adjustment::Invert), Color::new(55, 155, 205, 128));
+        assert_eq!(single_pixel(color, &Adjustment::Desaturate), Color::new(124, 124, 124, 128));
+        assert_eq!(single_pixel(color, &Adjustment::Threshold { level: 128 }), Color::new(0, 0, 0, 128));
+        assert_eq!(single_pixel(color, &Adjustment::Posterize { levels: 2 }), Color::new(255, 0, 0, 128));
+        assert_eq!(single_pixel(color, &Adjustment::BrightnessContrast { brightness: 0.0, contrast: 0.0 }), color);
+    }
+
+    #[test]
+    fn test_levels_and_curves() {
+        let mut levels = Levels::new();
+        levels.input_black = 50;
+        levels.input_white = 150;
+        assert_eq!(levels.map(50), 0);
+        assert_eq!(levels.map(100), 128);
+        assert_eq!(levels.map(200), 255);
+
+        let identity = curve_lookup_table(&[(0.0, 0.0), (1.0, 1.0)]);
+        assert!(identity.iter().enumerate().all(|(i, value)| *value == i as u8));
+        let curve = curve_lookup_table(&[(0.0, 0.0), (0.5, 0.75), (1.0, 1.0)]);
+        assert_eq!(curve[128], 192);
+        assert!(curve.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn test_hsl_round_trip() {
+        let color = Color::new(200, 100, 50, 255);
+        let (h, s, l) = rgb_to_hsl(color);
+        assert_eq!(hsl_to_rgb(h, s, l), color);
+        let rotated = single_pixel(color, &Adjustment::HueSaturation { hue: 120.0, saturation: 0.0, lightness: 0.0 });
+        assert_eq!(rotated, Color::new(50, 200, 100, 255));
+    }
+
+    #[test]
+    fn test_adjustment_layer_opacity() {
+        let color = Color::new(200, 100, 0, 255);
+        let mut adjustment_layer = AdjustmentLayer::new(Adjustment::Invert);
+        let luts = adjustment_layer.adjustment.lookup_tables();
+        let apply = |adjustment_layer: &AdjustmentLayer| Color::from_rgba(adjustment_layer.apply_to_rgba(color.to_rgba(), luts.as_ref()));
+        assert_eq!(apply(&adjustment_layer), Color::new(55, 155, 255, 255));
+        adjustment_layer.opacity = 0.0;
+        assert_eq!(apply(&adjustment_layer), color);
+        adjustment_layer.opacity = 0.5;
+        assert_eq!(apply(&adjustment_layer), Color::new(128, 128, 128, 255));
+    }
+
+    #[test]
+    fn test_wide_adjustments() {
+        // values between two 8 bit steps stay between them
+        let rgba = [100.5 / 255.0, 0.0, 1.0, 1.0];
+        let luts = Adjustment::Invert.lookup_tables();
+        let inverted = adjust_rgba(rgba, &Adjustment::Invert, luts.as_ref());
+        assert!((inverted[0] - 154.5 / 255.0).abs() < 1e-5);
+
+        let mut layer = FlatCanvasLayer::<Color16>::new(1, 1);
+        layer.fill(Color::new(200, 100, 50, 255));
+        apply_adjustment(&mut layer, &Adjustment::HueSaturation { hue: 120.0, saturation: 0.0, lightness: 0.0 });
+        assert_eq!(layer.get_pixel(PixelPos { x: 0, y: 0 }), Color::new(50, 200, 100, 255));
+    }
+}
@@ -2,65 +2,313 @@ use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::hash::Hash;
 use itertools::Itertools;
-use crate::paint_app::utils::{checkers_pattern, draw_rect, rasterize_line};
+use egui::{Key, Painter, Pos2, Ui, WidgetText};
+use crate::paint_app::actions::Shortcut;
+use crate::paint_app::adjustments::{apply_adjustment, histogram, luminance, Adjustment, AdjustmentLayer};
+use crate::paint_app::compositor::{composite_stack, CompositeStack};
+use crate::paint_app::filters::{apply_filter, EdgeMode, Filter};
+use crate::paint_app::image_io::ImportedDocument;
+use crate::paint_app::indexed::{nearest_color, quantize_layer, Dither};
+use crate::paint_app::palette::Palette;
+use crate::paint_app::symmetry::Symmetry;
+use crate::paint_app::text_layer::{render_text, FontLibrary, TextLayer};
+use crate::paint_app::tool_registry::{ToolCategory, ToolInfo, ToolOption, ToolOptionValue};
+use crate::paint_app::utils::{checkers_pattern, draw_rect, over, premultiply, unpremultiply, BlendSpace};
+use crate::paint_app::rasterize::{self, Fill, LineCap, Stroke, StrokeStyle};
+use crate::paint_app::slices::SliceSet;
+use crate::paint_app::vector_layer::{render_vector_layer, VectorLayer};
+use crate::paint_app::vector_path::VectorPath;
 use super::data_types::*;
 use super::canvas_layer::*;
 
 pub struct Canvas {
     layers: CanvasLayers,
-    undo_stack : Vec<EditCommand>,
-    redo_stack : Vec<EditCommand>,
+    undo_stack : Vec<UndoStep>,
+    redo_stack : Vec<UndoStep>,
 
     tool_layer: HashMapCanvasLayer,
+    // tool_layer with symmetry applied, this is what gets displayed
+    mirrored_tool_layer: HashMapCanvasLayer,
     draw_layer: FlatCanvasLayer,
+    // the layer stack without the checkers pattern, reused between updates
+    composite_layer: FlatCanvasLayer,
 
     checkers_pattern_layer: FlatCanvasLayer,
 
+    fonts: FontLibrary,
+
+    // indexed documents only contain colors of this palette (and transparent)
+    indexed_palette: Option<Palette>,
+
+    // paint on the mask of the active layer instead of its pixels
+    edit_mask: bool,
+
+    // why the last edit was refused, shown to the user
+    blocked_reason: Option<String>,
+
+    // shown instead of the active layer until applied or cancelled, with the result cached
+    operation_preview: Option<(LayerOperation, LayerBuffer)>,
+
+    // how the layers store their pixels, chosen when the document is created
+    format: PixelFormat,
+
     size: (u32, u32),
+
+    // the frames of the animation, the active one has its layers and history in the fields above
+    frames: Vec<AnimationFrame>,
+    active_frame: usize,
+    onion_skin: OnionSkin,
+
+    // the parts exported as sprites or tiles, for the whole document
+    slices: SliceSet,
+}
+
+// the frames that aren't active keep their layers and history here,
+// with the layers composited for onion skins and export
+pub struct AnimationFrame {
+    pub duration_ms: u32,
+    layers: Option<CanvasLayers>,
+    undo_stack: Vec<UndoStep>,
+    redo_stack: Vec<UndoStep>,
+    merged: Option<FlatCanvasLayer>,
 }
 
+impl AnimationFrame {
+    fn new(duration_ms: u32, layers: Option<CanvasLayers>) -> AnimationFrame {
+        AnimationFrame {
+            duration_ms,
+            layers,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            merged: None,
+        }
+    }
+}
+
+// frames around the active one shown faintly below it, earlier ones red and later ones green
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OnionSkin {
+    pub enabled: bool,
+    pub previous: usize,
+    pub next: usize,
+    // of the nearest frames, farther ones fade out
+    pub opacity: f32,
+}
+
+impl OnionSkin {
+    pub fn new() -> OnionSkin {
+        OnionSkin {
+            enabled: false,
+            previous: 1,
+            next: 1,
+            opacity: 0.4,
+        }
+    }
+}
+
+impl Default for OnionSkin {
+    fn default() -> OnionSkin {
+        OnionSkin::new()
+    }
+}
+
+pub const DEFAULT_FRAME_DURATION: u32 = 100;
+
 // supports equality comparison
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct CanvasLayerConfig{
     pub id : LayerId,
+    pub name: String,
+    pub kind : LayerKind,
     pub visible: bool,
+    pub clipped: bool,
+    // Some(enabled) when the layer has a mask
+    pub mask_enabled: Option<bool>,
+    pub locks: LayerLocks,
+    // only used by groups
+    pub collapsed: bool,
+    pub children: Vec<CanvasLayerConfig>,
 }
-// supports deep equality comparison
+// supports deep equality comparison, entries is a tree with the top layer first on every level
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CanvasLayersConfig{
     pub entries : Vec<CanvasLayerConfig>,
     pub active_layer_id : LayerId,
 }
 
+// a line of the layer panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LayerRow {
+    pub id: LayerId,
+    pub depth: usize,
+}
+
+impl CanvasLayersConfig {
+    pub fn find(&self, id: LayerId) -> Option<&CanvasLayerConfig> {
+        fn find_in(entries: &[CanvasLayerConfig], id: LayerId) -> Option<&CanvasLayerConfig> {
+            entries.iter().find_map(|entry| match entry.id == id {
+                true => Some(entry),
+                false => find_in(&entry.children, id)
+            })
+        }
+        find_in(&self.entries, id)
+    }
+
+    pub fn find_mut(&mut self, id: LayerId) -> Option<&mut CanvasLayerConfig> {
+        fn find_in(entries: &mut [CanvasLayerConfig], id: LayerId) -> Option<&mut CanvasLayerConfig> {
+            entries.iter_mut().find_map(|entry| match entry.id == id {
+                true => Some(entry),
+                false => find_in(&mut entry.children, id)
+            })
+        }
+        find_in(&mut self.entries, id)
+    }
+
+    // None for top level layers
+    pub fn parent_of(&self, id: LayerId) -> Option<LayerId> {
+        fn parent_in(entries: &[CanvasLayerConfig], parent: Option<LayerId>, id: LayerId) -> Option<Option<LayerId>> {
+            entries.iter().find_map(|entry| match entry.id == id {
+                true => Some(parent),
+                false => parent_in(&entry.children, Some(entry.id), id)
+            })
+        }
+        parent_in(&self.entries, None, id).flatten()
+    }
+
+    // every layer with its parent, parents before their children
+    pub fn flatten(&self) -> Vec<(&CanvasLayerConfig, Option<LayerId>)> {
+        fn flatten_into<'a>(entries: &'a [CanvasLayerConfig], parent: Option<LayerId>, result: &mut Vec<(&'a CanvasLayerConfig, Option<LayerId>)>) {
+            entries.iter().for_each(|entry| {
+                result.push((entry, parent));
+                flatten_into(&entry.children, Some(entry.id), result);
+            });
+        }
+        let mut result = Vec::new();
+        flatten_into(&self.entries, None, &mut result);
+        result
+    }
+
+    // the children of collapsed groups are left out
+    pub fn rows(&self) -> Vec<LayerRow> {
+        fn rows_into(entries: &[CanvasLayerConfig], depth: usize, result: &mut Vec<LayerRow>) {
+            entries.iter().for_each(|entry| {
+                result.push(LayerRow { id: entry.id, depth });
+                if !entry.collapsed {
+                    rows_into(&entry.children, depth + 1, result);
+                }
+            });
+        }
+        let mut result = Vec::new();
+        rows_into(&self.entries, 0, &mut result);
+        result
+    }
+
+    fn is_descendant(&self, id: LayerId, ancestor: LayerId) -> bool {
+        let mut parent = self.parent_of(id);
+        while let Some(parent_id) = parent {
+            if parent_id == ancestor {
+                return true;
+            }
+            parent = self.parent_of(parent_id);
+        }
+        false
+    }
+
+    // rows has already been reordered by dragging the row now at index, it goes into an open group
+    // right above it, otherwise next to the row below it, or to the bottom of the top level
+    pub fn move_row(&mut self, rows: &[LayerRow], index: usize) {
+        let id = rows[index].id;
+        let above = index.checked_sub(1).and_then(|above| self.find(rows[above].id));
+        let below = rows.get(index + 1).map(|row| row.id);
+        let (parent, before) = match (above, below) {
+            (Some(above), _) if above.kind == LayerKind::Group && !above.collapsed => (Some(above.id), above.children.first().map(|child| child.id)),
+            (_, Some(below)) => (self.parent_of(below), Some(below)),
+            _ => (None, None)
+        };
+        // a group can't go into itself
+        if parent.is_some_and(|parent| parent == id || self.is_descendant(parent, id)) || before == Some(id) {
+            return;
+        }
+
+        let old_parent = self.parent_of(id);
+        let siblings = match old_parent {
+            Some(old_parent) => &mut self.find_mut(old_parent).unwrap().children,
+            None => &mut self.entries
+        };
+        let Some(position) = siblings.iter().position(|entry| entry.id == id) else { return };
+        let moved = siblings.remove(position);
+
+        let siblings = match parent {
+            Some(parent) => &mut self.find_mut(parent).unwrap().children,
+            None => &mut self.entries
+        };
+        let position = before.and_then(|before| siblings.iter().position(|entry| entry.id == before)).unwrap_or(siblings.len());
+        siblings.insert(position, moved);
+    }
+}
+
 impl Canvas {
     pub fn new(w: u32, h: u32) -> Canvas {
+        Canvas::with_format(w, h, PixelFormat::Rgba8)
+    }
+
+    pub fn with_format(w: u32, h: u32, format: PixelFormat) -> Canvas {
         let mut canvas = Canvas {
             layers: CanvasLayers {
                 entries: Vec::new(),
                 active_layer_id: LayerId(0),
+                blend_space: BlendSpace::Srgb,
+                paths: Vec::new(),
+                active_path: None,
             },
             undo_stack : Vec::new(),
             redo_stack : Vec::new(),
 
             checkers_pattern_layer: Canvas::create_checkers_pattern(w, h, 10),
             tool_layer: HashMapCanvasLayer::new(w, h),
+            mirrored_tool_layer: HashMapCanvasLayer::new(w, h),
             draw_layer: FlatCanvasLayer::new(w, h),
+            composite_layer: FlatCanvasLayer::new(w, h),
+            fonts: FontLibrary::new(),
+            indexed_palette: None,
+            edit_mask: false,
+            blocked_reason: None,
+            operation_preview: None,
+            format,
             size: (w, h),
+            frames: vec![AnimationFrame::new(DEFAULT_FRAME_DURATION, None)],
+            active_frame: 0,
+            onion_skin: OnionSkin::new(),
+            slices: SliceSet::new(),
         };
-        let mut green_horizontal = FlatCanvasLayer::new(w, h);
+        let mut green_horizontal = LayerBuffer::new(w, h, format);
         draw_rect(&mut green_horizontal, PixelPos{x: 100, y: 100}, PixelPos{x: 100 + 100, y: 100 + 10}, Color::new(0, 255, 0, 255));
         canvas.layers.entries.push(CanvasLayerEntry {
             id: LayerId(0),
+            name: LayerKind::Raster.default_name(LayerId(0)),
             layer: green_horizontal,
+            content: LayerContent::Raster,
             visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
         });
 
-        let mut red_vertical = FlatCanvasLayer::new(w, h);
+        let mut red_vertical = LayerBuffer::new(w, h, format);
         draw_rect(&mut red_vertical, PixelPos{x: 100, y: 100}, PixelPos{x: 100 + 10, y: 100 + 100}, Color::new(255, 0, 0, 255));
         canvas.layers.entries.push(CanvasLayerEntry {
             id: LayerId(1),
+            name: LayerKind::Raster.default_name(LayerId(1)),
             layer: red_vertical,
+            content: LayerContent::Raster,
             visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
         });
 
         canvas.layers.active_layer_id = LayerId(0);
@@ -72,12 +320,53 @@ impl Canvas {
         canvas
     }
 
+    // opens a file from another editor, the top layer that has pixels becomes active
+    pub fn from_document(document: ImportedDocument) -> Canvas {
+        let (width, height) = document.size;
+        let mut canvas = Canvas::new(width, height);
+        canvas.layers.entries = document.entries;
+        canvas.slices = document.slices;
+        if canvas.layers.entries.is_empty() {
+            canvas.layers.entries.push(CanvasLayerEntry {
+                id: LayerId(0),
+                name: LayerKind::Raster.default_name(LayerId(0)),
+                layer: LayerBuffer::new(width, height, canvas.format),
+                content: LayerContent::Raster,
+                visible: true,
+                mask: None,
+                clipped: false,
+                parent: None,
+                locks: LayerLocks::default(),
+                revision: 0,
+            });
+        }
+        let active = canvas.layers.entries.iter().find(|entry| entry.content.is_raster()).unwrap_or(&canvas.layers.entries[0]);
+        canvas.layers.active_layer_id = active.id;
+        canvas.update_display_canvas();
+        canvas
+    }
+
     pub fn get_canvas_layers_config(&self) -> CanvasLayersConfig {
+        fn children_config(layers: &CanvasLayers, parent: Option<LayerId>) -> Vec<CanvasLayerConfig> {
+            layers.entries.iter()
+                .filter(|entry| layers.parent_of(entry) == parent)
+                .map(|entry| CanvasLayerConfig{
+                    id: entry.id,
+                    name: entry.name.clone(),
+                    kind: entry.content.kind(),
+                    visible: entry.visible,
+                    clipped: entry.clipped,
+                    mask_enabled: entry.mask.as_ref().map(|mask| mask.enabled),
+                    locks: entry.locks,
+                    collapsed: matches!(&entry.content, LayerContent::Group(group) if group.collapsed),
+                    children: match entry.content.kind() {
+                        LayerKind::Group => children_config(layers, Some(entry.id)),
+                        _ => Vec::new()
+                    },
+                }).collect_vec()
+        }
         CanvasLayersConfig{
-            entries: self.layers.entries.iter().map(|entry| CanvasLayerConfig{
-                id: entry.id,
-                visible: entry.visible,
-            }).collect_vec(),
+            entries: children_config(&self.layers, None),
             active_layer_id: self.layers.active_layer_id,
         }
     }
@@ -88,18 +377,29 @@ impl Canvas {
             self.layers.active_layer_id = config.active_layer_id;
 
 
-            //sort self.layers.entries based on LayerId looking at config.entries
+            //sort self.layers.entries based on LayerId looking at config.entries, groups come right before their children
+            let flattened = config.flatten();
             let mut id_to_order = HashMap::new();
-            config.entries.iter().enumerate().for_each(|(i, entry)|{
+            flattened.iter().enumerate().for_each(|(i, (entry, _))|{
                 id_to_order.insert(entry.id, i);
             });
             self.layers.entries.sort_by_key(|entry| id_to_order.get(&entry.id).unwrap_or(&0));
 
-            // set the "visible"
+            // set the parent, "visible", clipping and mask state
             self.layers.entries.iter_mut().for_each(|entry|{
-                let new_entry = config.entries.iter().find(|new_entry| new_entry.id == entry.id);
-                if let Some(new_entry) = new_entry {
+                let new_entry = flattened.iter().find(|(new_entry, _)| new_entry.id == entry.id);
+                if let Some((new_entry, parent)) = new_entry {
+                    entry.parent = *parent;
                     entry.visible = new_entry.visible;
+                    entry.name = new_entry.name.clone();
+                    entry.clipped = new_entry.clipped;
+                    entry.locks = new_entry.locks;
+                    if let (Some(mask), Some(enabled)) = (&mut entry.mask, new_entry.mask_enabled) {
+                        mask.enabled = enabled;
+                    }
+                    if let LayerContent::Group(group) = &mut entry.content {
+                        group.collapsed = new_entry.collapsed;
+                    }
                 }
             });
 
@@ -111,107 +411,1112 @@ impl Canvas {
     pub fn get_draw_layer(&self) -> &FlatCanvasLayer {
         &self.draw_layer
     }
-    pub fn get_active_layer(&self) -> Option<&FlatCanvasLayer>{
-        self.layers.get_active_layer()
+    pub fn get_size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn get_layers(&self) -> &CanvasLayers {
+        &self.layers
     }
 
-    pub fn get_active_layer_mut(&mut self) -> Option<&mut FlatCanvasLayer>{
-        self.layers.get_active_layer_mut()
+    // the content keeps its place relative to the anchor sides. vector and text layers are
+    // drawn again at the new size, the undo history is dropped since its positions are off now
+    pub fn set_size(&mut self, width: u32, height: u32, keep_horizontal: SideHorizontal, keep_vertical: SideVertical) {
+        let (width, height) = (width.max(1), height.max(1));
+        let (x, y) = anchor_offset(self.size, (width, height), keep_horizontal, keep_vertical);
+        let shift = |pos: PixelPos| PixelPos { x: (pos.x as i64 + x).max(0) as u32, y: (pos.y as i64 + y).max(0) as u32 };
+        for entry in every_frame(&mut self.layers, &mut self.frames).flat_map(|layers| layers.entries.iter_mut()) {
+            entry.layer.set_size(width, height, keep_horizontal, keep_vertical);
+            if let Some(mask) = &mut entry.mask {
+                mask.layer.set_size(width, height, keep_horizontal, keep_vertical);
+            }
+            match &mut entry.content {
+                LayerContent::Text { text, .. } => text.position = shift(text.position),
+                LayerContent::Vector { vector, stale } => {
+                    vector.shapes.iter_mut().for_each(|shape| shape.translate(glam::Vec2::new(x as f32, y as f32)));
+                    *stale = true;
+                }
+                _ => {}
+            }
+            entry.revision += 1;
+        }
+        for path in self.layers.paths.iter_mut() {
+            for node in path.nodes.iter_mut() {
+                for point in [&mut node.anchor, &mut node.handle_in, &mut node.handle_out] {
+                    *point += glam::Vec2::new(x as f32, y as f32);
+                }
+            }
+        }
+        for slice in self.slices.slices.iter_mut() {
+            let PixelPos { x, y } = shift(PixelPos { x: slice.x, y: slice.y });
+            (slice.x, slice.y) = (x, y);
+        }
+
+        self.size = (width, height);
+        self.checkers_pattern_layer = Canvas::create_checkers_pattern(width, height, 10);
+        self.tool_layer = HashMapCanvasLayer::new(width, height);
+        self.mirrored_tool_layer = HashMapCanvasLayer::new(width, height);
+        self.draw_layer = FlatCanvasLayer::new(width, height);
+        self.composite_layer = FlatCanvasLayer::new(width, height);
+        self.operation_preview = None;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.update_stored_frames(true);
+        self.render_text_layers();
     }
 
-    pub fn get_size(&self) -> (u32, u32) {
-        self.size
+    pub fn get_pixel_format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn get_blend_space(&self) -> BlendSpace {
+        self.layers.blend_space
+    }
+
+    pub fn set_blend_space(&mut self, blend_space: BlendSpace) {
+        if self.layers.blend_space != blend_space {
+            every_frame(&mut self.layers, &mut self.frames).for_each(|layers| layers.blend_space = blend_space);
+            self.mark_vector_layers_stale();
+            self.update_stored_frames(false);
+            self.update_display_canvas();
+        }
+    }
+    fn painting_mask(&self) -> bool {
+        self.edit_mask && self.layers.get_active_entry().is_some_and(|entry| entry.mask.is_some())
+    }
+
+    // the mask of the active layer while editing it, the layer otherwise
+    fn get_paint_target_mut(&mut self) -> Option<&mut dyn CanvasLayer> {
+        let painting_mask = self.painting_mask();
+        let entry = self.layers.get_active_entry_mut()?;
+        entry.revision += 1;
+        match (painting_mask, &mut entry.mask) {
+            (true, Some(mask)) => Some(&mut mask.layer),
+            _ => Some(&mut entry.layer)
+        }
     }
+
     fn apply_commands_handle_undo_redo(&mut self, commands : &Vec<EditCommand>){
         if commands.len() > 0 {
             self.redo_stack.clear();
         }
+        let painting_mask = self.painting_mask();
         commands.iter().for_each(|command|{
-            self.apply_command_handle_undo_redo(command);
+            match (painting_mask, &self.indexed_palette) {
+                (true, _) => {
+                    let mut gray = command.clone();
+                    gray.edits.iter_mut().for_each(|(_, color)| *color = LayerMask::mask_color(*color));
+                    self.apply_command_handle_undo_redo(&gray);
+                }
+                (false, Some(palette)) => {
+                    let mut snapped = command.clone();
+                    snapped.edits.iter_mut().for_each(|(_, color)| *color = nearest_color(&palette.colors, *color));
+                    self.apply_command_handle_undo_redo(&snapped);
+                }
+                (false, None) => self.apply_command_handle_undo_redo(command)
+            }
         });
     }
 
-    fn apply_command_handle_undo_redo(&mut self, command : &EditCommand){
-        self.layers.get_active_layer_mut().map(|active_canvas|{
-            self.undo_stack.push(command.reverse(active_canvas));
-            command.apply(active_canvas);
-        });
+    fn apply_command_handle_undo_redo(&mut self, command : &EditCommand){
+        if self.refuse_locked_edit(false) {
+            return;
+        }
+        let painting_mask = self.painting_mask();
+        let mut lock_alpha = false;
+        if let Some(entry) = self.layers.get_active_entry() {
+            if !entry.content.is_raster() && !painting_mask {
+                self.blocked_reason = Some(match entry.content.kind() {
+                    LayerKind::Text | LayerKind::Vector => format!("layer {} has to be rasterized before painting on it, use Layer > Rasterize layer", entry.id.0),
+                    _ => format!("layer {} has no pixels to paint on", entry.id.0)
+                });
+                return;
+            }
+            lock_alpha = self.layers.locks_of(entry).alpha && !painting_mask;
+        }
+        if let Some(target) = self.get_paint_target_mut() {
+            let mut command = command.flattened(target);
+            if lock_alpha {
+                command.edits.iter_mut().for_each(|(pos, color)| color.alpha = target.get_pixel(*pos).alpha);
+            }
+            let reverse = command.reverse(target);
+            command.apply(target);
+            self.undo_stack.push(UndoStep::Edit { id: self.layers.active_layer_id, mask: painting_mask, command: reverse });
+        }
+    }
+
+    // the layers as they are now, pushed before changing more than their pixels
+    fn layer_snapshot(&self, ids: &[LayerId]) -> LayerSnapshot {
+        let layers = ids.iter().map(|&id| {
+            let index = self.layers.entries.iter().position(|entry| entry.id == id);
+            (id, index.unwrap_or(0), index.map(|i| self.layers.entries[i].clone()))
+        }).collect();
+        LayerSnapshot {
+            layers,
+            active_layer_id: self.layers.active_layer_id,
+            setting: false,
+        }
+    }
+
+    fn push_layer_undo(&mut self, snapshot: LayerSnapshot) {
+        self.redo_stack.clear();
+        self.undo_stack.push(UndoStep::Layer(Box::new(snapshot)));
+    }
+
+    // settings are changed every frame while a slider is dragged, so changes of the
+    // same layer in a row become one step
+    fn push_setting_undo(&mut self, id: LayerId) {
+        self.redo_stack.clear();
+        if let Some(UndoStep::Layer(snapshot)) = self.undo_stack.last() {
+            if snapshot.setting && snapshot.layers.len() == 1 && snapshot.layers[0].0 == id {
+                return;
+            }
+        }
+        let mut snapshot = self.layer_snapshot(&[id]);
+        snapshot.setting = true;
+        self.undo_stack.push(UndoStep::Layer(Box::new(snapshot)));
+    }
+
+    // puts the layers back as they were and returns what gives them back as they are
+    fn restore_layers(&mut self, snapshot: LayerSnapshot) -> LayerSnapshot {
+        let ids: Vec<LayerId> = snapshot.layers.iter().map(|(id, _, _)| *id).collect();
+        let reverse = self.layer_snapshot(&ids);
+        self.layers.entries.retain(|entry| !ids.contains(&entry.id));
+        let mut layers: Vec<_> = snapshot.layers.into_iter().zip(reverse.layers.iter()).collect();
+        // lowest index first so each one lands where it was
+        layers.sort_by_key(|((_, index, _), _)| *index);
+        for ((_, index, entry), (_, _, current)) in layers {
+            if let Some(mut entry) = entry {
+                // newer than both so the thumbnail is made again
+                entry.revision = entry.revision.max(current.as_ref().map_or(0, |entry| entry.revision)) + 1;
+                let index = index.min(self.layers.entries.len());
+                self.layers.entries.insert(index, entry);
+            }
+        }
+        self.layers.active_layer_id = snapshot.active_layer_id;
+        reverse
+    }
+
+    fn apply_undo_step(&mut self, step: UndoStep) -> Option<UndoStep> {
+        match step {
+            UndoStep::Edit { id, mask, command } => {
+                // the layer or mask the edit was made on, whatever is active now
+                let entry = self.layers.entries.iter_mut().find(|entry| entry.id == id)?;
+                entry.revision += 1;
+                let target: &mut dyn CanvasLayer = match (mask, &mut entry.mask) {
+                    (true, Some(layer_mask)) => &mut layer_mask.layer,
+                    (true, None) => return None,
+                    (false, _) => &mut entry.layer
+                };
+                let reverse = command.reverse(target);
+                command.apply(target);
+                Some(UndoStep::Edit { id, mask, command: reverse })
+            }
+            UndoStep::Layer(snapshot) => Some(UndoStep::Layer(Box::new(self.restore_layers(*snapshot)))),
+            UndoStep::PaletteColor { index, color } => {
+                let old_color = self.replace_palette_color(index, color)?;
+                Some(UndoStep::PaletteColor { index, color: old_color })
+            }
+        }
+    }
+
+    // sets blocked_reason and returns true when the locks of the active layer don't allow the edit
+    fn refuse_locked_edit(&mut self, moving: bool) -> bool {
+        let Some(entry) = self.layers.get_active_entry() else { return false };
+        let locks = self.layers.locks_of(entry);
+        let reason = match (locks.all, moving && locks.position) {
+            (true, _) => format!("layer {} is locked", entry.id.0),
+            (false, true) => format!("the position of layer {} is locked", entry.id.0),
+            (false, false) => return false
+        };
+        self.blocked_reason = Some(reason);
+        true
+    }
+
+    pub fn take_blocked_reason(&mut self) -> Option<String> {
+        self.blocked_reason.take()
+    }
+
+    pub fn get_layer_revision(&self, id: LayerId) -> Option<u64> {
+        self.layers.entries.iter().find(|entry| entry.id == id).map(|entry| entry.revision)
+    }
+
+    // groups and adjustment layers have no pixels to show
+    pub fn get_layer_thumbnail(&self, id: LayerId, max_side: u32) -> Option<FlatCanvasLayer> {
+        let entry = self.layers.entries.iter().find(|entry| entry.id == id)?;
+        match entry.content {
+            LayerContent::Raster | LayerContent::Text { .. } | LayerContent::Vector { .. } => Some(entry.layer.thumbnail(max_side)),
+            _ => None
+        }
+    }
+
+    fn apply_symmetry(&mut self, global_params: &GlobalParams, tool : &dyn PaintTool, commands : Vec<EditCommand>) -> Vec<EditCommand> {
+        let symmetry = match tool.uses_symmetry() {
+            true => global_params.symmetry,
+            false => Symmetry::new()
+        };
+
+        self.mirrored_tool_layer.clear();
+        for (pos, color) in self.tool_layer.pixels_iter() {
+            for new_pos in symmetry.transforms(*pos, self.size) {
+                self.mirrored_tool_layer.set_pixel(new_pos, *color);
+            }
+        }
+
+        commands.iter().map(|command| symmetry.apply_to_command(command, self.size)).collect()
+    }
+
+    pub fn stroke_start(&mut self, global_params: &mut GlobalParams, tool : &mut dyn PaintTool){
+        let mut commands = Vec::new();
+        tool.stroke_start(global_params, &self.layers, &mut self.tool_layer, &mut |command| commands.push(command));
+        let commands = self.apply_symmetry(global_params, tool, commands);
+        self.apply_commands_handle_undo_redo(&commands);
+    }
+
+    pub fn stroke_update(&mut self, global_params: &mut GlobalParams, tool : &mut dyn PaintTool){
+        let mut commands = Vec::new();
+        tool.stroke_update(global_params, &self.layers, &mut self.tool_layer, &mut |command| commands.push(command));
+        let commands = self.apply_symmetry(global_params, tool, commands);
+        self.apply_commands_handle_undo_redo(&commands);
+
+        self.update_display_canvas();
+    }
+
+    pub fn stroke_end(&mut self, global_params: &mut GlobalParams, tool : &mut dyn PaintTool){
+        let mut commands = Vec::new();
+        tool.stroke_end(global_params, &self.layers, &mut self.tool_layer, &mut |command| commands.push(command));
+        let commands = self.apply_symmetry(global_params, tool, commands);
+        self.apply_commands_handle_undo_redo(&commands);
+
+        for layer_edit in tool.take_layer_edits() {
+            match layer_edit {
+                LayerEdit::AddText(text) => {
+                    self.add_text_layer(text);
+                }
+                LayerEdit::SetActive(id) => {
+                    self.layers.active_layer_id = id;
+                }
+                LayerEdit::SetPath(index, path) => {
+                    match index.filter(|i| *i < self.layers.paths.len()) {
+                        Some(i) => self.layers.paths[i] = path,
+                        None => {
+                            self.layers.paths.push(path);
+                            self.layers.active_path = Some(self.layers.paths.len() - 1);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.update_display_canvas();
+    }
+
+    pub fn undo(&mut self){
+        if let Some(step) = self.undo_stack.pop(){
+            if let Some(reverse) = self.apply_undo_step(step){
+                self.redo_stack.push(reverse);
+            }
+
+            self.update_display_canvas();
+            println!("undo");
+            println!("undo_stack: {}", self.undo_stack.len());
+            println!("redo_stack: {}", self.redo_stack.len());
+        }
+        else {
+            println!("nothing to undo");
+        }
+    }
+
+    pub fn redo(&mut self){
+        if let Some(step) = self.redo_stack.pop(){
+            //self.apply_command(&command);
+            if let Some(reverse) = self.apply_undo_step(step){
+                self.undo_stack.push(reverse);
+            }
+            self.update_display_canvas();
+
+            println!("redo");
+            println!("undo_stack: {}", self.undo_stack.len());
+            println!("redo_stack: {}", self.redo_stack.len());
+        }
+    }
+
+    pub fn get_fonts(&self) -> &FontLibrary {
+        &self.fonts
+    }
+
+    pub fn load_font(&mut self, name: String, bytes: Vec<u8>) -> Result<(), String> {
+        self.fonts.load_font(name, bytes)?;
+        self.update_stored_frames(false);
+        self.render_text_layers();
+        Ok(())
+    }
+
+    // new layers go on top and become active
+    pub fn add_text_layer(&mut self, text: TextLayer) -> LayerId {
+        let id = self.layers.next_layer_id();
+        self.push_layer_undo(self.layer_snapshot(&[id]));
+        let mut layer = LayerBuffer::new(self.size.0, self.size.1, self.format);
+        let bounds = render_text(&text, &self.fonts, &mut layer);
+        if let Some(palette) = &self.indexed_palette {
+            quantize_layer(&mut layer, &palette.colors, Dither::None);
+        }
+        self.layers.entries.insert(0, CanvasLayerEntry {
+            id,
+            name: LayerKind::Text.default_name(id),
+            layer,
+            content: LayerContent::Text { text, bounds },
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        self.layers.active_layer_id = id;
+        self.update_display_canvas();
+        id
+    }
+
+    pub fn get_active_text(&self) -> Option<&TextLayer> {
+        match &self.layers.get_active_entry()?.content {
+            LayerContent::Text { text, .. } => Some(text),
+            _ => None
+        }
+    }
+
+    pub fn set_active_text(&mut self, new_text: TextLayer) {
+        let moving = self.get_active_text().is_some_and(|text| text.position != new_text.position);
+        if self.refuse_locked_edit(moving) {
+            return;
+        }
+        if self.get_active_text().is_none_or(|text| *text == new_text) {
+            return;
+        }
+        self.push_layer_undo(self.layer_snapshot(&[self.layers.active_layer_id]));
+        if let Some(entry) = self.layers.get_active_entry_mut() {
+            if let LayerContent::Text { text, .. } = &mut entry.content {
+                *text = new_text;
+            }
+        }
+        self.render_text_layers();
+    }
+
+    // keeps the pixels and drops everything else, the layer can be painted on afterwards
+    pub fn rasterize_active_layer(&mut self) {
+        if self.refuse_locked_edit(false) {
+            return;
+        }
+        if self.layers.get_active_entry().is_none_or(|entry| entry.content.is_raster()) {
+            return;
+        }
+        self.push_layer_undo(self.layer_snapshot(&[self.layers.active_layer_id]));
+        if let Some(entry) = self.layers.get_active_entry_mut() {
+            entry.content = LayerContent::Raster;
+            entry.revision += 1;
+        }
+    }
+
+    fn render_text_layers(&mut self) {
+        render_text_entries(&mut self.layers, &self.fonts, self.indexed_palette.as_ref());
+        self.update_display_canvas();
+    }
+
+    // new layers go on top and become active
+    pub fn add_vector_layer(&mut self, vector: VectorLayer) -> LayerId {
+        let id = self.layers.next_layer_id();
+        self.push_layer_undo(self.layer_snapshot(&[id]));
+        self.layers.entries.insert(0, CanvasLayerEntry {
+            id,
+            name: LayerKind::Vector.default_name(id),
+            layer: LayerBuffer::new(self.size.0, self.size.1, self.format),
+            content: LayerContent::Vector { vector, stale: true },
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        self.layers.active_layer_id = id;
+        self.update_display_canvas();
+        id
+    }
+
+    pub fn get_active_vector(&self) -> Option<&VectorLayer> {
+        match &self.layers.get_active_entry()?.content {
+            LayerContent::Vector { vector, .. } => Some(vector),
+            _ => None
+        }
+    }
+
+    pub fn set_active_vector(&mut self, new_vector: VectorLayer) {
+        let moving = self.get_active_vector().is_some_and(|vector| {
+            vector.shapes.len() == new_vector.shapes.len() && vector.shapes.iter().zip(new_vector.shapes.iter()).any(|(a, b)| a.bounds() != b.bounds())
+        });
+        if self.refuse_locked_edit(moving) {
+            return;
+        }
+        if self.get_active_vector().is_none_or(|vector| *vector == new_vector) {
+            return;
+        }
+        self.push_setting_undo(self.layers.active_layer_id);
+        if let Some(LayerContent::Vector { vector, stale }) = self.layers.get_active_entry_mut().map(|entry| &mut entry.content) {
+            *vector = new_vector;
+            *stale = true;
+        }
+        self.update_display_canvas();
+    }
+
+    // in every frame
+    fn mark_vector_layers_stale(&mut self) {
+        every_frame(&mut self.layers, &mut self.frames).flat_map(|layers| layers.entries.iter_mut()).for_each(|entry| {
+            if let LayerContent::Vector { stale, .. } = &mut entry.content {
+                *stale = true;
+            }
+        });
+    }
+
+    fn render_vector_layers(&mut self) {
+        render_vector_entries(&mut self.layers, self.indexed_palette.as_ref());
+    }
+
+    // the new layer goes on top, pixels outside of the canvas are cut off
+    pub fn add_raster_layer(&mut self, source: &FlatCanvasLayer) -> LayerId {
+        let id = self.layers.next_layer_id();
+        let mut layer = FlatCanvasLayer::<Color>::new(self.size.0, self.size.1);
+        source.iter_pixels()
+            .filter(|(pos, _)| pos.x < self.size.0 && pos.y < self.size.1)
+            .for_each(|(pos, color)| layer.set_pixel(pos, *color));
+        if let Some(palette) = &self.indexed_palette {
+            quantize_layer(&mut layer, &palette.colors, Dither::FloydSteinberg);
+        }
+        self.push_layer_undo(self.layer_snapshot(&[id]));
+        self.layers.entries.insert(0, CanvasLayerEntry {
+            id,
+            name: LayerKind::Raster.default_name(id),
+            layer: LayerBuffer::from_layer(&layer, self.format),
+            content: LayerContent::Raster,
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        self.layers.active_layer_id = id;
+        self.update_display_canvas();
+        id
+    }
+
+    pub fn add_adjustment_layer(&mut self, adjustment_layer: AdjustmentLayer) -> LayerId {
+        let id = self.layers.next_layer_id();
+        self.push_layer_undo(self.layer_snapshot(&[id]));
+        self.layers.entries.insert(0, CanvasLayerEntry {
+            id,
+            name: LayerKind::Adjustment.default_name(id),
+            layer: LayerBuffer::new(self.size.0, self.size.1, self.format),
+            content: LayerContent::Adjustment(adjustment_layer),
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        self.layers.active_layer_id = id;
+        self.update_display_canvas();
+        id
+    }
+
+    pub fn get_active_adjustment(&self) -> Option<&AdjustmentLayer> {
+        match &self.layers.get_active_entry()?.content {
+            LayerContent::Adjustment(adjustment_layer) => Some(adjustment_layer),
+            _ => None
+        }
+    }
+
+    // by id so a dialog can keep editing the layer it was opened for
+    pub fn set_adjustment_layer(&mut self, id: LayerId, new_adjustment_layer: AdjustmentLayer) {
+        if self.layers.entries.iter().find(|entry| entry.id == id).is_some_and(|entry| self.layers.locks_of(entry).all) {
+            self.blocked_reason = Some(format!("layer {} is locked", id.0));
+            return;
+        }
+        let changed = self.layers.entries.iter().find(|entry| entry.id == id).is_some_and(|entry| {
+            matches!(&entry.content, LayerContent::Adjustment(adjustment_layer) if *adjustment_layer != new_adjustment_layer)
+        });
+        if !changed {
+            return;
+        }
+        self.push_setting_undo(id);
+        let entry = self.layers.entries.iter_mut().find(|entry| entry.id == id);
+        if let Some(LayerContent::Adjustment(adjustment_layer)) = entry.map(|entry| &mut entry.content) {
+            *adjustment_layer = new_adjustment_layer;
+        }
+        self.update_display_canvas();
+    }
+
+    // puts the active layer into a new group at its place
+    pub fn group_active_layer(&mut self) -> Option<LayerId> {
+        let id = self.layers.next_layer_id();
+        let index = self.layers.entries.iter().position(|entry| entry.id == self.layers.active_layer_id)?;
+        self.push_layer_undo(self.layer_snapshot(&[id, self.layers.active_layer_id]));
+        let parent = self.layers.parent_of(&self.layers.entries[index]);
+        self.layers.entries[index].parent = Some(id);
+        self.layers.entries.insert(index, CanvasLayerEntry {
+            id,
+            name: LayerKind::Group.default_name(id),
+            layer: LayerBuffer::new(self.size.0, self.size.1, self.format),
+            content: LayerContent::Group(LayerGroup::new()),
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        self.layers.active_layer_id = id;
+        self.update_display_canvas();
+        Some(id)
+    }
+
+    // removes the active group, its children take its place
+    pub fn ungroup_active_layer(&mut self) {
+        let Some(index) = self.layers.entries.iter().position(|entry| entry.id == self.layers.active_layer_id) else { return };
+        if self.layers.entries[index].content.kind() != LayerKind::Group {
+            return;
+        }
+        let group_id = self.layers.active_layer_id;
+        let ids: Vec<LayerId> = self.layers.entries.iter()
+            .filter(|entry| entry.id == group_id || entry.parent == Some(group_id))
+            .map(|entry| entry.id)
+            .collect();
+        self.push_layer_undo(self.layer_snapshot(&ids));
+        let group = self.layers.entries.remove(index);
+        let parent = self.layers.parent_of(&group);
+        self.layers.entries.iter_mut()
+            .filter(|entry| entry.parent == Some(group.id))
+            .for_each(|entry| entry.parent = parent);
+        if let Some(first) = self.layers.entries.get(index) {
+            self.layers.active_layer_id = first.id;
+        }
+        self.update_display_canvas();
+    }
+
+    pub fn get_active_group(&self) -> Option<&LayerGroup> {
+        match &self.layers.get_active_entry()?.content {
+            LayerContent::Group(group) => Some(group),
+            _ => None
+        }
+    }
+
+    pub fn set_group(&mut self, id: LayerId, new_group: LayerGroup) {
+        let changed = self.layers.entries.iter().find(|entry| entry.id == id).is_some_and(|entry| {
+            matches!(&entry.content, LayerContent::Group(group) if *group != new_group)
+        });
+        if !changed {
+            return;
+        }
+        self.push_setting_undo(id);
+        let entry = self.layers.entries.iter_mut().find(|entry| entry.id == id);
+        if let Some(LayerContent::Group(group)) = entry.map(|entry| &mut entry.content) {
+            *group = new_group;
+        }
+        self.update_display_canvas();
+    }
+
+    pub fn get_edit_mask(&self) -> bool {
+        self.edit_mask
+    }
+
+    pub fn set_edit_mask(&mut self, edit_mask: bool) {
+        self.edit_mask = edit_mask;
+    }
+
+    // the new mask shows the whole layer
+    pub fn add_active_mask(&mut self) {
+        if self.refuse_locked_edit(false) {
+            return;
+        }
+        if self.layers.get_active_entry().is_none_or(|entry| entry.mask.is_some()) {
+            return;
+        }
+        self.push_layer_undo(self.layer_snapshot(&[self.layers.active_layer_id]));
+        let size = self.size;
+        if let Some(entry) = self.layers.get_active_entry_mut() {
+            entry.mask = Some(LayerMask::new(size.0, size.1));
+            entry.revision += 1;
+            self.edit_mask = true;
+        }
+        self.update_display_canvas();
+    }
+
+    pub fn delete_active_mask(&mut self) {
+        if self.refuse_locked_edit(false) {
+            return;
+        }
+        if self.layers.get_active_entry().is_none_or(|entry| entry.mask.is_none()) {
+            return;
+        }
+        self.push_layer_undo(self.layer_snapshot(&[self.layers.active_layer_id]));
+        if let Some(entry) = self.layers.get_active_entry_mut() {
+            entry.mask = None;
+            entry.revision += 1;
+        }
+        self.edit_mask = false;
+        self.update_display_canvas();
+    }
+
+    // multiplies the alpha of the active layer with its mask, undone as a whole with the mask
+    // and the text. text layers are rasterized, adjustment layers and groups keep their mask
+    pub fn apply_active_mask(&mut self) {
+        if self.refuse_locked_edit(false) {
+            return;
+        }
+        let Some(entry) = self.layers.get_active_entry() else { return };
+        if entry.mask.is_none() || matches!(entry.content.kind(), LayerKind::Adjustment | LayerKind::Group) {
+            return;
+        }
+        // the mask would be gone without changing anything
+        if self.layers.locks_of(entry).alpha {
+            self.blocked_reason = Some(format!("the alpha of layer {} is locked", entry.id.0));
+            return;
+        }
+        self.push_layer_undo(self.layer_snapshot(&[self.layers.active_layer_id]));
+        let Some(entry) = self.layers.get_active_entry_mut() else { return };
+        let Some(mask) = entry.mask.take() else { return };
+        entry.content = LayerContent::Raster;
+        entry.revision += 1;
+        self.edit_mask = false;
+        mask.layer.iter_pixels()
+            .enumerate()
+            .filter(|(_, (_, value))| value.red < 255)
+            .for_each(|(index, (pos, value))| {
+                let mut rgba = entry.layer.rgba_at(index);
+                rgba[3] *= value.red as f32 / 255.0;
+                match &self.indexed_palette {
+                    Some(palette) => entry.layer.set_pixel(pos, nearest_color(&palette.colors, Color::from_rgba(rgba))),
+                    None => entry.layer.set_rgba(pos, rgba)
+                }
+            });
+        self.update_display_canvas();
+    }
+
+    pub fn get_mask_thumbnail(&self, id: LayerId, max_side: u32) -> Option<FlatCanvasLayer> {
+        let entry = self.layers.entries.iter().find(|entry| entry.id == id)?;
+        entry.mask.as_ref().map(|mask| mask.layer.thumbnail(max_side))
+    }
+
+    pub fn get_paths(&self) -> &[VectorPath] {
+        &self.layers.paths
+    }
+
+    pub fn get_active_path(&self) -> Option<usize> {
+        self.layers.active_path
+    }
+
+    // with None the pen tool starts a new path
+    pub fn set_active_path(&mut self, index: Option<usize>) {
+        self.layers.active_path = index.filter(|i| *i < self.layers.paths.len());
+    }
+
+    pub fn set_paths(&mut self, paths: Vec<VectorPath>) {
+        self.layers.paths = paths;
+        self.layers.active_path = None;
+    }
+
+    pub fn rename_path(&mut self, index: usize, name: String) {
+        if let Some(path) = self.layers.paths.get_mut(index) {
+            path.name = name;
+        }
+    }
+
+    pub fn delete_path(&mut self, index: usize) {
+        if index >= self.layers.paths.len() {
+            return;
+        }
+        self.layers.paths.remove(index);
+        self.layers.active_path = match self.layers.active_path {
+            Some(i) if i == index => None,
+            Some(i) if i > index => Some(i - 1),
+            active => active
+        };
+    }
+
+    // paints along the path like a tool would, as one undoable command
+    pub fn stroke_path(&mut self, index: usize, stroke: PathStroke, color: Color) {
+        let Some(path) = self.layers.paths.get(index) else { return };
+        let mut pixels = Stroke::new(path.flatten(), stroke.style, self.size);
+        if path.closed {
+            pixels = pixels.closed();
+        }
+        let command = EditCommand {
+            edits: pixels.map(|(pos, coverage)| (pos, with_coverage(color, coverage * stroke.opacity))).collect(),
+            blend: stroke.blend.then_some(self.layers.blend_space),
+            ..EditCommand::default()
+        };
+        self.apply_commands_handle_undo_redo(&vec![command]);
+        self.update_display_canvas();
+    }
+
+    // open paths are filled as if they were closed
+    pub fn fill_path(&mut self, index: usize, color: Color, anti_alias: bool) {
+        let Some(path) = self.layers.paths.get(index) else { return };
+        let command = EditCommand {
+            edits: Fill::new(path.flatten(), anti_alias, self.size).map(|(pos, coverage)| (pos, with_coverage(color, coverage))).collect(),
+            blend: Some(self.layers.blend_space),
+            ..EditCommand::default()
+        };
+        self.apply_commands_handle_undo_redo(&vec![command]);
+        self.update_display_canvas();
+    }
+
+    // the inside of the path becomes the mask of the active layer, there are no selections
+    // so masks are what limits where a layer shows. undo gives back the old mask
+    pub fn path_to_mask(&mut self, index: usize) {
+        let Some(path) = self.layers.paths.get(index) else { return };
+        let (width, height) = self.size;
+        let mut inside = vec![0.0; (width * height) as usize];
+        for (pos, coverage) in Fill::new(path.flatten(), true, self.size) {
+            inside[(pos.y * width + pos.x) as usize] = coverage;
+        }
+        self.add_active_mask();
+        // locked layers and ones that can't have masks stay as they are
+        let has_mask = self.layers.get_active_entry().is_some_and(|entry| entry.mask.is_some());
+        if !has_mask {
+            return;
+        }
+        self.edit_mask = true;
+        let mut command = EditCommand::default();
+        for (index, coverage) in inside.into_iter().enumerate() {
+            let value = (coverage * 255.0 + 0.5) as u8;
+            command.edits.push((PixelPos { x: index as u32 % width, y: index as u32 / width }, Color::new(value, value, value, 255)));
+        }
+        self.apply_commands_handle_undo_redo(&vec![command]);
+        self.update_display_canvas();
+    }
+
+    pub fn draw_tool_overlay(&self, tool: &dyn PaintTool, painter: &Painter, to_screen: &dyn Fn((f32, f32)) -> Pos2) {
+        tool.draw_overlay(&self.layers, painter, to_screen);
+    }
+
+    // visible layers composited on transparent, what gets exported
+    pub fn get_merged_layer(&self) -> FlatCanvasLayer {
+        let mut result = FlatCanvasLayer::new(self.size.0, self.size.1);
+        composite_stack(&self.layers.entries, None, self.layers.blend_space, &mut result);
+        result
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn get_active_frame(&self) -> usize {
+        self.active_frame
+    }
+
+    pub fn get_frame_durations(&self) -> Vec<u32> {
+        self.frames.iter().map(|frame| frame.duration_ms).collect()
+    }
+
+    pub fn set_frame_duration(&mut self, index: usize, duration_ms: u32) {
+        if let Some(frame) = self.frames.get_mut(index) {
+            frame.duration_ms = duration_ms.max(1);
+        }
+    }
+
+    // the paths belong to the document and go along to the new frame, the history stays with its frame
+    pub fn set_active_frame(&mut self, index: usize) {
+        if index == self.active_frame || index >= self.frames.len() {
+            return;
+        }
+        let merged = self.get_merged_layer();
+        let Some(mut layers) = self.frames[index].layers.take() else { return };
+        layers.paths = std::mem::take(&mut self.layers.paths);
+        layers.active_path = self.layers.active_path.take();
+
+        let old = &mut self.frames[self.active_frame];
+        old.layers = Some(std::mem::replace(&mut self.layers, layers));
+        old.merged = Some(merged);
+        std::mem::swap(&mut old.undo_stack, &mut self.undo_stack);
+        std::mem::swap(&mut old.redo_stack, &mut self.redo_stack);
+
+        let new = &mut self.frames[index];
+        new.merged = None;
+        std::mem::swap(&mut new.undo_stack, &mut self.undo_stack);
+        std::mem::swap(&mut new.redo_stack, &mut self.redo_stack);
+        self.active_frame = index;
+        self.operation_preview = None;
+        self.update_display_canvas();
+    }
+
+    // right after the active frame, it becomes active. a new frame has the same layers
+    // with the raster ones empty, a duplicate a copy of all pixels
+    pub fn add_frame(&mut self, duplicate: bool) {
+        let mut layers = self.layers.clone();
+        layers.paths = Vec::new();
+        layers.active_path = None;
+        if !duplicate {
+            layers.entries.iter_mut().filter(|entry| entry.content.is_raster()).for_each(|entry| {
+                entry.layer = LayerBuffer::new(self.size.0, self.size.1, self.format);
+                entry.revision += 1;
+            });
+        }
+        let duration_ms = self.frames[self.active_frame].duration_ms;
+        self.frames.insert(self.active_frame + 1, AnimationFrame::new(duration_ms, Some(layers)));
+        self.set_active_frame(self.active_frame + 1);
+    }
+
+    // the last frame can't be deleted, the next frame becomes active when the active one goes
+    pub fn delete_frame(&mut self, index: usize) {
+        if self.frames.len() < 2 || index >= self.frames.len() {
+            return;
+        }
+        if index == self.active_frame {
+            self.set_active_frame(if index + 1 < self.frames.len() { index + 1 } else { index - 1 });
+        }
+        self.frames.remove(index);
+        if self.active_frame > index {
+            self.active_frame -= 1;
+        }
+        self.update_display_canvas();
+    }
+
+    pub fn move_frame(&mut self, from: usize, to: usize) {
+        if from >= self.frames.len() || to >= self.frames.len() {
+            return;
+        }
+        let frame = self.frames.remove(from);
+        self.frames.insert(to, frame);
+        // the active frame is the one without stored layers
+        self.active_frame = self.frames.iter().position(|frame| frame.layers.is_none()).unwrap_or(0);
+        self.update_display_canvas();
+    }
+
+    // what get_merged_layer gives for the frame
+    pub fn get_frame_merged_layer(&self, index: usize) -> Option<FlatCanvasLayer> {
+        let frame = self.frames.get(index)?;
+        match (&frame.layers, &frame.merged) {
+            (None, _) => Some(self.get_merged_layer()),
+            (Some(_), Some(merged)) => Some(merged.clone()),
+            (Some(layers), None) => {
+                let mut result = FlatCanvasLayer::new(self.size.0, self.size.1);
+                composite_stack(&layers.entries, None, layers.blend_space, &mut result);
+                Some(result)
+            }
+        }
+    }
+
+    pub fn get_slices(&self) -> &SliceSet {
+        &self.slices
+    }
+
+    pub fn set_slices(&mut self, slices: SliceSet) {
+        self.slices = slices;
+    }
+
+    pub fn get_onion_skin(&self) -> OnionSkin {
+        self.onion_skin
+    }
+
+    pub fn set_onion_skin(&mut self, onion_skin: OnionSkin) {
+        if self.onion_skin != onion_skin {
+            self.onion_skin = onion_skin;
+            self.update_display_canvas();
+        }
+    }
+
+    // after a change to the whole document the other frames are drawn again, so their onion skins
+    // and exports show it. clear_history is for changes their undo commands don't fit anymore
+    fn update_stored_frames(&mut self, clear_history: bool) {
+        let (fonts, indexed_palette) = (&self.fonts, self.indexed_palette.as_ref());
+        for frame in self.frames.iter_mut() {
+            let Some(layers) = &mut frame.layers else { continue };
+            render_text_entries(layers, fonts, indexed_palette);
+            render_vector_entries(layers, indexed_palette);
+            let mut merged = FlatCanvasLayer::new(self.size.0, self.size.1);
+            composite_stack(&layers.entries, None, layers.blend_space, &mut merged);
+            frame.merged = Some(merged);
+            if clear_history {
+                frame.undo_stack.clear();
+                frame.redo_stack.clear();
+            }
+        }
+    }
+
+    // nearest frames last so they end up on top
+    fn draw_onion_skins(&mut self) {
+        if !self.onion_skin.enabled {
+            return;
+        }
+        let active = self.active_frame;
+        let previous = (1..=self.onion_skin.previous).filter_map(|distance| active.checked_sub(distance).map(|index| (index, distance, Color::new(255, 40, 40, 255))));
+        let next = (1..=self.onion_skin.next).map(|distance| (active + distance, distance, Color::new(40, 200, 80, 255)));
+        let skins = previous.chain(next).sorted_by_key(|(_, distance, _)| std::cmp::Reverse(*distance));
+        for (index, distance, tint) in skins {
+            let Some(merged) = self.frames.get(index).and_then(|frame| frame.merged.as_ref()) else { continue };
+            if merged.get_size() != self.size {
+                continue;
+            }
+            let opacity = self.onion_skin.opacity.clamp(0.0, 1.0) / distance as f32;
+            for (target, source) in self.draw_layer.get_data_mut().iter_mut().zip(merged.get_data()) {
+                let alpha = (source.alpha as f32 * opacity) as u8;
+                if alpha > 0 {
+                    let tinted = source.interpolate(&tint, 128);
+                    *target = Color { alpha: target.alpha, ..tinted.interpolate(target, alpha) };
+                }
+            }
+        }
     }
 
-    pub fn stroke_start(&mut self, global_params: &GlobalParams, tool : &mut dyn PaintTool){
-        let mut commands = Vec::new();
-        tool.stroke_start(global_params, &mut self.tool_layer, &mut |command| commands.push(command));
-        self.apply_commands_handle_undo_redo(&commands);
+    pub fn get_indexed_palette(&self) -> Option<&Palette> {
+        self.indexed_palette.as_ref()
     }
 
-    pub fn stroke_update(&mut self, global_params: &GlobalParams, tool : &mut dyn PaintTool){
-        let mut commands = Vec::new();
-        tool.stroke_update(global_params, &mut self.tool_layer, &mut |command| commands.push(command));
-        self.apply_commands_handle_undo_redo(&commands);
+    // quantizes every layer, this can't be undone so the history is cleared.
+    // palettes are 8 bit, so the document becomes 8 bit as well
+    pub fn convert_to_indexed(&mut self, palette: Palette, dither: Dither) {
+        every_frame(&mut self.layers, &mut self.frames).flat_map(|layers| layers.entries.iter_mut()).for_each(|entry|{
+            let mut layer = entry.layer.to_rgba8();
+            quantize_layer(&mut layer, &palette.colors, dither);
+            entry.layer = LayerBuffer::Rgba8(layer);
+            entry.revision += 1;
+        });
+        self.format = PixelFormat::Rgba8;
+        self.indexed_palette = Some(palette);
+        self.mark_vector_layers_stale();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.update_stored_frames(true);
+        self.update_display_canvas();
+    }
 
+    pub fn convert_to_rgba(&mut self) {
+        self.indexed_palette = None;
+        self.mark_vector_layers_stale();
+        self.update_stored_frames(false);
         self.update_display_canvas();
     }
 
-    pub fn stroke_end(&mut self, global_params: &GlobalParams, tool : &mut dyn PaintTool){
-        let mut commands = Vec::new();
-        tool.stroke_end(global_params, &mut self.tool_layer, &mut |command| commands.push(command));
-        self.apply_commands_handle_undo_redo(&commands);
+    // every pixel using the old color gets the new one, dragging through colors is one step
+    pub fn set_indexed_palette_color(&mut self, index: usize, color: Color) {
+        let Some(palette) = &self.indexed_palette else { return };
+        let Some(old_color) = palette.colors.get(index).copied() else { return };
+        let color = Color::new(color.red, color.green, color.blue, 255);
+        if old_color == color {
+            return;
+        }
+        // the pixels of both colors couldn't be told apart anymore to undo it
+        if palette.colors.contains(&color) {
+            self.blocked_reason = Some("the palette already has this color".to_string());
+            return;
+        }
+        self.redo_stack.clear();
+        if !matches!(self.undo_stack.last(), Some(UndoStep::PaletteColor { index: last, .. }) if *last == index) {
+            self.undo_stack.push(UndoStep::PaletteColor { index, color: old_color });
+        }
+        self.replace_palette_color(index, color);
+    }
 
+    // returns the color that was there
+    fn replace_palette_color(&mut self, index: usize, color: Color) -> Option<Color> {
+        let palette = self.indexed_palette.as_mut()?;
+        let old_color = *palette.colors.get(index)?;
+        palette.colors[index] = color;
+        if !palette.colors.contains(&old_color) {
+            // indexed documents are always 8 bit
+            every_frame(&mut self.layers, &mut self.frames).flat_map(|layers| layers.entries.iter_mut()).for_each(|entry| {
+                if let LayerBuffer::Rgba8(layer) = &mut entry.layer {
+                    layer.iter_pixels_mut()
+                        .filter(|(_, pixel)| **pixel == old_color)
+                        .for_each(|(_, pixel)| *pixel = color);
+                }
+                entry.revision += 1;
+            });
+        }
+        self.update_stored_frames(false);
         self.update_display_canvas();
+        Some(old_color)
     }
 
-    pub fn undo(&mut self){
-        if let Some(command) = self.undo_stack.pop(){
-            if let Some(active_layer) = self.layers.get_active_layer_mut(){
-                self.redo_stack.push(command.reverse(active_layer));
-                command.apply(active_layer);
+    // for adjustment layers this is the histogram of everything below them
+    pub fn get_active_histogram(&self) -> Option<[[u32; 256]; 4]> {
+        let entry = self.layers.get_active_entry()?;
+        match entry.content {
+            LayerContent::Adjustment(_) => {
+                let index = self.layers.entries.iter().position(|other| other.id == entry.id)?;
+                let mut below = FlatCanvasLayer::new(self.size.0, self.size.1);
+                composite_stack(&self.layers.entries[index + 1..], None, self.layers.blend_space, &mut below);
+                Some(histogram(&below))
             }
+            _ => Some(histogram(&entry.layer.to_rgba8()))
+        }
+    }
 
-            self.update_display_canvas();
-            println!("undo");
-            println!("undo_stack: {}", self.undo_stack.len());
-            println!("redo_stack: {}", self.redo_stack.len());
+    pub fn set_operation_preview(&mut self, operation: Option<LayerOperation>) {
+        // operations work on the pixels of the layer, not on its mask
+        if self.painting_mask() {
+            if self.operation_preview.take().is_some() {
+                self.update_display_canvas();
+            }
+            return;
         }
-        else {
-            println!("nothing to undo");
+        if self.operation_preview.as_ref().map(|(previewed, _)| previewed) == operation.as_ref() {
+            return;
         }
+        self.operation_preview = operation.and_then(|operation| {
+            let layer = operation.apply(self.layers.get_active_layer()?);
+            Some((operation, layer))
+        });
+        self.update_display_canvas();
     }
 
-    pub fn redo(&mut self){
-        if let Some(command) = self.redo_stack.pop(){
-            self.undo_stack.push(command.reverse(self.get_active_layer().unwrap()));
-            //self.apply_command(&command);
-            if let Some(active_layer) = self.layers.get_active_layer_mut(){
-                command.apply(active_layer);
-            }
+    // applies to the active layer as a single undoable command
+    pub fn apply_operation(&mut self, operation: &LayerOperation) {
+        let preview = self.operation_preview.take()
+            .filter(|(previewed, _)| previewed == operation)
+            .map(|(_, layer)| layer);
+        if self.painting_mask() {
+            self.blocked_reason = Some("filters and adjustments can't be applied to a mask".to_string());
             self.update_display_canvas();
-
-            println!("redo");
-            println!("undo_stack: {}", self.undo_stack.len());
-            println!("redo_stack: {}", self.redo_stack.len());
+            return;
+        }
+        if let Some(layer) = self.layers.get_active_layer() {
+            let result = preview.unwrap_or_else(|| operation.apply(layer));
+            let mut command = EditCommand::default();
+            let (width, height) = layer.get_size();
+            for index in 0..(width * height) as usize {
+                let new_rgba = result.rgba_at(index);
+                if layer.rgba_at(index) != new_rgba {
+                    let pos = PixelPos { x: index as u32 % width, y: index as u32 / width };
+                    command.push_rgba(layer.format(), pos, new_rgba);
+                }
+            }
+            self.apply_commands_handle_undo_redo(&vec![command]);
         }
+        self.update_display_canvas();
     }
 
     fn update_display_canvas(&mut self){
+        self.render_vector_layers();
         self.draw_layer.clear();
+        self.checkers_pattern_layer.apply_to_canvas(&mut self.draw_layer);
+        self.draw_onion_skins();
 
-        let mut layers_to_apply = Vec::new();
-
-        layers_to_apply.push(&self.checkers_pattern_layer as &dyn CanvasLayer);
-
-        let canvas_layers = self.layers.entries.iter()
-            .rev()
-            .filter(|entry| entry.visible)
-            .map(|entry| &entry.layer as &dyn CanvasLayer)
-            .collect_vec();
-
-        layers_to_apply.extend(canvas_layers);
+        let preview = self.operation_preview.as_ref().map(|(_, layer)| (self.layers.active_layer_id, layer));
+        let has_adjustments = self.layers.entries.iter()
+            .any(|entry| entry.visible && entry.content.kind() == LayerKind::Adjustment);
+        if has_adjustments {
+            // composited on its own so adjustment layers don't change the checkers pattern
+            self.composite_layer.fill(Color::new(0, 0, 0, 0));
+            composite_stack(&self.layers.entries, preview, self.layers.blend_space, &mut self.composite_layer);
+            self.composite_layer.apply_to_canvas(&mut self.draw_layer);
+        } else {
+            composite_stack(&self.layers.entries, preview, self.layers.blend_space, &mut self.draw_layer);
+        }
 
         // make the tool_layer appear on top (you may want to apply it to correct layer instead)
-        layers_to_apply.push(&self.tool_layer);
-
-
-        apply_layers(layers_to_apply.into_iter(), &mut self.draw_layer);
+        self.mirrored_tool_layer.apply_to_canvas(&mut self.draw_layer);
     }
 
     fn create_checkers_pattern(w: u32, h: u32, grid_len : usize) -> FlatCanvasLayer {
@@ -223,21 +1528,228 @@ impl Canvas {
     }
 }
 
-// applied in order (last one is on top)
-pub fn apply_layers<'a>(canvases_iter : impl Iterator<Item = &'a dyn CanvasLayer>, target_canvas : &mut dyn CanvasLayer){
-    canvases_iter.for_each(|canvas|{
-        canvas.apply_to_canvas(target_canvas);
+// the layers of the active frame first, then the ones the other frames keep
+fn every_frame<'a>(layers: &'a mut CanvasLayers, frames: &'a mut [AnimationFrame]) -> impl Iterator<Item = &'a mut CanvasLayers> {
+    std::iter::once(layers).chain(frames.iter_mut().filter_map(|frame| frame.layers.as_mut()))
+}
+
+fn render_text_entries(layers: &mut CanvasLayers, fonts: &FontLibrary, indexed_palette: Option<&Palette>) {
+    layers.entries.iter_mut().for_each(|entry|{
+        if let LayerContent::Text { text, bounds } = &mut entry.content {
+            *bounds = render_text(text, fonts, &mut entry.layer);
+            entry.revision += 1;
+            if let Some(palette) = indexed_palette {
+                quantize_layer(&mut entry.layer, &palette.colors, Dither::None);
+            }
+        }
+    });
+}
+
+// at the size of the canvas, so they stay sharp whatever it is
+fn render_vector_entries(layers: &mut CanvasLayers, indexed_palette: Option<&Palette>) {
+    let blend_space = layers.blend_space;
+    layers.entries.iter_mut().for_each(|entry| {
+        if let LayerContent::Vector { vector, stale: stale @ true } = &mut entry.content {
+            render_vector_layer(vector, blend_space, &mut entry.layer);
+            *stale = false;
+            entry.revision += 1;
+            if let Some(palette) = indexed_palette {
+                quantize_layer(&mut entry.layer, &palette.colors, Dither::None);
+            }
+        }
     });
 }
 
+// adjustments and filters both turn the active layer into a new one
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerOperation {
+    Adjustment(Adjustment),
+    Filter(Filter, EdgeMode),
+}
+
+impl LayerOperation {
+    // the result keeps the format of layer
+    pub fn apply(&self, layer: &LayerBuffer) -> LayerBuffer {
+        match layer {
+            LayerBuffer::Rgba8(layer) => LayerBuffer::Rgba8(self.apply_to(layer)),
+            LayerBuffer::Rgba16(layer) => LayerBuffer::Rgba16(self.apply_to(layer)),
+            LayerBuffer::RgbaF32(layer) => LayerBuffer::RgbaF32(self.apply_to(layer)),
+        }
+    }
+
+    fn apply_to<P: Pixel>(&self, layer: &FlatCanvasLayer<P>) -> FlatCanvasLayer<P> {
+        match self {
+            LayerOperation::Adjustment(adjustment) => {
+                let mut result = layer.clone();
+                apply_adjustment(&mut result, adjustment);
+                result
+            }
+            LayerOperation::Filter(filter, edge_mode) => apply_filter(layer, filter, *edge_mode),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct CanvasLayers{
     pub entries: Vec<CanvasLayerEntry>,
     pub active_layer_id: LayerId,
+    pub blend_space: BlendSpace,
+    // the document's vector paths, the pen tool edits the active one
+    pub paths: Vec<VectorPath>,
+    pub active_path: Option<usize>,
 }
+#[derive(Clone)]
 pub struct CanvasLayerEntry{
     pub id: LayerId,
-    pub layer: FlatCanvasLayer,
+    pub name: String,
+    // the rendered pixels, for non raster layers this is a cache of the content
+    pub layer: LayerBuffer,
+    pub content: LayerContent,
     pub visible: bool,
+    pub mask: Option<LayerMask>,
+    // clipped to the alpha of the first unclipped layer below
+    pub clipped: bool,
+    // the group this layer is in, entries are ordered so groups come right before their children
+    pub parent: Option<LayerId>,
+    pub locks: LayerLocks,
+    // goes up whenever the pixels or the mask change, for caching thumbnails
+    pub revision: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct LayerLocks {
+    // no edits at all
+    pub all: bool,
+    // painting keeps the alpha of every pixel
+    pub alpha: bool,
+    // the content can't be moved
+    pub position: bool,
+}
+
+impl CanvasLayerEntry {
+    // 255 where the layer is fully shown
+    pub fn mask_value(&self, index: usize) -> u32 {
+        match &self.mask {
+            Some(mask) if mask.enabled => mask.layer.get_data()[index].red as u32,
+            _ => 255
+        }
+    }
+}
+
+// grayscale, white shows the layer and black hides it. kept as an opaque layer
+// with the value in every channel so edit commands and undo work on it as well
+#[derive(Clone)]
+pub struct LayerMask {
+    pub layer: FlatCanvasLayer,
+    pub enabled: bool,
+}
+
+impl LayerMask {
+    pub fn new(w: u32, h: u32) -> LayerMask {
+        let mut layer = FlatCanvasLayer::new(w, h);
+        layer.fill(Color::white());
+        LayerMask {
+            layer,
+            enabled: true,
+        }
+    }
+
+    // what painting with color does to the mask, transparent paint hides
+    pub fn mask_color(color: Color) -> Color {
+        let value = (luminance(color) as u32 * color.alpha as u32 / 255) as u8;
+        Color::new(value, value, value, 255)
+    }
+}
+
+#[derive(Clone)]
+pub enum LayerContent {
+    Raster,
+    Text { text: TextLayer, bounds: Option<(PixelPos, PixelPos)> },
+    // stale layers are drawn again before the next display update
+    Vector { vector: VectorLayer, stale: bool },
+    // has no pixels of its own
+    Adjustment(AdjustmentLayer),
+    // the layers whose parent it is
+    Group(LayerGroup),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayerKind {
+    Raster,
+    Text,
+    Vector,
+    Adjustment,
+    Group,
+}
+
+impl LayerKind {
+    // what new layers are called until renamed
+    pub fn default_name(&self, id: LayerId) -> String {
+        let kind = match self {
+            LayerKind::Raster => "Layer",
+            LayerKind::Text => "Text",
+            LayerKind::Vector => "Vector",
+            LayerKind::Adjustment => "Adjustment",
+            LayerKind::Group => "Group",
+        };
+        format!("{} {}", kind, id.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBlend {
+    // the children are composited straight onto the layers below, so their adjustment layers change those too
+    PassThrough,
+    // the children are composited on their own first and the result is put on the layers below
+    Isolated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerGroup {
+    pub opacity: f32,
+    pub blend: GroupBlend,
+    pub collapsed: bool,
+}
+
+impl LayerGroup {
+    pub fn new() -> LayerGroup {
+        LayerGroup {
+            opacity: 1.0,
+            blend: GroupBlend::PassThrough,
+            collapsed: false,
+        }
+    }
+}
+
+impl Default for LayerGroup {
+    fn default() -> LayerGroup {
+        LayerGroup::new()
+    }
+}
+
+impl LayerContent {
+    pub fn kind(&self) -> LayerKind {
+        match self {
+            LayerContent::Raster => LayerKind::Raster,
+            LayerContent::Text { .. } => LayerKind::Text,
+            LayerContent::Vector { .. } => LayerKind::Vector,
+            LayerContent::Adjustment(_) => LayerKind::Adjustment,
+            LayerContent::Group(_) => LayerKind::Group,
+        }
+    }
+
+    pub fn is_raster(&self) -> bool {
+        self.kind() == LayerKind::Raster
+    }
+}
+impl From<CanvasLayerEntry> for WidgetText {
+    fn from(entry: CanvasLayerEntry) -> WidgetText {
+        let layer_str = entry.id.0.to_string();
+
+        // layer : layer_str
+        let final_str = format!("layer : {}", layer_str);
+        WidgetText::from(final_str)
+    }
 }
 impl Hash for CanvasLayerEntry {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
@@ -246,15 +1758,60 @@ impl Hash for CanvasLayerEntry {
 }
 
 impl CanvasLayers {
-    pub fn get_active_layer(&self) -> Option<&FlatCanvasLayer>{
+    pub fn get_active_path(&self) -> Option<&VectorPath> {
+        self.active_path.and_then(|i| self.paths.get(i))
+    }
+
+    pub fn get_active_layer(&self) -> Option<&LayerBuffer>{
         //self.entries.get(&self.active_layer_id).map(|canvas| &canvas.layer)
         self.entries.iter().find(|entry| entry.id == self.active_layer_id).map(|entry| &entry.layer)
     }
 
-    pub fn get_active_layer_mut(&mut self) -> Option<&mut FlatCanvasLayer>{
+    pub fn get_active_layer_mut(&mut self) -> Option<&mut LayerBuffer>{
         //self.entries.get_mut(&self.active_layer_id).map(|canvas| &mut canvas.layer)
         self.entries.iter_mut().find(|entry| entry.id == self.active_layer_id).map(|entry| &mut entry.layer)
     }
+
+    pub fn get_active_entry(&self) -> Option<&CanvasLayerEntry>{
+        self.entries.iter().find(|entry| entry.id == self.active_layer_id)
+    }
+
+    pub fn get_active_entry_mut(&mut self) -> Option<&mut CanvasLayerEntry>{
+        self.entries.iter_mut().find(|entry| entry.id == self.active_layer_id)
+    }
+
+    // the locks of the entry together with the ones of the groups it is in
+    pub fn locks_of(&self, entry: &CanvasLayerEntry) -> LayerLocks {
+        let mut locks = entry.locks;
+        let mut parent = self.parent_of(entry);
+        while let Some(group) = parent.and_then(|id| self.entries.iter().find(|other| other.id == id)) {
+            locks.all |= group.locks.all;
+            locks.alpha |= group.locks.alpha;
+            locks.position |= group.locks.position;
+            parent = self.parent_of(group);
+        }
+        locks
+    }
+
+    // None for top level layers and ones whose group is gone
+    pub fn parent_of(&self, entry: &CanvasLayerEntry) -> Option<LayerId> {
+        entry.parent.filter(|parent| self.entries.iter().any(|other| other.id == *parent))
+    }
+
+    pub fn next_layer_id(&self) -> LayerId {
+        LayerId(self.entries.iter().map(|entry| entry.id.0 + 1).max().unwrap_or(0))
+    }
+
+    pub fn get_size(&self) -> Option<(u32, u32)> {
+        self.entries.first().map(|entry| entry.layer.get_size())
+    }
+
+    // the visible layers composited at a single pixel, without the checkers pattern or the tool layer
+    pub fn get_merged_pixel(&self, pixel_pos: PixelPos) -> Color {
+        let Some((width, _)) = self.get_size() else { return Color::new(255, 255, 255, 0) };
+        let index = (pixel_pos.x + pixel_pos.y * width) as usize;
+        CompositeStack::new(&self.entries, None, self.blend_space).composite(index, Color::new(255, 255, 255, 0))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -272,67 +1829,186 @@ pub struct CanvasOrder(usize);
 #[derive(Default, Clone)]
 pub struct EditCommand {
     pub edits : Vec<(PixelPos, Color)>,
+    // for layers with more than 8 bits, applied after edits
+    pub precise_edits : Vec<(PixelPos, [f32; 4])>,
+    // edits are drawn over the pixels in this space instead of replacing them
+    pub blend : Option<BlendSpace>,
 }
 
 impl EditCommand {
     pub fn apply(&self, canvas : &mut dyn CanvasLayer){
+        if self.blend.is_some() {
+            return self.flattened(canvas).apply(canvas);
+        }
         self.edits.iter().for_each(|(pos, color)|{
             canvas.set_pixel(*pos, *color);
         });
+        self.precise_edits.iter().for_each(|(pos, rgba)|{
+            canvas.set_rgba(*pos, *rgba);
+        });
     }
+    // keeps the precision of canvas, so undo gives back exactly what was there
     pub fn reverse(&self, canvas : &dyn CanvasLayer) -> EditCommand {
         let mut result = EditCommand::default();
-        self.edits.iter().for_each(|(pos, _color)|{
-            result.edits.push((*pos, canvas.get_pixel(*pos)));
-        });
+        let format = canvas.format();
+        self.edits.iter().map(|(pos, _)| pos)
+            .chain(self.precise_edits.iter().map(|(pos, _)| pos))
+            .for_each(|pos| result.push_rgba(format, *pos, canvas.get_rgba(*pos)));
+        result
+    }
+    // the edits that replace the pixels of canvas with what blending would give
+    pub fn flattened(&self, canvas : &dyn CanvasLayer) -> EditCommand {
+        let Some(space) = self.blend else { return self.clone() };
+        let mut result = EditCommand::default();
+        let format = canvas.format();
+        self.edits.iter().map(|(pos, color)| (*pos, color.to_rgba()))
+            .chain(self.precise_edits.iter().copied())
+            .for_each(|(pos, rgba)| {
+                let blended = over(premultiply(rgba, space), premultiply(canvas.get_rgba(pos), space));
+                result.push_rgba(format, pos, unpremultiply(blended, space));
+            });
         result
     }
+    // an edit for a layer of format, only 8 bit layers get a Color
+    pub fn push_rgba(&mut self, format: PixelFormat, pos: PixelPos, rgba: [f32; 4]) {
+        match format {
+            PixelFormat::Rgba8 => self.edits.push((pos, Color::from_rgba(rgba))),
+            _ => self.precise_edits.push((pos, rgba))
+        }
+    }
+}
+
+// what undo and redo give back
+#[derive(Clone)]
+enum UndoStep {
+    // pixels of a layer or of its mask
+    Edit { id: LayerId, mask: bool, command: EditCommand },
+    // a whole layer, for changes that are more than pixels like adding a text layer
+    Layer(Box<LayerSnapshot>),
+    // a color of the indexed palette along with the pixels using it in every frame
+    PaletteColor { index: usize, color: Color },
+}
+
+#[derive(Clone)]
+struct LayerSnapshot {
+    // each layer with where in entries it goes back to, None when it didn't exist
+    layers: Vec<(LayerId, usize, Option<CanvasLayerEntry>)>,
+    active_layer_id: LayerId,
+    // pushed by push_setting_undo, the next change of the same layer is part of it
+    setting: bool,
 }
 
 pub trait PaintTool {
-    fn get_name(&self) -> &str;
+    fn info(&self) -> ToolInfo;
+
+    fn get_name(&self) -> &str {
+        self.info().name
+    }
 
     // pass a function to push commands to
-    fn stroke_start(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
-    fn stroke_update(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
-    fn stroke_end(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
+    // layers are read only, tools change them by pushing commands
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
+    fn stroke_end(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand));
+
+    // the options panel shows these, changes come back through set_option
+    fn options(&self) -> Vec<ToolOption> { Vec::new() }
+    fn set_option(&mut self, _id: &str, _value: ToolOptionValue) {}
+
+    // settings the options can't describe, shown below them
+    fn settings_ui(&mut self, _ui: &mut Ui) {}
 
+    // layer changes requested during the stroke, applied by the canvas after stroke_end
+    fn take_layer_edits(&mut self) -> Vec<LayerEdit> { Vec::new() }
+
+    // tools that don't paint strokes (fills, pickers) opt out of symmetry
+    fn uses_symmetry(&self) -> bool { true }
+
+    // drawn over the canvas with egui, to_screen takes canvas coordinates
+    fn draw_overlay(&self, _layers: &CanvasLayers, _painter: &Painter, _to_screen: &dyn Fn((f32, f32)) -> Pos2) {}
+
+    // how this tool's settings stroke a vector path, None for tools that can't
+    fn path_stroke(&self) -> Option<PathStroke> { None }
+}
+
+pub enum LayerEdit {
+    AddText(TextLayer),
+    SetActive(LayerId),
+    // replaces the path at the index, or adds it as the active path
+    SetPath(Option<usize>, VectorPath),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathStroke {
+    pub style: StrokeStyle,
+    // 0..1
+    pub opacity: f32,
+    pub blend: bool,
 }
 pub struct PixelPencil {
-    name: String,
     previous_point : Option<PixelPos>,
+    pub size: u32,
+    // in percent
+    pub opacity: u32,
+    // replacing keeps the alpha of the color, so a transparent color erases
+    pub blend: bool,
 }
 impl PixelPencil {
     pub fn new() -> PixelPencil {
         PixelPencil {
-            name: "Pencil".to_string(),
-            previous_point : None
+            previous_point : None,
+            size: 1,
+            opacity: 100,
+            blend: false,
         }
     }
 }
 
+impl Default for PixelPencil {
+    fn default() -> PixelPencil {
+        PixelPencil::new()
+    }
+}
+
+// the color with its alpha scaled, for partly covered pixels and opacity
+fn with_coverage(color: Color, coverage: f32) -> Color {
+    Color { alpha: (color.alpha as f32 * coverage + 0.5) as u8, ..color }
+}
+
+const MODE_CHOICES: &[&str] = &["Replace", "Blend"];
+
 impl PaintTool for PixelPencil {
-    fn get_name(&self) -> &str {
-        &self.name
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "pencil", name: "Pencil", icon: "✏", shortcut: Some(Shortcut::new(Key::B)), category: ToolCategory::Paint }
     }
 
     // like that but push_command should be of type Action<EditCommand> in c#
-    fn stroke_start(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, _layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
         tool_canvas.clear();
         self.previous_point = None;
     }
 
-    fn stroke_update(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
-        if let Some(previous_point) = self.previous_point {
-            rasterize_line(previous_point, global_params.current_pixel.unwrap_or_default()).iter().for_each(|pos|{
-                tool_canvas.set_pixel(*pos, global_params.primary_color);
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        if let (Some(previous_point), Some(size)) = (self.previous_point, layers.get_size()) {
+            // replacing with a partly transparent color would erase what is under it
+            let opacity = match self.blend {
+                true => self.opacity as f32 / 100.0,
+                false => 1.0
+            };
+            let color = with_coverage(global_params.stroke_color(), opacity);
+            let style = StrokeStyle { anti_alias: false, ..StrokeStyle::new(self.size as f32) };
+            rasterize::line(previous_point, global_params.current_pixel.unwrap_or_default(), style, size).for_each(|(pos, _)|{
+                tool_canvas.set_pixel(pos, color);
             });
         }
 
         self.previous_point = global_params.current_pixel;
     }
-    fn stroke_end(&mut self, global_params: &GlobalParams, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand)){
+    fn stroke_end(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand)){
         let mut command = EditCommand::default();
+        if self.blend {
+            command.blend = Some(layers.blend_space);
+        }
         tool_canvas.pixels_iter().for_each(|(pos, color)|{
             command.edits.push((*pos, *color));
         });
@@ -343,54 +2019,497 @@ impl PaintTool for PixelPencil {
         self.previous_point = None;
     }
 
+    fn options(&self) -> Vec<ToolOption> {
+        vec![
+            ToolOption::int_slider("size", "Size", self.size, 1, 64).suffix(" px"),
+            // only blending has something to show through
+            ToolOption::int_slider("opacity", "Opacity", self.opacity, 1, 100).suffix("%").enabled(self.blend),
+            ToolOption::choice("mode", "Mode", self.blend as usize, MODE_CHOICES),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        match (id, value) {
+            ("size", ToolOptionValue::Number(size)) => self.size = size as u32,
+            ("opacity", ToolOptionValue::Number(opacity)) => self.opacity = opacity as u32,
+            ("mode", ToolOptionValue::Choice(i)) => self.blend = i == 1,
+            _ => {}
+        }
+    }
+
+    fn path_stroke(&self) -> Option<PathStroke> {
+        let style = StrokeStyle { anti_alias: false, ..StrokeStyle::new(self.size as f32) };
+        // like painting, replacing ignores the opacity
+        Some(PathStroke { style, opacity: if self.blend { self.opacity as f32 / 100.0 } else { 1.0 }, blend: self.blend })
+    }
 }
 
 pub struct LineTool {
-    name: String,
     line_start_point : Option<PixelPos>,
+    pub width: u32,
+    pub anti_alias: bool,
+    pub cap: LineCap,
+    // in percent
+    pub opacity: u32,
 }
 
+const CAP_CHOICES: &[&str] = &["Round", "Butt", "Square"];
+const CAPS: [LineCap; 3] = [LineCap::Round, LineCap::Butt, LineCap::Square];
+
 impl LineTool {
     pub fn new() -> LineTool {
         LineTool {
-            name: "Line".to_string(),
             line_start_point : None,
+            width: 1,
+            anti_alias: false,
+            cap: LineCap::Round,
+            opacity: 100,
         }
     }
+
+    // the line's pixels with the stroke color faded by coverage and opacity
+    fn line_pixels(&self, global_params: &GlobalParams, layers: &CanvasLayers) -> Vec<(PixelPos, Color)> {
+        let (Some(start), Some(size)) = (self.line_start_point, layers.get_size()) else { return Vec::new() };
+        let end = global_params.current_pixel.unwrap_or_default();
+        let color = global_params.stroke_color();
+        let opacity = self.opacity as f32 / 100.0;
+        let style = StrokeStyle { cap: self.cap, anti_alias: self.anti_alias, ..StrokeStyle::new(self.width as f32) };
+        rasterize::line(start, end, style, size)
+            .map(|(pos, coverage)| (pos, with_coverage(color, coverage * opacity)))
+            .collect()
+    }
+}
+
+impl Default for LineTool {
+    fn default() -> LineTool {
+        LineTool::new()
+    }
 }
 
 impl PaintTool for LineTool {
-    fn get_name(&self) -> &str {
-        &self.name
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "line", name: "Line", icon: "📏", shortcut: Some(Shortcut::new(Key::L)), category: ToolCategory::Shapes }
     }
     
-    fn stroke_start(&mut self, global_params: &GlobalParams, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
         self.line_start_point = global_params.current_pixel;
     }
 
-    fn stroke_update(&mut self, global_params: &GlobalParams, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
         _tool_canvas.clear();
-        rasterize_line(self.line_start_point.unwrap_or_default(), global_params.current_pixel.unwrap_or_default()).iter().for_each(|pos|{
-            _tool_canvas.set_pixel(*pos, global_params.primary_color);
+        self.line_pixels(global_params, layers).into_iter().for_each(|(pos, color)|{
+            _tool_canvas.set_pixel(pos, color);
         });
     }
-    fn stroke_end(&mut self, global_params: &GlobalParams, _tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand)){
-        if let Some(line_start_point) = self.line_start_point {
-            let mut command = EditCommand::default();
-            rasterize_line(line_start_point, global_params.current_pixel.unwrap_or_default()).iter().for_each(|pos|{
-                command.edits.push((*pos, global_params.primary_color));
+    // blended, so the anti-aliased edges don't cut holes into the layer
+    fn stroke_end(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand)){
+        if self.line_start_point.is_some() {
+            push_command(EditCommand {
+                edits: self.line_pixels(global_params, layers),
+                blend: Some(layers.blend_space),
+                ..EditCommand::default()
             });
-            push_command(command);
             println!("command pushed");
         }
         _tool_canvas.clear();
         self.line_start_point = None;
     }
 
+    fn options(&self) -> Vec<ToolOption> {
+        vec![
+            ToolOption::int_slider("width", "Width", self.width, 1, 64).suffix(" px"),
+            ToolOption::choice("cap", "Caps", CAPS.iter().position(|cap| *cap == self.cap).unwrap_or(0), CAP_CHOICES),
+            ToolOption::toggle("anti_alias", "Anti-aliasing", self.anti_alias),
+            ToolOption::int_slider("opacity", "Opacity", self.opacity, 1, 100).suffix("%"),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        match (id, value) {
+            ("width", ToolOptionValue::Number(width)) => self.width = width as u32,
+            ("cap", ToolOptionValue::Choice(cap)) => self.cap = CAPS.get(cap).copied().unwrap_or(LineCap::Round),
+            ("anti_alias", ToolOptionValue::Toggle(anti_alias)) => self.anti_alias = anti_alias,
+            ("opacity", ToolOptionValue::Number(opacity)) => self.opacity = opacity as u32,
+            _ => {}
+        }
+    }
+
+    fn path_stroke(&self) -> Option<PathStroke> {
+        let style = StrokeStyle { cap: self.cap, anti_alias: self.anti_alias, ..StrokeStyle::new(self.width as f32) };
+        Some(PathStroke { style, opacity: self.opacity as f32 / 100.0, blend: true })
+    }
 }
 
 #[derive(Debug,Clone, Copy, PartialEq, Eq, Hash)]
 pub struct LayerConfig{
     pub id : LayerId,
     pub visible : bool,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(id: usize, kind: LayerKind, children: Vec<CanvasLayerConfig>) -> CanvasLayerConfig {
+        CanvasLayerConfig {
+            id: LayerId(id),
+            name: kind.default_name(LayerId(id)),
+            kind,
+            visible: true,
+            clipped: false,
+            mask_enabled: None,
+            locks: LayerLocks::default(),
+            collapsed: false,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_move_row() {
+        // group 0 holding layer 1, then layer 2 at the top level
+        let mut layers = CanvasLayersConfig {
+            entries: vec![config(0, LayerKind::Group, vec![config(1, LayerKind::Raster, vec![])]), config(2, LayerKind::Raster, vec![])],
+            active_layer_id: LayerId(0),
+        };
+
+        // dropping layer 2 right below the open group puts it into the group
+        let mut rows = layers.rows();
+        rows.swap(1, 2);
+        layers.move_row(&rows, 1);
+        assert_eq!(layers.parent_of(LayerId(2)), Some(LayerId(0)));
+        assert_eq!(layers.find(LayerId(0)).unwrap().children.iter().map(|child| child.id).collect_vec(), vec![LayerId(2), LayerId(1)]);
+
+        // dropping it at the bottom takes it out again
+        let mut rows = layers.rows();
+        let row = rows.remove(1);
+        rows.push(row);
+        layers.move_row(&rows, 2);
+        assert_eq!(layers.parent_of(LayerId(2)), None);
+        assert_eq!(layers.rows().iter().map(|row| (row.id.0, row.depth)).collect_vec(), vec![(0, 0), (1, 1), (2, 0)]);
+
+        // a group can't go into a group inside of it
+        layers.find_mut(LayerId(0)).unwrap().children.insert(0, config(3, LayerKind::Group, vec![]));
+        let mut rows = layers.rows();
+        rows.swap(0, 1);
+        layers.move_row(&rows, 1);
+        assert_eq!(layers.parent_of(LayerId(0)), None);
+        assert_eq!(layers.parent_of(LayerId(3)), Some(LayerId(0)));
+    }
+
+    #[test]
+    fn test_layer_locks() {
+        let mut canvas = Canvas::new(256, 256);
+        let transparent = PixelPos { x: 0, y: 0 };
+        let red = Color::new(255, 0, 0, 255);
+        let mut command = EditCommand::default();
+        command.edits.push((transparent, red));
+
+        // lock alpha keeps the transparent pixel transparent
+        canvas.layers.get_active_entry_mut().unwrap().locks.alpha = true;
+        canvas.apply_commands_handle_undo_redo(&vec![command.clone()]);
+        assert_eq!(canvas.layers.get_active_layer().unwrap().get_pixel(transparent).alpha, 0);
+        assert_eq!(canvas.layers.get_active_layer().unwrap().get_pixel(transparent).red, 255);
+
+        // lock all on the group refuses the edit with a reason
+        canvas.layers.get_active_entry_mut().unwrap().locks.alpha = false;
+        let group = canvas.group_active_layer().unwrap();
+        canvas.layers.entries.iter_mut().find(|entry| entry.id == group).unwrap().locks.all = true;
+        let layer = canvas.layers.entries.iter().find(|entry| entry.parent == Some(group)).unwrap().id;
+        canvas.layers.active_layer_id = layer;
+        canvas.apply_commands_handle_undo_redo(&vec![command]);
+        assert_eq!(canvas.layers.get_active_layer().unwrap().get_pixel(transparent).alpha, 0);
+        assert!(canvas.take_blocked_reason().is_some());
+    }
+
+    #[test]
+    fn test_group_undo() {
+        let mut canvas = Canvas::new(8, 8);
+        let layer = canvas.layers.active_layer_id;
+        let rows = |canvas: &Canvas| canvas.layers.entries.iter().map(|entry| (entry.id, entry.parent)).collect_vec();
+        let before = rows(&canvas);
+        let group = canvas.group_active_layer().unwrap();
+        let grouped = rows(&canvas);
+
+        // dragging the opacity slider is one step
+        for opacity in [0.8, 0.5] {
+            canvas.set_group(group, LayerGroup { opacity, ..LayerGroup::new() });
+        }
+        canvas.ungroup_active_layer();
+        assert_eq!(rows(&canvas), before);
+
+        canvas.undo();
+        assert_eq!(rows(&canvas), grouped);
+        assert_eq!(canvas.get_active_group().unwrap().opacity, 0.5);
+        canvas.undo();
+        assert_eq!(canvas.get_active_group().unwrap().opacity, 1.0);
+        canvas.undo();
+        assert_eq!(rows(&canvas), before);
+        assert_eq!(canvas.layers.active_layer_id, layer);
+    }
+
+    #[test]
+    fn test_pencil_opacity() {
+        use crate::paint_app::vector_path::PathNode;
+        let mut canvas = Canvas::new(8, 8);
+        let mut global_params = GlobalParams::new();
+        global_params.primary_color = Color::new(255, 0, 0, 255);
+        global_params.current_pixel = Some(PixelPos { x: 2, y: 2 });
+        let mut tool_canvas = HashMapCanvasLayer::new(8, 8);
+        let mut stroke = |pencil: &mut PixelPencil| {
+            let mut commands = Vec::new();
+            pencil.stroke_start(&mut global_params, &canvas.layers, &mut tool_canvas, &mut |command| commands.push(command));
+            pencil.stroke_update(&mut global_params, &canvas.layers, &mut tool_canvas, &mut |command| commands.push(command));
+            pencil.stroke_update(&mut global_params, &canvas.layers, &mut tool_canvas, &mut |command| commands.push(command));
+            pencil.stroke_end(&mut global_params, &canvas.layers, &mut tool_canvas, &mut |command| commands.push(command));
+            commands.remove(0)
+        };
+
+        // replacing ignores the opacity instead of punching a hole
+        let mut pencil = PixelPencil::new();
+        pencil.opacity = 50;
+        let command = stroke(&mut pencil);
+        assert_eq!(command.edits, vec![(PixelPos { x: 2, y: 2 }, Color::new(255, 0, 0, 255))]);
+        assert!(!pencil.options().iter().find(|option| option.id == "opacity").unwrap().enabled);
+
+        pencil.blend = true;
+        let command = stroke(&mut pencil);
+        assert_eq!(command.edits[0].1.alpha, 128);
+        assert!(command.blend.is_some());
+
+        // and so does stroking a path with it
+        let mut path = VectorPath::new("Line".to_string());
+        path.nodes.push(PathNode::corner(glam::Vec2::new(1.5, 4.5)));
+        path.nodes.push(PathNode::corner(glam::Vec2::new(6.5, 4.5)));
+        canvas.set_paths(vec![path]);
+        let red = Color::new(255, 0, 0, 255);
+        let pixel = |canvas: &Canvas| canvas.layers.get_active_layer().unwrap().get_pixel(PixelPos { x: 4, y: 4 });
+        canvas.stroke_path(0, pencil.path_stroke().unwrap(), red);
+        assert_eq!(pixel(&canvas).alpha, 128);
+        canvas.undo();
+        pencil.blend = false;
+        canvas.stroke_path(0, pencil.path_stroke().unwrap(), red);
+        assert_eq!(pixel(&canvas), red);
+    }
+
+    #[test]
+    fn test_paths() {
+        use crate::paint_app::vector_path::PathNode;
+        let mut canvas = Canvas::new(256, 256);
+        let mut path = VectorPath::new("Square".to_string());
+        for (x, y) in [(10.0, 10.0), (30.0, 10.0), (30.0, 30.0), (10.0, 30.0)] {
+            path.nodes.push(PathNode::corner(glam::Vec2::new(x, y)));
+        }
+        path.closed = true;
+        canvas.set_paths(vec![VectorPath::new("Empty".to_string()), path]);
+        canvas.set_active_path(Some(1));
+        canvas.delete_path(0);
+        assert_eq!(canvas.get_active_path(), Some(0));
+
+        let red = Color::new(255, 0, 0, 255);
+        let layer = |canvas: &Canvas, x, y| canvas.layers.get_active_layer().unwrap().get_pixel(PixelPos { x, y });
+        canvas.fill_path(0, red, false);
+        assert_eq!(layer(&canvas, 20, 20), red);
+        assert_eq!(layer(&canvas, 40, 20).alpha, 0);
+        canvas.undo();
+        assert_eq!(layer(&canvas, 20, 20).alpha, 0);
+
+        let stroke = PathStroke { style: StrokeStyle { anti_alias: false, ..StrokeStyle::new(3.0) }, opacity: 1.0, blend: false };
+        canvas.stroke_path(0, stroke, red);
+        assert_eq!(layer(&canvas, 31, 20), red);
+        assert_eq!(layer(&canvas, 20, 20).alpha, 0);
+
+        canvas.path_to_mask(0);
+        let mask = &canvas.layers.get_active_entry().unwrap().mask.as_ref().unwrap().layer;
+        assert_eq!(mask.get_pixel(PixelPos { x: 20, y: 20 }).red, 255);
+        assert_eq!(mask.get_pixel(PixelPos { x: 40, y: 20 }).red, 0);
+    }
+    #[test]
+    fn test_text_layer_undo() {
+        let mut canvas = Canvas::new(64, 64);
+        let below = canvas.layers.active_layer_id;
+        let id = canvas.add_text_layer(TextLayer::new(PixelPos { x: 4, y: 4 }, Color::new(255, 0, 0, 255)));
+        let mut text = canvas.get_active_text().unwrap().clone();
+        text.text = "Edited".to_string();
+        canvas.set_active_text(text);
+
+        // painting on it is refused with a reason the status bar shows
+        let mut command = EditCommand::default();
+        command.edits.push((PixelPos { x: 0, y: 0 }, Color::new(0, 0, 255, 255)));
+        canvas.apply_commands_handle_undo_redo(&vec![command]);
+        assert!(canvas.take_blocked_reason().unwrap().contains("rasterized"));
+
+        canvas.rasterize_active_layer();
+        assert!(canvas.get_active_text().is_none());
+        canvas.undo();
+        assert_eq!(canvas.get_active_text().unwrap().text, "Edited");
+        canvas.undo();
+        assert_eq!(canvas.get_active_text().unwrap().text, "Text");
+        canvas.undo();
+        assert!(canvas.layers.entries.iter().all(|entry| entry.id != id));
+        assert_eq!(canvas.layers.active_layer_id, below);
+
+        canvas.redo();
+        canvas.redo();
+        assert_eq!((canvas.layers.active_layer_id, canvas.layers.entries[0].id), (id, id));
+        assert_eq!(canvas.get_active_text().unwrap().text, "Edited");
+    }
+
+    #[test]
+    fn test_mask_undo() {
+        let mut canvas = Canvas::new(8, 8);
+        let pos = PixelPos { x: 1, y: 1 };
+        let red = Color::new(255, 0, 0, 255);
+        canvas.layers.entries[0].layer.set_pixel(pos, red);
+        let pixel = |canvas: &Canvas| canvas.layers.get_active_layer().unwrap().get_pixel(pos);
+        let mask_value = |canvas: &Canvas| canvas.layers.get_active_entry().unwrap().mask.as_ref().map(|mask| mask.layer.get_pixel(pos).red);
+
+        canvas.add_active_mask();
+        let mut command = EditCommand::default();
+        command.edits.push((pos, Color::black()));
+        canvas.apply_commands_handle_undo_redo(&vec![command]);
+        assert_eq!((mask_value(&canvas), pixel(&canvas)), (Some(0), red));
+
+        // filters and adjustments aren't applied to the mask
+        canvas.apply_operation(&LayerOperation::Adjustment(Adjustment::BrightnessContrast { brightness: 0.5, contrast: 0.0 }));
+        assert!(canvas.take_blocked_reason().unwrap().contains("mask"));
+        assert_eq!((mask_value(&canvas), pixel(&canvas)), (Some(0), red));
+
+        // undone on the mask it was painted on, not on what is painted on now
+        canvas.set_edit_mask(false);
+        canvas.undo();
+        assert_eq!((mask_value(&canvas), pixel(&canvas)), (Some(255), red));
+        canvas.redo();
+
+        canvas.apply_active_mask();
+        assert_eq!((mask_value(&canvas), pixel(&canvas).alpha), (None, 0));
+        canvas.undo();
+        assert_eq!((mask_value(&canvas), pixel(&canvas)), (Some(0), red));
+        canvas.undo();
+        canvas.undo();
+        assert_eq!(mask_value(&canvas), None);
+
+        // with the alpha locked the mask is kept
+        canvas.redo();
+        canvas.redo();
+        canvas.layers.entries[0].locks.alpha = true;
+        canvas.apply_active_mask();
+        assert!(canvas.take_blocked_reason().unwrap().contains("alpha"));
+        assert_eq!((mask_value(&canvas), pixel(&canvas)), (Some(0), red));
+    }
+
+    #[test]
+    fn test_palette_color_undo() {
+        let mut canvas = Canvas::new(8, 8);
+        let pos = PixelPos { x: 1, y: 1 };
+        let (red, green, blue) = (Color::new(255, 0, 0, 255), Color::new(0, 255, 0, 255), Color::new(0, 0, 255, 255));
+        canvas.layers.entries[0].layer.set_pixel(pos, red);
+        let mut palette = Palette::new("");
+        palette.colors = vec![Color::black(), red, Color::new(255, 255, 255, 255)];
+        canvas.convert_to_indexed(palette, Dither::None);
+        let pixel = |canvas: &Canvas| canvas.layers.get_active_layer().unwrap().get_pixel(pos);
+
+        // dragging through colors is one step
+        canvas.set_indexed_palette_color(1, green);
+        canvas.set_indexed_palette_color(1, blue);
+        assert_eq!(pixel(&canvas), blue);
+        canvas.undo();
+        assert_eq!((pixel(&canvas), canvas.indexed_palette.as_ref().unwrap().colors[1]), (red, red));
+        canvas.redo();
+        assert_eq!(pixel(&canvas), blue);
+
+        // a color already in the palette would merge its pixels
+        canvas.set_indexed_palette_color(1, Color::black());
+        assert!(canvas.take_blocked_reason().is_some());
+        assert_eq!(pixel(&canvas), blue);
+    }
+
+    #[test]
+    fn test_vector_layer_resize() {
+        use crate::paint_app::vector_layer::VectorShape;
+        let red = Color::new(255, 0, 0, 255);
+        let mut canvas = Canvas::new(256, 256);
+        let mut vector = VectorLayer::new();
+        vector.shapes.push(VectorShape::rectangle(glam::Vec2::new(4.0, 4.0), glam::Vec2::new(20.0, 20.0), red));
+        canvas.add_vector_layer(vector);
+        let layer = |canvas: &Canvas, x, y| canvas.layers.get_active_layer().unwrap().get_pixel(PixelPos { x, y });
+        assert_eq!(layer(&canvas, 10, 10), red);
+
+        // growing to the left and top moves the shape along with the canvas
+        canvas.set_size(320, 288, SideHorizontal::right, SideVertical::bottom);
+        canvas.update_display_canvas();
+        assert_eq!(canvas.get_size(), (320, 288));
+        assert_eq!(canvas.get_active_vector().unwrap().shapes[0].bounds().0, glam::Vec2::new(68.0, 36.0));
+        assert_eq!(layer(&canvas, 74, 42), red);
+        assert_eq!(layer(&canvas, 10, 10).alpha, 0);
+
+        canvas.rasterize_active_layer();
+        assert!(canvas.get_active_vector().is_none());
+        assert_eq!(layer(&canvas, 74, 42), red);
+    }
+
+    #[test]
+    fn test_vector_layer_undo() {
+        use crate::paint_app::vector_layer::VectorShape;
+        let mut canvas = Canvas::new(64, 64);
+        let below = canvas.layers.active_layer_id;
+        let id = canvas.add_vector_layer(VectorLayer::new());
+        let mut vector = VectorLayer::new();
+        vector.shapes.push(VectorShape::rectangle(glam::Vec2::new(4.0, 4.0), glam::Vec2::new(20.0, 20.0), Color::new(255, 0, 0, 255)));
+        canvas.set_active_vector(vector);
+
+        canvas.undo();
+        assert!(canvas.get_active_vector().unwrap().shapes.is_empty());
+        canvas.undo();
+        assert!(canvas.layers.entries.iter().all(|entry| entry.id != id));
+        assert_eq!(canvas.layers.active_layer_id, below);
+
+        canvas.redo();
+        canvas.redo();
+        assert_eq!(canvas.layers.active_layer_id, id);
+        assert_eq!(canvas.get_active_vector().unwrap().shapes.len(), 1);
+    }
+
+    #[test]
+    fn test_animation_frames() {
+        let mut canvas = Canvas::new(256, 256);
+        let (pos, red, blue) = (PixelPos { x: 0, y: 0 }, Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255));
+        let paint = |canvas: &mut Canvas, color| {
+            let mut command = EditCommand::default();
+            command.edits.push((pos, color));
+            canvas.apply_commands_handle_undo_redo(&vec![command]);
+        };
+        paint(&mut canvas, red);
+
+        // a new frame keeps the layers without their pixels, a duplicate keeps the pixels
+        canvas.add_frame(false);
+        assert_eq!((canvas.get_frame_count(), canvas.get_active_frame()), (2, 1));
+        assert_eq!(canvas.layers.entries.len(), 2);
+        assert_eq!(canvas.get_merged_layer().get_pixel(pos).alpha, 0);
+        paint(&mut canvas, blue);
+        canvas.add_frame(true);
+        assert_eq!(canvas.get_merged_layer().get_pixel(pos), blue);
+        canvas.set_frame_duration(2, 40);
+        assert_eq!(canvas.get_frame_durations(), vec![DEFAULT_FRAME_DURATION, DEFAULT_FRAME_DURATION, 40]);
+
+        // every frame has its own history
+        canvas.set_active_frame(0);
+        assert_eq!(canvas.get_frame_merged_layer(1).unwrap().get_pixel(pos), blue);
+        canvas.undo();
+        assert_eq!(canvas.get_merged_layer().get_pixel(pos).alpha, 0);
+        assert_eq!(canvas.get_frame_merged_layer(1).unwrap().get_pixel(pos), blue);
+
+        // the onion skin of the next frame shows below the layers
+        canvas.set_onion_skin(OnionSkin { enabled: true, ..OnionSkin::new() });
+        assert!(canvas.get_draw_layer().get_pixel(pos).blue > canvas.get_draw_layer().get_pixel(pos).red);
+
+        // resizing changes every frame
+        canvas.set_size(300, 200, SideHorizontal::left, SideVertical::top);
+        assert_eq!(canvas.get_frame_merged_layer(2).unwrap().get_size(), (300, 200));
+
+        canvas.move_frame(0, 2);
+        assert_eq!(canvas.get_active_frame(), 2);
+        canvas.delete_frame(2);
+        assert_eq!((canvas.get_frame_count(), canvas.get_active_frame()), (2, 1));
+        assert_eq!(canvas.get_frame_durations(), vec![DEFAULT_FRAME_DURATION, 40]);
+        assert_eq!(canvas.get_merged_layer().get_pixel(pos), blue);
+    }
+}
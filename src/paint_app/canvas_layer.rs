@@ -1,10 +1,28 @@
+use std::any::Any;
 use std::collections::HashMap;
+use crate::paint_app::fast_overlap::overlap_layers;
 use crate::paint_app::utils::pixel_overlap;
 use super::data_types::*;
 
 
 
 
+// where the old content ends up when resizing from old_size to new_size, negative when it's cut off
+pub fn anchor_offset(old_size: (u32, u32), new_size: (u32, u32), keep_horizontal: SideHorizontal, keep_vertical: SideVertical) -> (i64, i64) {
+    let grow = (new_size.0 as i64 - old_size.0 as i64, new_size.1 as i64 - old_size.1 as i64);
+    let x = match keep_horizontal {
+        SideHorizontal::left => 0,
+        SideHorizontal::center => grow.0 / 2,
+        SideHorizontal::right => grow.0
+    };
+    let y = match keep_vertical {
+        SideVertical::top => 0,
+        SideVertical::center => grow.1 / 2,
+        SideVertical::bottom => grow.1
+    };
+    (x, y)
+}
+
 pub trait CanvasLayer {
     fn get_pixel(&self, pixel_pos: PixelPos) -> Color;
     fn set_pixel(&mut self, pixel_pos: PixelPos, color: Color);
@@ -13,28 +31,61 @@ pub trait CanvasLayer {
     fn fill(&mut self, color: Color);
     fn get_size(&self) -> (u32, u32);
     fn set_size(&mut self, width: u32, height: u32, keep_horizontal: SideHorizontal, keep_vertical: SideVertical);
+
+    // full precision access, straight sRGB in 0..1
+    fn get_rgba(&self, pixel_pos: PixelPos) -> [f32; 4] {
+        self.get_pixel(pixel_pos).to_rgba()
+    }
+
+    fn set_rgba(&mut self, pixel_pos: PixelPos, rgba: [f32; 4]) {
+        self.set_pixel(pixel_pos, Color::from_rgba(rgba));
+    }
+
+    fn format(&self) -> PixelFormat {
+        PixelFormat::Rgba8
+    }
+
+    // lets flat 8 bit layers take the fast path in apply_to_canvas
+    fn as_flat_mut(&mut self) -> Option<&mut FlatCanvasLayer> {
+        None
+    }
 }
 
-pub struct FlatCanvasLayer {
+// Color pixels unless another format is given
+#[derive(Clone)]
+pub struct FlatCanvasLayer<P: Pixel = Color> {
     width: u32,
     height: u32,
-    data: Vec<Color>
+    data: Vec<P>
 }
-impl FlatCanvasLayer {
-    pub fn new(w: u32, h: u32) -> FlatCanvasLayer {
+impl<P: Pixel> FlatCanvasLayer<P> {
+    pub fn new(w: u32, h: u32) -> FlatCanvasLayer<P> {
+        FlatCanvasLayer {
+            width: w,
+            height: h,
+            data: vec!(P::from_color(Color::new(255, 255, 255, 0)); (w * h) as usize)
+        }
+    }
+
+    pub fn from_data(w: u32, h: u32, data: Vec<P>) -> FlatCanvasLayer<P> {
+        assert_eq!(data.len(), (w * h) as usize);
         FlatCanvasLayer {
             width: w,
             height: h,
-            data: vec!(Color::new(255, 255, 255, 0); (w * h) as usize)
+            data
         }
     }
 
-    pub fn get_data(&self) -> &Vec<Color> {
+    pub fn get_data(&self) -> &Vec<P> {
         &self.data
     }
 
+    pub fn get_data_mut(&mut self) -> &mut [P] {
+        &mut self.data
+    }
+
     // with &pixelpos and &color
-    pub fn iter_pixels(&self) -> impl Iterator<Item = (PixelPos, &Color)> {
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (PixelPos, &P)> {
         self.data.iter().enumerate().map(|(i, color)| {
             let x = i as u32 % self.width;
             let y = i as u32 / self.width;
@@ -43,7 +94,7 @@ impl FlatCanvasLayer {
     }
 
     // itermut
-    pub fn iter_pixels_mut(&mut self) -> impl Iterator<Item = (PixelPos, &mut Color)> {
+    pub fn iter_pixels_mut(&mut self) -> impl Iterator<Item = (PixelPos, &mut P)> {
         self.data.iter_mut().enumerate().map(|(i, color)| {
             let x = i as u32 % self.width;
             let y = i as u32 / self.width;
@@ -51,20 +102,66 @@ impl FlatCanvasLayer {
         })
     }
 
+    // nearest neighbour downscale, the longer side becomes max_side
+    pub fn thumbnail(&self, max_side: u32) -> FlatCanvasLayer {
+        let scale = (self.width.max(self.height) as f32 / max_side.max(1) as f32).max(1.0);
+        let width = ((self.width as f32 / scale) as u32).max(1);
+        let height = ((self.height as f32 / scale) as u32).max(1);
+        let mut result = FlatCanvasLayer::new(width, height);
+        result.iter_pixels_mut().for_each(|(pos, color)| {
+            let x = ((pos.x as f32 * scale) as u32).min(self.width - 1);
+            let y = ((pos.y as f32 * scale) as u32).min(self.height - 1);
+            *color = self.get_pixel(PixelPos { x, y });
+        });
+        result
+    }
+
+    // the same pixels in another format
+    pub fn convert<Q: Pixel>(&self) -> FlatCanvasLayer<Q> {
+        let data = match P::FORMAT {
+            // from_color is exact where the formats allow it
+            PixelFormat::Rgba8 => self.data.iter().map(|pixel| Q::from_color(pixel.to_color())).collect(),
+            _ => self.data.iter().map(|pixel| Q::from_rgba(pixel.to_rgba())).collect()
+        };
+        FlatCanvasLayer::from_data(self.width, self.height, data)
+    }
 }
 
-impl CanvasLayer for FlatCanvasLayer {
+impl<P: Pixel> CanvasLayer for FlatCanvasLayer<P> {
     fn get_pixel(&self, pixel_pos: PixelPos) -> Color {
-        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize]
+        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize].to_color()
     }
 
     fn set_pixel(&mut self, pixel_pos: PixelPos, color: Color) {
-        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize] = color;
+        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize] = P::from_color(color);
+    }
+
+    fn get_rgba(&self, pixel_pos: PixelPos) -> [f32; 4] {
+        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize].to_rgba()
+    }
+
+    fn set_rgba(&mut self, pixel_pos: PixelPos, rgba: [f32; 4]) {
+        self.data[(pixel_pos.x + pixel_pos.y * self.width) as usize] = P::from_rgba(rgba);
+    }
+
+    fn format(&self) -> PixelFormat {
+        P::FORMAT
+    }
+
+    fn as_flat_mut(&mut self) -> Option<&mut FlatCanvasLayer> {
+        (self as &mut dyn Any).downcast_mut::<FlatCanvasLayer>()
     }
 
     fn apply_to_canvas(&self, target_canvas: &mut dyn CanvasLayer) {
-        for x in 0..self.width {
-            for y in 0..self.height {
+        let source = (self as &dyn Any).downcast_ref::<FlatCanvasLayer>();
+        if let (Some(source), Some(target)) = (source, target_canvas.as_flat_mut()) {
+            if source.get_size() == target.get_size() {
+                overlap_layers(source, target);
+                return;
+            }
+        }
+        for y in 0..self.height {
+            for x in 0..self.width {
                 let result = pixel_overlap(
                     self.get_pixel(PixelPos{x, y}),
                     target_canvas.get_pixel(PixelPos{x, y})
@@ -79,8 +176,9 @@ impl CanvasLayer for FlatCanvasLayer {
     }
 
     fn fill(&mut self, color: Color) {
+        let pixel = P::from_color(color);
         for i in 0..self.width as usize * self.height as usize {
-            self.data[i] = color;
+            self.data[i] = pixel;
         }
     }
 
@@ -89,46 +187,134 @@ impl CanvasLayer for FlatCanvasLayer {
     }
 
     fn set_size(&mut self, width: u32, height: u32, keep_horizontal: SideHorizontal, keep_vertical: SideVertical) {
-        let mut new_data = vec!(Color::new(255, 255, 255, 0); (width * height) as usize);
-        let mut new_width = width;
-        let mut new_height = height;
-        let mut x_offset = 0;
-        let mut y_offset = 0;
-        match keep_horizontal {
-            SideHorizontal::center => {
-                x_offset = ((width - self.width) / 2) as usize;
-            },
-            SideHorizontal::left => {
-                x_offset = 0;
-            },
-            SideHorizontal::right => {
-                x_offset = (width - self.width) as usize;
-            }
-        }
-        match keep_vertical {
-            SideVertical::center => {
-                y_offset = ((height - self.height) / 2) as usize;
-            },
-            SideVertical::top => {
-                y_offset = 0;
-            },
-            SideVertical::bottom => {
-                y_offset = (height - self.height) as usize;
-            }
-        }
+        let mut new_data = vec!(P::from_color(Color::new(255, 255, 255, 0)); (width * height) as usize);
+        let (x_offset, y_offset) = anchor_offset((self.width, self.height), (width, height), keep_horizontal, keep_vertical);
         for x in 0..self.width {
             for y in 0..self.height {
-                let new_x = x + x_offset as u32;
-                let new_y = y + y_offset as u32;
-                if new_x < width && new_y < height {
-                    new_data[(new_x + new_y * width) as usize] = self.get_pixel(PixelPos{x, y});
+                let new_x = x as i64 + x_offset;
+                let new_y = y as i64 + y_offset;
+                if (0..width as i64).contains(&new_x) && (0..height as i64).contains(&new_y) {
+                    new_data[(new_x + new_y * width as i64) as usize] = self.data[(x + y * self.width) as usize];
                 }
             }
         }
         self.data = new_data;
-        self.width = new_width;
-        self.height = new_height;
-    
+        self.width = width;
+        self.height = height;
+    }
+}
+
+// the pixels of a document layer, in the format chosen for the document
+#[derive(Clone)]
+pub enum LayerBuffer {
+    Rgba8(FlatCanvasLayer<Color>),
+    Rgba16(FlatCanvasLayer<Color16>),
+    RgbaF32(FlatCanvasLayer<ColorF32>),
+}
+
+impl LayerBuffer {
+    pub fn new(w: u32, h: u32, format: PixelFormat) -> LayerBuffer {
+        match format {
+            PixelFormat::Rgba8 => LayerBuffer::Rgba8(FlatCanvasLayer::new(w, h)),
+            PixelFormat::Rgba16 => LayerBuffer::Rgba16(FlatCanvasLayer::new(w, h)),
+            PixelFormat::RgbaF32 => LayerBuffer::RgbaF32(FlatCanvasLayer::new(w, h)),
+        }
+    }
+
+    pub fn from_layer<P: Pixel>(layer: &FlatCanvasLayer<P>, format: PixelFormat) -> LayerBuffer {
+        match format {
+            PixelFormat::Rgba8 => LayerBuffer::Rgba8(layer.convert()),
+            PixelFormat::Rgba16 => LayerBuffer::Rgba16(layer.convert()),
+            PixelFormat::RgbaF32 => LayerBuffer::RgbaF32(layer.convert()),
+        }
+    }
+
+    // for everything that only works with Color, like export and the indexed mode
+    pub fn to_rgba8(&self) -> FlatCanvasLayer {
+        match self {
+            LayerBuffer::Rgba8(layer) => layer.clone(),
+            LayerBuffer::Rgba16(layer) => layer.convert(),
+            LayerBuffer::RgbaF32(layer) => layer.convert(),
+        }
+    }
+
+    // straight sRGB rgba of the pixel at index
+    pub fn rgba_at(&self, index: usize) -> [f32; 4] {
+        match self {
+            LayerBuffer::Rgba8(layer) => layer.get_data()[index].to_rgba(),
+            LayerBuffer::Rgba16(layer) => layer.get_data()[index].to_rgba(),
+            LayerBuffer::RgbaF32(layer) => layer.get_data()[index].to_rgba(),
+        }
+    }
+
+    pub fn thumbnail(&self, max_side: u32) -> FlatCanvasLayer {
+        match self {
+            LayerBuffer::Rgba8(layer) => layer.thumbnail(max_side),
+            LayerBuffer::Rgba16(layer) => layer.thumbnail(max_side),
+            LayerBuffer::RgbaF32(layer) => layer.thumbnail(max_side),
+        }
+    }
+
+    fn as_layer(&self) -> &dyn CanvasLayer {
+        match self {
+            LayerBuffer::Rgba8(layer) => layer,
+            LayerBuffer::Rgba16(layer) => layer,
+            LayerBuffer::RgbaF32(layer) => layer,
+        }
+    }
+
+    fn as_layer_mut(&mut self) -> &mut dyn CanvasLayer {
+        match self {
+            LayerBuffer::Rgba8(layer) => layer,
+            LayerBuffer::Rgba16(layer) => layer,
+            LayerBuffer::RgbaF32(layer) => layer,
+        }
+    }
+}
+
+impl CanvasLayer for LayerBuffer {
+    fn get_pixel(&self, pixel_pos: PixelPos) -> Color {
+        self.as_layer().get_pixel(pixel_pos)
+    }
+
+    fn set_pixel(&mut self, pixel_pos: PixelPos, color: Color) {
+        self.as_layer_mut().set_pixel(pixel_pos, color);
+    }
+
+    fn apply_to_canvas(&self, target_canvas: &mut dyn CanvasLayer) {
+        self.as_layer().apply_to_canvas(target_canvas);
+    }
+
+    fn clear(&mut self) {
+        self.as_layer_mut().clear();
+    }
+
+    fn fill(&mut self, color: Color) {
+        self.as_layer_mut().fill(color);
+    }
+
+    fn get_size(&self) -> (u32, u32) {
+        self.as_layer().get_size()
+    }
+
+    fn set_size(&mut self, width: u32, height: u32, keep_horizontal: SideHorizontal, keep_vertical: SideVertical) {
+        self.as_layer_mut().set_size(width, height, keep_horizontal, keep_vertical);
+    }
+
+    fn get_rgba(&self, pixel_pos: PixelPos) -> [f32; 4] {
+        self.as_layer().get_rgba(pixel_pos)
+    }
+
+    fn set_rgba(&mut self, pixel_pos: PixelPos, rgba: [f32; 4]) {
+        self.as_layer_mut().set_rgba(pixel_pos, rgba);
+    }
+
+    fn format(&self) -> PixelFormat {
+        self.as_layer().format()
+    }
+
+    fn as_flat_mut(&mut self) -> Option<&mut FlatCanvasLayer> {
+        self.as_layer_mut().as_flat_mut()
     }
 }
 
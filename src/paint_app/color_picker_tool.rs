@@ -0,0 +1,186 @@
+use egui::Key;
+use crate::paint_app::actions::Shortcut;
+use crate::paint_app::canvas::{CanvasLayers, EditCommand, PaintTool};
+use crate::paint_app::canvas_layer::{CanvasLayer, HashMapCanvasLayer};
+use crate::paint_app::tool_registry::{ToolCategory, ToolInfo, ToolOption, ToolOptionValue};
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleSource {
+    ActiveLayer,
+    Merged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleSize {
+    Point,
+    Average3x3,
+    Average5x5,
+}
+
+impl SampleSize {
+    pub fn radius(&self) -> u32 {
+        match self {
+            SampleSize::Point => 0,
+            SampleSize::Average3x3 => 1,
+            SampleSize::Average5x5 => 2,
+        }
+    }
+}
+
+pub struct ColorPickerTool {
+    pub sample_source: SampleSource,
+    pub sample_size: SampleSize,
+}
+
+impl ColorPickerTool {
+    pub fn new() -> ColorPickerTool {
+        ColorPickerTool {
+            sample_source: SampleSource::ActiveLayer,
+            sample_size: SampleSize::Point,
+        }
+    }
+
+    // averages the pixels around center, rgb is weighted by alpha so transparent pixels don't bleed in
+    pub fn sample(&self, layers: &CanvasLayers, center: PixelPos) -> Option<Color> {
+        let (width, height) = layers.get_size()?;
+        if center.x >= width || center.y >= height {
+            return None;
+        }
+        let active_layer = layers.get_active_layer();
+        if self.sample_source == SampleSource::ActiveLayer && active_layer.is_none() {
+            return None;
+        }
+
+        let radius = self.sample_size.radius();
+        let mut sum = [0u32; 3];
+        let mut alpha_sum = 0u32;
+        let mut count = 0u32;
+        for y in center.y.saturating_sub(radius)..(center.y + radius + 1).min(height) {
+            for x in center.x.saturating_sub(radius)..(center.x + radius + 1).min(width) {
+                let pos = PixelPos{x, y};
+                let color = match (self.sample_source, active_layer) {
+                    (SampleSource::ActiveLayer, Some(layer)) => layer.get_pixel(pos),
+                    _ => layers.get_merged_pixel(pos),
+                };
+                let alpha = color.alpha as u32;
+                sum[0] += color.red as u32 * alpha;
+                sum[1] += color.green as u32 * alpha;
+                sum[2] += color.blue as u32 * alpha;
+                alpha_sum += alpha;
+                count += 1;
+            }
+        }
+
+        if alpha_sum == 0 {
+            return Some(Color::new(0, 0, 0, 0));
+        }
+        Some(Color::new(
+            (sum[0] / alpha_sum) as u8,
+            (sum[1] / alpha_sum) as u8,
+            (sum[2] / alpha_sum) as u8,
+            (alpha_sum / count) as u8,
+        ))
+    }
+
+    fn pick(&self, global_params: &mut GlobalParams, layers: &CanvasLayers) {
+        let color = global_params.current_pixel.and_then(|pixel| self.sample(layers, pixel));
+        if let Some(color) = color {
+            if global_params.secondary_stroke {
+                global_params.secondary_color = color;
+            } else {
+                global_params.primary_color = color;
+            }
+        }
+    }
+}
+
+impl Default for ColorPickerTool {
+    fn default() -> ColorPickerTool {
+        ColorPickerTool::new()
+    }
+}
+
+impl PaintTool for ColorPickerTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "color_picker", name: "Color picker", icon: "💧", shortcut: Some(Shortcut::new(Key::I)), category: ToolCategory::Color }
+    }
+
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        self.pick(global_params, layers);
+    }
+
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        self.pick(global_params, layers);
+    }
+
+    fn stroke_end(&mut self, _global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    }
+
+    fn options(&self) -> Vec<ToolOption> {
+        let sources = [SampleSource::ActiveLayer, SampleSource::Merged];
+        let sizes = [SampleSize::Point, SampleSize::Average3x3, SampleSize::Average5x5];
+        vec![
+            ToolOption::choice("sample", "Sample", sources.iter().position(|source| *source == self.sample_source).unwrap_or(0), &["Active layer", "All layers"]),
+            ToolOption::choice("sample_size", "Sample size", sizes.iter().position(|size| *size == self.sample_size).unwrap_or(0), &["Point", "3 x 3 average", "5 x 5 average"]),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        match (id, value) {
+            ("sample", ToolOptionValue::Choice(i)) => self.sample_source = [SampleSource::ActiveLayer, SampleSource::Merged][i.min(1)],
+            ("sample_size", ToolOptionValue::Choice(i)) => self.sample_size = [SampleSize::Point, SampleSize::Average3x3, SampleSize::Average5x5][i.min(2)],
+            _ => {}
+        }
+    }
+
+    fn uses_symmetry(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas::Canvas;
+    use crate::paint_app::canvas_layer::FlatCanvasLayer;
+
+    #[test]
+    fn test_color_picker_sample() {
+        let mut canvas = Canvas::new(8, 8);
+        let mut bottom = FlatCanvasLayer::new(8, 8);
+        bottom.fill(Color::new(0, 0, 255, 255));
+        canvas.add_raster_layer(&bottom);
+        let mut top = FlatCanvasLayer::new(8, 8);
+        top.set_pixel(PixelPos { x: 3, y: 3 }, Color::new(255, 0, 0, 255));
+        top.set_pixel(PixelPos { x: 2, y: 3 }, Color::new(255, 255, 255, 85));
+        // fully transparent, its color must not bleed into the average
+        top.set_pixel(PixelPos { x: 4, y: 3 }, Color::new(0, 255, 0, 0));
+        top.set_pixel(PixelPos { x: 1, y: 1 }, Color::new(0, 0, 0, 255));
+        canvas.add_raster_layer(&top);
+        let layers = canvas.get_layers();
+        let picker = |sample_source, sample_size| ColorPickerTool { sample_source, sample_size };
+
+        let point = picker(SampleSource::ActiveLayer, SampleSize::Point);
+        assert_eq!(point.sample(layers, PixelPos { x: 3, y: 3 }), Some(Color::new(255, 0, 0, 255)));
+        assert_eq!(point.sample(layers, PixelPos { x: 4, y: 3 }), Some(Color::new(0, 0, 0, 0)));
+        assert_eq!(point.sample(layers, PixelPos { x: 8, y: 3 }), None);
+
+        // rgb is weighted by alpha, alpha is the plain average
+        let average = picker(SampleSource::ActiveLayer, SampleSize::Average3x3);
+        assert_eq!(average.sample(layers, PixelPos { x: 3, y: 3 }), Some(Color::new(255, 63, 63, 37)));
+        let average = picker(SampleSource::ActiveLayer, SampleSize::Average5x5);
+        assert_eq!(average.sample(layers, PixelPos { x: 3, y: 3 }), Some(Color::new(145, 36, 36, 23)));
+
+        let merged = picker(SampleSource::Merged, SampleSize::Point);
+        assert_eq!(merged.sample(layers, PixelPos { x: 4, y: 3 }), Some(Color::new(0, 0, 255, 255)));
+        assert_eq!(merged.sample(layers, PixelPos { x: 3, y: 3 }), Some(Color::new(255, 0, 0, 255)));
+        assert_eq!(layers.get_merged_pixel(PixelPos { x: 6, y: 6 }), Color::new(0, 0, 255, 255));
+
+        // at the edge only the pixels inside count, no transparent ones from outside
+        let edge = picker(SampleSource::Merged, SampleSize::Average5x5);
+        assert_eq!(edge.sample(layers, PixelPos { x: 7, y: 0 }), Some(Color::new(0, 0, 255, 255)));
+        let edge = picker(SampleSource::ActiveLayer, SampleSize::Average3x3);
+        assert_eq!(edge.sample(layers, PixelPos { x: 0, y: 0 }), Some(Color::new(0, 0, 0, 63)));
+    }
+}
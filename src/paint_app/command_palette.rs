@@ -0,0 +1,93 @@
+use eframe::egui;
+use egui::{Key, TextEdit};
+use crate::paint_app::actions::ActionRegistry;
+
+// how many matches are listed
+const MAX_RESULTS: usize = 12;
+
+pub struct CommandPalette {
+    pub open: bool,
+    pub query: String,
+    selected: usize,
+}
+
+impl CommandPalette {
+    pub fn new() -> CommandPalette {
+        CommandPalette {
+            open: false,
+            query: String::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    // returns the id of the action to run
+    pub fn show_command_palette(&mut self, ctx: &egui::Context, actions: &ActionRegistry) -> Option<String> {
+        let mut result = None;
+        let (up, down, enter, escape) = ctx.input_mut(|s| (
+            s.consume_key(egui::Modifiers::NONE, Key::ArrowUp),
+            s.consume_key(egui::Modifiers::NONE, Key::ArrowDown),
+            s.consume_key(egui::Modifiers::NONE, Key::Enter),
+            s.consume_key(egui::Modifiers::NONE, Key::Escape)
+        ));
+        let matches = actions.search(&self.query);
+        let shown = matches.len().min(MAX_RESULTS);
+        if down && shown > 0 {
+            self.selected = (self.selected + 1) % shown;
+        }
+        if up && shown > 0 {
+            self.selected = (self.selected + shown - 1) % shown;
+        }
+        self.selected = self.selected.min(shown.saturating_sub(1));
+
+        egui::Window::new("Command palette")
+            .title_bar(false)
+            .resizable(false)
+            .collapsible(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 60.0])
+            .fixed_size([360.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(TextEdit::singleline(&mut self.query).hint_text("Search actions").desired_width(f32::INFINITY));
+                response.request_focus();
+                if response.changed() {
+                    self.selected = 0;
+                }
+
+                ui.separator();
+
+                if matches.is_empty() {
+                    ui.weak("No matching actions");
+                }
+                for (i, action) in matches.iter().take(MAX_RESULTS).enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.selectable_label(i == self.selected, &action.name).clicked() {
+                            result = Some(action.id.clone());
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            if let Some(text) = actions.shortcut_text(ctx, &action.id) {
+                                ui.weak(text);
+                            }
+                        });
+                    });
+                }
+                if enter {
+                    result = matches.get(self.selected).map(|action| action.id.clone());
+                }
+            });
+        if result.is_some() || escape || (enter && matches.is_empty()) {
+            self.open = false;
+        }
+        result
+    }
+}
+
+impl Default for CommandPalette {
+    fn default() -> CommandPalette {
+        CommandPalette::new()
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use crate::paint_app::canvas::{CanvasLayerEntry, GroupBlend, LayerContent, LayerId};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer, LayerBuffer};
+use crate::paint_app::utils::{over, premultiply, unpremultiply, BlendSpace};
+use super::data_types::*;
+
+// premultiplied rgba in the blend space, colors stay like this until the stack is done
+type Rgba = [f32; 4];
+
+const TRANSPARENT: Rgba = [0.0; 4];
+
+// the layer stack prepared for compositing, a row of pixels at a time
+pub struct CompositeStack<'a> {
+    entries: &'a [CanvasLayerEntry],
+    // the pixels of each entry, one of them may be replaced by a preview
+    layers: Vec<&'a LayerBuffer>,
+    // lookup tables of the adjustment layers, computed once
+    luts: Vec<Option<[[u8; 256]; 3]>>,
+    // the indices of the entries in each group from top to bottom, by the index of the group.
+    // the top level comes after the entries
+    children: Vec<Vec<usize>>,
+    space: BlendSpace,
+}
+
+impl<'a> CompositeStack<'a> {
+    // entries go from top to bottom, replace swaps the pixels of one layer. layers whose group
+    // isn't in entries are treated as top level ones
+    pub fn new(entries: &'a [CanvasLayerEntry], replace: Option<(LayerId, &'a LayerBuffer)>, space: BlendSpace) -> CompositeStack<'a> {
+        let indices = entries.iter().enumerate().map(|(index, entry)| (entry.id, index)).collect::<HashMap<_, _>>();
+        let mut children = vec![Vec::new(); entries.len() + 1];
+        entries.iter().enumerate().for_each(|(index, entry)| {
+            let parent = entry.parent.and_then(|parent| indices.get(&parent).copied()).unwrap_or(entries.len());
+            children[parent].push(index);
+        });
+        CompositeStack {
+            entries,
+            layers: entries.iter().map(|entry| match replace {
+                Some((id, layer)) if id == entry.id => layer,
+                _ => &entry.layer
+            }).collect(),
+            luts: entries.iter().map(|entry| match &entry.content {
+                LayerContent::Adjustment(adjustment_layer) => adjustment_layer.adjustment.lookup_tables(),
+                _ => None
+            }).collect(),
+            children,
+            space,
+        }
+    }
+
+    // the visible entries composited over bottom at the pixel at index
+    pub fn composite(&self, index: usize, bottom: Color) -> Color {
+        Color::from_rgba(self.composite_rgba(index, bottom.to_rgba()))
+    }
+
+    // the same with straight rgba, without losing precision
+    pub fn composite_rgba(&self, index: usize, bottom: [f32; 4]) -> [f32; 4] {
+        let mut row = [premultiply(bottom, self.space)];
+        self.composite_group(self.entries.len(), index, &mut row);
+        unpremultiply(row[0], self.space)
+    }
+
+    // the pixels from start on, as many as the target has, composited over it
+    pub fn composite_row<P: Pixel>(&self, start: usize, target: &mut [P]) {
+        let mut row = target.iter().map(|pixel| premultiply(pixel.to_rgba(), self.space)).collect::<Vec<_>>();
+        self.composite_group(self.entries.len(), start, &mut row);
+        target.iter_mut().zip(row).for_each(|(pixel, color)| *pixel = P::from_rgba(unpremultiply(color, self.space)));
+    }
+
+    // how much of each pixel of the row the entry covers, 0..1
+    fn coverage(&self, entry_index: usize, start: usize, coverage: &mut [f32]) {
+        match self.entries[entry_index].content {
+            LayerContent::Adjustment(_) => coverage.fill(1.0),
+            LayerContent::Group(_) => {
+                let mut group = vec![TRANSPARENT; coverage.len()];
+                self.composite_group(entry_index, start, &mut group);
+                coverage.iter_mut().zip(group).for_each(|(coverage, color)| *coverage = color[3]);
+            }
+            _ => match self.layers[entry_index] {
+                LayerBuffer::Rgba8(layer) => alpha_row(&layer.get_data()[start..], coverage),
+                LayerBuffer::Rgba16(layer) => alpha_row(&layer.get_data()[start..], coverage),
+                LayerBuffer::RgbaF32(layer) => alpha_row(&layer.get_data()[start..], coverage),
+            }
+        }
+        self.apply_mask(entry_index, start, coverage);
+    }
+
+    fn apply_mask(&self, entry_index: usize, start: usize, coverage: &mut [f32]) {
+        if let Some(mask) = self.entries[entry_index].mask.as_ref().filter(|mask| mask.enabled) {
+            coverage.iter_mut()
+                .zip(&mask.layer.get_data()[start..])
+                .for_each(|(coverage, value)| *coverage *= value.red as f32 / 255.0);
+        }
+    }
+
+    fn composite_group(&self, group: usize, start: usize, row: &mut [Rgba]) {
+        let children = &self.children[group];
+        // coverage of the layer the clipped layers above it are clipped to, None when it is hidden
+        let mut clip_base: Option<Vec<f32>> = None;
+        let mut coverage = vec![0.0; row.len()];
+        for (position, entry_index) in children.iter().copied().enumerate().rev() {
+            let entry = &self.entries[entry_index];
+            // the bottom layer has nothing to clip to
+            let clipped = entry.clipped && position + 1 < children.len();
+            if !clipped {
+                // only needed when the layer above is clipped to this one
+                let clipped_above = position > 0 && self.entries[children[position - 1]].clipped;
+                clip_base = (entry.visible && clipped_above).then(|| {
+                    let mut base = vec![0.0; row.len()];
+                    self.coverage(entry_index, start, &mut base);
+                    base
+                });
+            }
+            if !entry.visible {
+                continue;
+            }
+            coverage.fill(1.0);
+            self.apply_mask(entry_index, start, &mut coverage);
+            match (clipped, &clip_base) {
+                (false, _) => {}
+                (true, Some(base)) => coverage.iter_mut().zip(base).for_each(|(coverage, base)| *coverage *= base),
+                (true, None) => continue
+            }
+            match &entry.content {
+                LayerContent::Adjustment(adjustment_layer) => {
+                    row.iter_mut().zip(&coverage).for_each(|(color, coverage)| {
+                        // adjustments are defined on straight sRGB values
+                        let straight = unpremultiply(*color, self.space);
+                        let adjusted = adjustment_layer.apply_to_rgba(straight, self.luts[entry_index].as_ref());
+                        *color = mix(premultiply(adjusted, self.space), *color, *coverage);
+                    });
+                }
+                LayerContent::Group(layer_group) => {
+                    let opacity = layer_group.opacity.clamp(0.0, 1.0);
+                    match layer_group.blend {
+                        GroupBlend::PassThrough => {
+                            let mut passed = row.to_vec();
+                            self.composite_group(entry_index, start, &mut passed);
+                            row.iter_mut().zip(passed).zip(&coverage)
+                                .for_each(|((color, passed), coverage)| *color = mix(passed, *color, coverage * opacity));
+                        }
+                        GroupBlend::Isolated => {
+                            let mut isolated = vec![TRANSPARENT; row.len()];
+                            self.composite_group(entry_index, start, &mut isolated);
+                            row.iter_mut().zip(isolated).zip(&coverage)
+                                .for_each(|((color, isolated), coverage)| *color = over(isolated.map(|value| value * coverage * opacity), *color));
+                        }
+                    }
+                }
+                _ => match self.layers[entry_index] {
+                    LayerBuffer::Rgba8(layer) => over_row(&layer.get_data()[start..], &coverage, self.space, row),
+                    LayerBuffer::Rgba16(layer) => over_row(&layer.get_data()[start..], &coverage, self.space, row),
+                    LayerBuffer::RgbaF32(layer) => over_row(&layer.get_data()[start..], &coverage, self.space, row),
+                }
+            }
+        }
+    }
+}
+
+fn alpha_row<P: Pixel>(pixels: &[P], coverage: &mut [f32]) {
+    coverage.iter_mut().zip(pixels).for_each(|(coverage, pixel)| *coverage = pixel.to_rgba()[3]);
+}
+
+// the pixels over the row, fully transparent ones are skipped
+fn over_row<P: Pixel>(pixels: &[P], coverage: &[f32], space: BlendSpace, row: &mut [Rgba]) {
+    for ((color, pixel), coverage) in row.iter_mut().zip(pixels).zip(coverage) {
+        let rgba = pixel.to_rgba();
+        if rgba[3] > 0.0 && *coverage > 0.0 {
+            *color = over(premultiply(rgba, space).map(|value| value * coverage), *color);
+        }
+    }
+}
+
+// a where t is 1, b where it is 0
+fn mix(a: Rgba, b: Rgba, t: f32) -> Rgba {
+    [0, 1, 2, 3].map(|i| a[i] * t + b[i] * (1.0 - t))
+}
+
+// composites the visible entries (first one is on top) over target, honouring adjustment layers,
+// masks, clipping and groups, replace swaps the pixels of one layer. rows run on all cores on native
+pub fn composite_stack<P: Pixel>(entries: &[CanvasLayerEntry], replace: Option<(LayerId, &LayerBuffer)>, space: BlendSpace, target: &mut FlatCanvasLayer<P>) {
+    let stack = CompositeStack::new(entries, replace, space);
+    let width = (target.get_size().0 as usize).max(1);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use rayon::prelude::*;
+        target.get_data_mut().par_chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| stack.composite_row(y * width, row));
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        target.get_data_mut().chunks_mut(width)
+            .enumerate()
+            .for_each(|(y, row)| stack.composite_row(y * width, row));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::adjustments::{Adjustment, AdjustmentLayer};
+    use crate::paint_app::canvas::{LayerGroup, LayerLocks, LayerMask};
+
+    fn entry(id: usize, color: Color) -> CanvasLayerEntry {
+        let mut layer = FlatCanvasLayer::new(2, 1);
+        layer.fill(color);
+        CanvasLayerEntry {
+            id: LayerId(id),
+            name: String::new(),
+            layer: LayerBuffer::Rgba8(layer),
+            content: LayerContent::Raster,
+            visible: true,
+            mask: None,
+            clipped: false,
+            parent: None,
+            locks: LayerLocks::default(),
+            revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_mask_and_clipping() {
+        let red = Color::new(255, 0, 0, 255);
+        let blue = Color::new(0, 0, 255, 255);
+        let transparent = Color::new(0, 0, 0, 0);
+
+        // the base only covers the left pixel
+        let mut base = entry(0, red);
+        base.layer.set_pixel(PixelPos { x: 1, y: 0 }, transparent);
+        let mut clipped = entry(1, blue);
+        clipped.clipped = true;
+        let mut entries = vec![clipped, base];
+
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert_eq!(result.get_pixel(PixelPos { x: 0, y: 0 }), blue);
+        assert_eq!(result.get_pixel(PixelPos { x: 1, y: 0 }).alpha, 0);
+
+        // a black mask hides the left pixel of the clipped layer
+        let mut mask = LayerMask::new(2, 1);
+        mask.layer.set_pixel(PixelPos { x: 0, y: 0 }, Color::black());
+        entries[0].mask = Some(mask);
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert_eq!(result.get_pixel(PixelPos { x: 0, y: 0 }), red);
+
+        // clipped layers are hidden with their base
+        entries[1].visible = false;
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert!(result.get_data().iter().all(|color| color.alpha == 0));
+    }
+
+    #[test]
+    fn test_group_blending() {
+        let gray = Color::new(100, 100, 100, 255);
+        let mut group = entry(0, Color::new(0, 0, 0, 0));
+        group.content = LayerContent::Group(LayerGroup::new());
+        let mut invert = entry(1, Color::new(0, 0, 0, 0));
+        invert.content = LayerContent::Adjustment(AdjustmentLayer::new(Adjustment::Invert));
+        invert.parent = Some(LayerId(0));
+        let mut entries = vec![group, invert, entry(2, gray)];
+
+        // pass through lets the adjustment in the group change the layer below the group
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert_eq!(result.get_pixel(PixelPos { x: 0, y: 0 }), Color::new(155, 155, 155, 255));
+
+        // isolated groups only adjust their own children
+        if let LayerContent::Group(layer_group) = &mut entries[0].content {
+            layer_group.blend = GroupBlend::Isolated;
+        }
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert_eq!(result.get_pixel(PixelPos { x: 0, y: 0 }), gray);
+
+        // hiding the group hides its children
+        if let LayerContent::Group(layer_group) = &mut entries[0].content {
+            layer_group.blend = GroupBlend::PassThrough;
+        }
+        entries[0].visible = false;
+        let mut result = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut result);
+        assert_eq!(result.get_pixel(PixelPos { x: 0, y: 0 }), gray);
+    }
+
+    #[test]
+    fn test_formats_and_blend_space() {
+        let half_red = Color::new(255, 0, 0, 128);
+        let green = Color::new(0, 255, 0, 255);
+        let entries = vec![entry(0, half_red), entry(1, green)];
+        let mut srgb = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Srgb, &mut srgb);
+        let mut linear = FlatCanvasLayer::<Color>::new(2, 1);
+        composite_stack(&entries, None, BlendSpace::Linear, &mut linear);
+        // linear light doesn't darken the mix of the two colors
+        assert_eq!(srgb.get_pixel(PixelPos { x: 0, y: 0 }), Color::new(128, 127, 0, 255));
+        assert_eq!(linear.get_pixel(PixelPos { x: 0, y: 0 }), Color::new(188, 187, 0, 255));
+
+        // many faint layers add up without being rounded to 8 bits in between
+        let faint = |format: PixelFormat| (0..32).map(|id| {
+            let mut faint = entry(id, Color::new(0, 0, 0, 0));
+            faint.layer = LayerBuffer::new(2, 1, format);
+            faint.layer.set_rgba(PixelPos { x: 0, y: 0 }, [1.0, 1.0, 1.0, 0.01]);
+            faint.layer.set_rgba(PixelPos { x: 1, y: 0 }, [1.0, 1.0, 1.0, 0.01]);
+            faint
+        }).collect::<Vec<_>>();
+        let exact = 1.0 - 0.99f32.powi(32);
+        for (format, tolerance) in [(PixelFormat::Rgba16, 1e-3), (PixelFormat::RgbaF32, 1e-5)] {
+            let mut result = FlatCanvasLayer::<ColorF32>::new(2, 1);
+            result.fill(Color::new(0, 0, 0, 0));
+            composite_stack(&faint(format), None, BlendSpace::Srgb, &mut result);
+            assert!((result.get_rgba(PixelPos { x: 0, y: 0 })[3] - exact).abs() < tolerance);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+// settings files in the user's config directory, "paintdesk/<name>"
+
+#[cfg(not(target_arch = "wasm32"))]
+fn config_path(name: &str) -> Option<std::path::PathBuf> {
+    let env_path = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(std::path::PathBuf::from);
+    let dir = env_path("APPDATA")
+        .or_else(|| env_path("XDG_CONFIG_HOME"))
+        .or_else(|| env_path("HOME").map(|home| home.join(".config")))?;
+    Some(dir.join("paintdesk").join(name))
+}
+
+// None when the file hasn't been written yet
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_config(name: &str) -> Result<Option<String>, String> {
+    let Some(path) = config_path(name) else { return Ok(None) };
+    match std::fs::read_to_string(&path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("{}: {}", path.display(), err))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_config(name: &str, text: &str) -> Result<(), String> {
+    let path = config_path(name).ok_or("no config directory")?;
+    let write = || {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, text)
+    };
+    write().map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+// the web has no config files, settings are kept until the page is closed
+#[cfg(target_arch = "wasm32")]
+pub fn read_config(_name: &str) -> Result<Option<String>, String> {
+    Ok(None)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_config(_name: &str, _text: &str) -> Result<(), String> {
+    Ok(())
+}
@@ -1,18 +1,22 @@
-#[derive(PartialEq)]
+use crate::paint_app::symmetry::Symmetry;
+
+#[derive(PartialEq, Clone, Copy)]
 pub enum SideHorizontal {
     center,
     left,
     right
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy)]
 pub enum SideVertical {
     center,
     top,
     bottom
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+// laid out as four bytes so rows of pixels can be read as words, see fast_overlap
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
 pub struct Color {
     pub red: u8,
     pub green: u8,
@@ -72,6 +76,135 @@ impl Color {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color16 {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16
+}
+
+impl Color16 {
+    pub fn new(r: u16, g: u16, b: u16, a: u16) -> Color16 {
+        Color16 {
+            red: r,
+            green: g,
+            blue: b,
+            alpha: a
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorF32 {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32
+}
+
+impl ColorF32 {
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> ColorF32 {
+        ColorF32 {
+            red: r,
+            green: g,
+            blue: b,
+            alpha: a
+        }
+    }
+}
+
+// how the pixels of a document are stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PixelFormat {
+    Rgba8,
+    Rgba16,
+    RgbaF32,
+}
+
+impl PixelFormat {
+    pub fn name(&self) -> &str {
+        match self {
+            PixelFormat::Rgba8 => "8 bit",
+            PixelFormat::Rgba16 => "16 bit",
+            PixelFormat::RgbaF32 => "32 bit float",
+        }
+    }
+
+    pub fn all() -> [PixelFormat; 3] {
+        [PixelFormat::Rgba8, PixelFormat::Rgba16, PixelFormat::RgbaF32]
+    }
+}
+
+// a stored pixel, rgba is straight (not premultiplied) sRGB in 0..1.
+// Color is the 8 bit one, tools and edit commands work with it
+pub trait Pixel: Copy + PartialEq + Send + Sync + std::fmt::Debug + 'static {
+    const FORMAT: PixelFormat;
+
+    fn from_rgba(rgba: [f32; 4]) -> Self;
+    fn to_rgba(&self) -> [f32; 4];
+
+    fn from_color(color: Color) -> Self {
+        Self::from_rgba(color.to_rgba())
+    }
+
+    fn to_color(&self) -> Color {
+        Color::from_rgba(self.to_rgba())
+    }
+}
+
+impl Pixel for Color {
+    const FORMAT: PixelFormat = PixelFormat::Rgba8;
+
+    fn from_rgba(rgba: [f32; 4]) -> Color {
+        let to_u8 = |value: f32| (value * 255.0 + 0.5).clamp(0.0, 255.0) as u8;
+        Color::new(to_u8(rgba[0]), to_u8(rgba[1]), to_u8(rgba[2]), to_u8(rgba[3]))
+    }
+
+    fn to_rgba(&self) -> [f32; 4] {
+        [self.red, self.green, self.blue, self.alpha].map(|value| value as f32 / 255.0)
+    }
+
+    fn from_color(color: Color) -> Color {
+        color
+    }
+
+    fn to_color(&self) -> Color {
+        *self
+    }
+}
+
+impl Pixel for Color16 {
+    const FORMAT: PixelFormat = PixelFormat::Rgba16;
+
+    fn from_rgba(rgba: [f32; 4]) -> Color16 {
+        let to_u16 = |value: f32| (value * 65535.0 + 0.5).clamp(0.0, 65535.0) as u16;
+        Color16::new(to_u16(rgba[0]), to_u16(rgba[1]), to_u16(rgba[2]), to_u16(rgba[3]))
+    }
+
+    fn to_rgba(&self) -> [f32; 4] {
+        [self.red, self.green, self.blue, self.alpha].map(|value| value as f32 / 65535.0)
+    }
+
+    // 257 maps 255 to 65535, so 8 bit colors survive the round trip
+    fn from_color(color: Color) -> Color16 {
+        Color16::new(color.red as u16 * 257, color.green as u16 * 257, color.blue as u16 * 257, color.alpha as u16 * 257)
+    }
+}
+
+// values outside of 0..1 are kept until the pixel is turned into a Color
+impl Pixel for ColorF32 {
+    const FORMAT: PixelFormat = PixelFormat::RgbaF32;
+
+    fn from_rgba(rgba: [f32; 4]) -> ColorF32 {
+        ColorF32::new(rgba[0], rgba[1], rgba[2], rgba[3])
+    }
+
+    fn to_rgba(&self) -> [f32; 4] {
+        [self.red, self.green, self.blue, self.alpha]
+    }
+}
+
 #[derive(Default, Debug, Clone, Copy, Eq, Hash, PartialEq)]
 pub struct PixelPos {
     pub x: u32,
@@ -82,7 +215,10 @@ pub struct GlobalParams {
     pub primary_color: Color,
     pub secondary_color: Color,
     pub cursor_in_canvas: bool,
-    pub current_pixel: Option<PixelPos>
+    pub current_pixel: Option<PixelPos>,
+    // the current stroke was started with the right mouse button (or alt with the color picker)
+    pub secondary_stroke: bool,
+    pub symmetry: Symmetry
 }
 
 impl GlobalParams {
@@ -91,7 +227,26 @@ impl GlobalParams {
             primary_color: Color::new(0, 0, 0, 255),
             secondary_color: Color::new(255, 255, 255, 255),
             cursor_in_canvas: false,
-            current_pixel: None
+            current_pixel: None,
+            secondary_stroke: false,
+            symmetry: Symmetry::new()
         }
     }
+
+    pub fn swap_colors(&mut self) {
+        std::mem::swap(&mut self.primary_color, &mut self.secondary_color);
+    }
+
+    pub fn stroke_color(&self) -> Color {
+        match self.secondary_stroke {
+            true => self.secondary_color,
+            false => self.primary_color
+        }
+    }
+}
+
+impl Default for GlobalParams {
+    fn default() -> GlobalParams {
+        GlobalParams::new()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,481 @@
+use std::fmt::Write;
+use crate::paint_app::canvas::{CanvasLayerEntry, CanvasLayers, LayerContent, LayerId};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::compositor::composite_stack;
+use crate::paint_app::image_io::encode_png;
+use crate::paint_app::utils::BlendSpace;
+use crate::paint_app::vector_layer::{ShapeKind, VectorShape};
+use super::data_types::*;
+
+// svg units are css pixels, which are 3/4 of a pdf point
+const POINTS_PER_PIXEL: f32 = 0.75;
+
+// a layer the way svg and pdf both describe it, so the two files look the same
+pub struct ExportNode {
+    pub id: LayerId,
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    // grayscale, kept apart so the content stays what it is
+    pub mask: Option<FlatCanvasLayer>,
+    pub content: ExportContent,
+}
+
+pub enum ExportContent {
+    // bottom first
+    Group(Vec<ExportNode>),
+    Image(FlatCanvasLayer),
+    Shapes(Vec<VectorShape>),
+}
+
+// the layer tree bottom first. clipped layers become images with the clipping applied and
+// adjustment layers are left out, neither format has anything like them
+pub fn export_nodes(layers: &CanvasLayers) -> Vec<ExportNode> {
+    group_nodes(&layers.entries, None, layers.blend_space)
+}
+
+fn group_nodes(entries: &[CanvasLayerEntry], group: Option<LayerId>, space: BlendSpace) -> Vec<ExportNode> {
+    let children = entries.iter().enumerate().filter(|(_, entry)| entry.parent == group).map(|(index, _)| index).collect::<Vec<_>>();
+    let mut result = Vec::new();
+    // the alpha clipped layers are cut to, None when the base is hidden
+    let mut clip_base = None;
+    for (position, index) in children.iter().copied().enumerate().rev() {
+        let entry = &entries[index];
+        let clipped = entry.clipped && position + 1 < children.len();
+        if !clipped {
+            clip_base = entry.visible.then(|| coverage(entries, index, space));
+        }
+        let mask = entry.mask.as_ref().filter(|mask| mask.enabled).map(|mask| mask.layer.clone());
+        let (opacity, content) = match &entry.content {
+            LayerContent::Adjustment(_) => continue,
+            LayerContent::Group(layer_group) if !clipped => (layer_group.opacity.clamp(0.0, 1.0), ExportContent::Group(group_nodes(entries, Some(entry.id), space))),
+            LayerContent::Vector { vector, .. } if !clipped => (1.0, ExportContent::Shapes(vector.shapes.clone())),
+            content => {
+                let mut image = rendered(entries, index, space);
+                if let Some(base) = clip_base.as_ref().filter(|_| clipped) {
+                    for (color, alpha) in image.get_data_mut().iter_mut().zip(base.iter()) {
+                        color.alpha = (color.alpha as f32 * alpha).round() as u8;
+                    }
+                }
+                let opacity = match content {
+                    LayerContent::Group(layer_group) => layer_group.opacity.clamp(0.0, 1.0),
+                    _ => 1.0
+                };
+                (opacity, ExportContent::Image(image))
+            }
+        };
+        result.push(ExportNode { id: entry.id, name: entry.name.clone(), visible: entry.visible && (!clipped || clip_base.is_some()), opacity, mask, content });
+    }
+    result
+}
+
+// the pixels of the entry, groups get their children composited
+fn rendered(entries: &[CanvasLayerEntry], index: usize, space: BlendSpace) -> FlatCanvasLayer {
+    let entry = &entries[index];
+    if !matches!(entry.content, LayerContent::Group(_)) {
+        return entry.layer.to_rgba8();
+    }
+    // groups come right before their children, so they are a run of entries. the children's
+    // parent isn't in the run, which makes them top level
+    let end = (index + 1..entries.len()).find(|i| !inside(entries, *i, entry.id)).unwrap_or(entries.len());
+    let (width, height) = entry.layer.get_size();
+    let mut result = FlatCanvasLayer::new(width, height);
+    composite_stack(&entries[index + 1..end], None, space, &mut result);
+    result
+}
+
+fn inside(entries: &[CanvasLayerEntry], index: usize, group: LayerId) -> bool {
+    let mut parent = entries[index].parent;
+    while let Some(id) = parent {
+        if id == group {
+            return true;
+        }
+        parent = entries.iter().find(|entry| entry.id == id).and_then(|entry| entry.parent);
+    }
+    false
+}
+
+// 0..1 per pixel, like the compositor's clip base
+fn coverage(entries: &[CanvasLayerEntry], index: usize, space: BlendSpace) -> Vec<f32> {
+    let entry = &entries[index];
+    let (width, height) = entry.layer.get_size();
+    let pixels = (width * height) as usize;
+    (0..pixels).map(|i| entry.mask_value(i) as f32 / 255.0).zip(match entry.content {
+        LayerContent::Adjustment(_) => vec![1.0; pixels],
+        _ => rendered(entries, index, space).get_data().iter().map(|color| color.alpha as f32 / 255.0).collect()
+    }).map(|(mask, alpha)| mask * alpha).collect()
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk.iter().enumerate().fold(0u32, |value, (i, byte)| value | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            result.push(match i <= chunk.len() {
+                true => BASE64[(value >> (18 - 6 * i) & 63) as usize] as char,
+                false => '='
+            });
+        }
+    }
+    result
+}
+
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// the attribute for fill or stroke with its opacity
+fn svg_paint(attribute: &str, color: Option<Color>) -> String {
+    match color {
+        Some(color) => format!("{}=\"#{:02x}{:02x}{:02x}\"{}", attribute, color.red, color.green, color.blue, match color.alpha {
+            255 => String::new(),
+            alpha => format!(" {}-opacity=\"{}\"", attribute, alpha as f32 / 255.0)
+        }),
+        None => format!("{}=\"none\"", attribute)
+    }
+}
+
+fn svg_image(image: &FlatCanvasLayer) -> Result<String, String> {
+    let (width, height) = image.get_size();
+    Ok(format!("<image width=\"{}\" height=\"{}\" href=\"data:image/png;base64,{}\"/>", width, height, base64(&encode_png(image)?)))
+}
+
+fn write_svg_node(out: &mut String, node: &ExportNode, indent: usize) -> Result<(), String> {
+    let pad = "  ".repeat(indent);
+    let id = format!("layer-{}", node.id.0);
+    if let Some(mask) = &node.mask {
+        let (width, height) = mask.get_size();
+        writeln!(out, "{}<mask id=\"{}-mask\" maskUnits=\"userSpaceOnUse\" x=\"0\" y=\"0\" width=\"{}\" height=\"{}\">{}</mask>", pad, id, width, height, svg_image(mask)?).unwrap();
+    }
+    write!(out, "{}<g id=\"{}\" data-name=\"{}\"", pad, id, escape_xml(&node.name)).unwrap();
+    if !node.visible {
+        out.push_str(" display=\"none\"");
+    }
+    if node.opacity < 1.0 {
+        write!(out, " opacity=\"{}\"", node.opacity).unwrap();
+    }
+    if node.mask.is_some() {
+        write!(out, " mask=\"url(#{}-mask)\"", id).unwrap();
+    }
+    out.push_str(">\n");
+    match &node.content {
+        ExportContent::Group(children) => {
+            for child in children {
+                write_svg_node(out, child, indent + 1)?;
+            }
+        }
+        ExportContent::Image(image) => writeln!(out, "{}  {}", pad, svg_image(image)?).unwrap(),
+        ExportContent::Shapes(shapes) => {
+            // empty paths have nothing to draw
+            for shape in shapes.iter().filter(|shape| !shape.outline().nodes.is_empty()) {
+                let element = match &shape.kind {
+                    ShapeKind::Rectangle { min, max, corner_radius } => {
+                        let (min, max) = (min.min(*max), min.max(*max));
+                        format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"", min.x, min.y, max.x - min.x, max.y - min.y, corner_radius.max(0.0))
+                    }
+                    ShapeKind::Ellipse { min, max } => {
+                        let (center, radius) = ((*min + *max) / 2.0, (*max - *min).abs() / 2.0);
+                        format!("<ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\"", center.x, center.y, radius.x, radius.y)
+                    }
+                    ShapeKind::Path(path) => format!("<path d=\"{}\" stroke-linecap=\"round\"", path.to_svg_data())
+                };
+                // the same joins the layer is drawn with
+                writeln!(out, "{}  {} {} {} stroke-width=\"{}\" stroke-linejoin=\"miter\"/>",
+                    pad, element, svg_paint("fill", shape.fill), svg_paint("stroke", shape.stroke.filter(|_| shape.stroke_width > 0.0)), shape.stroke_width).unwrap();
+            }
+        }
+    }
+    writeln!(out, "{}</g>", pad).unwrap();
+    Ok(())
+}
+
+// vector layers become svg shapes and everything else embedded png images
+pub fn encode_svg(nodes: &[ExportNode], size: (u32, u32)) -> Result<String, String> {
+    let mut result = String::new();
+    writeln!(result, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(result, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" viewBox=\"0 0 {0} {1}\">", size.0, size.1).unwrap();
+    for node in nodes {
+        write_svg_node(&mut result, node, 1)?;
+    }
+    result.push_str("</svg>\n");
+    Ok(result)
+}
+
+// the objects of a pdf file, numbered from 1 in the order they are added
+struct PdfWriter {
+    objects: Vec<Vec<u8>>,
+    // names and object numbers of the shared resources
+    xobjects: Vec<(String, usize)>,
+    states: Vec<(String, String)>,
+    // optional content groups, which pdf viewers show as layers
+    layers: Vec<(String, usize, bool)>,
+    size: (u32, u32),
+}
+
+// utf-16 with a byte order mark, which takes any name
+fn pdf_string(text: &str) -> String {
+    let units = text.encode_utf16().map(|unit| format!("{:04X}", unit)).collect::<String>();
+    format!("<FEFF{}>", units)
+}
+
+// the catalog, the page tree, the page and the resources have fixed numbers
+const CATALOG: usize = 1;
+const PAGES: usize = 2;
+const PAGE: usize = 3;
+const RESOURCES: usize = 4;
+
+impl PdfWriter {
+    fn new(size: (u32, u32)) -> PdfWriter {
+        PdfWriter {
+            objects: vec![Vec::new(); RESOURCES],
+            xobjects: Vec::new(),
+            states: Vec::new(),
+            layers: Vec::new(),
+            size,
+        }
+    }
+
+    fn add(&mut self, object: Vec<u8>) -> usize {
+        self.objects.push(object);
+        self.objects.len()
+    }
+
+    fn set(&mut self, number: usize, object: String) {
+        self.objects[number - 1] = object.into_bytes();
+    }
+
+    fn stream(dictionary: &str, data: &[u8]) -> Vec<u8> {
+        let data = miniz_oxide::deflate::compress_to_vec_zlib(data, 6);
+        let entries = format!("{} /Filter /FlateDecode /Length {}", dictionary, data.len());
+        let mut result = format!("<< {} >>\nstream\n", entries.trim_start()).into_bytes();
+        result.extend_from_slice(&data);
+        result.extend_from_slice(b"\nendstream");
+        result
+    }
+
+    // same settings share one graphics state
+    fn state(&mut self, settings: String) -> String {
+        if let Some((name, _)) = self.states.iter().find(|(_, existing)| *existing == settings) {
+            return name.clone();
+        }
+        let name = format!("GS{}", self.states.len());
+        self.states.push((name.clone(), settings));
+        name
+    }
+
+    // the resource name and the object number
+    fn xobject(&mut self, object: Vec<u8>) -> (String, usize) {
+        let name = format!("X{}", self.xobjects.len());
+        let number = self.add(object);
+        self.xobjects.push((name.clone(), number));
+        (name, number)
+    }
+
+    // a transparency group over the whole page, in pixel coordinates like the page content
+    fn form(&mut self, content: &str, gray: bool) -> (String, usize) {
+        let color_space = if gray { " /CS /DeviceGray" } else { "" };
+        let dictionary = format!("/Type /XObject /Subtype /Form /BBox [0 0 {} {}] /Group << /S /Transparency{} >> /Resources {} 0 R",
+            self.size.0, self.size.1, color_space, RESOURCES);
+        self.xobject(PdfWriter::stream(&dictionary, content.as_bytes()))
+    }
+
+    // drawn over the unit square, like all pdf images
+    fn image(&mut self, image: &FlatCanvasLayer) -> String {
+        let (width, height) = image.get_size();
+        let dictionary = |color_space: &str| format!("/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} /BitsPerComponent 8", width, height, color_space);
+        let data = image.get_data();
+        let alpha = data.iter().map(|color| color.alpha).collect::<Vec<_>>();
+        let rgb = data.iter().flat_map(|color| [color.red, color.green, color.blue]).collect::<Vec<_>>();
+        let smask = self.add(PdfWriter::stream(&dictionary("DeviceGray"), &alpha));
+        self.xobject(PdfWriter::stream(&format!("{} /SMask {} 0 R", dictionary("DeviceRGB"), smask), &rgb)).0
+    }
+
+    fn draw_image(&mut self, image: &FlatCanvasLayer) -> String {
+        let (width, height) = image.get_size();
+        format!("q {} 0 0 {} 0 {} cm /{} Do Q\n", width, -(height as f32), height, self.image(image))
+    }
+
+    fn node(&mut self, node: &ExportNode) -> String {
+        let layer = format!("OC{}", self.layers.len());
+        let number = self.add(format!("<< /Type /OCG /Name {} >>", pdf_string(&node.name)).into_bytes());
+        self.layers.push((layer.clone(), number, node.visible));
+
+        let mut content = String::new();
+        match &node.content {
+            ExportContent::Group(children) => {
+                for child in children {
+                    content += &self.node(child);
+                }
+            }
+            ExportContent::Image(image) => content += &self.draw_image(image),
+            ExportContent::Shapes(shapes) => {
+                for shape in shapes {
+                    content += &self.shape(shape);
+                }
+            }
+        }
+
+        // opacity and masks apply to the layer as a whole, so it becomes a group of its own
+        if node.opacity < 1.0 || node.mask.is_some() {
+            let mut settings = format!("/ca {0} /CA {0}", node.opacity);
+            if let Some(mask) = &node.mask {
+                let mask_content = self.draw_image(mask);
+                let (_, mask_form) = self.form(&mask_content, true);
+                settings += &format!(" /SMask << /Type /Mask /S /Luminosity /G {} 0 R >>", mask_form);
+            }
+            let state = self.state(settings);
+            let (form, _) = self.form(&content, false);
+            content = format!("q /{} gs /{} Do Q\n", state, form);
+        }
+        format!("/OC /{} BDC\n{}EMC\n", layer, content)
+    }
+
+    fn shape(&mut self, shape: &VectorShape) -> String {
+        let outline = shape.outline();
+        let Some(first) = outline.nodes.first() else { return String::new() };
+        let mut path = format!("{} {} m\n", first.anchor.x, first.anchor.y);
+        for [_, p1, p2, p3] in outline.segments() {
+            writeln!(path, "{} {} {} {} {} {} c", p1.x, p1.y, p2.x, p2.y, p3.x, p3.y).unwrap();
+        }
+        if outline.closed {
+            path += "h\n";
+        }
+        let channels = |color: Color| format!("{} {} {}", color.red as f32 / 255.0, color.green as f32 / 255.0, color.blue as f32 / 255.0);
+        let mut result = String::from("q\n");
+        let stroke = shape.stroke.filter(|_| shape.stroke_width > 0.0);
+        let state = self.state(format!("/ca {} /CA {}", shape.fill.map_or(1.0, |color| color.alpha as f32 / 255.0), stroke.map_or(1.0, |color| color.alpha as f32 / 255.0)));
+        writeln!(result, "/{} gs", state).unwrap();
+        if let Some(fill) = shape.fill {
+            write!(result, "{} rg\n{}f\n", channels(fill), path).unwrap();
+        }
+        if let Some(stroke) = stroke {
+            let cap = matches!(shape.kind, ShapeKind::Path(_)) as u8;
+            write!(result, "{} RG {} w 0 j 4 M {} J\n{}S\n", channels(stroke), shape.stroke_width, cap, path).unwrap();
+        }
+        result + "Q\n"
+    }
+
+    fn finish(mut self, content: String) -> Vec<u8> {
+        let (width, height) = (self.size.0 as f32 * POINTS_PER_PIXEL, self.size.1 as f32 * POINTS_PER_PIXEL);
+        // y goes down and units are pixels, like on the canvas
+        let content = format!("{0} 0 0 -{0} 0 {1} cm\n{2}", POINTS_PER_PIXEL, height, content);
+        let contents = self.add(PdfWriter::stream("", content.as_bytes()));
+
+        let list = |items: &mut dyn Iterator<Item = String>| items.collect::<Vec<_>>().join(" ");
+        let all = list(&mut self.layers.iter().map(|(_, number, _)| format!("{} 0 R", number)));
+        let hidden = list(&mut self.layers.iter().filter(|(_, _, visible)| !visible).map(|(_, number, _)| format!("{} 0 R", number)));
+        self.set(CATALOG, format!("<< /Type /Catalog /Pages {} 0 R /OCProperties << /OCGs [{1}] /D << /Order [{1}] /OFF [{2}] >> >> >>", PAGES, all, hidden));
+        self.set(PAGES, format!("<< /Type /Pages /Kids [{} 0 R] /Count 1 >>", PAGE));
+        self.set(PAGE, format!("<< /Type /Page /Parent {} 0 R /MediaBox [0 0 {} {}] /Resources {} 0 R /Contents {} 0 R >>", PAGES, width, height, RESOURCES, contents));
+        let xobjects = list(&mut self.xobjects.iter().map(|(name, number)| format!("/{} {} 0 R", name, number)));
+        let states = list(&mut self.states.iter().map(|(name, settings)| format!("/{} << {} >>", name, settings)));
+        let properties = list(&mut self.layers.iter().map(|(name, number, _)| format!("/{} {} 0 R", name, number)));
+        self.set(RESOURCES, format!("<< /XObject << {} >> /ExtGState << {} >> /Properties << {} >> >>", xobjects, states, properties));
+
+        let mut result = b"%PDF-1.5\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in self.objects.iter().enumerate() {
+            offsets.push(result.len());
+            result.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            result.extend_from_slice(object);
+            result.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = result.len();
+        let mut table = format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1);
+        for offset in offsets {
+            writeln!(table, "{:010} 00000 n ", offset).unwrap();
+        }
+        write!(table, "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n", self.objects.len() + 1, CATALOG, xref).unwrap();
+        result.extend_from_slice(table.as_bytes());
+        result
+    }
+}
+
+// one page the size of the canvas, the layers are optional content so viewers can toggle them
+pub fn encode_pdf(nodes: &[ExportNode], size: (u32, u32)) -> Vec<u8> {
+    let mut writer = PdfWriter::new(size);
+    let content = nodes.iter().map(|node| writer.node(node)).collect::<String>();
+    writer.finish(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas::Canvas;
+    use crate::paint_app::vector_layer::VectorLayer;
+    use crate::paint_app::vector_path::VectorPath;
+    use glam::Vec2;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"paint"), "cGFpbnQ=");
+        assert_eq!(base64(b"pa"), "cGE=");
+        assert_eq!(base64(b"pai"), "cGFp");
+    }
+
+    #[test]
+    fn test_clipping() {
+        use crate::paint_app::canvas::LayerLocks;
+        use crate::paint_app::canvas_layer::LayerBuffer;
+        let entry = |id: usize, color: Color| {
+            let mut layer = FlatCanvasLayer::new(2, 1);
+            layer.fill(color);
+            CanvasLayerEntry { id: LayerId(id), name: String::new(), layer: LayerBuffer::Rgba8(layer), content: LayerContent::Raster, visible: true, mask: None, clipped: false, parent: None, locks: LayerLocks::default(), revision: 0 }
+        };
+        let blue = Color::new(0, 0, 255, 255);
+        let mut base = entry(0, Color::new(255, 0, 0, 255));
+        base.layer.set_pixel(PixelPos { x: 1, y: 0 }, Color::new(0, 0, 0, 0));
+        let mut clipped = entry(1, blue);
+        clipped.clipped = true;
+
+        let nodes = group_nodes(&[clipped, base], None, BlendSpace::Srgb);
+        let ExportContent::Image(image) = &nodes[1].content else { panic!("clipped layers are images") };
+        assert_eq!(image.get_pixel(PixelPos { x: 0, y: 0 }), blue);
+        assert_eq!(image.get_pixel(PixelPos { x: 1, y: 0 }).alpha, 0);
+    }
+
+    #[test]
+    fn test_export_layers() {
+        let mut canvas = Canvas::new(256, 256);
+        let mut vector = VectorLayer::new();
+        let mut rectangle = VectorShape::rectangle(Vec2::new(4.0, 4.0), Vec2::new(20.0, 12.0), Color::new(255, 0, 0, 128));
+        rectangle.stroke = Some(Color::black());
+        vector.shapes.push(rectangle);
+        vector.shapes.push(VectorShape::path(VectorPath::new("Empty".to_string()), Color::black()));
+        canvas.add_vector_layer(vector);
+        let group = canvas.group_active_layer().unwrap();
+
+        let mut nodes = export_nodes(canvas.get_layers());
+        // the raster layers are at the bottom, the group holding the shapes on top
+        assert_eq!(nodes.len(), 3);
+        assert!(matches!(nodes[0].content, ExportContent::Image(_)));
+        assert_eq!(nodes[2].id, group);
+        assert!(matches!(&nodes[2].content, ExportContent::Group(children) if matches!(children[0].content, ExportContent::Shapes(_))));
+        nodes[0].visible = false;
+
+        let svg = encode_svg(&nodes, (256, 256)).unwrap();
+        assert!(svg.contains("<image width=\"256\" height=\"256\" href=\"data:image/png;base64,"));
+        assert!(svg.contains("display=\"none\""));
+        assert!(svg.contains("<rect x=\"4\" y=\"4\" width=\"16\" height=\"8\" rx=\"0\" fill=\"#ff0000\" fill-opacity=\"0.5019608\" stroke=\"#000000\" "));
+        assert!(svg.find("<image").unwrap() < svg.find("<rect").unwrap());
+
+        let pdf = encode_pdf(&nodes, (256, 256));
+        // the streams are binary, the rest is text
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.5") && text.ends_with("%%EOF\n"));
+        assert!(text.contains("/MediaBox [0 0 192 192]"));
+        assert!(text.contains("/OFF [5 0 R]"));
+        // every xref offset points at its object
+        let start = pdf.windows(9).rposition(|window| window == b"startxref").unwrap();
+        let xref = std::str::from_utf8(&pdf[start..]).unwrap().lines().nth(1).unwrap().parse::<usize>().unwrap();
+        let table = std::str::from_utf8(&pdf[xref..start]).unwrap();
+        let offsets = table.lines().skip(3).take_while(|line| line.ends_with(" n ")).collect::<Vec<_>>();
+        assert!(offsets.len() > 6);
+        for (i, line) in offsets.iter().enumerate() {
+            let offset = line[..10].parse::<usize>().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()));
+        }
+    }
+}
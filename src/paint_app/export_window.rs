@@ -0,0 +1,186 @@
+use eframe::egui;
+use crate::paint_app::raster_formats::{RasterFormat, RasterOptions, ICO_SIZES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    // the merged image
+    Raster(RasterFormat),
+    IndexedPng,
+    // vector layers stay vectors, the rest is embedded as png
+    Svg,
+    Pdf,
+    // layers and groups for other painting apps
+    Ora,
+    // every frame of the animation
+    AnimatedGif,
+    Apng,
+    SpriteSheet,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Raster(format) => format.extension(),
+            ExportFormat::IndexedPng => "png",
+            ExportFormat::Svg => "svg",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Ora => "ora",
+            ExportFormat::AnimatedGif => "gif",
+            ExportFormat::Apng | ExportFormat::SpriteSheet => "png",
+        }
+    }
+}
+
+pub struct ExportWindow {
+    pub open: bool,
+    pub path: String,
+    pub format: ExportFormat,
+    pub options: RasterOptions,
+    // frames in a row of the sprite sheet
+    pub sprite_columns: u32,
+    // bytes the current settings produce, None until main encodes them again
+    pub estimated_size: Option<Result<usize, String>>,
+}
+
+impl ExportWindow {
+    pub fn new() -> ExportWindow {
+        ExportWindow {
+            open: false,
+            path: "image.png".to_string(),
+            format: ExportFormat::Raster(RasterFormat::Png),
+            options: RasterOptions::new(),
+            sprite_columns: 8,
+            estimated_size: None,
+        }
+    }
+
+    // returns true when export was clicked
+    pub fn show_export_window(&mut self, ctx: &egui::Context, indexed: bool, frame_count: usize) -> bool {
+        if !indexed && self.format == ExportFormat::IndexedPng {
+            self.format = ExportFormat::Raster(RasterFormat::Png);
+        }
+        let mut confirmed = false;
+        let mut close = false;
+        let mut open = self.open;
+        egui::Window::new("Export")
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("File: ");
+                    ui.text_edit_singleline(&mut self.path);
+                });
+
+                let old_format = self.format;
+                let old_options = self.options.clone();
+                for format in RasterFormat::all() {
+                    ui.radio_value(&mut self.format, ExportFormat::Raster(format), format.name());
+                    if format == RasterFormat::Png {
+                        ui.add_enabled_ui(indexed, |ui| {
+                            ui.radio_value(&mut self.format, ExportFormat::IndexedPng, "Indexed PNG");
+                        });
+                    }
+                }
+                ui.radio_value(&mut self.format, ExportFormat::Svg, "SVG");
+                ui.radio_value(&mut self.format, ExportFormat::Pdf, "PDF");
+                ui.radio_value(&mut self.format, ExportFormat::Ora, "OpenRaster");
+                ui.label(format!("Animation, {} frames:", frame_count));
+                ui.radio_value(&mut self.format, ExportFormat::AnimatedGif, "Animated GIF");
+                ui.radio_value(&mut self.format, ExportFormat::Apng, "APNG");
+                ui.radio_value(&mut self.format, ExportFormat::SpriteSheet, "Sprite sheet PNG");
+                // the file name follows the format
+                if self.format != old_format {
+                    if let Some((name, _)) = self.path.rsplit_once('.').filter(|(_, extension)| *extension == old_format.extension()) {
+                        self.path = format!("{}.{}", name, self.format.extension());
+                    }
+                }
+
+                let old_columns = self.sprite_columns;
+                match self.format {
+                    ExportFormat::Raster(format) => {
+                        ui.separator();
+                        self.show_options(ui, format);
+                    }
+                    ExportFormat::AnimatedGif => {
+                        ui.separator();
+                        ui.label("Partly transparent pixels are flattened onto the secondary color");
+                    }
+                    ExportFormat::SpriteSheet => {
+                        ui.separator();
+                        ui.add(egui::Slider::new(&mut self.sprite_columns, 1..=frame_count.max(1) as u32).text("Columns"));
+                    }
+                    _ => {}
+                }
+                if self.format != old_format || self.options != old_options || self.sprite_columns != old_columns {
+                    self.estimated_size = None;
+                }
+
+                ui.separator();
+                match &self.estimated_size {
+                    Some(Ok(size)) => ui.label(format!("Estimated size: {}", format_size(*size))),
+                    Some(Err(err)) => ui.colored_label(egui::Color32::RED, err),
+                    None => ui.label("Estimated size: ..."),
+                };
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("Export").clicked() {
+                        confirmed = true;
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        self.open = open && !close;
+        confirmed
+    }
+
+    fn show_options(&mut self, ui: &mut egui::Ui, format: RasterFormat) {
+        match format {
+            RasterFormat::Jpeg => {
+                ui.add(egui::Slider::new(&mut self.options.jpeg_quality, 1..=100).text("Quality"));
+            }
+            RasterFormat::WebP => {
+                ui.checkbox(&mut self.options.webp_lossless, "Lossless");
+                ui.add_enabled(!self.options.webp_lossless, egui::Slider::new(&mut self.options.webp_quality, 1..=100).text("Quality"));
+            }
+            RasterFormat::Ico => {
+                ui.label("Sizes:");
+                ui.horizontal_wrapped(|ui| {
+                    for size in ICO_SIZES {
+                        let mut checked = self.options.ico_sizes.contains(&size);
+                        if ui.checkbox(&mut checked, format!("{}", size)).changed() {
+                            match checked {
+                                true => self.options.ico_sizes.push(size),
+                                false => self.options.ico_sizes.retain(|other| *other != size)
+                            }
+                            self.options.ico_sizes.sort();
+                        }
+                    }
+                });
+            }
+            _ => {}
+        }
+        match format {
+            RasterFormat::Gif => { ui.label("Partly transparent pixels are flattened onto the secondary color"); }
+            _ if !format.has_alpha() => { ui.label("Transparency is flattened onto the secondary color"); }
+            _ => {}
+        }
+    }
+}
+
+impl Default for ExportWindow {
+    fn default() -> ExportWindow {
+        ExportWindow::new()
+    }
+}
+
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1048575 => format!("{:.1} KB", bytes as f32 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f32 / 1048576.0)
+    }
+}
@@ -0,0 +1,110 @@
+use wide::{f64x4, i32x4};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::utils::pixel_overlap;
+use super::data_types::*;
+
+// rows per band, bands are what gets spread across the threads
+const BAND_ROWS: usize = 16;
+
+// source over target for a row of pixels, four at a time. gives exactly what pixel_overlap
+// gives, the integer divisions are done in f64 which is exact for numbers this small
+pub fn overlap_row(source: &[Color], target: &mut [Color]) {
+    assert_eq!(source.len(), target.len());
+    let split = source.len() / 4 * 4;
+    let (source, source_rest) = source.split_at(split);
+    let (target, target_rest) = target.split_at_mut(split);
+    // each word is a whole pixel, red in the lowest byte
+    let source_words: &[[u32; 4]] = bytemuck::cast_slice(source);
+    let target_words: &mut [[u32; 4]] = bytemuck::cast_slice_mut(target);
+    for (source, target) in source_words.iter().zip(target_words) {
+        *target = overlap_4(source.map(u32::from_le), target.map(u32::from_le)).map(u32::to_le);
+    }
+    source_rest.iter()
+        .zip(target_rest)
+        .for_each(|(source, target)| *target = pixel_overlap(*source, *target));
+}
+
+fn overlap_4(source: [u32; 4], target: [u32; 4]) -> [u32; 4] {
+    // opaque pixels replace the target, transparent ones leave it alone unless that is transparent too
+    if source.iter().all(|pixel| pixel >> 24 == 255) {
+        return source;
+    }
+    if source.iter().all(|pixel| pixel >> 24 == 0) && target.iter().all(|pixel| pixel >> 24 != 0) {
+        return target;
+    }
+    let source = i32x4::from(bytemuck::cast::<_, [i32; 4]>(source));
+    let target = i32x4::from(bytemuck::cast::<_, [i32; 4]>(target));
+    let lanes = |pixels: i32x4, shift: i32| (pixels >> shift) & i32x4::splat(255);
+    let full = i32x4::splat(255);
+
+    let alpha_a = lanes(source, 24);
+    let alpha_b = lanes(target, 24) * (full - alpha_a);
+    let alpha = alpha_a * full + alpha_b;
+    let weight_a = alpha_a * full;
+    let rounding = alpha >> 1;
+    // the sums fit in i32. without any alpha every sum is 0, which stays 0
+    let divide = |sum: i32x4, divisor: f64x4| (f64x4::from_i32x4(sum) / divisor).to_array().map(|value| value as u32);
+    let divisor = f64x4::from_i32x4(alpha.max(i32x4::splat(1)));
+    let channel = |shift: i32| divide(lanes(source, shift) * weight_a + lanes(target, shift) * alpha_b + rounding, divisor);
+
+    let [red, green, blue] = [channel(0), channel(8), channel(16)];
+    let result_alpha = divide(alpha + i32x4::splat(127), f64x4::splat(255.0));
+    [0, 1, 2, 3].map(|i| red[i] | green[i] << 8 | blue[i] << 16 | result_alpha[i] << 24)
+}
+
+// source over target, both have to be the same size. bands of rows run on all cores on native
+pub fn overlap_layers(source: &FlatCanvasLayer, target: &mut FlatCanvasLayer) {
+    assert_eq!(source.get_size(), target.get_size());
+    let band = (source.get_size().0 as usize * BAND_ROWS).max(1);
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use rayon::prelude::*;
+        target.get_data_mut().par_chunks_mut(band)
+            .zip(source.get_data().par_chunks(band))
+            .for_each(|(target, source)| overlap_row(source, target));
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        target.get_data_mut().chunks_mut(band)
+            .zip(source.get_data().chunks(band))
+            .for_each(|(target, source)| overlap_row(source, target));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_pixel_overlap() {
+        // every pair of alphas with colors that change along the row
+        let mut source = Vec::new();
+        let mut target = Vec::new();
+        for alpha_a in 0..=255u32 {
+            for alpha_b in 0..=255u32 {
+                let i = (alpha_a * 256 + alpha_b) as u8;
+                source.push(Color::new(i, 255 - i, i.wrapping_mul(7), alpha_a as u8));
+                target.push(Color::new(i.wrapping_mul(13), i, 255 - i, alpha_b as u8));
+            }
+        }
+        // an odd length so the remainder is checked as well
+        source.pop();
+        target.pop();
+        let expected = source.iter().zip(target.iter()).map(|(a, b)| pixel_overlap(*a, *b)).collect::<Vec<_>>();
+        overlap_row(&source, &mut target);
+        assert!(target == expected);
+    }
+
+    #[test]
+    fn test_overlap_layers() {
+        let mut source = FlatCanvasLayer::new(37, 41);
+        source.iter_pixels_mut().for_each(|(pos, color)| *color = Color::new(pos.x as u8 * 6, pos.y as u8 * 6, 100, (pos.x * 7) as u8));
+        let mut target = FlatCanvasLayer::new(37, 41);
+        target.fill(Color::new(10, 200, 30, 180));
+        let mut expected = target.clone();
+        source.iter_pixels().for_each(|(pos, color)| expected.set_pixel(pos, pixel_overlap(*color, expected.get_pixel(pos))));
+
+        overlap_layers(&source, &mut target);
+        assert!(target.get_data() == expected.get_data());
+    }
+}
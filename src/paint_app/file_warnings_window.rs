@@ -0,0 +1,58 @@
+use eframe::egui;
+
+// lists what didn't survive opening or saving a file
+pub struct FileWarningsWindow {
+    pub open: bool,
+    pub title: String,
+    pub warnings: Vec<String>,
+}
+
+impl FileWarningsWindow {
+    pub fn new() -> FileWarningsWindow {
+        FileWarningsWindow {
+            open: false,
+            title: String::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // opens the window when there is anything to show
+    pub fn open_with(&mut self, title: String, warnings: Vec<String>) {
+        self.open = !warnings.is_empty();
+        self.title = title;
+        self.warnings = warnings;
+    }
+
+    pub fn show_file_warnings_window(&mut self, ctx: &egui::Context) {
+        let mut close = false;
+        egui::Window::new(&self.title)
+            .id(egui::Id::new("file_warnings"))
+            .collapsible(false)
+            .default_width(360.0)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(320.0).show(ui, |ui| {
+                    for warning in &self.warnings {
+                        ui.label(warning);
+                    }
+                });
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("Close").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if close {
+            self.open = false;
+        }
+    }
+}
+
+impl Default for FileWarningsWindow {
+    fn default() -> FileWarningsWindow {
+        FileWarningsWindow::new()
+    }
+}
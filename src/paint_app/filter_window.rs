@@ -0,0 +1,103 @@
+use eframe::egui;
+use egui::Slider;
+use crate::paint_app::filters::{EdgeDetectKind, EdgeMode, Filter};
+
+pub struct FilterWindow {
+    pub open: bool,
+    pub filter: Filter,
+    pub edge_mode: EdgeMode,
+    // the preview is only updated while this is set
+    pub live_preview: bool,
+}
+
+impl FilterWindow {
+    pub fn new() -> FilterWindow {
+        FilterWindow {
+            open: false,
+            filter: Filter::GaussianBlur { radius: 4.0 },
+            edge_mode: EdgeMode::Clamp,
+            live_preview: true,
+        }
+    }
+
+    pub fn open_with(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.open = true;
+    }
+
+    // returns Some(true) when applied and Some(false) when cancelled
+    pub fn show_filter_window(&mut self, ctx: &egui::Context) -> Option<bool> {
+        let mut result = None;
+        let mut open = self.open;
+        egui::Window::new(self.filter.name().to_string())
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                self.settings_ui(ui);
+
+                ui.separator();
+
+                ui.label("Edges");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.edge_mode, EdgeMode::Clamp, "Clamp");
+                    ui.radio_value(&mut self.edge_mode, EdgeMode::Wrap, "Wrap");
+                    ui.radio_value(&mut self.edge_mode, EdgeMode::Transparent, "Transparent");
+                });
+                ui.checkbox(&mut self.live_preview, "Preview");
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("OK").clicked() {
+                        result = Some(true);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        result = Some(false);
+                    }
+                });
+            });
+        if !open {
+            result = Some(false);
+        }
+        if result.is_some() {
+            self.open = false;
+        }
+        result
+    }
+
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        match &mut self.filter {
+            Filter::GaussianBlur { radius } => {
+                ui.add(Slider::new(radius, 0.5..=50.0).logarithmic(true).text("Radius"));
+            }
+            Filter::BoxBlur { radius } | Filter::Median { radius } => {
+                ui.add(Slider::new(radius, 1..=20).text("Radius"));
+            }
+            Filter::MotionBlur { angle, distance } => {
+                ui.add(Slider::new(angle, -180.0..=180.0).text("Angle"));
+                ui.add(Slider::new(distance, 1..=100).text("Distance"));
+            }
+            Filter::UnsharpMask { radius, amount, threshold } => {
+                ui.add(Slider::new(radius, 0.5..=50.0).logarithmic(true).text("Radius"));
+                ui.add(Slider::new(amount, 0.0..=5.0).text("Amount"));
+                ui.add(Slider::new(threshold, 0..=255).text("Threshold"));
+            }
+            Filter::EdgeDetect { kind } => {
+                ui.horizontal(|ui| {
+                    ui.radio_value(kind, EdgeDetectKind::Sobel, "Sobel");
+                    ui.radio_value(kind, EdgeDetectKind::Laplacian, "Laplacian");
+                });
+            }
+            Filter::Emboss { angle } => {
+                ui.add(Slider::new(angle, -180.0..=180.0).text("Angle"));
+            }
+        }
+    }
+}
+
+impl Default for FilterWindow {
+    fn default() -> FilterWindow {
+        FilterWindow::new()
+    }
+}
@@ -0,0 +1,330 @@
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeMode {
+    Clamp,
+    Wrap,
+    Transparent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeDetectKind {
+    Sobel,
+    Laplacian,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    GaussianBlur { radius: f32 },
+    BoxBlur { radius: u32 },
+    // angle in degrees, distance in pixels
+    MotionBlur { angle: f32, distance: u32 },
+    // threshold in 0..255, differences below it are left alone
+    UnsharpMask { radius: f32, amount: f32, threshold: u8 },
+    EdgeDetect { kind: EdgeDetectKind },
+    Emboss { angle: f32 },
+    Median { radius: u32 },
+}
+
+impl Filter {
+    pub fn name(&self) -> &str {
+        match self {
+            Filter::GaussianBlur { .. } => "Gaussian blur",
+            Filter::BoxBlur { .. } => "Box blur",
+            Filter::MotionBlur { .. } => "Motion blur",
+            Filter::UnsharpMask { .. } => "Unsharp mask",
+            Filter::EdgeDetect { .. } => "Edge detect",
+            Filter::Emboss { .. } => "Emboss",
+            Filter::Median { .. } => "Median",
+        }
+    }
+
+    pub fn all_defaults() -> Vec<Filter> {
+        vec![
+            Filter::GaussianBlur { radius: 4.0 },
+            Filter::BoxBlur { radius: 3 },
+            Filter::MotionBlur { angle: 0.0, distance: 10 },
+            Filter::UnsharpMask { radius: 2.0, amount: 1.0, threshold: 0 },
+            Filter::EdgeDetect { kind: EdgeDetectKind::Sobel },
+            Filter::Emboss { angle: 135.0 },
+            Filter::Median { radius: 1 },
+        ]
+    }
+}
+
+type Rgba = [f32; 4];
+
+// premultiplied rgba in 0..1, filters work on this so transparent pixels don't bleed their color
+struct Image {
+    width: usize,
+    height: usize,
+    data: Vec<Rgba>,
+}
+
+impl Image {
+    fn from_layer<P: Pixel>(layer: &FlatCanvasLayer<P>) -> Image {
+        let (width, height) = layer.get_size();
+        let data = layer.get_data().iter().map(|pixel| {
+            let [red, green, blue, alpha] = pixel.to_rgba();
+            [red * alpha, green * alpha, blue * alpha, alpha]
+        }).collect();
+        Image { width: width as usize, height: height as usize, data }
+    }
+
+    fn to_layer<P: Pixel>(&self) -> FlatCanvasLayer<P> {
+        let data = self.data.iter().map(|pixel| {
+            let alpha = pixel[3].clamp(0.0, 1.0);
+            if alpha <= 0.0 {
+                return P::from_rgba([0.0; 4]);
+            }
+            P::from_rgba([pixel[0] / alpha, pixel[1] / alpha, pixel[2] / alpha, alpha].map(|value| value.clamp(0.0, 1.0)))
+        }).collect();
+        FlatCanvasLayer::from_data(self.width as u32, self.height as u32, data)
+    }
+
+    fn sample(&self, x: i64, y: i64, edge_mode: EdgeMode) -> Rgba {
+        let (w, h) = (self.width as i64, self.height as i64);
+        let (x, y) = match edge_mode {
+            EdgeMode::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+            EdgeMode::Wrap => (x.rem_euclid(w), y.rem_euclid(h)),
+            EdgeMode::Transparent => {
+                if x < 0 || y < 0 || x >= w || y >= h {
+                    return [0.0; 4];
+                }
+                (x, y)
+            }
+        };
+        self.data[(x + y * w) as usize]
+    }
+
+    // fills a new image row by row, rows run on all cores on native
+    fn map_rows(&self, f: impl Fn(usize, &mut [Rgba]) + Sync + Send) -> Image {
+        let mut data = vec![[0f32; 4]; self.width * self.height];
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            use rayon::prelude::*;
+            data.par_chunks_mut(self.width).enumerate().for_each(|(y, row)| f(y, row));
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            data.chunks_mut(self.width).enumerate().for_each(|(y, row)| f(y, row));
+        }
+        Image { width: self.width, height: self.height, data }
+    }
+
+    fn convolve_1d(&self, kernel: &[f32], horizontal: bool, edge_mode: EdgeMode) -> Image {
+        let half = (kernel.len() / 2) as i64;
+        self.map_rows(|y, row| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let mut sum = [0f32; 4];
+                for (i, weight) in kernel.iter().enumerate() {
+                    let offset = i as i64 - half;
+                    let sample = match horizontal {
+                        true => self.sample(x as i64 + offset, y as i64, edge_mode),
+                        false => self.sample(x as i64, y as i64 + offset, edge_mode)
+                    };
+                    (0..4).for_each(|c| sum[c] += sample[c] * weight);
+                }
+                *pixel = sum;
+            }
+        })
+    }
+
+    fn convolve_separable(&self, kernel: &[f32], edge_mode: EdgeMode) -> Image {
+        self.convolve_1d(kernel, true, edge_mode).convolve_1d(kernel, false, edge_mode)
+    }
+
+    // 3x3 kernel on the color channels, alpha is kept
+    fn convolve_3x3(&self, kernel: [[f32; 3]; 3], bias: f32, edge_mode: EdgeMode) -> Image {
+        self.map_rows(|y, row| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let alpha = self.data[x + y * self.width][3];
+                let mut sum = [0f32; 3];
+                for (ky, kernel_row) in kernel.iter().enumerate() {
+                    for (kx, weight) in kernel_row.iter().enumerate() {
+                        let sample = self.sample(x as i64 + kx as i64 - 1, y as i64 + ky as i64 - 1, edge_mode);
+                        (0..3).for_each(|c| sum[c] += sample[c] * weight);
+                    }
+                }
+                *pixel = [(sum[0] + bias * alpha).clamp(0.0, alpha), (sum[1] + bias * alpha).clamp(0.0, alpha), (sum[2] + bias * alpha).clamp(0.0, alpha), alpha];
+            }
+        })
+    }
+}
+
+pub fn gaussian_kernel(radius: f32) -> Vec<f32> {
+    let sigma = (radius / 2.0).max(0.1);
+    let half = (sigma * 3.0).ceil() as i32;
+    let kernel = (-half..=half).map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp()).collect::<Vec<_>>();
+    let sum = kernel.iter().sum::<f32>();
+    kernel.iter().map(|weight| weight / sum).collect()
+}
+
+fn motion_blur(image: &Image, angle: f32, distance: u32, edge_mode: EdgeMode) -> Image {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let count = distance.max(1) as i64;
+    image.map_rows(|y, row| {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let mut sum = [0f32; 4];
+            for i in 0..count {
+                let t = i as f32 - (count - 1) as f32 / 2.0;
+                let sample = image.sample(x as i64 + (t * cos).round() as i64, y as i64 + (t * sin).round() as i64, edge_mode);
+                (0..4).for_each(|c| sum[c] += sample[c]);
+            }
+            *pixel = sum.map(|value| value / count as f32);
+        }
+    })
+}
+
+fn edge_detect(image: &Image, kind: EdgeDetectKind, edge_mode: EdgeMode) -> Image {
+    match kind {
+        // the kernel is signed, the magnitude is used
+        EdgeDetectKind::Laplacian => image.map_rows(|y, row| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let mut sum = [0f32; 3];
+                for (dx, dy, weight) in [(0, -1, 1.0), (-1, 0, 1.0), (0, 0, -4.0), (1, 0, 1.0), (0, 1, 1.0)] {
+                    let sample = image.sample(x as i64 + dx, y as i64 + dy, edge_mode);
+                    (0..3).for_each(|c| sum[c] += sample[c] * weight);
+                }
+                let alpha = image.data[x + y * image.width][3];
+                *pixel = [sum[0].abs().min(alpha), sum[1].abs().min(alpha), sum[2].abs().min(alpha), alpha];
+            }
+        }),
+        EdgeDetectKind::Sobel => image.map_rows(|y, row| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let mut gx = [0f32; 3];
+                let mut gy = [0f32; 3];
+                for dy in -1..=1i64 {
+                    for dx in -1..=1i64 {
+                        let sample = image.sample(x as i64 + dx, y as i64 + dy, edge_mode);
+                        let wx = dx as f32 * if dy == 0 { 2.0 } else { 1.0 };
+                        let wy = dy as f32 * if dx == 0 { 2.0 } else { 1.0 };
+                        (0..3).for_each(|c| {
+                            gx[c] += sample[c] * wx;
+                            gy[c] += sample[c] * wy;
+                        });
+                    }
+                }
+                let alpha = image.data[x + y * image.width][3];
+                let magnitude = |c: usize| (gx[c] * gx[c] + gy[c] * gy[c]).sqrt().min(alpha);
+                *pixel = [magnitude(0), magnitude(1), magnitude(2), alpha];
+            }
+        }),
+    }
+}
+
+fn median(image: &Image, radius: u32, edge_mode: EdgeMode) -> Image {
+    let radius = radius.clamp(1, 5) as i64;
+    image.map_rows(|y, row| {
+        let mut window: [Vec<f32>; 4] = Default::default();
+        for (x, pixel) in row.iter_mut().enumerate() {
+            window.iter_mut().for_each(|values| values.clear());
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let sample = image.sample(x as i64 + dx, y as i64 + dy, edge_mode);
+                    (0..4).for_each(|c| window[c].push(sample[c]));
+                }
+            }
+            for c in 0..4 {
+                let middle = window[c].len() / 2;
+                window[c].select_nth_unstable_by(middle, |a, b| a.total_cmp(b));
+                pixel[c] = window[c][middle];
+            }
+            // keep the color valid for premultiplied alpha
+            let alpha = pixel[3];
+            (0..3).for_each(|c| pixel[c] = pixel[c].min(alpha));
+        }
+    })
+}
+
+pub fn apply_filter<P: Pixel>(layer: &FlatCanvasLayer<P>, filter: &Filter, edge_mode: EdgeMode) -> FlatCanvasLayer<P> {
+    let image = Image::from_layer(layer);
+    let result = match filter {
+        Filter::GaussianBlur { radius } => image.convolve_separable(&gaussian_kernel(*radius), edge_mode),
+        Filter::BoxBlur { radius } => {
+            let size = *radius as usize * 2 + 1;
+            image.convolve_separable(&vec![1.0 / size as f32; size], edge_mode)
+        }
+        Filter::MotionBlur { angle, distance } => motion_blur(&image, *angle, *distance, edge_mode),
+        Filter::UnsharpMask { radius, amount, threshold } => {
+            let blurred = image.convolve_separable(&gaussian_kernel(*radius), edge_mode);
+            let threshold = *threshold as f32 / 255.0;
+            image.map_rows(|y, row| {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let original = image.data[x + y * image.width];
+                    let blur = blurred.data[x + y * image.width];
+                    let alpha = original[3];
+                    *pixel = original;
+                    for c in 0..3 {
+                        let difference = original[c] - blur[c];
+                        if difference.abs() >= threshold {
+                            pixel[c] = (original[c] + difference * amount).clamp(0.0, alpha);
+                        }
+                    }
+                }
+            })
+        }
+        Filter::EdgeDetect { kind } => edge_detect(&image, *kind, edge_mode),
+        Filter::Emboss { angle } => {
+            // the light comes from angle, the kernel sums to zero and gray is added back
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let mut kernel = [[0f32; 3]; 3];
+            for (ky, kernel_row) in kernel.iter_mut().enumerate() {
+                for (kx, weight) in kernel_row.iter_mut().enumerate() {
+                    *weight = (kx as f32 - 1.0) * cos + (ky as f32 - 1.0) * sin;
+                }
+            }
+            image.convolve_3x3(kernel, 0.5, edge_mode)
+        }
+        Filter::Median { radius } => median(&image, *radius, edge_mode),
+    };
+    result.to_layer()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gaussian_kernel() {
+        let kernel = gaussian_kernel(4.0);
+        assert_eq!(kernel.len() % 2, 1);
+        assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(kernel[kernel.len() / 2] > kernel[0]);
+    }
+
+    #[test]
+    fn test_blur_premultiplied() {
+        // a red pixel next to transparent white must not turn pink when blurred
+        let mut layer = FlatCanvasLayer::<Color>::new(5, 1);
+        layer.fill(Color::new(255, 255, 255, 0));
+        layer.set_pixel(PixelPos { x: 2, y: 0 }, Color::new(255, 0, 0, 255));
+        // a single row, the vertical pass divides again when outside is transparent
+        for (edge_mode, alpha) in [(EdgeMode::Clamp, 85), (EdgeMode::Wrap, 85), (EdgeMode::Transparent, 28)] {
+            let result = apply_filter(&layer, &Filter::BoxBlur { radius: 1 }, edge_mode);
+            let side = result.get_pixel(PixelPos { x: 1, y: 0 });
+            assert_eq!((side.red, side.green, side.blue), (255, 0, 0));
+            assert_eq!(side.alpha, alpha);
+        }
+    }
+
+    #[test]
+    fn test_edge_modes() {
+        let mut layer = FlatCanvasLayer::<Color>::new(3, 3);
+        layer.fill(Color::new(0, 0, 255, 255));
+        let clamp = apply_filter(&layer, &Filter::BoxBlur { radius: 1 }, EdgeMode::Clamp);
+        assert_eq!(clamp.get_pixel(PixelPos { x: 0, y: 0 }), Color::new(0, 0, 255, 255));
+        let transparent = apply_filter(&layer, &Filter::BoxBlur { radius: 1 }, EdgeMode::Transparent);
+        assert_eq!(transparent.get_pixel(PixelPos { x: 0, y: 0 }).alpha, 113);
+    }
+
+    #[test]
+    fn test_median_removes_noise() {
+        let mut layer = FlatCanvasLayer::<Color>::new(3, 3);
+        layer.fill(Color::black());
+        layer.set_pixel(PixelPos { x: 1, y: 1 }, Color::white());
+        let result = apply_filter(&layer, &Filter::Median { radius: 1 }, EdgeMode::Clamp);
+        assert_eq!(result.get_pixel(PixelPos { x: 1, y: 1 }), Color::black());
+    }
+}
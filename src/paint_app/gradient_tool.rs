@@ -0,0 +1,281 @@
+use egui::{DragValue, Key, Ui};
+use crate::paint_app::actions::Shortcut;
+use crate::paint_app::canvas::{CanvasLayers, EditCommand, PaintTool};
+use crate::paint_app::tool_registry::{ToolCategory, ToolInfo, ToolOption, ToolOptionValue};
+use crate::paint_app::canvas_layer::{CanvasLayer, HashMapCanvasLayer};
+use crate::paint_app::rasterize::BresenhamLine;
+use crate::paint_app::utils::{bayer_dither, linear_to_srgb, over, premultiply, srgb_to_linear, unpremultiply};
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientKind {
+    Linear,
+    Radial,
+    Angular,
+    Reflected,
+    Diamond,
+}
+
+const KINDS: [GradientKind; 5] = [GradientKind::Linear, GradientKind::Radial, GradientKind::Angular, GradientKind::Reflected, GradientKind::Diamond];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GradientInterpolation {
+    Srgb,
+    LinearLight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GradientStop {
+    pub position: f32,
+    pub color: Color,
+}
+
+// position of pixel_pos along the gradient, 0 at start and 1 at end
+pub fn gradient_position(kind: GradientKind, start: PixelPos, end: PixelPos, pixel_pos: PixelPos) -> f32 {
+    let start = glam::Vec2::new(start.x as f32, start.y as f32);
+    let end = glam::Vec2::new(end.x as f32, end.y as f32);
+    let pos = glam::Vec2::new(pixel_pos.x as f32, pixel_pos.y as f32);
+
+    let direction = end - start;
+    let length = direction.length();
+    if length == 0.0 {
+        return 0.0;
+    }
+    let offset = pos - start;
+    let along = offset.dot(direction) / (length * length);
+    let across = offset.perp_dot(direction) / (length * length);
+
+    let t = match kind {
+        GradientKind::Linear => along,
+        GradientKind::Radial => offset.length() / length,
+        GradientKind::Angular => {
+            let angle = offset.y.atan2(offset.x) - direction.y.atan2(direction.x);
+            (angle / std::f32::consts::TAU).rem_euclid(1.0)
+        }
+        GradientKind::Reflected => along.abs(),
+        GradientKind::Diamond => along.abs() + across.abs(),
+    };
+    t.clamp(0.0, 1.0)
+}
+
+// rgba in 0..1, interpolated between the stops around t
+pub fn sample_stops(stops: &[GradientStop], t: f32, interpolation: GradientInterpolation) -> [f32; 4] {
+    let to_f32 = |color: Color| {
+        let channel = |value: u8| match interpolation {
+            GradientInterpolation::Srgb => value as f32 / 255.0,
+            GradientInterpolation::LinearLight => srgb_to_linear(value as f32 / 255.0),
+        };
+        [channel(color.red), channel(color.green), channel(color.blue), color.alpha as f32 / 255.0]
+    };
+
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return [0.0; 4],
+    };
+    let mut result = if t <= first.position {
+        to_f32(first.color)
+    } else if t >= last.position {
+        to_f32(last.color)
+    } else {
+        let next = stops.iter().position(|stop| stop.position > t).unwrap_or(stops.len() - 1);
+        let a = &stops[next - 1];
+        let b = &stops[next];
+        let span = b.position - a.position;
+        let local_t = if span > 0.0 { (t - a.position) / span } else { 0.0 };
+        let a = to_f32(a.color);
+        let b = to_f32(b.color);
+        [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * local_t)
+    };
+
+    if interpolation == GradientInterpolation::LinearLight {
+        for channel in result.iter_mut().take(3) {
+            *channel = linear_to_srgb(*channel);
+        }
+    }
+    result
+}
+
+pub struct GradientTool {
+    pub kind: GradientKind,
+    pub interpolation: GradientInterpolation,
+    pub dither: bool,
+    pub stops: Vec<GradientStop>,
+    // stops follow the primary and secondary colors until edited
+    pub follow_colors: bool,
+    start_point: Option<PixelPos>,
+}
+
+impl GradientTool {
+    pub fn new() -> GradientTool {
+        GradientTool {
+            kind: GradientKind::Linear,
+            interpolation: GradientInterpolation::Srgb,
+            dither: false,
+            stops: vec![
+                GradientStop { position: 0.0, color: Color::black() },
+                GradientStop { position: 1.0, color: Color::white() },
+            ],
+            follow_colors: true,
+            start_point: None,
+        }
+    }
+
+    pub fn color_at(&self, start: PixelPos, end: PixelPos, pixel_pos: PixelPos) -> Color {
+        let t = gradient_position(self.kind, start, end, pixel_pos);
+        let rgba = sample_stops(&self.stops, t, self.interpolation);
+        let noise = match self.dither {
+            true => bayer_dither(pixel_pos),
+            false => 0.0
+        };
+        let quantize = |value: f32| (value * 255.0 + 0.5 + noise).clamp(0.0, 255.0) as u8;
+        Color::new(quantize(rgba[0]), quantize(rgba[1]), quantize(rgba[2]), quantize(rgba[3]))
+    }
+}
+
+impl Default for GradientTool {
+    fn default() -> GradientTool {
+        GradientTool::new()
+    }
+}
+
+impl PaintTool for GradientTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "gradient", name: "Gradient", icon: "🌈", shortcut: Some(Shortcut::new(Key::G)), category: ToolCategory::Paint }
+    }
+
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, _layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        if self.follow_colors {
+            self.stops = vec![
+                GradientStop { position: 0.0, color: global_params.primary_color },
+                GradientStop { position: 1.0, color: global_params.secondary_color },
+            ];
+        }
+        tool_canvas.clear();
+        self.start_point = global_params.current_pixel;
+    }
+
+    // only the guide line is previewed, filling the whole layer every frame is too slow
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, _layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        tool_canvas.clear();
+        if let (Some(start), Some(end)) = (self.start_point, global_params.current_pixel) {
+            BresenhamLine::new(start, end).for_each(|(pos, _)|{
+                tool_canvas.set_pixel(pos, global_params.primary_color);
+            });
+        }
+    }
+
+    fn stroke_end(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, push_command : &mut dyn FnMut(EditCommand)){
+        tool_canvas.clear();
+        let start = self.start_point.take();
+        let end = global_params.current_pixel;
+        if let (Some(start), Some(end), Some(layer)) = (start, end, layers.get_active_layer()) {
+            let mut command = EditCommand::default();
+            let format = layer.format();
+            let space = layers.blend_space;
+            let (width, height) = layer.get_size();
+            for index in 0..(width * height) as usize {
+                let pos = PixelPos { x: index as u32 % width, y: index as u32 / width };
+                // wider formats take the gradient as it is, they don't need dithering
+                let rgba = match format {
+                    PixelFormat::Rgba8 => self.color_at(start, end, pos).to_rgba(),
+                    _ => sample_stops(&self.stops, gradient_position(self.kind, start, end, pos), self.interpolation)
+                };
+                let result = over(premultiply(rgba, space), premultiply(layer.rgba_at(index), space));
+                command.push_rgba(format, pos, unpremultiply(result, space));
+            }
+            push_command(command);
+        }
+    }
+
+    fn uses_symmetry(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> Vec<ToolOption> {
+        let interpolations = [GradientInterpolation::Srgb, GradientInterpolation::LinearLight];
+        vec![
+            ToolOption::choice("type", "Type", KINDS.iter().position(|kind| *kind == self.kind).unwrap_or(0), &["Linear", "Radial", "Angular", "Reflected", "Diamond"]),
+            ToolOption::choice("interpolation", "Interpolation", interpolations.iter().position(|interpolation| *interpolation == self.interpolation).unwrap_or(0), &["sRGB", "Linear light"]),
+            ToolOption::toggle("dither", "Dither", self.dither),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        match (id, value) {
+            ("type", ToolOptionValue::Choice(i)) => self.kind = KINDS[i.min(KINDS.len() - 1)],
+            ("interpolation", ToolOptionValue::Choice(i)) => self.interpolation = match i {
+                0 => GradientInterpolation::Srgb,
+                _ => GradientInterpolation::LinearLight
+            },
+            ("dither", ToolOptionValue::Toggle(dither)) => self.dither = dither,
+            _ => {}
+        }
+    }
+
+    // the stops don't fit the options, they get their own editor
+    fn settings_ui(&mut self, ui: &mut Ui) {
+        ui.label("Color stops");
+        let mut edited = false;
+        let mut remove = None;
+        let can_remove = self.stops.len() > 2;
+        for (i, stop) in self.stops.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                let mut color32 = stop.color.to_color32();
+                if ui.color_edit_button_srgba(&mut color32).changed() {
+                    stop.color = Color::from_color32(&color32);
+                    edited = true;
+                }
+                edited |= ui.add(DragValue::new(&mut stop.position).speed(0.01).clamp_range(0.0..=1.0)).changed();
+                if can_remove && ui.small_button("x").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.stops.remove(i);
+            edited = true;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add stop").clicked() {
+                let rgba = sample_stops(&self.stops, 0.5, self.interpolation).map(|value| (value * 255.0 + 0.5) as u8);
+                let color = Color::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+                self.stops.push(GradientStop { position: 0.5, color });
+                edited = true;
+            }
+            if ui.button("Primary to secondary").clicked() {
+                self.follow_colors = true;
+            }
+        });
+
+        if edited {
+            self.follow_colors = false;
+            self.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_position() {
+        let start = PixelPos { x: 0, y: 0 };
+        let end = PixelPos { x: 10, y: 0 };
+        assert_eq!(gradient_position(GradientKind::Linear, start, end, PixelPos { x: 5, y: 7 }), 0.5);
+        assert_eq!(gradient_position(GradientKind::Radial, start, end, PixelPos { x: 0, y: 5 }), 0.5);
+        assert_eq!(gradient_position(GradientKind::Diamond, start, end, PixelPos { x: 2, y: 3 }), 0.5);
+        assert_eq!(gradient_position(GradientKind::Angular, start, end, PixelPos { x: 0, y: 5 }), 0.25);
+    }
+
+    #[test]
+    fn test_sample_stops() {
+        let stops = [
+            GradientStop { position: 0.0, color: Color::black() },
+            GradientStop { position: 1.0, color: Color::white() },
+        ];
+        assert_eq!(sample_stops(&stops, 0.5, GradientInterpolation::Srgb), [0.5, 0.5, 0.5, 1.0]);
+        let linear = sample_stops(&stops, 0.5, GradientInterpolation::LinearLight);
+        assert!((linear[0] - 0.735).abs() < 0.01);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use crate::paint_app::canvas::{CanvasLayerEntry, LayerContent, LayerGroup, LayerId, LayerLocks};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer, LayerBuffer};
+use crate::paint_app::indexed::{median_cut, nearest_index, TRANSPARENT_ALPHA};
+use crate::paint_app::raster_formats::decode_raster;
+use crate::paint_app::slices::SliceSet;
+use super::data_types::*;
+
+// the largest width or height a layered file can have, photoshop's own limit
+pub const MAX_DOCUMENT_SIZE: u32 = 30000;
+
+// a layered file from another editor, the entries are ordered like CanvasLayers::entries
+pub struct ImportedDocument {
+    pub size: (u32, u32),
+    pub entries: Vec<CanvasLayerEntry>,
+    // what couldn't be brought over as it was
+    pub warnings: Vec<String>,
+    pub slices: SliceSet,
+}
+
+impl ImportedDocument {
+    // every layer gets the size from the header of the file, so it is checked before anything is allocated
+    pub fn new(width: u32, height: u32) -> Result<ImportedDocument, String> {
+        if width == 0 || height == 0 || width > MAX_DOCUMENT_SIZE || height > MAX_DOCUMENT_SIZE {
+            return Err(format!("the size {}x{} isn't between 1x1 and {}x{}", width, height, MAX_DOCUMENT_SIZE, MAX_DOCUMENT_SIZE));
+        }
+        Ok(ImportedDocument {
+            size: (width, height),
+            entries: Vec::new(),
+            warnings: Vec::new(),
+            slices: SliceSet::new(),
+        })
+    }
+
+    // layers are added from the top down, groups right before their children
+    pub fn push(&mut self, name: String, content: LayerContent, layer: FlatCanvasLayer, visible: bool, parent: Option<LayerId>) -> LayerId {
+        let id = LayerId(self.entries.len());
+        let name = match name.trim().is_empty() {
+            true => content.kind().default_name(id),
+            false => name
+        };
+        self.entries.push(CanvasLayerEntry {
+            id,
+            name,
+            layer: LayerBuffer::Rgba8(layer),
+            content,
+            visible,
+            mask: None,
+            clipped: false,
+            parent,
+            locks: LayerLocks::default(),
+            revision: 0,
+        });
+        id
+    }
+
+    pub fn push_group(&mut self, name: String, group: LayerGroup, visible: bool, parent: Option<LayerId>) -> LayerId {
+        let layer = FlatCanvasLayer::new(self.size.0, self.size.1);
+        self.push(name, LayerContent::Group(group), layer, visible, parent)
+    }
+
+    // pixels outside of the document are cut off, with a warning
+    pub fn place(&mut self, name: &str, image: &FlatCanvasLayer, x: i64, y: i64) -> FlatCanvasLayer {
+        let mut result = FlatCanvasLayer::new(self.size.0, self.size.1);
+        let mut cut = false;
+        for (pos, color) in image.iter_pixels() {
+            let (target_x, target_y) = (pos.x as i64 + x, pos.y as i64 + y);
+            match target_x >= 0 && target_y >= 0 && target_x < self.size.0 as i64 && target_y < self.size.1 as i64 {
+                true => result.set_pixel(PixelPos { x: target_x as u32, y: target_y as u32 }, *color),
+                false => cut |= color.alpha > 0
+            }
+        }
+        if cut {
+            self.warnings.push(format!("{}: the parts outside of the canvas were cut off", name));
+        }
+        result
+    }
+
+    // only groups have an opacity here, other layers get theirs multiplied into the pixels
+    pub fn apply_opacity(&mut self, name: &str, layer: &mut FlatCanvasLayer, opacity: f32) {
+        if opacity >= 1.0 {
+            return;
+        }
+        layer.get_data_mut().iter_mut().for_each(|color| color.alpha = (color.alpha as f32 * opacity.max(0.0)).round() as u8);
+        self.warnings.push(format!("{}: the layer opacity of {}% was applied to the pixels", name, (opacity * 100.0).round()));
+    }
+}
+
+// the format comes from the bytes, see RasterFormat::detect
+pub fn decode_image(bytes: &[u8]) -> Result<FlatCanvasLayer, String> {
+    decode_raster(bytes)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_file(path: &str, bytes: &[u8]) -> Result<(), String> {
+    std::fs::write(path, bytes).map_err(|err| format!("{}: {}", path, err))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_file(path: &str, _bytes: &[u8]) -> Result<(), String> {
+    Err(format!("{}: saving files is not supported on the web yet", path))
+}
+
+// the folder is made when it doesn't exist yet
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_files(folder: &str, files: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let folder = std::path::Path::new(folder);
+    std::fs::create_dir_all(folder).map_err(|err| format!("{}: {}", folder.display(), err))?;
+    files.iter().try_for_each(|(name, bytes)| write_file(&folder.join(name).to_string_lossy(), bytes))
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_files(folder: &str, _files: &[(String, Vec<u8>)]) -> Result<(), String> {
+    Err(format!("{}: saving files is not supported on the web yet", folder))
+}
+
+fn layer_bytes(layer: &FlatCanvasLayer) -> Vec<u8> {
+    layer.get_data().iter().flat_map(|color| [color.red, color.green, color.blue, color.alpha]).collect()
+}
+
+pub fn encode_png(layer: &FlatCanvasLayer) -> Result<Vec<u8>, String> {
+    let (width, height) = layer.get_size();
+    let mut result = Vec::new();
+    let mut encoder = png::Encoder::new(&mut result, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer.write_image_data(&layer_bytes(layer)).map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())?;
+    Ok(result)
+}
+
+// durations are in milliseconds, it loops forever. viewers without apng support show the first frame
+pub fn encode_apng(frames: &[(FlatCanvasLayer, u32)]) -> Result<Vec<u8>, String> {
+    let Some((first, _)) = frames.first() else { return Err("an animation needs at least one frame".to_string()) };
+    let (width, height) = first.get_size();
+    if frames.iter().any(|(layer, _)| layer.get_size() != (width, height)) {
+        return Err("all frames of an animation need the same size".to_string());
+    }
+    let mut result = Vec::new();
+    let mut encoder = png::Encoder::new(&mut result, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0).map_err(|err| err.to_string())?;
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    for (layer, duration) in frames {
+        writer.set_frame_delay((*duration).min(u16::MAX as u32) as u16, 1000).map_err(|err| err.to_string())?;
+        // every frame replaces the one before completely
+        writer.set_dispose_op(png::DisposeOp::Background).map_err(|err| err.to_string())?;
+        writer.set_blend_op(png::BlendOp::Source).map_err(|err| err.to_string())?;
+        writer.write_image_data(&layer_bytes(layer)).map_err(|err| err.to_string())?;
+    }
+    writer.finish().map_err(|err| err.to_string())?;
+    Ok(result)
+}
+
+// the frames share one palette and durations are in milliseconds. it loops forever and every frame
+// is cleared before the next one, so the one before doesn't show through transparent pixels
+pub fn encode_animated_gif(frames: &[(FlatCanvasLayer, u32)]) -> Result<Vec<u8>, String> {
+    let Some((first, _)) = frames.first() else { return Err("an animation needs at least one frame".to_string()) };
+    let (width, height) = first.get_size();
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("gif images can be at most {} pixels wide and high", u16::MAX));
+    }
+    if frames.iter().any(|(layer, _)| layer.get_size() != (width, height)) {
+        return Err("all frames of an animation need the same size".to_string());
+    }
+    let pixels = || frames.iter().flat_map(|(layer, _)| layer.get_data().iter());
+    let has_transparency = pixels().any(|color| color.alpha < TRANSPARENT_ALPHA);
+    let search = median_cut(pixels().copied(), if has_transparency { 255 } else { 256 });
+    let transparent_index = search.len() as u8;
+    let mut palette = search.iter().flat_map(|color| [color.red, color.green, color.blue]).collect::<Vec<_>>();
+    if has_transparency || search.is_empty() {
+        palette.extend_from_slice(&[0, 0, 0]);
+    }
+    let mut nearest: HashMap<[u8; 3], u8> = HashMap::new();
+
+    let mut result = Vec::new();
+    let mut encoder = gif::Encoder::new(&mut result, width as u16, height as u16, &palette).map_err(|err| err.to_string())?;
+    encoder.set_repeat(gif::Repeat::Infinite).map_err(|err| err.to_string())?;
+    for (layer, duration) in frames {
+        let indices = layer.get_data().iter().map(|color| match color.alpha < TRANSPARENT_ALPHA {
+            true => transparent_index,
+            false => *nearest.entry([color.red, color.green, color.blue]).or_insert_with(|| {
+                nearest_index(&search, [color.red as f32, color.green as f32, color.blue as f32]).unwrap_or(0) as u8
+            })
+        }).collect::<Vec<_>>();
+        let mut frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, indices, has_transparency.then_some(transparent_index));
+        // in hundredths of a second, browsers slow down anything below 2
+        frame.delay = duration.div_ceil(10).clamp(2, u16::MAX as u32) as u16;
+        frame.dispose = gif::DisposalMethod::Background;
+        encoder.write_frame(&frame).map_err(|err| err.to_string())?;
+    }
+    drop(encoder);
+    Ok(result)
+}
+
+// transparent pixels get an extra palette entry with alpha 0, other pixels use the nearest palette color
+pub fn encode_indexed_png(layer: &FlatCanvasLayer, palette: &[Color]) -> Result<Vec<u8>, String> {
+    if palette.is_empty() || palette.len() > 255 {
+        return Err(format!("indexed png needs 1 to 255 colors, got {}", palette.len()));
+    }
+    let transparent_index = palette.len() as u8;
+    let indices = layer.get_data().iter().map(|color| match color.alpha {
+        0..=127 => transparent_index,
+        _ => nearest_index(palette, [color.red as f32, color.green as f32, color.blue as f32]).unwrap_or(0) as u8,
+    }).collect::<Vec<_>>();
+
+    let mut rgb = palette.iter().flat_map(|color| [color.red, color.green, color.blue]).collect::<Vec<_>>();
+    let mut alpha = vec![255u8; palette.len()];
+    if indices.contains(&transparent_index) {
+        rgb.extend_from_slice(&[0, 0, 0]);
+        alpha.push(0);
+    }
+
+    let (width, height) = layer.get_size();
+    let mut result = Vec::new();
+    let mut encoder = png::Encoder::new(&mut result, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(rgb);
+    encoder.set_trns(alpha);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer.write_image_data(&indices).map_err(|err| err.to_string())?;
+    writer.finish().map_err(|err| err.to_string())?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indexed_png_round_trip() {
+        let palette = [Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255)];
+        let mut layer = FlatCanvasLayer::new(4, 2);
+        layer.fill(palette[0]);
+        layer.set_pixel(PixelPos { x: 1, y: 0 }, palette[1]);
+        layer.set_pixel(PixelPos { x: 2, y: 1 }, Color::new(0, 0, 0, 0));
+
+        let bytes = encode_indexed_png(&layer, &palette).unwrap();
+        let decoded = decode_image(&bytes).unwrap();
+        assert_eq!(decoded.get_size(), (4, 2));
+        assert_eq!(decoded.get_pixel(PixelPos { x: 0, y: 0 }), palette[0]);
+        assert_eq!(decoded.get_pixel(PixelPos { x: 1, y: 0 }), palette[1]);
+        assert_eq!(decoded.get_pixel(PixelPos { x: 2, y: 1 }).alpha, 0);
+    }
+
+    #[test]
+    fn test_apng() {
+        let frames = [Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 128)].map(|color| {
+            let mut layer = FlatCanvasLayer::new(3, 2);
+            layer.fill(color);
+            (layer, 250)
+        });
+        let bytes = encode_apng(&frames).unwrap();
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        assert_eq!(reader.info().animation_control.map(|control| control.num_frames), Some(2));
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for (layer, _) in &frames {
+            reader.next_frame(&mut buffer).unwrap();
+            let control = reader.info().frame_control.unwrap();
+            assert_eq!((control.delay_num, control.delay_den), (250, 1000));
+            assert_eq!(&buffer[..4], &layer_bytes(layer)[..4]);
+        }
+        // the first frame is the default image
+        assert_eq!(decode_image(&bytes).unwrap().get_data(), frames[0].0.get_data());
+    }
+
+    #[test]
+    fn test_animated_gif() {
+        let frames = [Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255)].map(|color| {
+            let mut layer = FlatCanvasLayer::new(4, 4);
+            layer.set_pixel(PixelPos { x: 1, y: 2 }, color);
+            (layer, 120)
+        });
+        let bytes = encode_animated_gif(&frames).unwrap();
+        assert!(bytes.windows(11).any(|window| window == b"NETSCAPE2.0"));
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(bytes.as_slice()).unwrap();
+        for (layer, _) in &frames {
+            let frame = decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!((frame.delay, frame.dispose), (12, gif::DisposalMethod::Background));
+            assert_eq!(&frame.buffer[36..40], &layer_bytes(layer)[36..40]);
+            assert_eq!(frame.buffer[3], 0);
+        }
+        assert!(decoder.read_next_frame().unwrap().is_none());
+        let first = decode_image(&bytes).unwrap();
+        assert_eq!(first.get_pixel(PixelPos { x: 1, y: 2 }), Color::new(255, 0, 0, 255));
+        assert_eq!(first.get_pixel(PixelPos { x: 0, y: 0 }).alpha, 0);
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use crate::paint_app::canvas_layer::CanvasLayer;
+use crate::paint_app::utils::bayer_dither;
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dither {
+    None,
+    FloydSteinberg,
+    Ordered,
+}
+
+// pixels below this alpha become transparent, everything else is snapped to an opaque palette entry
+pub const TRANSPARENT_ALPHA: u8 = 128;
+
+pub fn transparent() -> Color {
+    Color::new(255, 255, 255, 0)
+}
+
+fn distance(a: [f32; 3], b: Color) -> f32 {
+    let dr = a[0] - b.red as f32;
+    let dg = a[1] - b.green as f32;
+    let db = a[2] - b.blue as f32;
+    dr * dr + dg * dg + db * db
+}
+
+pub fn nearest_index(palette: &[Color], rgb: [f32; 3]) -> Option<usize> {
+    palette.iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(rgb, **a).total_cmp(&distance(rgb, **b)))
+        .map(|(i, _)| i)
+}
+
+pub fn nearest_color(palette: &[Color], color: Color) -> Color {
+    if color.alpha < TRANSPARENT_ALPHA {
+        return transparent();
+    }
+    let rgb = [color.red as f32, color.green as f32, color.blue as f32];
+    nearest_index(palette, rgb).map(|i| palette[i]).unwrap_or(color)
+}
+
+// weighted median cut over the distinct opaque colors
+pub fn median_cut(colors: impl Iterator<Item = Color>, count: usize) -> Vec<Color> {
+    let mut histogram: HashMap<[u8; 3], u32> = HashMap::new();
+    colors.filter(|color| color.alpha >= TRANSPARENT_ALPHA).for_each(|color| {
+        *histogram.entry([color.red, color.green, color.blue]).or_insert(0) += 1;
+    });
+    if histogram.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let channel_range = |bucket: &Vec<([u8; 3], u32)>, channel: usize| {
+        let min = bucket.iter().map(|(rgb, _)| rgb[channel]).min().unwrap_or(0);
+        let max = bucket.iter().map(|(rgb, _)| rgb[channel]).max().unwrap_or(0);
+        max - min
+    };
+
+    let mut buckets = vec![histogram.into_iter().collect::<Vec<_>>()];
+    while buckets.len() < count {
+        // split the bucket with the widest channel
+        let widest = buckets.iter()
+            .enumerate()
+            .flat_map(|(i, bucket)| (0..3).map(move |channel| (i, channel, channel_range(bucket, channel))))
+            .max_by_key(|(_, _, range)| *range);
+        let Some((index, channel, range)) = widest else { break };
+        if range == 0 {
+            break;
+        }
+
+        let mut bucket = buckets.swap_remove(index);
+        bucket.sort_by_key(|(rgb, _)| rgb[channel]);
+        let total = bucket.iter().map(|(_, weight)| *weight as u64).sum::<u64>();
+        let mut running = 0u64;
+        let mut split = bucket.iter().position(|(_, weight)| {
+            running += *weight as u64;
+            running * 2 >= total
+        }).unwrap_or(0) + 1;
+        split = split.clamp(1, bucket.len() - 1);
+        let upper = bucket.split_off(split);
+        buckets.push(bucket);
+        buckets.push(upper);
+    }
+
+    buckets.iter().map(|bucket| {
+        let total = bucket.iter().map(|(_, weight)| *weight as u64).sum::<u64>().max(1);
+        let average = |channel: usize| (bucket.iter().map(|(rgb, weight)| rgb[channel] as u64 * *weight as u64).sum::<u64>() / total) as u8;
+        Color::new(average(0), average(1), average(2), 255)
+    }).collect()
+}
+
+// replaces every pixel of layer with a palette color or transparent
+pub fn quantize_layer(layer: &mut dyn CanvasLayer, palette: &[Color], dither: Dither) {
+    if palette.is_empty() {
+        return;
+    }
+    let (width, height) = layer.get_size();
+    let ordered_spread = 255.0 / (palette.len() as f32).cbrt().max(1.0);
+    // error carried to the next pixels for floyd-steinberg, one row ahead is enough
+    let mut errors = vec![[0f32; 3]; (width as usize + 2) * 2];
+    let row_len = width as usize + 2;
+
+    for y in 0..height {
+        let (current, next) = errors.split_at_mut(row_len);
+        for x in 0..width {
+            let pos = PixelPos { x, y };
+            let color = layer.get_pixel(pos);
+            if color.alpha < TRANSPARENT_ALPHA {
+                layer.set_pixel(pos, transparent());
+                continue;
+            }
+            let mut rgb = [color.red as f32, color.green as f32, color.blue as f32];
+            match dither {
+                Dither::None => {}
+                Dither::FloydSteinberg => {
+                    let error = current[x as usize + 1];
+                    (0..3).for_each(|i| rgb[i] = (rgb[i] + error[i]).clamp(0.0, 255.0));
+                }
+                Dither::Ordered => {
+                    let offset = bayer_dither(pos) * ordered_spread;
+                    (0..3).for_each(|i| rgb[i] = (rgb[i] + offset).clamp(0.0, 255.0));
+                }
+            }
+
+            let new_color = palette[nearest_index(palette, rgb).unwrap_or(0)];
+            layer.set_pixel(pos, new_color);
+
+            if dither == Dither::FloydSteinberg {
+                let error = [rgb[0] - new_color.red as f32, rgb[1] - new_color.green as f32, rgb[2] - new_color.blue as f32];
+                let i = x as usize + 1;
+                for channel in 0..3 {
+                    current[i + 1][channel] += error[channel] * 7.0 / 16.0;
+                    next[i - 1][channel] += error[channel] * 3.0 / 16.0;
+                    next[i][channel] += error[channel] * 5.0 / 16.0;
+                    next[i + 1][channel] += error[channel] * 1.0 / 16.0;
+                }
+            }
+        }
+        // move the next row up and start a fresh one
+        current.copy_from_slice(next);
+        next.iter_mut().for_each(|error| *error = [0.0; 3]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas_layer::FlatCanvasLayer;
+
+    #[test]
+    fn test_median_cut() {
+        let colors = [Color::new(255, 0, 0, 255), Color::new(250, 0, 0, 255), Color::new(0, 0, 255, 255), Color::new(0, 0, 0, 0)];
+        let mut palette = median_cut(colors.into_iter(), 2);
+        palette.sort_by_key(|color| color.red);
+        assert_eq!(palette, vec![Color::new(0, 0, 255, 255), Color::new(252, 0, 0, 255)]);
+    }
+
+    #[test]
+    fn test_quantize_layer() {
+        let palette = [Color::black(), Color::white()];
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+            let mut layer = FlatCanvasLayer::new(8, 8);
+            layer.fill(Color::new(128, 128, 128, 255));
+            layer.set_pixel(PixelPos { x: 0, y: 0 }, Color::new(0, 0, 0, 0));
+            quantize_layer(&mut layer, &palette, dither);
+            assert_eq!(layer.get_pixel(PixelPos { x: 0, y: 0 }), transparent());
+            assert!(layer.iter_pixels().skip(1).all(|(_, color)| palette.contains(color)));
+            if dither != Dither::None {
+                let white = layer.iter_pixels().filter(|(_, color)| **color == Color::white()).count();
+                assert!(white > 16 && white < 48, "{:?} {}", dither, white);
+            }
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use eframe::egui;
+use egui::DragValue;
+use crate::paint_app::indexed::Dither;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteSource {
+    Generate,
+    PalettePanel,
+}
+
+pub struct IndexedWindow {
+    pub open: bool,
+    pub colors: usize,
+    pub palette_source: PaletteSource,
+    pub dither: Dither,
+}
+
+impl IndexedWindow {
+    pub fn new() -> IndexedWindow {
+        IndexedWindow {
+            open: false,
+            colors: 16,
+            palette_source: PaletteSource::Generate,
+            dither: Dither::None,
+        }
+    }
+
+    // returns true when the conversion was confirmed
+    pub fn show_indexed_window(&mut self, ctx: &egui::Context) -> bool {
+        let mut confirmed = false;
+        let mut close = false;
+        egui::Window::new("Indexed color")
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                ui.heading("Palette");
+                ui.radio_value(&mut self.palette_source, PaletteSource::Generate, "Generate (median cut)");
+                ui.add_enabled_ui(self.palette_source == PaletteSource::Generate, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.label("Colors: ");
+                        ui.add(DragValue::new(&mut self.colors).speed(1.0).clamp_range(2..=255));
+                    });
+                });
+                ui.radio_value(&mut self.palette_source, PaletteSource::PalettePanel, "Use the palette panel");
+
+                ui.separator();
+
+                ui.heading("Dithering");
+                ui.radio_value(&mut self.dither, Dither::None, "None");
+                ui.radio_value(&mut self.dither, Dither::FloydSteinberg, "Floyd-Steinberg");
+                ui.radio_value(&mut self.dither, Dither::Ordered, "Ordered");
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("OK").clicked() {
+                        confirmed = true;
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if close {
+            self.open = false;
+        }
+        confirmed
+    }
+}
+
+impl Default for IndexedWindow {
+    fn default() -> IndexedWindow {
+        IndexedWindow::new()
+    }
+}
@@ -2,4 +2,42 @@ pub mod canvas_layer;
 pub mod data_types;
 pub mod utils;
 pub mod canvas;
-pub mod size_window;
\ No newline at end of file
+pub mod size_window;
+pub mod gradient_tool;
+pub mod color_picker_tool;
+pub mod text_layer;
+pub mod text_tool;
+pub mod palette;
+pub mod indexed;
+pub mod image_io;
+pub mod indexed_window;
+pub mod export_window;
+pub mod symmetry;
+pub mod adjustments;
+pub mod adjustment_window;
+pub mod filters;
+pub mod filter_window;
+pub mod compositor;
+pub mod new_document_window;
+pub mod fast_overlap;
+pub mod actions;
+pub mod command_palette;
+pub mod shortcuts_window;
+pub mod tool_registry;
+pub mod config;
+pub mod rasterize;
+pub mod vector_path;
+pub mod pen_tool;
+pub mod paths_panel;
+pub mod vector_layer;
+pub mod document_export;
+pub mod zip;
+pub mod ora;
+pub mod psd;
+pub mod file_warnings_window;
+pub mod webp;
+pub mod raster_formats;
+pub mod sprite_sheet;
+pub mod timeline_panel;
+pub mod slices;
+pub mod slices_window;
@@ -0,0 +1,80 @@
+use eframe::egui;
+use egui::DragValue;
+use crate::paint_app::utils::BlendSpace;
+use super::data_types::*;
+
+pub struct NewDocumentWindow {
+    pub open: bool,
+    pub width: u32,
+    pub height: u32,
+    pub format: PixelFormat,
+    pub blend_space: BlendSpace,
+}
+
+impl NewDocumentWindow {
+    pub fn new() -> NewDocumentWindow {
+        NewDocumentWindow {
+            open: false,
+            width: 1024,
+            height: 768,
+            format: PixelFormat::Rgba8,
+            blend_space: BlendSpace::Srgb,
+        }
+    }
+
+    // returns true when the document should be created
+    pub fn show_new_document_window(&mut self, ctx: &egui::Context) -> bool {
+        let mut create = false;
+        let mut close = false;
+        egui::Window::new("New document")
+            .resizable(false)
+            .collapsible(false)
+            .open(&mut self.open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Width: ");
+                    ui.add(DragValue::new(&mut self.width).speed(1.0).clamp_range(1..=16384));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Height: ");
+                    ui.add(DragValue::new(&mut self.height).speed(1.0).clamp_range(1..=16384));
+                });
+
+                ui.separator();
+
+                ui.label("Bit depth");
+                ui.horizontal(|ui| {
+                    for format in PixelFormat::all() {
+                        ui.radio_value(&mut self.format, format, format.name());
+                    }
+                });
+                ui.label("Blending");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.blend_space, BlendSpace::Srgb, "sRGB");
+                    ui.radio_value(&mut self.blend_space, BlendSpace::Linear, "Linear light");
+                });
+
+                ui.separator();
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.button("OK").clicked() {
+                        create = true;
+                        close = true;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        close = true;
+                    }
+                });
+            });
+        if close {
+            self.open = false;
+        }
+        create
+    }
+}
+
+impl Default for NewDocumentWindow {
+    fn default() -> NewDocumentWindow {
+        NewDocumentWindow::new()
+    }
+}
@@ -0,0 +1,308 @@
+use crate::paint_app::canvas::{CanvasLayers, GroupBlend, LayerContent, LayerGroup, LayerId};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::document_export::{escape_xml, export_nodes, ExportContent, ExportNode};
+use crate::paint_app::image_io::{decode_image, encode_png, ImportedDocument};
+use crate::paint_app::slices::SliceSet;
+use crate::paint_app::utils::BlendSpace;
+use crate::paint_app::vector_layer::{render_vector_layer, VectorLayer};
+use crate::paint_app::zip::{read_zip, ZipWriter};
+
+// openraster is a zip with this as the first file, a stack.xml listing the layers
+// top first and a png for every layer
+const MIME_TYPE: &str = "image/openraster";
+const NORMAL: &str = "svg:src-over";
+// other apps ignore files they don't know, the slices go along in one
+const SLICES: &str = "paintdesk/slices.txt";
+
+// an element of stack.xml, end tags have no attributes
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+    end: bool,
+    // <layer ... />, which ends right away
+    empty: bool,
+}
+
+impl Tag {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+fn unescape_xml(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result += &rest[..start];
+        rest = &rest[start..];
+        let Some(end) = rest.find(';') else { break };
+        let entity = &rest[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity.strip_prefix('#').and_then(|number| number.parse().ok()).and_then(char::from_u32)
+            }
+        };
+        match character {
+            Some(character) => {
+                result.push(character);
+                rest = &rest[end + 1..];
+            }
+            // not an entity, keep the & as it is
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result + rest
+}
+
+// the tags in order, text between them is ignored since stack.xml keeps everything in attributes
+fn parse_tags(xml: &str) -> Result<Vec<Tag>, String> {
+    let mut result = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        // declarations and comments
+        if rest.starts_with('?') || rest.starts_with('!') {
+            let end = match rest.starts_with("!--") {
+                true => rest.find("-->").map(|end| end + 3),
+                false => rest.find('>').map(|end| end + 1)
+            };
+            rest = &rest[end.ok_or("stack.xml ends inside a tag")?..];
+            continue;
+        }
+        // a > inside quotes doesn't end the tag
+        let mut quote = None;
+        let end = rest.char_indices().find(|(_, character)| match quote {
+            Some(open) => {
+                if *character == open {
+                    quote = None;
+                }
+                false
+            }
+            None => {
+                if *character == '"' || *character == '\'' {
+                    quote = Some(*character);
+                }
+                *character == '>'
+            }
+        }).map(|(end, _)| end).ok_or("stack.xml ends inside a tag")?;
+        let mut body = rest[..end].trim();
+        rest = &rest[end + 1..];
+
+        let end_tag = body.starts_with('/');
+        body = body.trim_start_matches('/');
+        let empty = body.ends_with('/');
+        body = body.trim_end_matches('/').trim_end();
+        let name_end = body.find(char::is_whitespace).unwrap_or(body.len());
+        let mut attributes = Vec::new();
+        let mut text = body[name_end..].trim_start();
+        while !text.is_empty() {
+            let (key, value) = text.split_once('=').ok_or(format!("broken attribute in <{}>", &body[..name_end]))?;
+            let value = value.trim_start();
+            let quote = value.chars().next().filter(|quote| *quote == '"' || *quote == '\'').ok_or(format!("unquoted attribute {}", key.trim()))?;
+            let value_end = value[1..].find(quote).ok_or(format!("unclosed attribute {}", key.trim()))? + 1;
+            attributes.push((key.trim().to_string(), unescape_xml(&value[1..value_end])));
+            text = value[value_end + 1..].trim_start();
+        }
+        result.push(Tag { name: body[..name_end].to_string(), attributes, end: end_tag, empty });
+    }
+    Ok(result)
+}
+
+pub fn read_ora(bytes: &[u8]) -> Result<ImportedDocument, String> {
+    let files = read_zip(bytes)?;
+    let file = |name: &str| files.iter().find(|(file_name, _)| file_name == name).map(|(_, data)| data);
+    if file("mimetype").is_some_and(|mime| mime.trim_ascii() != MIME_TYPE.as_bytes()) {
+        return Err("not an OpenRaster file".to_string());
+    }
+    let stack = file("stack.xml").ok_or("stack.xml is missing")?;
+    let tags = parse_tags(&String::from_utf8_lossy(stack))?;
+    let image = tags.iter().find(|tag| tag.name == "image" && !tag.end).ok_or("stack.xml has no image")?;
+    let size = |name: &str| image.get(name).and_then(|value| value.parse::<u32>().ok()).ok_or(format!("the image has no {}", name));
+    let mut document = ImportedDocument::new(size("w")?, size("h")?)?;
+
+    // the groups the next layer goes into, the outermost stack is the document itself
+    let mut parents: Vec<Option<LayerId>> = Vec::new();
+    for tag in tags.iter() {
+        let parent = parents.last().copied().flatten();
+        let name = tag.get("name").or(tag.get("src")).unwrap_or("").to_string();
+        let visible = tag.get("visibility") != Some("hidden");
+        let opacity = tag.get("opacity").and_then(|opacity| opacity.parse::<f32>().ok()).unwrap_or(1.0).clamp(0.0, 1.0);
+        let blend = tag.get("composite-op").unwrap_or(NORMAL);
+        if !tag.end && blend != NORMAL && matches!(tag.name.as_str(), "stack" | "layer") {
+            document.warnings.push(format!("{}: the blend mode {} isn't supported, normal is used", name, blend));
+        }
+        match (tag.name.as_str(), tag.end) {
+            ("image", _) => {}
+            ("stack", false) => {
+                if parents.is_empty() {
+                    parents.push(None);
+                } else {
+                    let blend = match tag.get("isolation") {
+                        Some("isolate") => GroupBlend::Isolated,
+                        _ => GroupBlend::PassThrough
+                    };
+                    let id = document.push_group(name, LayerGroup { opacity, blend, collapsed: false }, visible, parent);
+                    parents.push(Some(id));
+                }
+                if tag.empty {
+                    parents.pop();
+                }
+            }
+            ("stack", true) => {
+                parents.pop();
+            }
+            ("layer", false) => {
+                let src = tag.get("src").ok_or(format!("{}: the layer has no image", name))?;
+                let data = file(src).ok_or(format!("{}: {} is missing", name, src))?;
+                let image = decode_image(data).map_err(|err| format!("{}: {}", src, err))?;
+                let offset = |name: &str| tag.get(name).and_then(|value| value.parse::<i64>().ok()).unwrap_or(0);
+                let mut layer = document.place(&name, &image, offset("x"), offset("y"));
+                document.apply_opacity(&name, &mut layer, opacity);
+                document.push(name, LayerContent::Raster, layer, visible, parent);
+            }
+            (_, true) => {}
+            (other, false) => document.warnings.push(format!("{}: <{}> elements aren't supported and were left out", name, other))
+        }
+    }
+    if let Some(slices) = file(SLICES) {
+        match SliceSet::from_text(&String::from_utf8_lossy(slices)) {
+            Ok(slices) => document.slices = slices,
+            Err(err) => document.warnings.push(format!("the slices were left out, {}", err))
+        }
+    }
+    Ok(document)
+}
+
+// openraster has no masks, clipping, vector or adjustment layers. the first three are
+// written as the pixels they end up as, the warnings say what was changed
+pub fn encode_ora(layers: &CanvasLayers, merged: &FlatCanvasLayer, slices: &SliceSet) -> Result<(Vec<u8>, Vec<String>), String> {
+    let (width, height) = merged.get_size();
+    let mut warnings = Vec::new();
+    for entry in layers.entries.iter() {
+        match &entry.content {
+            LayerContent::Adjustment(_) => warnings.push(format!("{}: adjustment layers can't be stored and were left out", entry.name)),
+            LayerContent::Text { .. } | LayerContent::Vector { .. } => warnings.push(format!("{}: stored as pixels", entry.name)),
+            _ => {}
+        }
+        if entry.clipped {
+            warnings.push(format!("{}: the clipping was applied to the pixels", entry.name));
+        }
+    }
+
+    let mut stack = format!("<?xml version='1.0' encoding='UTF-8'?>\n<image version=\"0.0.5\" w=\"{}\" h=\"{}\">\n<stack>\n", width, height);
+    let mut files = Vec::new();
+    for node in export_nodes(layers).iter().rev() {
+        write_node(&mut stack, &mut files, &mut warnings, node, None, (merged.get_size(), layers.blend_space), 1)?;
+    }
+    stack += "</stack>\n</image>\n";
+
+    let mut zip = ZipWriter::new();
+    zip.add("mimetype", MIME_TYPE.as_bytes());
+    zip.add("stack.xml", stack.as_bytes());
+    for (name, bytes) in files.iter() {
+        zip.add(name, bytes);
+    }
+    zip.add("mergedimage.png", &encode_png(merged)?);
+    zip.add("Thumbnails/thumbnail.png", &encode_png(&merged.thumbnail(256))?);
+    if !slices.is_empty() {
+        zip.add(SLICES, slices.to_text().as_bytes());
+    }
+    Ok((zip.finish(), warnings))
+}
+
+// mask is what the masks of the groups around the node leave of each pixel, render
+// is the size and blend space vector layers are drawn with
+fn write_node(stack: &mut String, files: &mut Vec<(String, Vec<u8>)>, warnings: &mut Vec<String>, node: &ExportNode, mask: Option<&[f32]>, render: ((u32, u32), BlendSpace), depth: usize) -> Result<(), String> {
+    let mask = match &node.mask {
+        Some(own) => {
+            warnings.push(format!("{}: the mask was applied to the pixels", node.name));
+            let own = own.get_data().iter().map(|color| color.red as f32 / 255.0);
+            Some(match mask {
+                Some(outer) => own.zip(outer.iter()).map(|(own, outer)| own * outer).collect::<Vec<_>>(),
+                None => own.collect()
+            })
+        }
+        None => mask.map(|mask| mask.to_vec())
+    };
+    let pad = "  ".repeat(depth);
+    let attributes = format!("name=\"{}\" visibility=\"{}\" opacity=\"{}\"", escape_xml(&node.name), if node.visible { "visible" } else { "hidden" }, node.opacity);
+    let mut image = match &node.content {
+        ExportContent::Group(children) => {
+            *stack += &format!("{}<stack {}>\n", pad, attributes);
+            for child in children.iter().rev() {
+                write_node(stack, files, warnings, child, mask.as_deref(), render, depth + 1)?;
+            }
+            *stack += &format!("{}</stack>\n", pad);
+            return Ok(());
+        }
+        ExportContent::Image(image) => image.clone(),
+        ExportContent::Shapes(shapes) => {
+            let ((width, height), space) = render;
+            let mut layer = FlatCanvasLayer::new(width, height);
+            render_vector_layer(&VectorLayer { shapes: shapes.clone() }, space, &mut layer);
+            layer
+        }
+    };
+    if let Some(mask) = mask {
+        for (color, coverage) in image.get_data_mut().iter_mut().zip(mask.iter()) {
+            color.alpha = (color.alpha as f32 * coverage).round() as u8;
+        }
+    }
+    let src = format!("data/layer{}.png", files.len());
+    files.push((src.clone(), encode_png(&image)?));
+    *stack += &format!("{}<layer {} src=\"{}\" x=\"0\" y=\"0\" composite-op=\"{}\"/>\n", pad, attributes, src, NORMAL);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas::Canvas;
+    use crate::paint_app::data_types::*;
+
+    #[test]
+    fn test_parse_tags() {
+        let tags = parse_tags("<?xml version='1.0'?><!-- a > b --><image w=\"4\" h='2'><stack><layer name=\"a &amp; &#62; b\" src=\"x.png\"/></stack></image>").unwrap();
+        assert_eq!(tags.iter().map(|tag| (tag.name.as_str(), tag.end, tag.empty)).collect::<Vec<_>>(),
+            vec![("image", false, false), ("stack", false, false), ("layer", false, true), ("stack", true, false), ("image", true, false)]);
+        assert_eq!(tags[0].get("h"), Some("2"));
+        assert_eq!(tags[2].get("name"), Some("a & > b"));
+        assert!(parse_tags("<layer name=\"open").is_err());
+    }
+
+    #[test]
+    fn test_ora_round_trip() {
+        let mut canvas = Canvas::new(256, 256);
+        canvas.group_active_layer();
+        let mut config = canvas.get_canvas_layers_config();
+        let hidden = config.entries[1].id;
+        config.find_mut(hidden).unwrap().visible = false;
+        config.find_mut(hidden).unwrap().name = "Red & <blue>".to_string();
+        canvas.set_canvas_layers_config(config);
+
+        let layers = canvas.get_layers();
+        let mut slices = SliceSet::new();
+        slices.slices.push(crate::paint_app::slices::Slice { name: "hero".to_string(), x: 1, y: 2, width: 3, height: 4 });
+        let (bytes, warnings) = encode_ora(layers, &canvas.get_merged_layer(), &slices).unwrap();
+        assert!(warnings.is_empty());
+        let document = read_ora(&bytes).unwrap();
+        assert!(document.warnings.is_empty());
+        assert_eq!(document.size, (256, 256));
+        assert_eq!(document.slices, slices);
+        let summary = |entries: &[crate::paint_app::canvas::CanvasLayerEntry]| entries.iter()
+            .map(|entry| (entry.name.clone(), entry.visible, entry.content.kind(), entry.parent.is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(summary(&document.entries), summary(&layers.entries));
+        let pixel = PixelPos { x: 150, y: 105 };
+        assert_eq!(document.entries[1].layer.get_pixel(pixel), layers.entries[1].layer.get_pixel(pixel));
+    }
+}
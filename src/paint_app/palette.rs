@@ -0,0 +1,451 @@
+use eframe::egui;
+use egui::{Color32, Sense, Stroke, Vec2};
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaletteFormat {
+    Gpl,
+    Ase,
+    PaintNet,
+    Hex,
+}
+
+impl PaletteFormat {
+    pub fn extension(&self) -> &str {
+        match self {
+            PaletteFormat::Gpl => "gpl",
+            PaletteFormat::Ase => "ase",
+            PaletteFormat::PaintNet => "txt",
+            PaletteFormat::Hex => "hex",
+        }
+    }
+
+    // the extension decides first, the content is checked when it doesn't
+    pub fn detect(file_name: &str, bytes: &[u8]) -> Option<PaletteFormat> {
+        let lowercase = file_name.to_lowercase();
+        let by_extension = [PaletteFormat::Gpl, PaletteFormat::Ase, PaletteFormat::PaintNet, PaletteFormat::Hex]
+            .into_iter()
+            .find(|format| lowercase.ends_with(&format!(".{}", format.extension())));
+        if by_extension.is_some() {
+            return by_extension;
+        }
+        if bytes.starts_with(b"ASEF") {
+            Some(PaletteFormat::Ase)
+        } else if bytes.starts_with(b"GIMP Palette") {
+            Some(PaletteFormat::Gpl)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<Color>,
+}
+
+impl Palette {
+    pub fn new(name: &str) -> Palette {
+        Palette {
+            name: name.to_string(),
+            colors: Vec::new(),
+        }
+    }
+
+    pub fn default_palette() -> Palette {
+        let mut palette = Palette::new("Default");
+        palette.colors = [
+            0x000000, 0x404040, 0x808080, 0xc0c0c0, 0xffffff,
+            0x800000, 0xff0000, 0xff8000, 0xffff00, 0x808000,
+            0x008000, 0x00ff00, 0x008080, 0x00ffff, 0x000080,
+            0x0000ff, 0x800080, 0xff00ff, 0x804000, 0xffc0c0,
+        ].iter().map(|rgb| Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8, 255)).collect();
+        palette
+    }
+
+    // with what couldn't be imported, like colors in models that aren't supported
+    pub fn import(file_name: &str, bytes: &[u8]) -> Result<(Palette, Vec<String>), String> {
+        let name = file_name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(file_name);
+        let format = PaletteFormat::detect(file_name, bytes).ok_or(format!("unknown palette format: {}", file_name))?;
+        let text = || String::from_utf8_lossy(bytes).to_string();
+        let (palette, warnings) = match format {
+            PaletteFormat::Gpl => (parse_gpl(&text())?, Vec::new()),
+            PaletteFormat::Ase => parse_ase(bytes)?,
+            PaletteFormat::PaintNet => (parse_paint_net(&text())?, Vec::new()),
+            PaletteFormat::Hex => (parse_hex_list(&text())?, Vec::new()),
+        };
+        let palette = Palette {
+            name: if palette.name.is_empty() { name.to_string() } else { palette.name },
+            colors: palette.colors,
+        };
+        Ok((palette, warnings))
+    }
+
+    pub fn export(&self, format: PaletteFormat) -> Vec<u8> {
+        match format {
+            PaletteFormat::Gpl => to_gpl(self).into_bytes(),
+            PaletteFormat::Ase => to_ase(self),
+            PaletteFormat::PaintNet => to_paint_net(self).into_bytes(),
+            PaletteFormat::Hex => to_hex_list(self).into_bytes(),
+        }
+    }
+}
+
+fn parse_hex(digits: &str) -> Option<u32> {
+    u32::from_str_radix(digits, 16).ok()
+}
+
+pub fn parse_gpl(text: &str) -> Result<Palette, String> {
+    let mut lines = text.lines();
+    if lines.next().map(|line| line.trim()) != Some("GIMP Palette") {
+        return Err("missing GIMP Palette header".to_string());
+    }
+    let mut palette = Palette::new("");
+    for line in lines {
+        let line = line.trim();
+        if let Some(name) = line.strip_prefix("Name:") {
+            palette.name = name.trim().to_string();
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') || line.starts_with("Columns:") {
+            continue;
+        }
+        let values = line.split_whitespace().take(3).map(|value| value.parse::<u8>()).collect::<Result<Vec<_>, _>>();
+        match values {
+            Ok(values) if values.len() == 3 => palette.colors.push(Color::new(values[0], values[1], values[2], 255)),
+            _ => return Err(format!("invalid GIMP palette line: {}", line)),
+        }
+    }
+    Ok(palette)
+}
+
+pub fn to_gpl(palette: &Palette) -> String {
+    let mut result = format!("GIMP Palette\nName: {}\nColumns: 0\n#\n", palette.name);
+    for color in &palette.colors {
+        result += &format!("{:3} {:3} {:3}\t#{:02x}{:02x}{:02x}\n", color.red, color.green, color.blue, color.red, color.green, color.blue);
+    }
+    result
+}
+
+// one AARRGGBB value per line, ';' starts a comment
+pub fn parse_paint_net(text: &str) -> Result<Palette, String> {
+    let mut palette = Palette::new("");
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        match (line.len(), parse_hex(line)) {
+            (8, Some(argb)) => palette.colors.push(Color::new((argb >> 16) as u8, (argb >> 8) as u8, argb as u8, (argb >> 24) as u8)),
+            _ => return Err(format!("invalid Paint.NET palette line: {}", line)),
+        }
+    }
+    Ok(palette)
+}
+
+pub fn to_paint_net(palette: &Palette) -> String {
+    let mut result = format!("; paint.net Palette File\n; {}\n", palette.name);
+    for color in &palette.colors {
+        result += &format!("{:02X}{:02X}{:02X}{:02X}\n", color.alpha, color.red, color.green, color.blue);
+    }
+    result
+}
+
+// RRGGBB or RRGGBBAA per line, the leading '#' is optional
+pub fn parse_hex_list(text: &str) -> Result<Palette, String> {
+    let mut palette = Palette::new("");
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() {
+            continue;
+        }
+        let digits = line.strip_prefix('#').unwrap_or(line);
+        match (digits.len(), parse_hex(digits)) {
+            (6, Some(rgb)) => palette.colors.push(Color::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255)),
+            (8, Some(rgba)) => palette.colors.push(Color::new((rgba >> 24) as u8, (rgba >> 16) as u8, (rgba >> 8) as u8, rgba as u8)),
+            _ => return Err(format!("invalid hex color: {}", line)),
+        }
+    }
+    Ok(palette)
+}
+
+pub fn to_hex_list(palette: &Palette) -> String {
+    palette.colors.iter().map(|color| match color.alpha {
+        255 => format!("{:02x}{:02x}{:02x}\n", color.red, color.green, color.blue),
+        _ => format!("{:02x}{:02x}{:02x}{:02x}\n", color.red, color.green, color.blue, color.alpha),
+    }).collect()
+}
+
+const ASE_GROUP_START: u16 = 0xc001;
+const ASE_GROUP_END: u16 = 0xc002;
+const ASE_COLOR: u16 = 0x0001;
+
+// Adobe swatch exchange, big endian blocks of groups and colors, groups are flattened. colors in
+// other models than RGB, Gray and CMYK are skipped with a warning
+pub fn parse_ase(bytes: &[u8]) -> Result<(Palette, Vec<String>), String> {
+    let error = || "truncated ASE file".to_string();
+    let read_u16 = |offset: usize| bytes.get(offset..offset + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(error);
+    let read_u32 = |offset: usize| bytes.get(offset..offset + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]])).ok_or_else(error);
+    let read_f32 = |offset: usize| read_u32(offset).map(f32::from_bits);
+
+    if !bytes.starts_with(b"ASEF") {
+        return Err("missing ASEF header".to_string());
+    }
+    let block_count = read_u32(8)?;
+    let mut offset = 12;
+    let mut palette = Palette::new("");
+    let mut skipped: Vec<(String, usize)> = Vec::new();
+    for _ in 0..block_count {
+        let block_type = read_u16(offset)?;
+        let block_len = read_u32(offset + 2)? as usize;
+        let block = offset + 6;
+        offset = block + block_len;
+        if block_type != ASE_COLOR {
+            continue;
+        }
+
+        let name_len = read_u16(block)? as usize;
+        let model_offset = block + 2 + name_len * 2;
+        let model = bytes.get(model_offset..model_offset + 4).ok_or_else(error)?;
+        let values = model_offset + 4;
+        let to_u8 = |value: f32| (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8;
+        let color = match model {
+            b"RGB " => Color::new(to_u8(read_f32(values)?), to_u8(read_f32(values + 4)?), to_u8(read_f32(values + 8)?), 255),
+            b"Gray" => {
+                let gray = to_u8(read_f32(values)?);
+                Color::new(gray, gray, gray, 255)
+            }
+            b"CMYK" => {
+                let k = 1.0 - read_f32(values + 12)?;
+                Color::new(
+                    to_u8((1.0 - read_f32(values)?) * k),
+                    to_u8((1.0 - read_f32(values + 4)?) * k),
+                    to_u8((1.0 - read_f32(values + 8)?) * k),
+                    255,
+                )
+            }
+            _ => {
+                let model = String::from_utf8_lossy(model).trim().to_string();
+                match skipped.iter_mut().find(|(other, _)| *other == model) {
+                    Some((_, count)) => *count += 1,
+                    None => skipped.push((model, 1))
+                }
+                continue;
+            }
+        };
+        palette.colors.push(color);
+    }
+    let warnings = skipped.into_iter()
+        .map(|(model, count)| format!("{} colors in the {} model were skipped, it isn't supported", count, model))
+        .collect();
+    Ok((palette, warnings))
+}
+
+pub fn to_ase(palette: &Palette) -> Vec<u8> {
+    let mut result = Vec::new();
+    result.extend_from_slice(b"ASEF");
+    result.extend_from_slice(&1u16.to_be_bytes());
+    result.extend_from_slice(&0u16.to_be_bytes());
+    result.extend_from_slice(&(palette.colors.len() as u32 + 2).to_be_bytes());
+
+    let push_name = |block: &mut Vec<u8>, name: &str| {
+        let utf16 = name.encode_utf16().chain(std::iter::once(0)).collect::<Vec<_>>();
+        block.extend_from_slice(&(utf16.len() as u16).to_be_bytes());
+        utf16.iter().for_each(|c| block.extend_from_slice(&c.to_be_bytes()));
+    };
+    let push_block = |result: &mut Vec<u8>, block_type: u16, block: Vec<u8>| {
+        result.extend_from_slice(&block_type.to_be_bytes());
+        result.extend_from_slice(&(block.len() as u32).to_be_bytes());
+        result.extend_from_slice(&block);
+    };
+
+    let mut group = Vec::new();
+    push_name(&mut group, &palette.name);
+    push_block(&mut result, ASE_GROUP_START, group);
+    for color in &palette.colors {
+        let mut block = Vec::new();
+        push_name(&mut block, &format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue));
+        block.extend_from_slice(b"RGB ");
+        for value in [color.red, color.green, color.blue] {
+            block.extend_from_slice(&(value as f32 / 255.0).to_be_bytes());
+        }
+        // global color
+        block.extend_from_slice(&0u16.to_be_bytes());
+        push_block(&mut result, ASE_COLOR, block);
+    }
+    push_block(&mut result, ASE_GROUP_END, Vec::new());
+    result
+}
+
+pub struct PalettePanel {
+    pub palette: Palette,
+    pub recent_colors: Vec<Color>,
+    pub selected: Option<usize>,
+    pub export_format: PaletteFormat,
+    pub export_path: String,
+    // why the last export failed
+    pub export_error: Option<String>,
+}
+
+const MAX_RECENT_COLORS: usize = 16;
+
+impl PalettePanel {
+    pub fn new() -> PalettePanel {
+        PalettePanel {
+            palette: Palette::default_palette(),
+            recent_colors: Vec::new(),
+            selected: None,
+            export_format: PaletteFormat::Gpl,
+            export_path: "palette.gpl".to_string(),
+            export_error: None,
+        }
+    }
+
+    pub fn add_recent_color(&mut self, color: Color) {
+        self.recent_colors.retain(|recent| *recent != color);
+        self.recent_colors.insert(0, color);
+        self.recent_colors.truncate(MAX_RECENT_COLORS);
+    }
+
+    // returns true when clicked so the caller can select the swatch
+    fn swatch(ui: &mut egui::Ui, color: Color, selected: bool, global_params: &mut GlobalParams) -> bool {
+        let (rect, response) = ui.allocate_exact_size(Vec2::splat(16.0), Sense::click());
+        ui.painter().rect_filled(rect, 0.0, color.to_color32());
+        let stroke_color = if selected { Color32::WHITE } else { Color32::DARK_GRAY };
+        ui.painter().rect_stroke(rect, 0.0, Stroke::new(1.0, stroke_color));
+        let response = response.on_hover_text(format!("#{:02x}{:02x}{:02x}{:02x}", color.red, color.green, color.blue, color.alpha));
+        if response.secondary_clicked() {
+            global_params.secondary_color = color;
+        }
+        if response.clicked() {
+            global_params.primary_color = color;
+        }
+        response.clicked() || response.secondary_clicked()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, global_params: &mut GlobalParams) {
+        ui.heading("Palette");
+        ui.label(&self.palette.name);
+
+        ui.horizontal_wrapped(|ui| {
+            ui.spacing_mut().item_spacing = Vec2::splat(2.0);
+            for (i, color) in self.palette.colors.iter().enumerate() {
+                if PalettePanel::swatch(ui, *color, self.selected == Some(i), global_params) {
+                    self.selected = Some(i);
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if ui.button("+").on_hover_text("Add the primary color").clicked() {
+                self.palette.colors.push(global_params.primary_color);
+                self.selected = Some(self.palette.colors.len() - 1);
+            }
+            if let Some(selected) = self.selected.filter(|selected| *selected < self.palette.colors.len()) {
+                if ui.button("-").on_hover_text("Remove the selected color").clicked() {
+                    self.palette.colors.remove(selected);
+                    self.selected = None;
+                }
+                if ui.button("<").clicked() && selected > 0 {
+                    self.palette.colors.swap(selected, selected - 1);
+                    self.selected = Some(selected - 1);
+                }
+                if ui.button(">").clicked() && selected + 1 < self.palette.colors.len() {
+                    self.palette.colors.swap(selected, selected + 1);
+                    self.selected = Some(selected + 1);
+                }
+            }
+        });
+
+        if !self.recent_colors.is_empty() {
+            ui.label("Recent");
+            ui.horizontal_wrapped(|ui| {
+                ui.spacing_mut().item_spacing = Vec2::splat(2.0);
+                for color in self.recent_colors.iter() {
+                    PalettePanel::swatch(ui, *color, false, global_params);
+                }
+            });
+        }
+
+        ui.collapsing("Export", |ui| {
+            egui::ComboBox::from_id_source("palette_export_format")
+                .selected_text(self.export_format.extension())
+                .show_ui(ui, |ui| {
+                    for format in [PaletteFormat::Gpl, PaletteFormat::Ase, PaletteFormat::PaintNet, PaletteFormat::Hex] {
+                        if ui.selectable_value(&mut self.export_format, format, format.extension()).clicked() {
+                            let stem = self.export_path.rsplit_once('.').map(|(stem, _)| stem.to_string()).unwrap_or(self.export_path.clone());
+                            self.export_path = format!("{}.{}", stem, format.extension());
+                        }
+                    }
+                });
+            self.export_ui(ui);
+            ui.label("Drop a .gpl, .ase, .txt or .hex file to import a palette");
+        });
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn export_ui(&mut self, ui: &mut egui::Ui) {
+        ui.text_edit_singleline(&mut self.export_path);
+        if ui.button("Export").clicked() {
+            self.export_error = std::fs::write(&self.export_path, self.palette.export(self.export_format))
+                .err()
+                .map(|err| format!("{}: {}", self.export_path, err));
+        }
+        if let Some(err) = &self.export_error {
+            ui.colored_label(Color32::LIGHT_RED, err);
+        }
+    }
+
+    // no file system on the web, text formats go to the clipboard
+    #[cfg(target_arch = "wasm32")]
+    fn export_ui(&mut self, ui: &mut egui::Ui) {
+        if self.export_format == PaletteFormat::Ase {
+            ui.label("ASE export is only available in the desktop version");
+        } else if ui.button("Copy to clipboard").clicked() {
+            let text = String::from_utf8_lossy(&self.palette.export(self.export_format)).to_string();
+            ui.output_mut(|output| output.copied_text = text);
+        }
+    }
+}
+
+impl Default for PalettePanel {
+    fn default() -> PalettePanel {
+        PalettePanel::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_round_trip() {
+        let mut palette = Palette::new("Test");
+        palette.colors = vec![Color::new(255, 0, 0, 255), Color::new(12, 34, 56, 255), Color::new(0, 0, 0, 255)];
+        for format in [PaletteFormat::Gpl, PaletteFormat::Ase, PaletteFormat::PaintNet, PaletteFormat::Hex] {
+            let file_name = format!("Test.{}", format.extension());
+            let (imported, warnings) = Palette::import(&file_name, &palette.export(format)).unwrap();
+            assert_eq!(imported, palette, "{:?}", format);
+            assert!(warnings.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_parse_gpl() {
+        let palette = parse_gpl("GIMP Palette\nName: Small\nColumns: 2\n#\n  0   0   0\tBlack\n255 128  64 Orange\n").unwrap();
+        assert_eq!(palette.name, "Small");
+        assert_eq!(palette.colors, vec![Color::new(0, 0, 0, 255), Color::new(255, 128, 64, 255)]);
+    }
+
+    #[test]
+    fn test_ase_unsupported_model() {
+        let mut palette = Palette::new("Lab");
+        palette.colors = vec![Color::new(255, 0, 0, 255), Color::new(0, 0, 255, 255), Color::new(0, 255, 0, 255)];
+        let mut bytes = to_ase(&palette);
+        // the last two colors become LAB ones, with the same number of values as RGB
+        let lab_positions = bytes.windows(4).enumerate().filter(|(_, window)| *window == b"RGB ").map(|(i, _)| i).skip(1).collect::<Vec<_>>();
+        lab_positions.iter().for_each(|i| bytes[*i..*i + 4].copy_from_slice(b"LAB "));
+        let (imported, warnings) = parse_ase(&bytes).unwrap();
+        assert_eq!(imported.colors, vec![Color::new(255, 0, 0, 255)]);
+        assert_eq!(warnings, vec!["2 colors in the LAB model were skipped, it isn't supported"]);
+    }
+}
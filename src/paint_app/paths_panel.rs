@@ -0,0 +1,101 @@
+use eframe::egui;
+use egui::ComboBox;
+use crate::paint_app::vector_path::VectorPath;
+
+// what the panel asks the document to do with a path
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathCommand {
+    // None starts a new path with the pen tool
+    Select(Option<usize>),
+    Rename(usize, String),
+    Delete(usize),
+    // with the settings of the tool with this id
+    Stroke(usize, &'static str),
+    Fill(usize),
+    ToMask(usize),
+}
+
+pub struct PathsPanel {
+    // the path being renamed and the name typed so far
+    renaming: Option<(usize, String)>,
+    stroke_tool: &'static str,
+}
+
+impl PathsPanel {
+    pub fn new() -> PathsPanel {
+        PathsPanel {
+            renaming: None,
+            stroke_tool: "",
+        }
+    }
+
+    // stroke_tools are the ids and names of the tools that can stroke paths
+    pub fn show(&mut self, ui: &mut egui::Ui, paths: &[VectorPath], active: Option<usize>, stroke_tools: &[(&'static str, &'static str)]) -> Option<PathCommand> {
+        let mut result = None;
+        ui.heading("Paths");
+        if paths.is_empty() {
+            ui.weak("Draw with the pen tool to add a path");
+        }
+        for (i, path) in paths.iter().enumerate() {
+            ui.horizontal(|ui| {
+                match &mut self.renaming {
+                    Some((index, name)) if *index == i => {
+                        let response = ui.text_edit_singleline(name);
+                        response.request_focus();
+                        if response.lost_focus() {
+                            result = Some(PathCommand::Rename(i, name.clone()));
+                            self.renaming = None;
+                        }
+                    }
+                    _ => {
+                        let response = ui.selectable_label(active == Some(i), &path.name).on_hover_text("Double click to rename");
+                        if response.double_clicked() {
+                            self.renaming = Some((i, path.name.clone()));
+                        } else if response.clicked() {
+                            result = Some(PathCommand::Select(Some(i)));
+                        }
+                    }
+                }
+                if ui.small_button("x").on_hover_text("Delete path").clicked() {
+                    result = Some(PathCommand::Delete(i));
+                }
+            });
+        }
+        if ui.button("New path").clicked() {
+            result = Some(PathCommand::Select(None));
+        }
+
+        let Some(index) = active.filter(|i| *i < paths.len()) else { return result };
+        if !stroke_tools.iter().any(|(id, _)| *id == self.stroke_tool) {
+            self.stroke_tool = stroke_tools.first().map(|(id, _)| *id).unwrap_or("");
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Stroke").clicked() && !self.stroke_tool.is_empty() {
+                result = Some(PathCommand::Stroke(index, self.stroke_tool));
+            }
+            let selected = stroke_tools.iter().find(|(id, _)| *id == self.stroke_tool).map(|(_, name)| *name).unwrap_or("");
+            ComboBox::from_id_source("path_stroke_tool")
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (id, name) in stroke_tools {
+                        ui.selectable_value(&mut self.stroke_tool, id, *name);
+                    }
+                });
+        });
+        ui.horizontal(|ui| {
+            if ui.button("Fill").clicked() {
+                result = Some(PathCommand::Fill(index));
+            }
+            if ui.button("To mask").on_hover_text("Replace the mask of the active layer with the inside of the path").clicked() {
+                result = Some(PathCommand::ToMask(index));
+            }
+        });
+        result
+    }
+}
+
+impl Default for PathsPanel {
+    fn default() -> PathsPanel {
+        PathsPanel::new()
+    }
+}
@@ -0,0 +1,144 @@
+use egui::{Color32, Key, Painter, Pos2, Stroke};
+use glam::Vec2;
+use crate::paint_app::actions::Shortcut;
+use crate::paint_app::canvas::{CanvasLayers, EditCommand, LayerEdit, PaintTool};
+use crate::paint_app::tool_registry::{ToolCategory, ToolInfo, ToolOption, ToolOptionValue};
+use crate::paint_app::canvas_layer::{CanvasLayer, HashMapCanvasLayer};
+use crate::paint_app::vector_path::{PathHandle, PathNode, VectorPath};
+use super::data_types::*;
+
+// how close to an anchor or handle a click has to be, in pixels
+const HIT_RADIUS: f32 = 4.0;
+
+// clicks add corner anchors to the active path, dragging right away pulls out smooth
+// handles. anchors and handles can be dragged later, clicking the first anchor closes the path
+pub struct PenTool {
+    // the path being edited and which document path it came from, only while dragging
+    editing: Option<(Option<usize>, VectorPath)>,
+    dragging: Option<PathHandle>,
+    // a new anchor drags out both handles
+    new_anchor: bool,
+    // moving a handle moves the opposite one as well
+    pub mirror_handles: bool,
+    layer_edits: Vec<LayerEdit>,
+}
+
+impl PenTool {
+    pub fn new() -> PenTool {
+        PenTool {
+            editing: None,
+            dragging: None,
+            new_anchor: false,
+            mirror_handles: true,
+            layer_edits: Vec::new(),
+        }
+    }
+}
+
+impl Default for PenTool {
+    fn default() -> PenTool {
+        PenTool::new()
+    }
+}
+
+fn to_vec(pos: PixelPos) -> Vec2 {
+    Vec2::new(pos.x as f32, pos.y as f32)
+}
+
+impl PaintTool for PenTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "pen", name: "Pen", icon: "✒", shortcut: Some(Shortcut::new(Key::P)), category: ToolCategory::Shapes }
+    }
+
+    fn stroke_start(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        tool_canvas.clear();
+        let Some(pos) = global_params.current_pixel.map(to_vec) else { return };
+        let new_path = || VectorPath::new(format!("Path {}", layers.paths.len() + 1));
+        let (mut index, mut path) = match layers.get_active_path() {
+            Some(path) => (layers.active_path, path.clone()),
+            None => (None, new_path())
+        };
+        self.new_anchor = false;
+        self.dragging = match path.hit(pos, HIT_RADIUS) {
+            Some(PathHandle::Anchor(0)) if !path.closed && path.nodes.len() > 2 => {
+                path.closed = true;
+                None
+            }
+            Some(handle) => Some(handle),
+            None => {
+                // a closed path is done, clicking next to it starts the next one
+                if path.closed {
+                    (index, path) = (None, new_path());
+                }
+                path.nodes.push(PathNode::corner(pos));
+                self.new_anchor = true;
+                Some(PathHandle::Out(path.nodes.len() - 1))
+            }
+        };
+        self.editing = Some((index, path));
+    }
+
+    fn stroke_update(&mut self, global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        if let (Some((_, path)), Some(handle), Some(pos)) = (&mut self.editing, self.dragging, global_params.current_pixel) {
+            path.move_handle(handle, to_vec(pos), self.new_anchor || self.mirror_handles);
+        }
+    }
+
+    fn stroke_end(&mut self, _global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        if let Some((index, path)) = self.editing.take() {
+            self.layer_edits.push(LayerEdit::SetPath(index, path));
+        }
+        self.dragging = None;
+    }
+
+    fn take_layer_edits(&mut self) -> Vec<LayerEdit> {
+        std::mem::take(&mut self.layer_edits)
+    }
+
+    fn uses_symmetry(&self) -> bool {
+        false
+    }
+
+    fn options(&self) -> Vec<ToolOption> {
+        vec![
+            ToolOption::toggle("mirror_handles", "Mirror handles", self.mirror_handles),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        if let ("mirror_handles", ToolOptionValue::Toggle(mirror)) = (id, value) {
+            self.mirror_handles = mirror;
+        }
+    }
+
+    // the path being dragged, otherwise the document's active one
+    fn draw_overlay(&self, layers: &CanvasLayers, painter: &Painter, to_screen: &dyn Fn((f32, f32)) -> Pos2) {
+        let Some(path) = self.editing.as_ref().map(|(_, path)| path).or(layers.get_active_path()) else { return };
+        let screen = |point: Vec2| to_screen((point.x, point.y));
+        let curve = Stroke::new(1.5, Color32::from_rgb(0, 160, 255));
+        let handle_line = Stroke::new(1.0, Color32::from_gray(160));
+
+        let points = path.flatten();
+        let mut outline = points.iter().map(|point| screen(*point)).collect::<Vec<_>>();
+        if path.closed {
+            outline.extend(outline.first().copied());
+        }
+        painter.add(egui::Shape::line(outline, curve));
+
+        for (i, node) in path.nodes.iter().enumerate() {
+            let anchor = screen(node.anchor);
+            for handle in [node.handle_in, node.handle_out].into_iter().filter(|handle| *handle != node.anchor) {
+                painter.line_segment([anchor, screen(handle)], handle_line);
+                painter.circle(screen(handle), 2.5, Color32::WHITE, handle_line);
+            }
+            let selected = self.dragging == Some(PathHandle::Anchor(i));
+            let fill = match (selected, i == 0 && !path.closed) {
+                (true, _) => Color32::from_rgb(0, 160, 255),
+                // the first anchor closes the path
+                (false, true) => Color32::from_rgb(255, 220, 120),
+                (false, false) => Color32::WHITE
+            };
+            painter.rect(egui::Rect::from_center_size(anchor, egui::vec2(6.0, 6.0)), 0.0, fill, curve);
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use crate::paint_app::canvas::{GroupBlend, LayerContent, LayerGroup, LayerId, LayerMask};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::image_io::{ImportedDocument, MAX_DOCUMENT_SIZE};
+use super::data_types::*;
+
+// reads 8 bit rgb and grayscale photoshop files. raster layers and groups come over with their
+// names, visibility, opacity, clipping and masks, everything else is reported as a warning
+
+const RGB: u16 = 3;
+const GRAYSCALE: u16 = 1;
+// groups inside groups, reading them goes one level deeper on the stack each
+const MAX_GROUP_DEPTH: usize = 256;
+
+// big endian, with errors instead of panics for cut off files
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(count).ok_or("the psd file ends early")?;
+        let result = self.bytes.get(self.pos..end).ok_or("the psd file ends early")?;
+        self.pos += count;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(self.u32()? as i32)
+    }
+
+    // a block starting with its length
+    fn section(&mut self) -> Result<&'a [u8], String> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    top: i32,
+    left: i32,
+    bottom: i32,
+    right: i32,
+}
+
+impl Rect {
+    fn read(reader: &mut Reader) -> Result<Rect, String> {
+        Ok(Rect { top: reader.i32()?, left: reader.i32()?, bottom: reader.i32()?, right: reader.i32()? })
+    }
+
+    // rects come from the file, they can be anything
+    fn size(&self) -> Result<(u32, u32), String> {
+        let width = (self.right as i64 - self.left as i64).max(0);
+        let height = (self.bottom as i64 - self.top as i64).max(0);
+        match width <= MAX_DOCUMENT_SIZE as i64 && height <= MAX_DOCUMENT_SIZE as i64 {
+            true => Ok((width as u32, height as u32)),
+            false => Err(format!("a layer of {}x{} is too large", width, height))
+        }
+    }
+}
+
+// what lsct says a record is, groups are a divider below their children and the group record above them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Layer,
+    Group { open: bool },
+    GroupEnd,
+}
+
+struct LayerRecord {
+    name: String,
+    rect: Rect,
+    // channel id and the length of its data
+    channels: Vec<(i16, usize)>,
+    blend: [u8; 4],
+    opacity: u8,
+    clipped: bool,
+    visible: bool,
+    // the rect, the value outside of it and whether the mask is turned off
+    mask: Option<(Rect, u8, bool)>,
+    section: Section,
+    // adjustment layers have no pixels that mean anything
+    adjustment: bool,
+    pixels: Option<FlatCanvasLayer>,
+    mask_pixels: Option<FlatCanvasLayer>,
+}
+
+fn blend_name(key: &[u8; 4]) -> String {
+    match key {
+        b"diss" => "dissolve",
+        b"dark" => "darken",
+        b"mul " => "multiply",
+        b"idiv" => "color burn",
+        b"lbrn" => "linear burn",
+        b"dkCl" => "darker color",
+        b"lite" => "lighten",
+        b"scrn" => "screen",
+        b"div " => "color dodge",
+        b"lddg" => "linear dodge",
+        b"lgCl" => "lighter color",
+        b"over" => "overlay",
+        b"sLit" => "soft light",
+        b"hLit" => "hard light",
+        b"vLit" => "vivid light",
+        b"lLit" => "linear light",
+        b"pLit" => "pin light",
+        b"hMix" => "hard mix",
+        b"diff" => "difference",
+        b"smud" => "exclusion",
+        b"fsub" => "subtract",
+        b"fdiv" => "divide",
+        b"hue " => "hue",
+        b"sat " => "saturation",
+        b"colr" => "color",
+        b"lum " => "luminosity",
+        _ => return String::from_utf8_lossy(key).trim().to_string()
+    }.to_string()
+}
+
+// the keys of layers that only change the layers below them
+const ADJUSTMENT_KEYS: [&[u8; 4]; 17] = [
+    b"levl", b"curv", b"brit", b"hue2", b"hue ", b"blnc", b"expA", b"vibA", b"mixr",
+    b"clrL", b"nvrt", b"post", b"thrs", b"grdm", b"selc", b"blwh", b"phfl",
+];
+
+fn read_record(reader: &mut Reader, warnings: &mut Vec<String>) -> Result<LayerRecord, String> {
+    let rect = Rect::read(reader)?;
+    let channel_count = reader.u16()?;
+    let channels = (0..channel_count).map(|_| Ok((reader.u16()? as i16, reader.u32()? as usize))).collect::<Result<Vec<_>, String>>()?;
+    if reader.take(4)? != b"8BIM" {
+        return Err("broken layer record".to_string());
+    }
+    let blend = reader.take(4)?.try_into().unwrap();
+    let opacity = reader.u8()?;
+    let clipped = reader.u8()? != 0;
+    let flags = reader.u8()?;
+    reader.u8()?;
+    let extra = reader.section()?;
+
+    let mut extra = Reader { bytes: extra, pos: 0 };
+    let mask_data = extra.section()?;
+    let mask = match mask_data.len() >= 18 {
+        true => {
+            let mut mask_reader = Reader { bytes: mask_data, pos: 0 };
+            Some((Rect::read(&mut mask_reader)?, mask_reader.u8()?, mask_reader.u8()? & 2 != 0))
+        }
+        false => None
+    };
+    extra.section()?;
+    // a pascal string padded to 4 bytes
+    let length = extra.u8()? as usize;
+    let mut name = String::from_utf8_lossy(extra.take(length)?).to_string();
+    extra.take((4 - (length + 1) % 4) % 4)?;
+
+    let mut record = LayerRecord {
+        name: String::new(),
+        rect,
+        channels,
+        blend,
+        opacity,
+        clipped,
+        visible: flags & 2 == 0,
+        mask,
+        section: Section::Layer,
+        adjustment: false,
+        pixels: None,
+        mask_pixels: None,
+    };
+    // tagged blocks until the end of the record
+    let mut notes = Vec::new();
+    while extra.pos + 12 <= extra.bytes.len() {
+        let signature = extra.take(4)?;
+        if signature != b"8BIM" && signature != b"8B64" {
+            break;
+        }
+        let key: &[u8; 4] = extra.take(4)?.try_into().unwrap();
+        let mut data = Reader { bytes: extra.section()?, pos: 0 };
+        match key {
+            b"luni" => {
+                let length = data.u32()? as usize;
+                let units = (0..length).map(|_| data.u16()).collect::<Result<Vec<_>, String>>()?;
+                name = String::from_utf16_lossy(&units).trim_end_matches('\0').to_string();
+            }
+            b"lsct" | b"lsdk" => {
+                record.section = match data.u32()? {
+                    1 => Section::Group { open: true },
+                    2 => Section::Group { open: false },
+                    3 => Section::GroupEnd,
+                    _ => Section::Layer
+                };
+            }
+            b"lfx2" | b"lrFX" | b"lmfx" => notes.push("layer effects aren't supported and were left out"),
+            b"SoLd" | b"PlLd" | b"SoLE" => notes.push("smart objects aren't supported, only the pixels were imported"),
+            b"TySh" | b"tySh" => notes.push("text layers aren't supported, only the pixels were imported"),
+            b"SoCo" | b"GdFl" | b"PtFl" => notes.push("fill layers aren't supported, only the pixels were imported"),
+            b"vmsk" | b"vsms" => notes.push("vector masks aren't supported and were left out"),
+            _ if ADJUSTMENT_KEYS.contains(&key) => {
+                record.adjustment = true;
+                notes.push("adjustment layers aren't supported and were left out");
+            }
+            _ => {}
+        }
+    }
+    record.name = name;
+    notes.dedup();
+    warnings.extend(notes.into_iter().map(|note| format!("{}: {}", record.name, note)));
+    Ok(record)
+}
+
+// packbits, a count byte then that many bytes or one byte repeated
+fn unpack_bits(mut data: &[u8], length: usize) -> Result<Vec<u8>, String> {
+    let mut result = Vec::with_capacity(length);
+    while result.len() < length && !data.is_empty() {
+        let count = data[0] as i8;
+        data = &data[1..];
+        match count {
+            0.. => {
+                let run = data.get(..count as usize + 1).ok_or("broken rle data")?;
+                result.extend_from_slice(run);
+                data = &data[run.len()..];
+            }
+            // a no-op
+            -128 => {}
+            _ => {
+                let value = *data.first().ok_or("broken rle data")?;
+                result.extend(std::iter::repeat_n(value, (1 - count as isize) as usize));
+                data = &data[1..];
+            }
+        }
+    }
+    result.resize(length, 0);
+    Ok(result)
+}
+
+// a packed row can't grow more than this, two bytes give 128
+const MAX_RLE_GROWTH: usize = 64;
+
+// one channel of width by height bytes
+fn read_channel(data: &[u8], compression: u16, (width, height): (u32, u32)) -> Result<Vec<u8>, String> {
+    let (width, height) = (width as usize, height as usize);
+    // at most 30000 by 30000, fits in usize everywhere
+    let pixels = width * height;
+    let mut result = match compression {
+        0 => data.get(..pixels).ok_or("the channel data is too short")?.to_vec(),
+        1 => {
+            let mut reader = Reader { bytes: data, pos: 0 };
+            let lengths = (0..height).map(|_| reader.u16().map(|length| length as usize)).collect::<Result<Vec<_>, String>>()?;
+            let mut result = Vec::with_capacity(pixels.min(data.len() * MAX_RLE_GROWTH));
+            for length in lengths {
+                if length * MAX_RLE_GROWTH < width {
+                    return Err("broken rle data".to_string());
+                }
+                result.extend(unpack_bits(reader.take(length)?, width)?);
+            }
+            result
+        }
+        2 | 3 => miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(data, pixels).map_err(|err| format!("broken zip data: {:?}", err.status))?,
+        _ => return Err(format!("unknown compression {}", compression))
+    };
+    result.resize(pixels, 0);
+    // with prediction every byte is stored as the difference to the one before it
+    if compression == 3 && width > 0 {
+        for row in result.chunks_mut(width) {
+            for x in 1..row.len() {
+                row[x] = row[x].wrapping_add(row[x - 1]);
+            }
+        }
+    }
+    Ok(result)
+}
+
+// red, green, blue and alpha planes, or gray and alpha, into colors
+fn to_colors(planes: &[Option<Vec<u8>>; 4], mode: u16, pixels: usize) -> Vec<Color> {
+    let value = |plane: usize, i: usize, default: u8| planes[plane].as_ref().map_or(default, |plane| plane[i]);
+    (0..pixels).map(|i| match mode {
+        GRAYSCALE => {
+            let gray = value(0, i, 0);
+            Color::new(gray, gray, gray, value(3, i, 255))
+        }
+        _ => Color::new(value(0, i, 0), value(1, i, 0), value(2, i, 0), value(3, i, 255))
+    }).collect()
+}
+
+fn read_pixels(reader: &mut Reader, record: &mut LayerRecord, mode: u16, warnings: &mut Vec<String>) -> Result<(), String> {
+    let size = record.rect.size()?;
+    let mut planes: [Option<Vec<u8>>; 4] = Default::default();
+    for (id, length) in record.channels.clone() {
+        let data = reader.take(length)?;
+        let Some((compression, data)) = data.split_first_chunk::<2>() else { continue };
+        let compression = u16::from_be_bytes(*compression);
+        match id {
+            // gray is channel 0
+            0..=2 => planes[id as usize] = Some(read_channel(data, compression, size)?),
+            -1 => planes[3] = Some(read_channel(data, compression, size)?),
+            -2 => if let Some((rect, _, _)) = record.mask {
+                let (width, height) = rect.size()?;
+                let values = read_channel(data, compression, (width, height))?;
+                record.mask_pixels = Some(FlatCanvasLayer::from_data(width, height, values.iter().map(|value| Color::new(*value, *value, *value, 255)).collect()));
+            }
+            -3 => warnings.push(format!("{}: the second layer mask isn't supported and was left out", record.name)),
+            _ => {}
+        }
+    }
+    // a large rect without any data shouldn't allocate anything
+    if planes.iter().all(|plane| plane.is_none()) {
+        return Ok(());
+    }
+    let pixels = size.0 as usize * size.1 as usize;
+    if mode == RGB && planes[..3].iter().any(|plane| plane.is_none()) && pixels > 0 {
+        warnings.push(format!("{}: some color channels are missing", record.name));
+    }
+    record.pixels = Some(FlatCanvasLayer::from_data(size.0, size.1, to_colors(&planes, mode, pixels)));
+    Ok(())
+}
+
+// the flattened image at the end of the file, for files saved without layers
+fn read_merged(reader: &mut Reader, (width, height): (u32, u32), channels: u16, mode: u16) -> Result<FlatCanvasLayer, String> {
+    let compression = reader.u16()?;
+    // the document checked the size already
+    let pixels = width as usize * height as usize;
+    // the rle row lengths of all channels come first
+    let lengths = match compression {
+        1 => (0..channels as usize * height as usize).map(|_| reader.u16().map(|length| length as usize)).collect::<Result<Vec<_>, String>>()?,
+        _ => Vec::new()
+    };
+    let color_channels = if mode == GRAYSCALE { 1 } else { 3 };
+    let mut planes: [Option<Vec<u8>>; 4] = Default::default();
+    for (channel, plane) in planes.iter_mut().enumerate().take(color_channels.min(channels as usize)) {
+        *plane = Some(match compression {
+            0 => reader.take(pixels)?.to_vec(),
+            1 => {
+                let mut result = Vec::with_capacity(pixels);
+                for length in &lengths[channel * height as usize..(channel + 1) * height as usize] {
+                    if length * MAX_RLE_GROWTH < width as usize {
+                        return Err("broken rle data".to_string());
+                    }
+                    result.extend(unpack_bits(reader.take(*length)?, width as usize)?);
+                }
+                result
+            }
+            _ => return Err(format!("unknown compression {}", compression))
+        });
+    }
+    Ok(FlatCanvasLayer::from_data(width, height, to_colors(&planes, mode, pixels)))
+}
+
+// records bottom first, so a group is the records since the divider below it
+enum Node {
+    Layer(usize),
+    Group(usize, Vec<Node>),
+}
+
+fn push_nodes(document: &mut ImportedDocument, records: &mut [LayerRecord], nodes: &[Node], parent: Option<LayerId>) {
+    for node in nodes.iter().rev() {
+        let index = match node {
+            Node::Layer(index) | Node::Group(index, _) => *index
+        };
+        let record = &mut records[index];
+        let name = record.name.clone();
+        let blend = &record.blend;
+        let opacity = record.opacity as f32 / 255.0;
+        let id = match node {
+            Node::Layer(_) if record.adjustment => continue,
+            Node::Layer(_) => {
+                if blend != b"norm" {
+                    document.warnings.push(format!("{}: the {} blend mode isn't supported, normal is used", name, blend_name(blend)));
+                }
+                let image = record.pixels.take().unwrap_or_else(|| FlatCanvasLayer::new(0, 0));
+                let mut layer = document.place(&name, &image, record.rect.left as i64, record.rect.top as i64);
+                document.apply_opacity(&name, &mut layer, opacity);
+                document.push(name, LayerContent::Raster, layer, record.visible, parent)
+            }
+            Node::Group(_, _) => {
+                // normal on a group composites it on its own first
+                let blend = match blend {
+                    b"pass" => GroupBlend::PassThrough,
+                    b"norm" => GroupBlend::Isolated,
+                    other => {
+                        document.warnings.push(format!("{}: the {} blend mode isn't supported, normal is used", name, blend_name(other)));
+                        GroupBlend::Isolated
+                    }
+                };
+                let collapsed = matches!(record.section, Section::Group { open: false });
+                document.push_group(name, LayerGroup { opacity, blend, collapsed }, record.visible, parent)
+            }
+        };
+
+        let record = &mut records[index];
+        let (width, height) = document.size;
+        let entry = document.entries.last_mut().unwrap();
+        entry.clipped = record.clipped;
+        if let (Some((rect, default, disabled)), Some(values)) = (record.mask, record.mask_pixels.take()) {
+            let mut mask = LayerMask::new(width, height);
+            mask.layer.fill(Color::new(default, default, default, 255));
+            values.iter_pixels().for_each(|(pos, color)| {
+                let (x, y) = (pos.x as i64 + rect.left as i64, pos.y as i64 + rect.top as i64);
+                if x >= 0 && y >= 0 && x < width as i64 && y < height as i64 {
+                    mask.layer.set_pixel(PixelPos { x: x as u32, y: y as u32 }, *color);
+                }
+            });
+            mask.enabled = !disabled;
+            entry.mask = Some(mask);
+        }
+        if let Node::Group(_, children) = node {
+            push_nodes(document, records, children, Some(id));
+        }
+    }
+}
+
+pub fn read_psd(bytes: &[u8]) -> Result<ImportedDocument, String> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.take(4)? != b"8BPS" {
+        return Err("not a psd file".to_string());
+    }
+    match reader.u16()? {
+        1 => {}
+        2 => return Err("large documents (psb) aren't supported".to_string()),
+        version => return Err(format!("unknown psd version {}", version))
+    }
+    reader.take(6)?;
+    let channels = reader.u16()?;
+    let height = reader.u32()?;
+    let width = reader.u32()?;
+    let depth = reader.u16()?;
+    let mode = reader.u16()?;
+    if depth != 8 {
+        return Err(format!("only 8 bit psd files are supported, this one has {} bits per channel", depth));
+    }
+    if mode != RGB && mode != GRAYSCALE {
+        return Err("only rgb and grayscale psd files are supported".to_string());
+    }
+    // the color table and image resources
+    reader.section()?;
+    reader.section()?;
+
+    let mut document = ImportedDocument::new(width, height)?;
+    let layer_section = reader.section()?;
+    let mut records = Vec::new();
+    if !layer_section.is_empty() {
+        let mut layers = Reader { bytes: layer_section, pos: 0 };
+        let info = layers.section()?;
+        let mut info = Reader { bytes: info, pos: 0 };
+        if !info.bytes.is_empty() {
+            // negative when the first alpha channel is the transparency of the merged image
+            let count = (info.u16()? as i16).unsigned_abs();
+            for _ in 0..count {
+                records.push(read_record(&mut info, &mut document.warnings)?);
+            }
+            for record in records.iter_mut() {
+                read_pixels(&mut info, record, mode, &mut document.warnings)?;
+            }
+        }
+    }
+
+    if records.is_empty() {
+        let image = read_merged(&mut reader, (width, height), channels, mode)?;
+        document.push("Background".to_string(), LayerContent::Raster, image, true, None);
+        return Ok(document);
+    }
+
+    let mut groups: Vec<Vec<Node>> = vec![Vec::new()];
+    for (index, record) in records.iter().enumerate() {
+        match record.section {
+            Section::GroupEnd if groups.len() > MAX_GROUP_DEPTH => return Err("the groups are nested too deeply".to_string()),
+            Section::GroupEnd => groups.push(Vec::new()),
+            Section::Group { .. } => {
+                let children = match groups.len() > 1 {
+                    true => groups.pop().unwrap(),
+                    false => Vec::new()
+                };
+                groups.last_mut().unwrap().push(Node::Group(index, children));
+            }
+            Section::Layer => groups.last_mut().unwrap().push(Node::Layer(index))
+        }
+    }
+    // dividers without their group keep their layers at the level above
+    while groups.len() > 1 {
+        let children = groups.pop().unwrap();
+        groups.last_mut().unwrap().extend(children);
+    }
+    push_nodes(&mut document, &mut records, &groups[0], None);
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(bytes: &[u8]) -> Vec<u8> {
+        let mut result = (bytes.len() as u32).to_be_bytes().to_vec();
+        result.extend_from_slice(bytes);
+        result
+    }
+
+    // a record with raw rgba channels of width by height, plus the channel data that follows later
+    fn record(name: &str, rect: [i32; 4], blend: &[u8; 4], opacity: u8, flags: u8, blocks: &[(&[u8; 4], Vec<u8>)], color: [u8; 4]) -> (Vec<u8>, Vec<u8>) {
+        let pixels = ((rect[3] - rect[1]) * (rect[2] - rect[0])) as usize;
+        let mut result = Vec::new();
+        rect.iter().for_each(|value| result.extend_from_slice(&value.to_be_bytes()));
+        result.extend_from_slice(&4u16.to_be_bytes());
+        let mut data = Vec::new();
+        for (id, value) in [(0i16, color[0]), (1, color[1]), (2, color[2]), (-1, color[3])] {
+            result.extend_from_slice(&id.to_be_bytes());
+            result.extend_from_slice(&(2 + pixels as u32).to_be_bytes());
+            data.extend_from_slice(&0u16.to_be_bytes());
+            data.extend(std::iter::repeat_n(value, pixels));
+        }
+        result.extend_from_slice(b"8BIM");
+        result.extend_from_slice(blend);
+        result.extend_from_slice(&[opacity, 0, flags, 0]);
+        let mut extra = section(&[]);
+        extra.extend(section(&[]));
+        let mut pascal = vec![name.len() as u8];
+        pascal.extend_from_slice(name.as_bytes());
+        pascal.resize(pascal.len().div_ceil(4) * 4, 0);
+        extra.extend(pascal);
+        for (key, block) in blocks {
+            extra.extend_from_slice(b"8BIM");
+            extra.extend_from_slice(*key);
+            extra.extend(section(block));
+        }
+        result.extend(section(&extra));
+        (result, data)
+    }
+
+    fn psd(records: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<u8> {
+        let mut result = b"8BPS".to_vec();
+        result.extend_from_slice(&1u16.to_be_bytes());
+        result.extend_from_slice(&[0; 6]);
+        result.extend_from_slice(&4u16.to_be_bytes());
+        result.extend_from_slice(&4u32.to_be_bytes());
+        result.extend_from_slice(&8u32.to_be_bytes());
+        result.extend_from_slice(&8u16.to_be_bytes());
+        result.extend_from_slice(&RGB.to_be_bytes());
+        result.extend(section(&[]));
+        result.extend(section(&[]));
+        let mut info = (records.len() as u16).to_be_bytes().to_vec();
+        records.iter().for_each(|(record, _)| info.extend_from_slice(record));
+        records.iter().for_each(|(_, data)| info.extend_from_slice(data));
+        let mut layers = section(&info);
+        // the global mask info
+        layers.extend(section(&[]));
+        result.extend(section(&layers));
+        result
+    }
+
+    #[test]
+    fn test_unpack_bits() {
+        // the example from the tiff spec
+        let packed = [0xfe, 0xaa, 0x02, 0x80, 0x00, 0x2a, 0xfd, 0xaa, 0x03, 0x80, 0x00, 0x2a, 0x22, 0xf7, 0xaa];
+        let expected = [0xaa, 0xaa, 0xaa, 0x80, 0x00, 0x2a, 0xaa, 0xaa, 0xaa, 0xaa, 0x80, 0x00, 0x2a, 0x22, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa, 0xaa];
+        assert_eq!(unpack_bits(&packed, expected.len()).unwrap(), expected);
+        assert!(unpack_bits(&[0x05, 0x01], 6).is_err());
+    }
+
+    #[test]
+    fn test_read_psd() {
+        let mut luni = 3u32.to_be_bytes().to_vec();
+        "Ünï".encode_utf16().for_each(|unit| luni.extend_from_slice(&unit.to_be_bytes()));
+        // bottom first: background, the end of the group, a hidden layer inside it, the group
+        let bytes = psd(vec![
+            record("Background", [0, 0, 4, 8], b"norm", 255, 0, &[], [255, 0, 0, 255]),
+            record("</Layer group>", [0, 0, 0, 0], b"norm", 255, 0, &[(b"lsct", 3u32.to_be_bytes().to_vec())], [0; 4]),
+            record("Uni", [1, 2, 3, 10], b"mul ", 255, 2, &[(b"luni", luni), (b"lfx2", vec![0; 4])], [0, 0, 255, 255]),
+            record("Group", [0, 0, 0, 0], b"pass", 128, 0, &[(b"lsct", 2u32.to_be_bytes().to_vec())], [0; 4]),
+        ]);
+        let document = read_psd(&bytes).unwrap();
+        assert_eq!(document.size, (8, 4));
+        let names = document.entries.iter().map(|entry| entry.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["Group", "Ünï", "Background"]);
+
+        let group = &document.entries[0];
+        let LayerContent::Group(settings) = &group.content else { panic!("not a group") };
+        assert_eq!(settings.blend, GroupBlend::PassThrough);
+        assert!(settings.collapsed);
+        assert!((settings.opacity - 128.0 / 255.0).abs() < 1e-6);
+
+        let layer = &document.entries[1];
+        assert_eq!(layer.parent, Some(group.id));
+        assert!(!layer.visible);
+        // placed at its offset and cut off at the right edge
+        assert_eq!(layer.layer.get_pixel(PixelPos { x: 2, y: 1 }), Color::new(0, 0, 255, 255));
+        assert_eq!(layer.layer.get_pixel(PixelPos { x: 1, y: 1 }).alpha, 0);
+        assert_eq!(document.entries[2].parent, None);
+
+        let warnings = document.warnings.join("\n");
+        assert!(warnings.contains("Ünï: layer effects"), "{}", warnings);
+        assert!(warnings.contains("multiply"), "{}", warnings);
+        assert!(warnings.contains("cut off"), "{}", warnings);
+
+        assert!(read_psd(b"8BPS\0\x02").err().unwrap().contains("psb"));
+        assert!(read_psd(&bytes[..bytes.len() - 20]).is_err());
+    }
+
+    #[test]
+    fn test_malformed_psd() {
+        let bytes = psd(vec![record("Layer", [0, 0, 4, 8], b"norm", 255, 0, &[], [255, 0, 0, 255])]);
+        // height then width in the header, 0, too large and a product that overflows u32
+        for (height, width) in [(0u32, 8u32), (4, 0), (4, 30001), (70000, 70000), (u32::MAX, u32::MAX)] {
+            let mut broken = bytes.clone();
+            broken[14..18].copy_from_slice(&height.to_be_bytes());
+            broken[18..22].copy_from_slice(&width.to_be_bytes());
+            assert!(read_psd(&broken).err().unwrap().contains("size"));
+        }
+        // a layer rect far outside of i32 range when subtracted
+        // after the header, three section lengths and the layer count
+        let mut broken = bytes.clone();
+        [i32::MIN, i32::MIN, i32::MAX, i32::MAX].iter().enumerate().for_each(|(i, value)| broken[44 + i * 4..48 + i * 4].copy_from_slice(&value.to_be_bytes()));
+        assert!(read_psd(&broken).err().unwrap().contains("too large"));
+
+        // every byte changed or the file cut off anywhere gives an error or a document, never a panic
+        for i in 0..bytes.len() {
+            for value in [0x00, 0x80, 0xff] {
+                let mut broken = bytes.clone();
+                broken[i] = value;
+                let _ = read_psd(&broken);
+            }
+            let _ = read_psd(&bytes[..i]);
+        }
+    }
+}
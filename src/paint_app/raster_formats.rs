@@ -0,0 +1,311 @@
+use image::codecs::bmp::BmpEncoder;
+use image::codecs::gif::GifEncoder;
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::qoi::QoiEncoder;
+use image::codecs::tga::TgaEncoder;
+use image::imageops::FilterType;
+use image::{ColorType, ImageEncoder, ImageFormat, RgbaImage};
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::image_io::encode_png;
+use crate::paint_app::webp::encode_webp;
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Bmp,
+    Gif,
+    Tga,
+    Qoi,
+    Ico,
+}
+
+pub const ICO_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+impl RasterFormat {
+    pub fn all() -> [RasterFormat; 8] {
+        [RasterFormat::Png, RasterFormat::Jpeg, RasterFormat::WebP, RasterFormat::Bmp, RasterFormat::Gif, RasterFormat::Tga, RasterFormat::Qoi, RasterFormat::Ico]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RasterFormat::Png => "PNG",
+            RasterFormat::Jpeg => "JPEG",
+            RasterFormat::WebP => "WebP",
+            RasterFormat::Bmp => "BMP",
+            RasterFormat::Gif => "GIF",
+            RasterFormat::Tga => "TGA",
+            RasterFormat::Qoi => "QOI",
+            RasterFormat::Ico => "ICO",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RasterFormat::Png => "png",
+            RasterFormat::Jpeg => "jpg",
+            RasterFormat::WebP => "webp",
+            RasterFormat::Bmp => "bmp",
+            RasterFormat::Gif => "gif",
+            RasterFormat::Tga => "tga",
+            RasterFormat::Qoi => "qoi",
+            RasterFormat::Ico => "ico",
+        }
+    }
+
+    // without alpha the pixels are flattened onto a background, gif only keeps fully transparent pixels
+    pub fn has_alpha(&self) -> bool {
+        !matches!(self, RasterFormat::Jpeg | RasterFormat::Bmp)
+    }
+
+    // by the first bytes of the file, tga has no signature so its header is checked last
+    pub fn detect(bytes: &[u8]) -> Option<RasterFormat> {
+        let starts = |magic: &[u8]| bytes.starts_with(magic);
+        if starts(b"\x89PNG\r\n\x1a\n") {
+            Some(RasterFormat::Png)
+        } else if starts(&[0xff, 0xd8, 0xff]) {
+            Some(RasterFormat::Jpeg)
+        } else if starts(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            Some(RasterFormat::WebP)
+        } else if starts(b"BM") {
+            Some(RasterFormat::Bmp)
+        } else if starts(b"GIF87a") || starts(b"GIF89a") {
+            Some(RasterFormat::Gif)
+        } else if starts(b"qoif") {
+            Some(RasterFormat::Qoi)
+        } else if starts(&[0, 0, 1, 0]) && bytes.get(4..6).is_some_and(|count| count != [0, 0]) {
+            Some(RasterFormat::Ico)
+        } else if is_tga(bytes) {
+            Some(RasterFormat::Tga)
+        } else {
+            None
+        }
+    }
+
+    fn image_format(&self) -> ImageFormat {
+        match self {
+            RasterFormat::Png => ImageFormat::Png,
+            RasterFormat::Jpeg => ImageFormat::Jpeg,
+            RasterFormat::WebP => ImageFormat::WebP,
+            RasterFormat::Bmp => ImageFormat::Bmp,
+            RasterFormat::Gif => ImageFormat::Gif,
+            RasterFormat::Tga => ImageFormat::Tga,
+            RasterFormat::Qoi => ImageFormat::Qoi,
+            RasterFormat::Ico => ImageFormat::Ico,
+        }
+    }
+}
+
+// the 2.0 footer, or a header with a known image type and pixel depth
+fn is_tga(bytes: &[u8]) -> bool {
+    if bytes.len() >= 26 && bytes[bytes.len() - 18..].starts_with(b"TRUEVISION-XFILE") {
+        return true;
+    }
+    let Some(header) = bytes.get(..18) else { return false };
+    let width = u16::from_le_bytes([header[12], header[13]]);
+    let height = u16::from_le_bytes([header[14], header[15]]);
+    header[1] <= 1
+        && matches!(header[2], 1 | 2 | 3 | 9 | 10 | 11)
+        && matches!(header[16], 8 | 15 | 16 | 24 | 32)
+        && width > 0
+        && height > 0
+}
+
+pub fn decode_raster(bytes: &[u8]) -> Result<FlatCanvasLayer, String> {
+    match RasterFormat::detect(bytes) {
+        Some(format) => {
+            let image = image::load_from_memory_with_format(bytes, format.image_format()).map_err(|err| err.to_string())?.to_rgba8();
+            Ok(from_image(&image))
+        }
+        None => Err("not an image format that can be opened".to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RasterOptions {
+    pub jpeg_quality: u8,
+    pub webp_lossless: bool,
+    pub webp_quality: u8,
+    // one image of each size goes into the icon
+    pub ico_sizes: Vec<u32>,
+}
+
+impl RasterOptions {
+    pub fn new() -> RasterOptions {
+        RasterOptions {
+            jpeg_quality: 90,
+            webp_lossless: false,
+            webp_quality: 80,
+            ico_sizes: vec![16, 32, 48, 256],
+        }
+    }
+}
+
+impl Default for RasterOptions {
+    fn default() -> RasterOptions {
+        RasterOptions::new()
+    }
+}
+
+pub fn encode_raster(layer: &FlatCanvasLayer, format: RasterFormat, options: &RasterOptions, background: Color) -> Result<Vec<u8>, String> {
+    match format {
+        RasterFormat::Png => encode_png(layer),
+        RasterFormat::Jpeg => {
+            let (width, height) = layer.get_size();
+            let mut bytes = Vec::new();
+            JpegEncoder::new_with_quality(&mut bytes, options.jpeg_quality.clamp(1, 100))
+                .encode(&rgb_bytes(&flatten(layer, background, false)), width, height, ColorType::Rgb8)
+                .map_err(|err| err.to_string())?;
+            Ok(bytes)
+        }
+        RasterFormat::WebP => encode_webp(layer, options.webp_lossless, options.webp_quality),
+        RasterFormat::Bmp => {
+            let (width, height) = layer.get_size();
+            let mut bytes = Vec::new();
+            BmpEncoder::new(&mut bytes).encode(&rgb_bytes(&flatten(layer, background, false)), width, height, ColorType::Rgb8).map_err(|err| err.to_string())?;
+            Ok(bytes)
+        }
+        RasterFormat::Gif => {
+            let image = to_image(&gif_pixels(layer, background));
+            let mut bytes = Vec::new();
+            GifEncoder::new(&mut bytes).encode(&image, image.width(), image.height(), ColorType::Rgba8).map_err(|err| err.to_string())?;
+            Ok(bytes)
+        }
+        RasterFormat::Tga => {
+            let image = to_image(layer);
+            let mut bytes = Vec::new();
+            TgaEncoder::new(&mut bytes).encode(&image, image.width(), image.height(), ColorType::Rgba8).map_err(|err| err.to_string())?;
+            Ok(bytes)
+        }
+        RasterFormat::Qoi => {
+            let image = to_image(layer);
+            let mut bytes = Vec::new();
+            QoiEncoder::new(&mut bytes).write_image(&image, image.width(), image.height(), ColorType::Rgba8).map_err(|err| err.to_string())?;
+            Ok(bytes)
+        }
+        RasterFormat::Ico => encode_ico(layer, &options.ico_sizes),
+    }
+}
+
+// blends the pixels onto an opaque background, keep_transparent leaves pixels below half alpha alone
+pub fn flatten(layer: &FlatCanvasLayer, background: Color, keep_transparent: bool) -> FlatCanvasLayer {
+    let mut result = layer.clone();
+    let background = Color { alpha: 255, ..background };
+    result.get_data_mut().iter_mut()
+        .filter(|color| !keep_transparent || color.alpha >= 128)
+        .for_each(|color| *color = Color { alpha: 255, ..color.interpolate(&background, color.alpha) });
+    result
+}
+
+// gif has one transparent color, pixels below half alpha all become it and the others are flattened
+fn gif_pixels(layer: &FlatCanvasLayer, background: Color) -> FlatCanvasLayer {
+    let mut result = flatten(layer, background, true);
+    result.get_data_mut().iter_mut()
+        .filter(|color| color.alpha < 128)
+        .for_each(|color| *color = Color::new(0, 0, 0, 0));
+    result
+}
+
+fn rgb_bytes(layer: &FlatCanvasLayer) -> Vec<u8> {
+    layer.get_data().iter().flat_map(|color| [color.red, color.green, color.blue]).collect()
+}
+
+// every size is a square, wider or taller images are centered in it
+fn encode_ico(layer: &FlatCanvasLayer, sizes: &[u32]) -> Result<Vec<u8>, String> {
+    if sizes.is_empty() {
+        return Err("an icon needs at least one size".to_string());
+    }
+    let image = to_image(layer);
+    let (width, height) = image.dimensions();
+    let frames = sizes.iter().map(|size| {
+        let scale = *size as f32 / width.max(height) as f32;
+        let (scaled_width, scaled_height) = (((width as f32 * scale).round() as u32).max(1), ((height as f32 * scale).round() as u32).max(1));
+        let scaled = image::imageops::resize(&image, scaled_width, scaled_height, FilterType::CatmullRom);
+        let mut square = RgbaImage::new(*size, *size);
+        image::imageops::overlay(&mut square, &scaled, ((size - scaled_width) / 2) as i64, ((size - scaled_height) / 2) as i64);
+        IcoFrame::as_png(&square, *size, *size, ColorType::Rgba8).map_err(|err| err.to_string())
+    }).collect::<Result<Vec<_>, String>>()?;
+    let mut bytes = Vec::new();
+    IcoEncoder::new(&mut bytes).encode_images(&frames).map_err(|err| err.to_string())?;
+    Ok(bytes)
+}
+
+fn to_image(layer: &FlatCanvasLayer) -> RgbaImage {
+    let (width, height) = layer.get_size();
+    let bytes = layer.get_data().iter().flat_map(|color| [color.red, color.green, color.blue, color.alpha]).collect();
+    RgbaImage::from_raw(width, height, bytes).unwrap()
+}
+
+fn from_image(image: &RgbaImage) -> FlatCanvasLayer {
+    let data = image.pixels().map(|pixel| Color::new(pixel[0], pixel[1], pixel[2], pixel[3])).collect();
+    FlatCanvasLayer::from_data(image.width(), image.height(), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raster_round_trip() {
+        let mut layer = FlatCanvasLayer::new(20, 12);
+        layer.get_data_mut().iter_mut().enumerate().for_each(|(i, color)| *color = Color::new((i * 2) as u8, 100, 255 - i as u8, 255));
+        // a whole block so lossy formats keep the background
+        (0..64).for_each(|i| layer.set_pixel(PixelPos { x: i % 8, y: i / 8 }, Color::new(255, 255, 255, 0)));
+        let background = Color::new(10, 20, 30, 255);
+        let options = RasterOptions { ico_sizes: vec![16, 32], ..RasterOptions::new() };
+
+        for format in RasterFormat::all() {
+            let bytes = encode_raster(&layer, format, &options, background).unwrap();
+            // the extension doesn't matter, only the bytes
+            assert_eq!(RasterFormat::detect(&bytes), Some(format), "{}", format.name());
+            let decoded = decode_raster(&bytes).unwrap();
+            let transparent = decoded.get_pixel(PixelPos { x: 2, y: 2 });
+            match format {
+                // scaled into squares, the largest comes back
+                RasterFormat::Ico => assert_eq!(decoded.get_size(), (32, 32)),
+                RasterFormat::Jpeg | RasterFormat::WebP => assert_eq!(decoded.get_size(), (20, 12)),
+                _ => assert_eq!(decoded.get_data(), &flatten_if_needed(&layer, format, background)),
+            }
+            match format.has_alpha() {
+                true => assert_eq!(transparent.alpha, 0, "{}", format.name()),
+                false => assert!(transparent.alpha == 255 && transparent.blue.abs_diff(30) < 12, "{} {:?}", format.name(), transparent)
+            }
+        }
+        assert_eq!(RasterFormat::detect(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_broken_raster() {
+        let mut layer = FlatCanvasLayer::new(24, 16);
+        layer.get_data_mut().iter_mut().enumerate().for_each(|(i, color)| *color = Color::new(i as u8, (i * 7) as u8, 90, 255));
+        let options = RasterOptions { ico_sizes: vec![16], ..RasterOptions::new() };
+        for format in RasterFormat::all() {
+            let bytes = encode_raster(&layer, format, &options, Color::black()).unwrap();
+            // with only the header there are no pixels, the webp decoder fills in missing pixels after that
+            assert!(decode_raster(&bytes[..24]).is_err(), "{}", format.name());
+            if format != RasterFormat::WebP {
+                assert!(decode_raster(&bytes[..bytes.len() / 2]).is_err(), "{}", format.name());
+            }
+            // anything else only has to come back as an error or an image, without panicking
+            for cut in (0..bytes.len()).step_by(7) {
+                let _ = decode_raster(&bytes[..cut]);
+            }
+            for i in (0..bytes.len()).step_by(3) {
+                let mut mutated = bytes.clone();
+                mutated[i] ^= 0x5a;
+                let _ = decode_raster(&mutated);
+            }
+        }
+    }
+
+    fn flatten_if_needed(layer: &FlatCanvasLayer, format: RasterFormat, background: Color) -> Vec<Color> {
+        match format {
+            RasterFormat::Bmp => flatten(layer, background, false).get_data().to_vec(),
+            RasterFormat::Gif => gif_pixels(layer, background).get_data().to_vec(),
+            _ => layer.get_data().to_vec()
+        }
+    }
+}
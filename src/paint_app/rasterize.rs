@@ -0,0 +1,722 @@
+use glam::Vec2;
+use super::data_types::*;
+
+// a pixel and how much of it a shape covers, 0..1. pixel x, y is sampled at the point (x, y)
+pub type Coverage = (PixelPos, f32);
+
+// points a curve is flattened into at most
+const MAX_CURVE_POINTS: usize = 65;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    // miters longer than this many widths become bevels, like svg
+    pub miter_limit: f32,
+    pub anti_alias: bool,
+}
+
+impl StrokeStyle {
+    pub fn new(width: f32) -> StrokeStyle {
+        StrokeStyle {
+            width,
+            cap: LineCap::Round,
+            join: LineJoin::Round,
+            miter_limit: 4.0,
+            anti_alias: true,
+        }
+    }
+}
+
+// 1 pixel wide line without gaps or doubled pixels, everything is fully covered
+pub struct BresenhamLine {
+    x: i32,
+    y: i32,
+    x1: i32,
+    dx: i32,
+    derror2: i32,
+    error2: i32,
+    y_step: i32,
+    steep: bool,
+}
+
+impl BresenhamLine {
+    pub fn new(start: PixelPos, end: PixelPos) -> BresenhamLine {
+        let (mut x0, mut y0, mut x1, mut y1) = (start.x as i32, start.y as i32, end.x as i32, end.y as i32);
+        let steep = (x0 - x1).abs() < (y0 - y1).abs();
+        if steep {
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+        BresenhamLine {
+            x: x0,
+            y: y0,
+            x1,
+            dx: x1 - x0,
+            derror2: (y1 - y0).abs() * 2,
+            error2: 0,
+            y_step: if y1 > y0 { 1 } else { -1 },
+            steep,
+        }
+    }
+}
+
+impl Iterator for BresenhamLine {
+    type Item = Coverage;
+
+    fn next(&mut self) -> Option<Coverage> {
+        if self.x > self.x1 {
+            return None;
+        }
+        let pos = match self.steep {
+            true => PixelPos { x: self.y as u32, y: self.x as u32 },
+            false => PixelPos { x: self.x as u32, y: self.y as u32 }
+        };
+        self.error2 += self.derror2;
+        if self.error2 > self.dx {
+            self.y += self.y_step;
+            self.error2 -= self.dx * 2;
+        }
+        self.x += 1;
+        Some((pos, 1.0))
+    }
+}
+
+// Xiaolin Wu's anti-aliased 1 pixel line, two pixels per column sharing the coverage
+pub struct WuLine {
+    steep: bool,
+    gradient: f32,
+    // the end columns, with the line's height there and how much of the column it spans
+    first: (i64, f32, f32),
+    last: (i64, f32, f32),
+    column: i64,
+    // which of the column's two pixels is next
+    lower: bool,
+    size: (u32, u32),
+}
+
+impl WuLine {
+    pub fn new(start: Vec2, end: Vec2, size: (u32, u32)) -> WuLine {
+        let (mut start, mut end) = (start, end);
+        let steep = (end.y - start.y).abs() > (end.x - start.x).abs();
+        if steep {
+            start = Vec2::new(start.y, start.x);
+            end = Vec2::new(end.y, end.x);
+        }
+        if start.x > end.x {
+            std::mem::swap(&mut start, &mut end);
+        }
+        let dx = end.x - start.x;
+        let gradient = match dx == 0.0 {
+            true => 1.0,
+            false => (end.y - start.y) / dx
+        };
+        let end_column = |point: Vec2, gap: f32| {
+            let x = point.x.round();
+            (x as i64, point.y + gradient * (x - point.x), gap)
+        };
+        let mut first = end_column(start, 1.0 - (start.x + 0.5).fract());
+        let last = end_column(end, (end.x + 0.5).fract());
+        if first.0 == last.0 {
+            // both ends in one column, that's a dot
+            first.2 = 1.0;
+        }
+        WuLine { steep, gradient, first, last, column: first.0, lower: false, size }
+    }
+}
+
+impl Iterator for WuLine {
+    type Item = Coverage;
+
+    fn next(&mut self) -> Option<Coverage> {
+        loop {
+            if self.column > self.last.0 || (self.column > self.first.0 && self.first.0 == self.last.0) {
+                return None;
+            }
+            let (y, gap) = match self.column {
+                column if column == self.first.0 => (self.first.1, self.first.2),
+                column if column == self.last.0 => (self.last.1, self.last.2),
+                column => (self.first.1 + self.gradient * (column - self.first.0) as f32, 1.0)
+            };
+            let (row, coverage) = match self.lower {
+                false => (y.floor() as i64, (1.0 - y.fract()) * gap),
+                true => (y.floor() as i64 + 1, y.fract() * gap)
+            };
+            let column = self.column;
+            if self.lower {
+                self.column += 1;
+            }
+            self.lower = !self.lower;
+
+            let (x, y) = match self.steep {
+                true => (row, column),
+                false => (column, row)
+            };
+            let inside = x >= 0 && y >= 0 && x < self.size.0 as i64 && y < self.size.1 as i64;
+            if inside && coverage > 0.0 {
+                return Some((PixelPos { x: x as u32, y: y as u32 }, coverage));
+            }
+        }
+    }
+}
+
+// a curve flattened into line segments, kept inline so strokes of curves don't allocate
+#[derive(Debug, Clone, Copy)]
+pub struct CurvePoints {
+    points: [Vec2; MAX_CURVE_POINTS],
+    len: usize,
+}
+
+impl CurvePoints {
+    fn sample(control_length: f32, point_at: impl Fn(f32) -> Vec2) -> CurvePoints {
+        // about 3 pixels per segment
+        let segments = ((control_length / 3.0).ceil() as usize).clamp(1, MAX_CURVE_POINTS - 1);
+        let mut points = [Vec2::ZERO; MAX_CURVE_POINTS];
+        for (i, point) in points.iter_mut().enumerate().take(segments + 1) {
+            *point = point_at(i as f32 / segments as f32);
+        }
+        CurvePoints { points, len: segments + 1 }
+    }
+}
+
+impl AsRef<[Vec2]> for CurvePoints {
+    fn as_ref(&self) -> &[Vec2] {
+        &self.points[..self.len]
+    }
+}
+
+pub fn quadratic_points(p0: Vec2, p1: Vec2, p2: Vec2) -> CurvePoints {
+    let control_length = p0.distance(p1) + p1.distance(p2);
+    CurvePoints::sample(control_length, |t| {
+        let s = 1.0 - t;
+        p0 * (s * s) + p1 * (2.0 * s * t) + p2 * (t * t)
+    })
+}
+
+pub fn cubic_points(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2) -> CurvePoints {
+    let control_length = p0.distance(p1) + p1.distance(p2) + p2.distance(p3);
+    CurvePoints::sample(control_length, |t| {
+        let s = 1.0 - t;
+        p0 * (s * s * s) + p1 * (3.0 * s * s * t) + p2 * (3.0 * s * t * t) + p3 * (t * t * t)
+    })
+}
+
+// a thick polyline with caps and joins. every pixel comes once, with the coverage of the
+// whole stroke, so overlapping segments don't add up. a row only visits the pixels near
+// its segments and tests those segments, not the whole bounding box against all of them
+pub struct Stroke<P: AsRef<[Vec2]>> {
+    points: P,
+    style: StrokeStyle,
+    // the last point joins the first, no caps
+    closed: bool,
+    // how far from its line a segment, its joins or caps can cover
+    reach: f32,
+    min: (u32, u32),
+    max: (u32, u32),
+    x: u32,
+    y: u32,
+    // segments near row y with the pixels they can cover there, from and to
+    segments: Vec<(usize, u32, u32)>,
+    // the merged pixel ranges of the row, the one x is in
+    spans: Vec<(u32, u32)>,
+    span: usize,
+}
+
+impl<P: AsRef<[Vec2]>> Stroke<P> {
+    pub fn new(points: P, style: StrokeStyle, size: (u32, u32)) -> Stroke<P> {
+        let radius = style.width.max(0.0) / 2.0;
+        let reach = match style.join {
+            LineJoin::Miter => radius * style.miter_limit.max(std::f32::consts::SQRT_2),
+            _ => radius * std::f32::consts::SQRT_2
+        } + 1.0;
+        let (low, high) = points.as_ref().iter().fold((Vec2::MAX, Vec2::MIN), |(low, high), point| (low.min(*point), high.max(*point)));
+        let (low, high) = ((low - reach).max(Vec2::ZERO).ceil(), (high + reach).floor());
+        let mut result = Stroke {
+            points, style, closed: false, reach, min: (low.x as u32, low.y as u32), max: (0, 0), x: 0, y: 0,
+            segments: Vec::new(), spans: Vec::new(), span: 0
+        };
+        match high.x >= low.x && high.y >= low.y && size.0 > 0 && size.1 > 0 {
+            true => result.max = ((high.x as u32).min(size.0 - 1), (high.y as u32).min(size.1 - 1)),
+            // nothing to visit, y starts past the end
+            false => result.min.1 = 1
+        }
+        result.y = result.min.1;
+        result.start_row();
+        result
+    }
+
+    pub fn closed(mut self) -> Stroke<P> {
+        self.closed = true;
+        self.start_row();
+        self
+    }
+
+    pub fn coverage_at(&self, point: Vec2) -> f32 {
+        let count = self.segment_count();
+        self.coverage(self.distance(point, 0..count))
+    }
+
+    fn coverage(&self, distance: f32) -> f32 {
+        match self.style.anti_alias {
+            true => (0.5 - distance).clamp(0.0, 1.0),
+            false => (distance <= 1e-4) as u8 as f32
+        }
+    }
+
+    fn segment_count(&self) -> usize {
+        let count = self.points.as_ref().len();
+        match self.closed && count > 2 {
+            true => count,
+            false => count.saturating_sub(1)
+        }
+    }
+
+    // finds the segments that can reach row y and the pixels between them to visit
+    fn start_row(&mut self) {
+        self.segments.clear();
+        self.spans.clear();
+        self.span = 0;
+        if self.y > self.max.1 || self.min.1 > self.max.1 {
+            return;
+        }
+        let points = self.points.as_ref();
+        let (y, reach) = (self.y as f32, self.reach);
+        let (min, max) = (self.min.0 as f32, self.max.0 as f32);
+        let pixels = |from: f32, to: f32| ((from - reach).ceil().max(min) as u32, (to + reach).floor().min(max) as u32);
+        for i in 0..self.segment_count() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            if a.y.min(b.y) - reach > y || a.y.max(b.y) + reach < y {
+                continue;
+            }
+            // the part of the segment within reach of the row
+            let (from, to) = match a.y == b.y {
+                true => (a.x.min(b.x), a.x.max(b.x)),
+                false => {
+                    let (t0, t1) = ((y - reach - a.y) / (b.y - a.y), (y + reach - a.y) / (b.y - a.y));
+                    let (x0, x1) = (a.x + t0.clamp(0.0, 1.0) * (b.x - a.x), a.x + t1.clamp(0.0, 1.0) * (b.x - a.x));
+                    (x0.min(x1), x0.max(x1))
+                }
+            };
+            let (from, to) = pixels(from, to);
+            if from <= to {
+                self.segments.push((i, from, to));
+                self.spans.push((from, to));
+            }
+        }
+        // caps of the ends, the only thing a single point has
+        if !self.closed || points.len() <= 2 {
+            for end in points.first().into_iter().chain(points.last()) {
+                let (from, to) = pixels(end.x, end.x);
+                if (end.y - y).abs() <= reach && from <= to {
+                    self.spans.push((from, to));
+                }
+            }
+        }
+        self.spans.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(self.spans.len());
+        for (from, to) in self.spans.drain(..) {
+            match merged.last_mut() {
+                Some(last) if from <= last.1 + 1 => last.1 = last.1.max(to),
+                _ => merged.push((from, to))
+            }
+        }
+        self.spans = merged;
+        self.x = self.spans.first().map(|span| span.0).unwrap_or(0);
+    }
+
+    // signed distance to the stroke, the union of the given segments, their joins and the caps
+    fn distance(&self, point: Vec2, segments: impl Iterator<Item = usize>) -> f32 {
+        let points = self.points.as_ref();
+        let radius = self.style.width.max(0.0) / 2.0;
+        let Some(first) = points.first() else { return f32::INFINITY };
+        let count = points.len();
+        let closed = self.closed && count > 2;
+        let mut result = f32::INFINITY;
+        for i in segments {
+            // ends shared with the next segment aren't edges of the stroke
+            let joined = (closed || i > 0, closed || i + 2 < count);
+            result = result.min(box_distance(point, points[i], points[(i + 1) % count], radius, joined));
+            // the corners at both ends of the segment
+            for corner in [i, i + 1] {
+                if closed || (corner > 0 && corner < count - 1) {
+                    let corner = corner % count;
+                    result = result.min(self.join_distance(point, points[(corner + count - 1) % count], points[corner], points[(corner + 1) % count], radius));
+                }
+            }
+        }
+
+        if !closed {
+            // caps face away from the first and last segment that has a length
+            let start_dir = points.iter().find(|other| *other != first).map(|other| (*other - *first).normalize());
+            let last = points[count - 1];
+            let end_dir = points.iter().rev().find(|other| **other != last).map(|other| (last - *other).normalize());
+            for (end, dir) in [(*first, start_dir.map(|dir| -dir)), (last, end_dir)] {
+                result = result.min(match (self.style.cap, dir) {
+                    (LineCap::Butt, _) => f32::INFINITY,
+                    (LineCap::Round, _) => disc_distance(point, end, radius),
+                    (LineCap::Square, Some(dir)) => box_distance(point, end, end + dir * radius, radius, (false, false)),
+                    // a single point has no direction
+                    (LineCap::Square, None) => box_distance(point, end - Vec2::X * radius, end + Vec2::X * radius, radius, (false, false))
+                });
+            }
+        }
+        result
+    }
+
+    fn join_distance(&self, point: Vec2, a: Vec2, corner: Vec2, b: Vec2, radius: f32) -> f32 {
+        if a == corner || corner == b || (point - corner).abs().max_element() > radius * self.style.miter_limit.max(2.0) + 1.0 {
+            return f32::INFINITY;
+        }
+        let (dir_a, dir_b) = ((corner - a).normalize(), (b - corner).normalize());
+        let turn = dir_a.perp_dot(dir_b);
+        if turn.abs() < 1e-6 {
+            // straight on, the segments already meet
+            return f32::INFINITY;
+        }
+        // the outer side of the turn is the one the join fills
+        let (normal_a, normal_b) = (-dir_a.perp() * turn.signum(), -dir_b.perp() * turn.signum());
+        let (outer_a, outer_b) = (corner + normal_a * radius, corner + normal_b * radius);
+        let miter = (normal_a + normal_b).normalize();
+        let ratio = 1.0 / miter.dot(normal_a);
+        match self.style.join {
+            LineJoin::Round => disc_distance(point, corner, radius),
+            LineJoin::Miter if ratio <= self.style.miter_limit => polygon_distance(point, &[corner, outer_a, corner + miter * radius * ratio, outer_b]),
+            _ => polygon_distance(point, &[corner, outer_a, outer_b])
+        }
+    }
+}
+
+impl<P: AsRef<[Vec2]>> Iterator for Stroke<P> {
+    type Item = Coverage;
+
+    fn next(&mut self) -> Option<Coverage> {
+        while self.y <= self.max.1 && self.min.1 <= self.max.1 {
+            let Some(&(_, span_end)) = self.spans.get(self.span) else {
+                self.y += 1;
+                self.start_row();
+                continue;
+            };
+            let pos = PixelPos { x: self.x, y: self.y };
+            if self.x < span_end {
+                self.x += 1;
+            } else {
+                self.span += 1;
+                self.x = self.spans.get(self.span).map(|span| span.0).unwrap_or(0);
+            }
+            let nearby = self.segments.iter().filter(|(_, from, to)| (*from..=*to).contains(&pos.x)).map(|(i, _, _)| *i);
+            let coverage = self.coverage(self.distance(Vec2::new(pos.x as f32, pos.y as f32), nearby));
+            if coverage > 0.0 {
+                return Some((pos, coverage));
+            }
+        }
+        None
+    }
+}
+
+// the inside of a polygon by the nonzero rule, the last point joins the first. this one
+// needs a row of coverage and the edge crossings, both allocated once
+pub struct Fill<P: AsRef<[Vec2]>> {
+    points: P,
+    anti_alias: bool,
+    row: Vec<f32>,
+    crossings: Vec<(f32, i32)>,
+    x: usize,
+    y: u32,
+    end_y: u32,
+}
+
+impl<P: AsRef<[Vec2]>> Fill<P> {
+    pub fn new(points: P, anti_alias: bool, size: (u32, u32)) -> Fill<P> {
+        let (low, high) = points.as_ref().iter().fold((f32::MAX, f32::MIN), |(low, high), point| (low.min(point.y), high.max(point.y)));
+        let y = (low - 1.0).max(0.0).ceil() as u32;
+        let end_y = match high >= low {
+            true => ((high + 1.0).max(0.0) as u32 + 1).min(size.1),
+            false => 0
+        };
+        // x starts at the end of the row so the first row gets filled
+        Fill { points, anti_alias, row: vec![0.0; size.0 as usize], crossings: Vec::new(), x: size.0 as usize, y: y.wrapping_sub(1), end_y }
+    }
+}
+
+// adds the coverage of the rows sample lines to row
+fn fill_row(points: &[Vec2], y: u32, anti_alias: bool, row: &mut [f32], crossings: &mut Vec<(f32, i32)>) {
+    row.iter_mut().for_each(|coverage| *coverage = 0.0);
+    let samples = match anti_alias {
+        true => 4,
+        false => 1
+    };
+    for sample in 0..samples {
+        let sample_y = match anti_alias {
+            true => y as f32 + (sample as f32 + 0.5) / samples as f32 - 0.5,
+            false => y as f32
+        };
+        crossings.clear();
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            if (a.y <= sample_y) != (b.y <= sample_y) {
+                let t = (sample_y - a.y) / (b.y - a.y);
+                crossings.push((a.x + t * (b.x - a.x), if b.y > a.y { 1 } else { -1 }));
+            }
+        }
+        crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut winding = 0;
+        for pair in crossings.windows(2) {
+            winding += pair[0].1;
+            if winding == 0 {
+                continue;
+            }
+            let (start, end) = (pair[0].0, pair[1].0);
+            // pixel x spans x - 0.5 to x + 0.5
+            let first = (start + 0.5).floor().max(0.0) as usize;
+            let last = ((end + 0.5).ceil().max(0.0) as usize).min(row.len());
+            for (x, coverage) in row.iter_mut().enumerate().take(last).skip(first) {
+                *coverage += match anti_alias {
+                    true => ((end.min(x as f32 + 0.5) - start.max(x as f32 - 0.5)).max(0.0)) / samples as f32,
+                    false => (start <= x as f32 && (x as f32) < end) as u8 as f32
+                };
+            }
+        }
+    }
+}
+
+impl<P: AsRef<[Vec2]>> Iterator for Fill<P> {
+    type Item = Coverage;
+
+    fn next(&mut self) -> Option<Coverage> {
+        loop {
+            if self.x >= self.row.len() {
+                self.y = self.y.wrapping_add(1);
+                if self.y >= self.end_y || self.row.is_empty() {
+                    return None;
+                }
+                fill_row(self.points.as_ref(), self.y, self.anti_alias, &mut self.row, &mut self.crossings);
+                self.x = 0;
+            }
+            let x = self.x;
+            self.x += 1;
+            if self.row[x] > 0.0 {
+                return Some((PixelPos { x: x as u32, y: self.y }, self.row[x].min(1.0)));
+            }
+        }
+    }
+}
+
+// the line tools use this, 1 pixel lines use bresenham or wu and wider ones are strokes
+pub enum Line {
+    Bresenham(BresenhamLine),
+    Wu(WuLine),
+    Stroke(Stroke<[Vec2; 2]>),
+}
+
+impl Iterator for Line {
+    type Item = Coverage;
+
+    fn next(&mut self) -> Option<Coverage> {
+        match self {
+            Line::Bresenham(line) => line.next(),
+            Line::Wu(line) => line.next(),
+            Line::Stroke(line) => line.next()
+        }
+    }
+}
+
+pub fn line(start: PixelPos, end: PixelPos, style: StrokeStyle, size: (u32, u32)) -> Line {
+    let to_vec = |pos: PixelPos| Vec2::new(pos.x as f32, pos.y as f32);
+    match (style.width <= 1.0, style.anti_alias) {
+        (true, false) => Line::Bresenham(BresenhamLine::new(start, end)),
+        (true, true) => Line::Wu(WuLine::new(to_vec(start), to_vec(end), size)),
+        (false, _) => Line::Stroke(Stroke::new([to_vec(start), to_vec(end)], style, size))
+    }
+}
+
+// a rectangle from a to b, radius to each side. negative inside
+// joined ends don't bound the inside, otherwise the short segments of a curve would
+// make its middle look close to an edge
+fn box_distance(point: Vec2, a: Vec2, b: Vec2, radius: f32, joined: (bool, bool)) -> f32 {
+    let length = a.distance(b);
+    if length == 0.0 || point.cmplt(a.min(b) - radius - 1.0).any() || point.cmpgt(a.max(b) + radius + 1.0).any() {
+        return f32::INFINITY;
+    }
+    let dir = (b - a) / length;
+    let local = point - a;
+    let along = local.dot(dir);
+    let across = local.dot(dir.perp());
+    let (start, end) = (-along, along - length);
+    let along_outside = match start.max(end) > 0.0 {
+        true => start.max(end),
+        false => match joined {
+            (false, false) => start.max(end),
+            (false, true) => start,
+            (true, false) => end,
+            (true, true) => f32::NEG_INFINITY
+        }
+    };
+    let outside = Vec2::new(along_outside, across.abs() - radius);
+    outside.max(Vec2::ZERO).length() + outside.max_element().min(0.0)
+}
+
+fn disc_distance(point: Vec2, center: Vec2, radius: f32) -> f32 {
+    point.distance(center) - radius
+}
+
+// convex polygons only, the farthest edge decides
+fn polygon_distance(point: Vec2, corners: &[Vec2]) -> f32 {
+    let area = (0..corners.len()).map(|i| corners[i].perp_dot(corners[(i + 1) % corners.len()])).sum::<f32>();
+    (0..corners.len())
+        .filter_map(|i| {
+            let (a, b) = (corners[i], corners[(i + 1) % corners.len()]);
+            let edge = (b - a).try_normalize()?;
+            // pointing out of the polygon whichever way it winds
+            let outward = -edge.perp() * area.signum();
+            Some((point - a).dot(outward))
+        })
+        .fold(f32::NEG_INFINITY, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covered<I: Iterator<Item = Coverage>>(pixels: I) -> Vec<(u32, u32, f32)> {
+        pixels.map(|(pos, coverage)| (pos.x, pos.y, coverage)).collect()
+    }
+
+    fn has(pixels: &[(u32, u32, f32)], x: u32, y: u32) -> bool {
+        pixels.iter().any(|(px, py, _)| (*px, *py) == (x, y))
+    }
+
+    #[test]
+    fn test_bresenham_and_wu() {
+        let line = covered(BresenhamLine::new(PixelPos { x: 0, y: 0 }, PixelPos { x: 4, y: 2 }));
+        assert_eq!(line, [(0, 0, 1.0), (1, 0, 1.0), (2, 1, 1.0), (3, 1, 1.0), (4, 2, 1.0)]);
+        let steep = covered(BresenhamLine::new(PixelPos { x: 3, y: 9 }, PixelPos { x: 1, y: 2 }));
+        assert_eq!(steep.len(), 8);
+        assert!(has(&steep, 3, 9) && has(&steep, 1, 2));
+
+        // every column shares out the coverage of one pixel, the ends get half
+        let wu = covered(WuLine::new(Vec2::new(2.0, 3.0), Vec2::new(8.0, 3.0), (16, 16)));
+        assert!(wu.iter().all(|(_, y, _)| *y == 3));
+        assert!((wu.iter().map(|(_, _, coverage)| coverage).sum::<f32>() - 6.0).abs() < 1e-4);
+        let diagonal = covered(WuLine::new(Vec2::new(1.0, 1.5), Vec2::new(9.0, 5.5), (16, 16)));
+        assert!(diagonal.iter().any(|(_, _, coverage)| *coverage < 0.75));
+        assert!((diagonal.iter().map(|(_, _, coverage)| coverage).sum::<f32>() - 8.0).abs() < 1e-4);
+        // clipped
+        assert!(covered(WuLine::new(Vec2::new(-5.0, 1.0), Vec2::new(20.0, 2.0), (8, 8))).iter().all(|(x, y, _)| *x < 8 && *y < 8));
+    }
+
+    #[test]
+    fn test_caps() {
+        let cap_ends = |cap| {
+            let style = StrokeStyle { cap, anti_alias: false, ..StrokeStyle::new(4.0) };
+            let pixels = covered(Stroke::new([Vec2::new(4.0, 8.0), Vec2::new(12.0, 8.0)], style, (20, 20)));
+            let row = pixels.iter().filter(|(_, y, _)| *y == 8).map(|(x, _, _)| *x).collect::<Vec<_>>();
+            (row[0], row[row.len() - 1], has(&pixels, 2, 6))
+        };
+        assert_eq!(cap_ends(LineCap::Butt), (4, 12, false));
+        assert_eq!(cap_ends(LineCap::Square), (2, 14, true));
+        assert_eq!(cap_ends(LineCap::Round), (2, 14, false));
+
+        // anti-aliased edges are partly covered and the center fully
+        let smooth = covered(Stroke::new([Vec2::new(4.0, 8.0), Vec2::new(12.0, 8.0)], StrokeStyle::new(2.0), (20, 20)));
+        assert!(smooth.contains(&(8, 8, 1.0)) && smooth.contains(&(8, 7, 0.5)));
+    }
+
+    #[test]
+    fn test_joins() {
+        let corner = [Vec2::new(4.0, 4.0), Vec2::new(12.0, 4.0), Vec2::new(12.0, 12.0)];
+        let pixels = |join, miter_limit| {
+            let style = StrokeStyle { join, miter_limit, anti_alias: false, ..StrokeStyle::new(4.0) };
+            covered(Stroke::new(corner, style, (20, 20)))
+        };
+        assert!(has(&pixels(LineJoin::Miter, 4.0), 14, 2));
+        assert!(!has(&pixels(LineJoin::Miter, 1.0), 14, 2));
+        assert!(!has(&pixels(LineJoin::Bevel, 4.0), 14, 2));
+        assert!(has(&pixels(LineJoin::Bevel, 4.0), 13, 3));
+        assert!(has(&pixels(LineJoin::Round, 4.0), 13, 3));
+        assert!(!has(&pixels(LineJoin::Round, 4.0), 14, 2));
+
+        // a path crossing itself still gives every pixel once
+        let crossing = [Vec2::new(2.0, 2.0), Vec2::new(14.0, 14.0), Vec2::new(14.0, 2.0), Vec2::new(2.0, 14.0)];
+        let mut positions = covered(Stroke::new(&crossing[..], StrokeStyle::new(3.0), (16, 16))).iter().map(|(x, y, _)| (*x, *y)).collect::<Vec<_>>();
+        let count = positions.len();
+        positions.sort();
+        positions.dedup();
+        assert_eq!(positions.len(), count);
+    }
+
+    #[test]
+    fn test_fill() {
+        let square = [Vec2::new(2.0, 2.0), Vec2::new(6.0, 2.0), Vec2::new(6.0, 6.0), Vec2::new(2.0, 6.0)];
+        let hard = covered(Fill::new(square, false, (10, 10)));
+        assert_eq!(hard.len(), 16);
+        assert!(has(&hard, 2, 2) && !has(&hard, 6, 6));
+        // the edges go through pixel centers, those pixels are half covered
+        let smooth = covered(Fill::new(square, true, (10, 10)));
+        assert!(smooth.contains(&(4, 4, 1.0)) && smooth.contains(&(2, 4, 0.5)) && smooth.contains(&(2, 2, 0.25)));
+        assert!((smooth.iter().map(|(_, _, coverage)| coverage).sum::<f32>() - 16.0).abs() < 1e-4);
+        assert!(covered(Fill::new(square, true, (4, 4))).iter().all(|(x, y, _)| *x < 4 && *y < 4));
+
+        // a closed stroke has joins all around and no caps
+        let outline = covered(Stroke::new(square, StrokeStyle { join: LineJoin::Miter, anti_alias: false, ..StrokeStyle::new(2.0) }, (10, 10)).closed());
+        assert!(has(&outline, 1, 1) && has(&outline, 7, 7) && !has(&outline, 4, 4));
+    }
+
+    #[test]
+    fn test_curves() {
+        let (p0, p1, p2, p3) = (Vec2::new(2.0, 20.0), Vec2::new(2.0, 2.0), Vec2::new(30.0, 2.0), Vec2::new(30.0, 20.0));
+        let points = cubic_points(p0, p1, p2, p3);
+        assert_eq!(points.as_ref()[0], p0);
+        assert_eq!(*points.as_ref().last().unwrap(), p3);
+        assert!(points.as_ref().len() > 10);
+        let top = cubic_points(p0, p1, p2, p3).as_ref()[points.as_ref().len() / 2];
+        assert!((top - Vec2::new(16.0, 6.5)).length() < 0.01);
+
+        let quadratic = quadratic_points(Vec2::new(0.0, 0.0), Vec2::new(4.0, 8.0), Vec2::new(8.0, 0.0));
+        assert_eq!(quadratic.as_ref().len(), 7);
+        let stroke = covered(Stroke::new(quadratic, StrokeStyle::new(1.0), (16, 16)));
+        assert!(has(&stroke, 4, 4) && !has(&stroke, 4, 8));
+    }
+
+    #[test]
+    fn test_stroke_visits_only_near_segments() {
+        // the rows only test nearby segments, that has to give what testing all of them gives
+        let tested = |stroke: &Stroke<&[Vec2]>| (0..32).flat_map(|y| (0..32).map(move |x| (x, y)))
+            .map(|(x, y)| (x, y, stroke.coverage_at(Vec2::new(x as f32, y as f32))))
+            .filter(|(_, _, coverage)| *coverage > 0.0)
+            .collect::<Vec<_>>();
+        let zigzag = [Vec2::new(2.0, 2.0), Vec2::new(30.0, 6.0), Vec2::new(3.0, 12.0), Vec2::new(28.0, 25.0), Vec2::new(28.0, 26.0)];
+        for (join, cap, closed) in [(LineJoin::Miter, LineCap::Square, false), (LineJoin::Round, LineCap::Round, false), (LineJoin::Bevel, LineCap::Butt, true)] {
+            let style = StrokeStyle { join, cap, ..StrokeStyle::new(3.0) };
+            let stroke = match closed {
+                true => Stroke::new(&zigzag[..], style, (32, 32)).closed(),
+                false => Stroke::new(&zigzag[..], style, (32, 32))
+            };
+            let expected = tested(&stroke);
+            assert!(!expected.is_empty());
+            assert_eq!(covered(stroke), expected);
+        }
+        let point = Stroke::new(&zigzag[..1], StrokeStyle::new(4.0), (32, 32));
+        let expected = tested(&point);
+        assert_eq!(expected.len(), 21);
+        assert_eq!(covered(point), expected);
+    }
+}
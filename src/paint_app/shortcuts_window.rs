@@ -0,0 +1,129 @@
+use eframe::egui;
+use egui::{Color32, Key, RichText};
+use crate::paint_app::actions::{ActionRegistry, Shortcut};
+
+pub struct ShortcutsWindow {
+    pub open: bool,
+    pub filter: String,
+    // the action waiting for a key press
+    recording: Option<String>,
+}
+
+impl ShortcutsWindow {
+    pub fn new() -> ShortcutsWindow {
+        ShortcutsWindow {
+            open: false,
+            filter: String::new(),
+            recording: None,
+        }
+    }
+
+    // returns true when a shortcut was changed
+    pub fn show_shortcuts_window(&mut self, ctx: &egui::Context, actions: &mut ActionRegistry) -> bool {
+        let mut changed = false;
+        if let Some(id) = self.recording.clone() {
+            // escape cancels, modifier keys on their own aren't key events
+            let pressed = ctx.input_mut(|s| {
+                let pressed = s.events.iter().find_map(|event| match event {
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                    _ => None
+                });
+                s.events.clear();
+                pressed
+            });
+            match pressed {
+                Some((Key::Escape, _)) => self.recording = None,
+                Some((key, modifiers)) => {
+                    let mut shortcuts = actions.get(&id).map(|action| action.shortcuts.clone()).unwrap_or_default();
+                    let shortcut = Shortcut::from_event(modifiers, key);
+                    if !shortcuts.contains(&shortcut) {
+                        shortcuts.push(shortcut);
+                    }
+                    actions.set_shortcuts(&id, shortcuts);
+                    self.recording = None;
+                    changed = true;
+                }
+                None => {}
+            }
+        }
+
+        let mut open = self.open;
+        egui::Window::new("Keyboard shortcuts")
+            .collapsible(false)
+            .default_height(420.0)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Search: ");
+                    ui.text_edit_singleline(&mut self.filter);
+                    if ui.button("Reset all").clicked() {
+                        actions.reset_all();
+                        changed = true;
+                    }
+                });
+
+                let conflicts = actions.conflicts();
+                for (shortcut, conflicting) in conflicts.iter() {
+                    let names = conflicting.iter().map(|action| action.name.as_str()).collect::<Vec<_>>();
+                    ui.colored_label(Color32::LIGHT_RED, format!("{} is used by {}", shortcut, names.join(", ")));
+                }
+
+                ui.separator();
+
+                let mut edit: Option<(String, Option<Vec<Shortcut>>)> = None;
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    egui::Grid::new("shortcuts_grid").striped(true).show(ui, |ui| {
+                        let filter = self.filter.to_lowercase();
+                        for action in actions.actions().iter().filter(|action| action.name.to_lowercase().contains(&filter)) {
+                            ui.label(&action.name);
+                            ui.horizontal(|ui| {
+                                for shortcut in action.shortcuts.iter() {
+                                    let conflict = conflicts.iter().any(|(other, _)| other == shortcut);
+                                    let text = match conflict {
+                                        true => RichText::new(shortcut.to_string()).color(Color32::LIGHT_RED),
+                                        false => RichText::new(shortcut.to_string())
+                                    };
+                                    if ui.button(text).on_hover_text("Click to remove").clicked() {
+                                        let rest = action.shortcuts.iter().copied().filter(|other| other != shortcut).collect();
+                                        edit = Some((action.id.clone(), Some(rest)));
+                                    }
+                                }
+                                let recording = self.recording.as_ref() == Some(&action.id);
+                                let label = match recording {
+                                    true => "Press a key...",
+                                    false => "+"
+                                };
+                                if ui.selectable_label(recording, label).clicked() {
+                                    self.recording = match recording {
+                                        true => None,
+                                        false => Some(action.id.clone())
+                                    };
+                                }
+                            });
+                            if action.shortcuts != action.default_shortcuts && ui.small_button("Reset").clicked() {
+                                edit = Some((action.id.clone(), None));
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+                changed |= edit.is_some();
+                match edit {
+                    Some((id, Some(shortcuts))) => actions.set_shortcuts(&id, shortcuts),
+                    Some((id, None)) => actions.reset(&id),
+                    None => {}
+                }
+            });
+        if !open {
+            self.open = false;
+            self.recording = None;
+        }
+        changed
+    }
+}
+
+impl Default for ShortcutsWindow {
+    fn default() -> ShortcutsWindow {
+        ShortcutsWindow::new()
+    }
+}
@@ -24,7 +24,10 @@ impl SizeWindow {
         }
     }
 
-    pub fn show_size_window(&mut self, ctx: &egui::Context) {
+    // returns true when the new size should be applied
+    pub fn show_size_window(&mut self, ctx: &egui::Context) -> bool {
+    let mut apply = false;
+    let mut close = false;
     let mut egui_window = egui::Window::new("Size")
     .resizable(false)
     .collapsible(false);
@@ -101,11 +104,23 @@ impl SizeWindow {
 
         ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
             if ui.button("OK").clicked() {
+                apply = true;
+                close = true;
             }
             if ui.button("Cancel").clicked() {
-                
+                close = true;
             }
         });
     });
+    if close {
+        self.open = false;
+    }
+    apply
 }   
+}
+
+impl Default for SizeWindow {
+    fn default() -> SizeWindow {
+        SizeWindow::new()
+    }
 }
\ No newline at end of file
@@ -0,0 +1,310 @@
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use crate::paint_app::image_io::encode_png;
+use super::data_types::*;
+
+// equal cells for tile sets, margin is around the whole grid and spacing between the cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SliceGrid {
+    pub cell_width: u32,
+    pub cell_height: u32,
+    pub margin: u32,
+    pub spacing: u32,
+    // fully transparent cells aren't exported
+    pub skip_empty: bool,
+}
+
+impl SliceGrid {
+    pub fn new(cell_width: u32, cell_height: u32) -> SliceGrid {
+        SliceGrid {
+            cell_width: cell_width.max(1),
+            cell_height: cell_height.max(1),
+            margin: 0,
+            spacing: 0,
+            skip_empty: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice {
+    pub name: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// what gets cut out of the document, kept with it in project files
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SliceSet {
+    pub grid: Option<SliceGrid>,
+    // named freeform rectangles, exported after the grid cells
+    pub slices: Vec<Slice>,
+}
+
+impl SliceSet {
+    pub fn new() -> SliceSet {
+        SliceSet {
+            grid: None,
+            slices: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.grid.is_none() && self.slices.is_empty()
+    }
+
+    // the grid cells and then the named slices
+    pub fn resolve(&self, size: (u32, u32)) -> Vec<Slice> {
+        let mut result = self.grid_cells(size);
+        result.extend(self.named_slices(size));
+        result
+    }
+
+    // the cells that fit completely, named by row and column
+    pub fn grid_cells(&self, (width, height): (u32, u32)) -> Vec<Slice> {
+        let mut result = Vec::new();
+        if let Some(grid) = self.grid {
+            let count = |size: u32, cell: u32| (size.saturating_sub(grid.margin * 2) + grid.spacing) / (cell.max(1) + grid.spacing);
+            for row in 0..count(height, grid.cell_height) {
+                for column in 0..count(width, grid.cell_width) {
+                    result.push(Slice {
+                        name: format!("cell_{}_{}", row, column),
+                        x: grid.margin + column * (grid.cell_width + grid.spacing),
+                        y: grid.margin + row * (grid.cell_height + grid.spacing),
+                        width: grid.cell_width,
+                        height: grid.cell_height,
+                    });
+                }
+            }
+        }
+        result
+    }
+
+    // cut to the size, the ones outside are left out
+    pub fn named_slices(&self, (width, height): (u32, u32)) -> Vec<Slice> {
+        self.slices.iter()
+            .filter(|slice| slice.x < width && slice.y < height)
+            .map(|slice| Slice { width: slice.width.min(width - slice.x).max(1), height: slice.height.min(height - slice.y).max(1), ..slice.clone() })
+            .collect()
+    }
+
+    // a line for the grid and one for each slice, the name is the rest of the line
+    pub fn to_text(&self) -> String {
+        let mut result = String::new();
+        if let Some(grid) = self.grid {
+            result += &format!("grid {} {} {} {} {}\n", grid.cell_width, grid.cell_height, grid.margin, grid.spacing, grid.skip_empty as u8);
+        }
+        for slice in self.slices.iter() {
+            result += &format!("slice {} {} {} {} {}\n", slice.x, slice.y, slice.width, slice.height, slice.name);
+        }
+        result
+    }
+
+    pub fn from_text(text: &str) -> Result<SliceSet, String> {
+        let mut result = SliceSet::new();
+        for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#')) {
+            let mut parts = line.splitn(6, ' ');
+            let kind = parts.next().unwrap_or("");
+            let mut number = || parts.next().and_then(|part| part.trim().parse::<u32>().ok()).ok_or(format!("line {}: a number is missing", i + 1));
+            match kind {
+                "grid" => {
+                    let mut grid = SliceGrid::new(number()?, number()?);
+                    grid.margin = number()?;
+                    grid.spacing = number()?;
+                    grid.skip_empty = number()? != 0;
+                    result.grid = Some(grid);
+                }
+                "slice" => {
+                    let (x, y, width, height) = (number()?, number()?, number()?.max(1), number()?.max(1));
+                    let name = parts.next().map(str::trim).filter(|name| !name.is_empty()).ok_or(format!("line {}: the slice has no name", i + 1))?;
+                    result.slices.push(Slice { name: name.to_string(), x, y, width, height });
+                }
+                other => return Err(format!("line {}: unknown entry {}", i + 1, other))
+            }
+        }
+        Ok(result)
+    }
+}
+
+pub fn cut_slice(layer: &FlatCanvasLayer, slice: &Slice) -> FlatCanvasLayer {
+    let mut result = FlatCanvasLayer::new(slice.width, slice.height);
+    let (width, height) = layer.get_size();
+    for y in 0..slice.height.min(height.saturating_sub(slice.y)) {
+        for x in 0..slice.width.min(width.saturating_sub(slice.x)) {
+            result.set_pixel(PixelPos { x, y }, layer.get_pixel(PixelPos { x: slice.x + x, y: slice.y + y }));
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtlasFormat {
+    Aseprite,
+    TexturePacker,
+}
+
+impl AtlasFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AtlasFormat::Aseprite => "Aseprite",
+            AtlasFormat::TexturePacker => "TexturePacker",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceExport {
+    // a png for every slice
+    Separate,
+    // one png with all slices and a json file saying where they are, padding is between them
+    Atlas { format: AtlasFormat, padding: u32 },
+}
+
+// the file names with their bytes, name is used for the atlas and its json
+pub fn slice_files(layer: &FlatCanvasLayer, set: &SliceSet, export: SliceExport, name: &str) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let skip_empty = set.grid.is_some_and(|grid| grid.skip_empty);
+    let cells = set.grid_cells(layer.get_size()).into_iter()
+        .map(|slice| (cut_slice(layer, &slice), slice))
+        .filter(|(image, _)| !skip_empty || image.get_data().iter().any(|color| color.alpha > 0));
+    let named = set.named_slices(layer.get_size()).into_iter().map(|slice| (cut_slice(layer, &slice), slice));
+    let slices = cells.chain(named)
+        .map(|(image, slice)| (Slice { name: file_name(&slice.name), ..slice }, image))
+        .collect::<Vec<_>>();
+    if slices.is_empty() {
+        return Err("there is nothing to slice".to_string());
+    }
+    for (i, (slice, _)) in slices.iter().enumerate() {
+        if slices[..i].iter().any(|(other, _)| other.name == slice.name) {
+            return Err(format!("more than one slice is called {}", slice.name));
+        }
+    }
+    match export {
+        SliceExport::Separate => slices.iter().map(|(slice, image)| Ok((format!("{}.png", slice.name), encode_png(image)?))).collect(),
+        SliceExport::Atlas { format, padding } => {
+            let (atlas, positions) = pack_atlas(&slices, padding);
+            let image_name = format!("{}.png", file_name(name));
+            let json = atlas_json(&slices, &positions, atlas.get_size(), format, &image_name, &set.slices);
+            Ok(vec![(image_name, encode_png(&atlas)?), (format!("{}.json", file_name(name)), json.into_bytes())])
+        }
+    }
+}
+
+// names are used for files, so path separators and such become underscores
+fn file_name(name: &str) -> String {
+    name.chars().map(|c| match c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ') {
+        true => c,
+        false => '_'
+    }).collect::<String>().trim_start_matches('.').to_string()
+}
+
+// rows of slices sorted by height, about as wide as they are high
+fn pack_atlas(slices: &[(Slice, FlatCanvasLayer)], padding: u32) -> (FlatCanvasLayer, Vec<(u32, u32)>) {
+    let area = slices.iter().map(|(slice, _)| ((slice.width + padding) * (slice.height + padding)) as f64).sum::<f64>();
+    let widest = slices.iter().map(|(slice, _)| slice.width).max().unwrap_or(1);
+    let row_width = (area.sqrt().ceil() as u32).max(widest);
+    let mut order = (0..slices.len()).collect::<Vec<_>>();
+    order.sort_by_key(|i| std::cmp::Reverse(slices[*i].0.height));
+
+    let mut positions = vec![(0, 0); slices.len()];
+    let (mut x, mut y, mut row_height, mut width) = (0, 0, 0, 0);
+    for i in order {
+        let slice = &slices[i].0;
+        if x > 0 && x + slice.width > row_width {
+            (x, y, row_height) = (0, y + row_height + padding, 0);
+        }
+        positions[i] = (x, y);
+        width = width.max(x + slice.width);
+        row_height = row_height.max(slice.height);
+        x += slice.width + padding;
+    }
+    let mut atlas = FlatCanvasLayer::new(width.max(1), (y + row_height).max(1));
+    for ((_, image), (left, top)) in slices.iter().zip(positions.iter()) {
+        image.iter_pixels().for_each(|(pos, color)| atlas.set_pixel(PixelPos { x: left + pos.x, y: top + pos.y }, *color));
+    }
+    (atlas, positions)
+}
+
+// the json hash layout both apps write, aseprite also lists the named slices where they are in the document
+fn atlas_json(slices: &[(Slice, FlatCanvasLayer)], positions: &[(u32, u32)], (width, height): (u32, u32), format: AtlasFormat, image_name: &str, named: &[Slice]) -> String {
+    let rect = |x: u32, y: u32, w: u32, h: u32| format!("{{ \"x\": {}, \"y\": {}, \"w\": {}, \"h\": {} }}", x, y, w, h);
+    let frames = slices.iter().zip(positions.iter()).map(|((slice, _), (x, y))| {
+        let extra = match format {
+            AtlasFormat::Aseprite => ",\n      \"duration\": 100".to_string(),
+            AtlasFormat::TexturePacker => ",\n      \"pivot\": { \"x\": 0.5, \"y\": 0.5 }".to_string(),
+        };
+        format!("    {}: {{\n      \"frame\": {},\n      \"rotated\": false,\n      \"trimmed\": false,\n      \"spriteSourceSize\": {},\n      \"sourceSize\": {{ \"w\": {}, \"h\": {} }}{}\n    }}",
+            json_string(&format!("{}.png", slice.name)), rect(*x, *y, slice.width, slice.height), rect(0, 0, slice.width, slice.height), slice.width, slice.height, extra)
+    }).collect::<Vec<_>>().join(",\n");
+    let (app, extra) = match format {
+        AtlasFormat::Aseprite => {
+            let named = named.iter().map(|slice| format!("      {{ \"name\": {}, \"color\": \"#0000ffff\", \"keys\": [{{ \"frame\": 0, \"bounds\": {} }}] }}",
+                json_string(&slice.name), rect(slice.x, slice.y, slice.width, slice.height))).collect::<Vec<_>>();
+            let slices = match named.is_empty() {
+                true => "[]".to_string(),
+                false => format!("[\n{}\n    ]", named.join(",\n"))
+            };
+            ("https://www.aseprite.org/", format!(",\n    \"frameTags\": [],\n    \"layers\": [],\n    \"slices\": {}", slices))
+        }
+        AtlasFormat::TexturePacker => ("https://www.codeandweb.com/texturepacker", String::new())
+    };
+    format!("{{\n  \"frames\": {{\n{}\n  }},\n  \"meta\": {{\n    \"app\": \"{}\",\n    \"version\": \"1.0\",\n    \"image\": {},\n    \"format\": \"RGBA8888\",\n    \"size\": {{ \"w\": {}, \"h\": {} }},\n    \"scale\": \"1\"{}\n  }}\n}}\n",
+        frames, app, json_string(image_name), width, height, extra)
+}
+
+fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grid_and_slices() {
+        let mut set = SliceSet::new();
+        set.grid = Some(SliceGrid { margin: 1, spacing: 2, ..SliceGrid::new(4, 3) });
+        set.slices.push(Slice { name: "hero idle".to_string(), x: 8, y: 6, width: 20, height: 20 });
+        let slices = set.resolve((16, 10));
+        // (16 - 2 + 2) / 6 columns and (10 - 2 + 2) / 5 rows
+        assert_eq!(slices.len(), 2 * 2 + 1);
+        assert_eq!((slices[3].x, slices[3].y), (7, 6));
+        assert_eq!((slices[4].width, slices[4].height), (8, 4));
+        assert_eq!(SliceSet::from_text(&set.to_text()).unwrap(), set);
+        assert!(SliceSet::from_text("slice 1 2 3").is_err());
+    }
+
+    #[test]
+    fn test_slice_files() {
+        let mut layer = FlatCanvasLayer::new(8, 4);
+        (0..4).for_each(|x| layer.set_pixel(PixelPos { x, y: 1 }, Color::new(255, 0, 0, 255)));
+        let mut set = SliceSet::new();
+        set.grid = Some(SliceGrid::new(4, 4));
+        set.slices.push(Slice { name: "a/b".to_string(), x: 1, y: 1, width: 2, height: 2 });
+
+        // the empty right cell is skipped
+        let files = slice_files(&layer, &set, SliceExport::Separate, "sheet").unwrap();
+        assert_eq!(files.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["cell_0_0.png", "a_b.png"]);
+
+        let files = slice_files(&layer, &set, SliceExport::Atlas { format: AtlasFormat::Aseprite, padding: 1 }, "sheet").unwrap();
+        assert_eq!((files[0].0.as_str(), files[1].0.as_str()), ("sheet.png", "sheet.json"));
+        let json = String::from_utf8(files[1].1.clone()).unwrap();
+        assert!(json.contains("\"cell_0_0.png\": {\n      \"frame\": { \"x\": 0, \"y\": 0, \"w\": 4, \"h\": 4 }"));
+        assert!(json.contains("\"a_b.png\": {\n      \"frame\": { \"x\": 0, \"y\": 5, \"w\": 2, \"h\": 2 }"));
+        assert!(json.contains("\"name\": \"a/b\""));
+        let atlas = crate::paint_app::image_io::decode_image(&files[0].1).unwrap();
+        assert_eq!(atlas.get_size(), (4, 7));
+        assert_eq!(atlas.get_pixel(PixelPos { x: 1, y: 5 }), Color::new(255, 0, 0, 255));
+    }
+}
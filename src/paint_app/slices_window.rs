@@ -0,0 +1,137 @@
+use eframe::egui;
+use egui::{Color32, DragValue, Painter, Pos2, Stroke};
+use crate::paint_app::slices::{AtlasFormat, Slice, SliceExport, SliceGrid, SliceSet};
+
+pub struct SlicesWindow {
+    pub open: bool,
+    // the folder the files are written to
+    pub folder: String,
+    // of the atlas and its json
+    pub name: String,
+    pub export: SliceExport,
+}
+
+impl SlicesWindow {
+    pub fn new() -> SlicesWindow {
+        SlicesWindow {
+            open: false,
+            folder: "slices".to_string(),
+            name: "atlas".to_string(),
+            export: SliceExport::Separate,
+        }
+    }
+
+    // edits slices in place, returns true when export was clicked
+    pub fn show_slices_window(&mut self, ctx: &egui::Context, slices: &mut SliceSet, size: (u32, u32)) -> bool {
+        let mut confirmed = false;
+        let mut open = self.open;
+        egui::Window::new("Slices")
+            .collapsible(false)
+            .open(&mut open)
+            .show(ctx, |ui| {
+                let mut use_grid = slices.grid.is_some();
+                ui.checkbox(&mut use_grid, "Grid");
+                match (use_grid, &mut slices.grid) {
+                    (true, None) => slices.grid = Some(SliceGrid::new(16, 16)),
+                    (false, Some(_)) => slices.grid = None,
+                    _ => {}
+                }
+                if let Some(grid) = &mut slices.grid {
+                    egui::Grid::new("slice_grid").show(ui, |ui| {
+                        ui.label("Cell size");
+                        ui.add(DragValue::new(&mut grid.cell_width).clamp_range(1..=size.0).suffix(" px"));
+                        ui.add(DragValue::new(&mut grid.cell_height).clamp_range(1..=size.1).suffix(" px"));
+                        ui.end_row();
+                        ui.label("Margin");
+                        ui.add(DragValue::new(&mut grid.margin).clamp_range(0..=size.0.max(size.1)));
+                        ui.end_row();
+                        ui.label("Spacing");
+                        ui.add(DragValue::new(&mut grid.spacing).clamp_range(0..=size.0.max(size.1)));
+                        ui.end_row();
+                    });
+                    ui.checkbox(&mut grid.skip_empty, "Leave out empty cells");
+                    ui.label(format!("{} cells", slices.grid_cells(size).len()));
+                }
+
+                ui.separator();
+                ui.label("Named slices");
+                let mut remove = None;
+                for (i, slice) in slices.slices.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.add(egui::TextEdit::singleline(&mut slice.name).desired_width(100.0));
+                        ui.add(DragValue::new(&mut slice.x).clamp_range(0..=size.0 - 1).prefix("x "));
+                        ui.add(DragValue::new(&mut slice.y).clamp_range(0..=size.1 - 1).prefix("y "));
+                        ui.add(DragValue::new(&mut slice.width).clamp_range(1..=size.0).prefix("w "));
+                        ui.add(DragValue::new(&mut slice.height).clamp_range(1..=size.1).prefix("h "));
+                        if ui.small_button("x").on_hover_text("Delete slice").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    slices.slices.remove(i);
+                }
+                if ui.button("Add slice").clicked() {
+                    let name = (1..).map(|i| format!("slice {}", i)).find(|name| slices.slices.iter().all(|slice| slice.name != *name)).unwrap_or_default();
+                    slices.slices.push(Slice { name, x: 0, y: 0, width: size.0.min(32), height: size.1.min(32) });
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Folder: ");
+                    ui.text_edit_singleline(&mut self.folder);
+                });
+                let atlas = SliceExport::Atlas { format: AtlasFormat::Aseprite, padding: 1 };
+                ui.horizontal(|ui| {
+                    if ui.radio(self.export == SliceExport::Separate, "A PNG for every slice").clicked() {
+                        self.export = SliceExport::Separate;
+                    }
+                    if ui.radio(self.export != SliceExport::Separate, "Packed atlas with JSON").clicked() && self.export == SliceExport::Separate {
+                        self.export = atlas;
+                    }
+                });
+                if let SliceExport::Atlas { format, padding } = &mut self.export {
+                    ui.horizontal(|ui| {
+                        ui.label("Name: ");
+                        ui.text_edit_singleline(&mut self.name);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("JSON for");
+                        ui.radio_value(format, AtlasFormat::Aseprite, AtlasFormat::Aseprite.name());
+                        ui.radio_value(format, AtlasFormat::TexturePacker, AtlasFormat::TexturePacker.name());
+                    });
+                    ui.add(DragValue::new(padding).clamp_range(0..=64).prefix("Padding ").suffix(" px"));
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::TOP), |ui| {
+                    if ui.add_enabled(!slices.is_empty(), egui::Button::new("Export")).clicked() {
+                        confirmed = true;
+                    }
+                });
+            });
+        self.open = open;
+        confirmed
+    }
+}
+
+impl Default for SlicesWindow {
+    fn default() -> SlicesWindow {
+        SlicesWindow::new()
+    }
+}
+
+// grid cells in a thin line, named slices thicker with their names
+pub fn draw_slices_overlay(painter: &Painter, slices: &SliceSet, size: (u32, u32), to_screen: &dyn Fn((f32, f32)) -> Pos2) {
+    let rect = |slice: &Slice| egui::Rect::from_two_pos(
+        to_screen((slice.x as f32, slice.y as f32)),
+        to_screen(((slice.x + slice.width) as f32, (slice.y + slice.height) as f32)));
+    let cell_stroke = Stroke::new(1.0, Color32::from_rgba_unmultiplied(0, 200, 255, 160));
+    for cell in slices.grid_cells(size) {
+        painter.rect_stroke(rect(&cell), 0.0, cell_stroke);
+    }
+    let slice_color = Color32::from_rgb(255, 140, 0);
+    for slice in slices.named_slices(size) {
+        let rect = rect(&slice);
+        painter.rect_stroke(rect, 0.0, Stroke::new(2.0, slice_color));
+        painter.text(rect.left_top() + egui::vec2(2.0, 1.0), egui::Align2::LEFT_TOP, &slice.name, egui::FontId::proportional(11.0), slice_color);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use super::data_types::*;
+
+// the frames in a grid left to right and then down, the last row may be short
+pub fn build_sprite_sheet(frames: &[FlatCanvasLayer], columns: u32) -> Result<FlatCanvasLayer, String> {
+    let Some(first) = frames.first() else { return Err("a sprite sheet needs at least one frame".to_string()) };
+    let (width, height) = first.get_size();
+    if frames.iter().any(|frame| frame.get_size() != (width, height)) {
+        return Err("all frames of a sprite sheet need the same size".to_string());
+    }
+    let columns = columns.clamp(1, frames.len() as u32);
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let mut sheet = FlatCanvasLayer::new(width * columns, height * rows);
+    for (i, frame) in frames.iter().enumerate() {
+        let (left, top) = ((i as u32 % columns) * width, (i as u32 / columns) * height);
+        frame.iter_pixels().for_each(|(pos, color)| sheet.set_pixel(PixelPos { x: left + pos.x, y: top + pos.y }, *color));
+    }
+    Ok(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sprite_sheet() {
+        let frames = (0..5).map(|i| {
+            let mut frame = FlatCanvasLayer::new(3, 2);
+            frame.set_pixel(PixelPos { x: 1, y: 1 }, Color::new(i * 50, 0, 0, 255));
+            frame
+        }).collect::<Vec<_>>();
+        let sheet = build_sprite_sheet(&frames, 2).unwrap();
+        assert_eq!(sheet.get_size(), (6, 6));
+        assert_eq!(sheet.get_pixel(PixelPos { x: 4, y: 3 }), Color::new(150, 0, 0, 255));
+        assert_eq!(sheet.get_pixel(PixelPos { x: 1, y: 5 }), Color::new(200, 0, 0, 255));
+        assert_eq!(sheet.get_pixel(PixelPos { x: 4, y: 5 }).alpha, 0);
+        assert_eq!(build_sprite_sheet(&frames, 0).unwrap().get_size(), (3, 10));
+    }
+}
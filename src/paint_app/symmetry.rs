@@ -0,0 +1,146 @@
+use crate::paint_app::canvas::EditCommand;
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymmetryMode {
+    Off,
+    Vertical,
+    Horizontal,
+    Both,
+    Radial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Symmetry {
+    pub mode: SymmetryMode,
+    // in pixels, canvas coordinates
+    pub center: (f32, f32),
+    pub radial_count: u32,
+}
+
+impl Symmetry {
+    pub fn new() -> Symmetry {
+        Symmetry {
+            mode: SymmetryMode::Off,
+            center: (0.0, 0.0),
+            radial_count: 6,
+        }
+    }
+
+    // every position pixel_pos maps to, including itself, without the ones outside of size
+    pub fn transforms(&self, pixel_pos: PixelPos, size: (u32, u32)) -> Vec<PixelPos> {
+        // work with pixel centers so mirroring around a pixel edge is exact
+        let x = pixel_pos.x as f32 + 0.5 - self.center.0;
+        let y = pixel_pos.y as f32 + 0.5 - self.center.1;
+
+        let offsets = match self.mode {
+            SymmetryMode::Off => vec![(x, y)],
+            SymmetryMode::Vertical => vec![(x, y), (-x, y)],
+            SymmetryMode::Horizontal => vec![(x, y), (x, -y)],
+            SymmetryMode::Both => vec![(x, y), (-x, y), (x, -y), (-x, -y)],
+            SymmetryMode::Radial => (0..self.radial_count.max(1)).map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / self.radial_count.max(1) as f32;
+                let (sin, cos) = angle.sin_cos();
+                (x * cos - y * sin, x * sin + y * cos)
+            }).collect(),
+        };
+
+        let mut result = Vec::with_capacity(offsets.len());
+        for (x, y) in offsets {
+            let x = (x + self.center.0).floor();
+            let y = (y + self.center.1).floor();
+            if x < 0.0 || y < 0.0 || x >= size.0 as f32 || y >= size.1 as f32 {
+                continue;
+            }
+            let pos = PixelPos { x: x as u32, y: y as u32 };
+            if !result.contains(&pos) {
+                result.push(pos);
+            }
+        }
+        result
+    }
+
+    pub fn apply_to_command(&self, command: &EditCommand, size: (u32, u32)) -> EditCommand {
+        if self.mode == SymmetryMode::Off {
+            return command.clone();
+        }
+        let mut result = EditCommand { blend: command.blend, ..EditCommand::default() };
+        command.edits.iter().for_each(|(pos, color)|{
+            self.transforms(*pos, size).into_iter().for_each(|new_pos| result.edits.push((new_pos, *color)));
+        });
+        command.precise_edits.iter().for_each(|(pos, rgba)|{
+            self.transforms(*pos, size).into_iter().for_each(|new_pos| result.precise_edits.push((new_pos, *rgba)));
+        });
+        result
+    }
+
+    // the lines to draw over the canvas, as pairs of canvas positions
+    pub fn axes(&self, size: (u32, u32)) -> Vec<((f32, f32), (f32, f32))> {
+        let (w, h) = (size.0 as f32, size.1 as f32);
+        let (cx, cy) = self.center;
+        let vertical = ((cx, 0.0), (cx, h));
+        let horizontal = ((0.0, cy), (w, cy));
+        match self.mode {
+            SymmetryMode::Off => Vec::new(),
+            SymmetryMode::Vertical => vec![vertical],
+            SymmetryMode::Horizontal => vec![horizontal],
+            SymmetryMode::Both => vec![vertical, horizontal],
+            SymmetryMode::Radial => {
+                let length = w.max(h) * 2.0;
+                (0..self.radial_count.max(1)).map(|i| {
+                    let angle = std::f32::consts::TAU * i as f32 / self.radial_count.max(1) as f32 - std::f32::consts::FRAC_PI_2;
+                    ((cx, cy), (cx + angle.cos() * length, cy + angle.sin() * length))
+                }).collect()
+            }
+        }
+    }
+}
+
+impl Default for Symmetry {
+    fn default() -> Symmetry {
+        Symmetry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas_layer::{CanvasLayer, LayerBuffer};
+
+    #[test]
+    fn test_symmetry_transforms() {
+        let size = (10, 10);
+        let mut symmetry = Symmetry::new();
+        symmetry.center = (5.0, 5.0);
+
+        symmetry.mode = SymmetryMode::Vertical;
+        assert_eq!(symmetry.transforms(PixelPos { x: 1, y: 2 }, size), vec![PixelPos { x: 1, y: 2 }, PixelPos { x: 8, y: 2 }]);
+
+        symmetry.mode = SymmetryMode::Both;
+        assert_eq!(symmetry.transforms(PixelPos { x: 1, y: 2 }, size).len(), 4);
+
+        symmetry.mode = SymmetryMode::Radial;
+        symmetry.radial_count = 4;
+        let rotated = symmetry.transforms(PixelPos { x: 7, y: 5 }, size);
+        assert_eq!(rotated, vec![PixelPos { x: 7, y: 5 }, PixelPos { x: 4, y: 7 }, PixelPos { x: 2, y: 4 }, PixelPos { x: 5, y: 2 }]);
+    }
+
+    #[test]
+    fn test_symmetry_precise_edits() {
+        let size = (10, 10);
+        let mut symmetry = Symmetry::new();
+        symmetry.center = (5.0, 5.0);
+        symmetry.mode = SymmetryMode::Vertical;
+
+        let mut layer = LayerBuffer::new(10, 10, PixelFormat::Rgba16);
+        let rgba = [0.25, 0.5, 0.75, 1.0];
+        let mut command = EditCommand::default();
+        command.push_rgba(layer.format(), PixelPos { x: 1, y: 2 }, rgba);
+        let mirrored = symmetry.apply_to_command(&command, size);
+        assert_eq!(mirrored.precise_edits.len(), 2);
+
+        mirrored.apply(&mut layer);
+        let mirrored_rgba = layer.get_rgba(PixelPos { x: 8, y: 2 });
+        assert!(mirrored_rgba.iter().zip(rgba).all(|(a, b)| (a - b).abs() < 1.0 / 65535.0));
+    }
+}
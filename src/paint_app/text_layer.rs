@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+use ab_glyph::{point, Font, FontArc, FontRef, PxScale, PxScaleFont, ScaleFont};
+use crate::paint_app::canvas_layer::CanvasLayer;
+use crate::paint_app::utils::pixel_overlap;
+use super::data_types::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextAlign {
+    Left,
+    Center,
+    Right,
+}
+
+// everything needed to render a text layer again, kept until the layer is rasterized
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayer {
+    pub text: String,
+    pub font_name: String,
+    pub size: f32,
+    pub color: Color,
+    pub align: TextAlign,
+    // multiplier of the font's line height
+    pub line_spacing: f32,
+    pub anti_alias: bool,
+    // top of the first line, x is the left edge, center or right edge depending on align
+    pub position: PixelPos,
+}
+
+impl TextLayer {
+    pub fn new(position: PixelPos, color: Color) -> TextLayer {
+        TextLayer {
+            text: "Text".to_string(),
+            font_name: String::new(),
+            size: 24.0,
+            color,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+            anti_alias: true,
+            position,
+        }
+    }
+}
+
+// bundled fonts come from egui, more can be loaded from ttf/otf files
+pub struct FontLibrary {
+    fonts: Vec<(String, FontArc)>,
+}
+
+impl FontLibrary {
+    pub fn new() -> FontLibrary {
+        let mut library = FontLibrary { fonts: Vec::new() };
+        for (name, data) in egui::FontDefinitions::default().font_data {
+            let font = match data.font {
+                Cow::Borrowed(bytes) => FontRef::try_from_slice_and_index(bytes, data.index).map(FontArc::new),
+                Cow::Owned(bytes) => ab_glyph::FontVec::try_from_vec_and_index(bytes, data.index).map(FontArc::new),
+            };
+            if let Ok(font) = font {
+                library.fonts.push((name, font));
+            }
+        }
+        library
+    }
+
+    pub fn load_font(&mut self, name: String, bytes: Vec<u8>) -> Result<(), String> {
+        let font = FontArc::try_from_vec(bytes).map_err(|err| format!("{}: {}", name, err))?;
+        self.fonts.retain(|(existing, _)| *existing != name);
+        self.fonts.push((name, font));
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.fonts.iter().map(|(name, _)| name.as_str())
+    }
+
+    // unknown names fall back to the first font so a layer never fails to render
+    pub fn get(&self, name: &str) -> Option<&FontArc> {
+        self.fonts.iter()
+            .find(|(existing, _)| existing == name)
+            .or(self.fonts.first())
+            .map(|(_, font)| font)
+    }
+}
+
+impl Default for FontLibrary {
+    fn default() -> FontLibrary {
+        FontLibrary::new()
+    }
+}
+
+fn line_width(font: &PxScaleFont<&FontArc>, line: &str) -> f32 {
+    let mut width = 0.0;
+    let mut previous = None;
+    for c in line.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+// renders the text into target (which is cleared first) and returns the bounds of the text block
+pub fn render_text(text_layer: &TextLayer, fonts: &FontLibrary, target: &mut dyn CanvasLayer) -> Option<(PixelPos, PixelPos)> {
+    target.fill(Color::new(255, 255, 255, 0));
+    let font = fonts.get(&text_layer.font_name)?;
+    let font = font.as_scaled(PxScale::from(text_layer.size.max(1.0)));
+    let (width, height) = target.get_size();
+
+    let line_height = (font.height() + font.line_gap()) * text_layer.line_spacing;
+    let origin_x = text_layer.position.x as f32;
+    let origin_y = text_layer.position.y as f32;
+    let mut min = glam::Vec2::new(origin_x, origin_y);
+    let mut max = min;
+
+    for (line_index, line) in text_layer.text.lines().enumerate() {
+        let line_width = line_width(&font, line);
+        let mut x = match text_layer.align {
+            TextAlign::Left => origin_x,
+            TextAlign::Center => origin_x - line_width / 2.0,
+            TextAlign::Right => origin_x - line_width,
+        };
+        let top = origin_y + line_index as f32 * line_height;
+        let baseline = top + font.ascent();
+        min = min.min(glam::Vec2::new(x, top));
+        max = max.max(glam::Vec2::new(x + line_width, top + font.height()));
+
+        let mut previous = None;
+        for c in line.chars() {
+            let id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                x += font.kern(previous, id);
+            }
+            let glyph = id.with_scale_and_position(font.scale(), point(x, baseline));
+            x += font.h_advance(id);
+            previous = Some(id);
+
+            let Some(outlined) = font.outline_glyph(glyph) else { continue };
+            let bounds = outlined.px_bounds();
+            outlined.draw(|gx, gy, coverage| {
+                let px = bounds.min.x as i32 + gx as i32;
+                let py = bounds.min.y as i32 + gy as i32;
+                if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 {
+                    return;
+                }
+                let coverage = match text_layer.anti_alias {
+                    true => coverage,
+                    false => if coverage >= 0.5 { 1.0 } else { 0.0 }
+                };
+                if coverage <= 0.0 {
+                    return;
+                }
+                let pos = PixelPos { x: px as u32, y: py as u32 };
+                let mut color = text_layer.color;
+                color.alpha = (color.alpha as f32 * coverage.min(1.0)) as u8;
+                target.set_pixel(pos, pixel_overlap(color, target.get_pixel(pos)));
+            });
+        }
+    }
+
+    let to_pixel = |value: glam::Vec2| PixelPos {
+        x: (value.x.max(0.0) as u32).min(width.saturating_sub(1)),
+        y: (value.y.max(0.0) as u32).min(height.saturating_sub(1)),
+    };
+    Some((to_pixel(min), to_pixel(max)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas_layer::FlatCanvasLayer;
+
+    #[test]
+    fn test_render_text() {
+        let fonts = FontLibrary::new();
+        let mut layer = FlatCanvasLayer::<Color>::new(100, 50);
+        let text = TextLayer::new(PixelPos { x: 10, y: 10 }, Color::black());
+        let (min, max) = render_text(&text, &fonts, &mut layer).unwrap();
+        assert_eq!(min, PixelPos { x: 10, y: 10 });
+        assert!(max.x > min.x && max.y > min.y);
+
+        let drawn = layer.iter_pixels().filter(|(_, color)| color.alpha > 0).collect::<Vec<_>>();
+        assert!(!drawn.is_empty());
+        assert!(drawn.iter().all(|(pos, _)| pos.x >= min.x && pos.x <= max.x && pos.y >= min.y && pos.y <= max.y));
+    }
+}
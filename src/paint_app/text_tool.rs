@@ -0,0 +1,102 @@
+use egui::Key;
+use crate::paint_app::actions::Shortcut;
+use crate::paint_app::canvas::{CanvasLayers, EditCommand, LayerContent, LayerEdit, PaintTool};
+use crate::paint_app::canvas_layer::HashMapCanvasLayer;
+use crate::paint_app::text_layer::{TextAlign, TextLayer};
+use crate::paint_app::tool_registry::{ToolCategory, ToolInfo, ToolOption, ToolOptionValue};
+use super::data_types::*;
+
+pub struct TextTool {
+    pub size: f32,
+    pub align: TextAlign,
+    pub line_spacing: f32,
+    pub anti_alias: bool,
+    layer_edits: Vec<LayerEdit>,
+}
+
+impl TextTool {
+    pub fn new() -> TextTool {
+        TextTool {
+            size: 24.0,
+            align: TextAlign::Left,
+            line_spacing: 1.0,
+            anti_alias: true,
+            layer_edits: Vec::new(),
+        }
+    }
+}
+
+impl Default for TextTool {
+    fn default() -> TextTool {
+        TextTool::new()
+    }
+}
+
+impl PaintTool for TextTool {
+    fn info(&self) -> ToolInfo {
+        ToolInfo { id: "text", name: "Text", icon: "🔤", shortcut: Some(Shortcut::new(Key::T)), category: ToolCategory::Text }
+    }
+
+    fn stroke_start(&mut self, _global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    }
+
+    fn stroke_update(&mut self, _global_params: &mut GlobalParams, _layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+    }
+
+    // clicking a text layer selects it for editing, clicking anywhere else places a new one
+    fn stroke_end(&mut self, global_params: &mut GlobalParams, layers: &CanvasLayers, _tool_canvas : &mut HashMapCanvasLayer, _push_command : &mut dyn FnMut(EditCommand)){
+        let Some(pixel) = global_params.current_pixel else { return };
+        let clicked_layer = layers.entries.iter()
+            .filter(|entry| entry.visible)
+            .find(|entry| match &entry.content {
+                LayerContent::Text { bounds: Some((min, max)), .. } => {
+                    pixel.x >= min.x && pixel.x <= max.x && pixel.y >= min.y && pixel.y <= max.y
+                }
+                _ => false
+            });
+
+        match clicked_layer {
+            Some(entry) => self.layer_edits.push(LayerEdit::SetActive(entry.id)),
+            None => {
+                let mut text = TextLayer::new(pixel, global_params.stroke_color());
+                text.size = self.size;
+                text.align = self.align;
+                text.line_spacing = self.line_spacing;
+                text.anti_alias = self.anti_alias;
+                if let Some(LayerContent::Text { text: active_text, .. }) = layers.get_active_entry().map(|entry| &entry.content) {
+                    text.font_name = active_text.font_name.clone();
+                }
+                self.layer_edits.push(LayerEdit::AddText(text));
+            }
+        }
+    }
+
+    // used for new text, text layers have their own settings
+    fn options(&self) -> Vec<ToolOption> {
+        let aligns = [TextAlign::Left, TextAlign::Center, TextAlign::Right];
+        vec![
+            ToolOption::slider("size", "Size", self.size, 1.0, 1000.0).logarithmic(),
+            ToolOption::choice("align", "Align", aligns.iter().position(|align| *align == self.align).unwrap_or(0), &["Left", "Center", "Right"]),
+            ToolOption::slider("line_spacing", "Line spacing", self.line_spacing, 0.1, 10.0),
+            ToolOption::toggle("anti_alias", "Anti-aliasing", self.anti_alias),
+        ]
+    }
+
+    fn set_option(&mut self, id: &str, value: ToolOptionValue) {
+        match (id, value) {
+            ("size", ToolOptionValue::Number(size)) => self.size = size,
+            ("align", ToolOptionValue::Choice(i)) => self.align = [TextAlign::Left, TextAlign::Center, TextAlign::Right][i.min(2)],
+            ("line_spacing", ToolOptionValue::Number(line_spacing)) => self.line_spacing = line_spacing,
+            ("anti_alias", ToolOptionValue::Toggle(anti_alias)) => self.anti_alias = anti_alias,
+            _ => {}
+        }
+    }
+
+    fn take_layer_edits(&mut self) -> Vec<LayerEdit> {
+        std::mem::take(&mut self.layer_edits)
+    }
+
+    fn uses_symmetry(&self) -> bool {
+        false
+    }
+}
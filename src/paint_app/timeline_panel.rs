@@ -0,0 +1,109 @@
+use eframe::egui;
+use crate::paint_app::canvas::OnionSkin;
+
+// what the panel asks the document to do with its frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimelineCommand {
+    Select(usize),
+    // a copy of the active frame when true, otherwise the same layers without pixels
+    Add(bool),
+    Delete(usize),
+    Move(usize, usize),
+    SetDuration(usize, u32),
+}
+
+pub struct TimelinePanel {
+    pub playing: bool,
+    // egui time when the playing frame was shown, in seconds
+    frame_shown: f64,
+    pub onion_skin: OnionSkin,
+}
+
+impl TimelinePanel {
+    pub fn new() -> TimelinePanel {
+        TimelinePanel {
+            playing: false,
+            frame_shown: 0.0,
+            onion_skin: OnionSkin::new(),
+        }
+    }
+
+    // what the canvas should show, onion skins would only flicker while playing
+    pub fn shown_onion_skin(&self) -> OnionSkin {
+        OnionSkin { enabled: self.onion_skin.enabled && !self.playing, ..self.onion_skin }
+    }
+
+    // durations are in milliseconds, one for each frame
+    pub fn show(&mut self, ui: &mut egui::Ui, durations: &[u32], active: usize) -> Option<TimelineCommand> {
+        let mut result = None;
+        let count = durations.len();
+        ui.horizontal(|ui| {
+            ui.heading("Timeline");
+            let play = if self.playing { "⏸" } else { "▶" };
+            if ui.button(play).on_hover_text("Play the frames in a loop").clicked() {
+                self.playing = !self.playing;
+                self.frame_shown = ui.input(|input| input.time);
+            }
+            ui.separator();
+            if ui.button("New").on_hover_text("Add a frame with the same layers, without their pixels").clicked() {
+                result = Some(TimelineCommand::Add(false));
+            }
+            if ui.button("Duplicate").clicked() {
+                result = Some(TimelineCommand::Add(true));
+            }
+            if ui.add_enabled(count > 1, egui::Button::new("Delete")).clicked() {
+                result = Some(TimelineCommand::Delete(active));
+            }
+            if ui.add_enabled(active > 0, egui::Button::new("⏴")).on_hover_text("Move the frame earlier").clicked() {
+                result = Some(TimelineCommand::Move(active, active - 1));
+            }
+            if ui.add_enabled(active + 1 < count, egui::Button::new("⏵")).on_hover_text("Move the frame later").clicked() {
+                result = Some(TimelineCommand::Move(active, active + 1));
+            }
+            ui.separator();
+            if let Some(duration) = durations.get(active) {
+                let mut duration = *duration;
+                ui.label("Duration");
+                if ui.add(egui::DragValue::new(&mut duration).clamp_range(10..=10000).suffix(" ms")).changed() {
+                    result = Some(TimelineCommand::SetDuration(active, duration));
+                }
+            }
+            ui.separator();
+            let onion_skin = &mut self.onion_skin;
+            ui.checkbox(&mut onion_skin.enabled, "Onion skin");
+            ui.add_enabled_ui(onion_skin.enabled, |ui| {
+                ui.add(egui::DragValue::new(&mut onion_skin.previous).clamp_range(0..=5).prefix("before "));
+                ui.add(egui::DragValue::new(&mut onion_skin.next).clamp_range(0..=5).prefix("after "));
+                ui.add(egui::Slider::new(&mut onion_skin.opacity, 0.05..=1.0).text("Opacity"));
+            });
+        });
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            ui.horizontal(|ui| {
+                for (i, duration) in durations.iter().enumerate() {
+                    let label = ui.selectable_label(i == active, format!("{}\n{} ms", i + 1, duration));
+                    if label.clicked() {
+                        result = Some(TimelineCommand::Select(i));
+                    }
+                }
+            });
+        });
+
+        if self.playing && count > 1 {
+            let now = ui.input(|input| input.time);
+            let remaining = durations.get(active).copied().unwrap_or(0) as f64 / 1000.0 - (now - self.frame_shown);
+            if remaining <= 0.0 {
+                self.frame_shown = now;
+                result = Some(TimelineCommand::Select((active + 1) % count));
+            } else {
+                ui.ctx().request_repaint_after(std::time::Duration::from_secs_f64(remaining));
+            }
+        }
+        result
+    }
+}
+
+impl Default for TimelinePanel {
+    fn default() -> TimelinePanel {
+        TimelinePanel::new()
+    }
+}
@@ -0,0 +1,397 @@
+use eframe::egui;
+use egui::{ComboBox, RichText, Slider, Vec2};
+use crate::paint_app::actions::{ActionRegistry, Shortcut};
+use crate::paint_app::canvas::PaintTool;
+use crate::paint_app::config::{read_config, write_config};
+
+const CONFIG_FILE: &str = "tools.cfg";
+
+// groups of the tool box, in the order they are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToolCategory {
+    Paint,
+    Shapes,
+    Color,
+    Text,
+}
+
+impl ToolCategory {
+    pub fn name(&self) -> &str {
+        match self {
+            ToolCategory::Paint => "Paint",
+            ToolCategory::Shapes => "Shapes",
+            ToolCategory::Color => "Color",
+            ToolCategory::Text => "Text",
+        }
+    }
+
+    pub fn all() -> [ToolCategory; 4] {
+        [ToolCategory::Paint, ToolCategory::Shapes, ToolCategory::Color, ToolCategory::Text]
+    }
+}
+
+// what a tool tells the tool box about itself
+#[derive(Debug, Clone)]
+pub struct ToolInfo {
+    // stable, used for the tool's action and to select it
+    pub id: &'static str,
+    pub name: &'static str,
+    // a glyph the default egui fonts have
+    pub icon: &'static str,
+    pub shortcut: Option<Shortcut>,
+    pub category: ToolCategory,
+}
+
+// what a tool option holds, also what gets saved between sessions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToolOptionValue {
+    Number(f32),
+    Toggle(bool),
+    Choice(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOptionKind {
+    Slider { min: f32, max: f32, integer: bool, logarithmic: bool, suffix: &'static str },
+    Toggle,
+    Choice(&'static [&'static str]),
+}
+
+// one setting of a tool, the options panel draws it from this
+#[derive(Debug, Clone)]
+pub struct ToolOption {
+    // stable, used in the settings file
+    pub id: &'static str,
+    pub name: &'static str,
+    pub kind: ToolOptionKind,
+    pub value: ToolOptionValue,
+    // shown greyed out when it does nothing with the other options
+    pub enabled: bool,
+}
+
+impl ToolOption {
+    pub fn slider(id: &'static str, name: &'static str, value: f32, min: f32, max: f32) -> ToolOption {
+        let kind = ToolOptionKind::Slider { min, max, integer: false, logarithmic: false, suffix: "" };
+        ToolOption { id, name, kind, value: ToolOptionValue::Number(value), enabled: true }
+    }
+
+    pub fn int_slider(id: &'static str, name: &'static str, value: u32, min: u32, max: u32) -> ToolOption {
+        let kind = ToolOptionKind::Slider { min: min as f32, max: max as f32, integer: true, logarithmic: false, suffix: "" };
+        ToolOption { id, name, kind, value: ToolOptionValue::Number(value as f32), enabled: true }
+    }
+
+    pub fn toggle(id: &'static str, name: &'static str, value: bool) -> ToolOption {
+        ToolOption { id, name, kind: ToolOptionKind::Toggle, value: ToolOptionValue::Toggle(value), enabled: true }
+    }
+
+    pub fn choice(id: &'static str, name: &'static str, selected: usize, choices: &'static [&'static str]) -> ToolOption {
+        ToolOption { id, name, kind: ToolOptionKind::Choice(choices), value: ToolOptionValue::Choice(selected), enabled: true }
+    }
+
+    pub fn logarithmic(mut self) -> ToolOption {
+        if let ToolOptionKind::Slider { logarithmic, .. } = &mut self.kind {
+            *logarithmic = true;
+        }
+        self
+    }
+
+    pub fn suffix(mut self, text: &'static str) -> ToolOption {
+        if let ToolOptionKind::Slider { suffix, .. } = &mut self.kind {
+            *suffix = text;
+        }
+        self
+    }
+
+    pub fn enabled(mut self, enabled: bool) -> ToolOption {
+        self.enabled = enabled;
+        self
+    }
+
+    // choices are saved by name so reordering them doesn't mix up settings
+    pub fn value_text(&self) -> String {
+        match (&self.kind, self.value) {
+            (ToolOptionKind::Choice(choices), ToolOptionValue::Choice(i)) => choices.get(i).unwrap_or(&"").to_string(),
+            (_, ToolOptionValue::Number(value)) => value.to_string(),
+            (_, ToolOptionValue::Toggle(value)) => value.to_string(),
+            (_, ToolOptionValue::Choice(i)) => i.to_string()
+        }
+    }
+
+    // numbers are clamped to the slider, None when text doesn't fit the option
+    pub fn parse_value(&self, text: &str) -> Option<ToolOptionValue> {
+        match &self.kind {
+            ToolOptionKind::Slider { min, max, integer, .. } => {
+                let value = text.parse::<f32>().ok().filter(|value| value.is_finite())?.clamp(*min, *max);
+                Some(ToolOptionValue::Number(match integer {
+                    true => value.round(),
+                    false => value
+                }))
+            }
+            ToolOptionKind::Toggle => text.parse().ok().map(ToolOptionValue::Toggle),
+            ToolOptionKind::Choice(choices) => choices.iter().position(|choice| *choice == text).map(ToolOptionValue::Choice)
+        }
+    }
+}
+
+// the tools in the order they were registered, looked up by their stable id
+pub struct ToolRegistry {
+    tools: Vec<Box<dyn PaintTool>>,
+    selected: &'static str,
+}
+
+impl ToolRegistry {
+    pub fn new() -> ToolRegistry {
+        ToolRegistry {
+            tools: Vec::new(),
+            selected: "",
+        }
+    }
+
+    // a tool with the same id replaces the old one, the first tool is selected
+    pub fn register(&mut self, tool: Box<dyn PaintTool>) {
+        let id = tool.info().id;
+        match self.tools.iter().position(|other| other.info().id == id) {
+            Some(i) => self.tools[i] = tool,
+            None => self.tools.push(tool)
+        }
+        if self.selected.is_empty() {
+            self.selected = id;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn PaintTool> {
+        self.tools.iter().map(|tool| tool.as_ref())
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn PaintTool> {
+        self.iter().find(|tool| tool.info().id == id)
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut (dyn PaintTool + 'static)> {
+        self.tools.iter_mut().find(|tool| tool.info().id == id).map(|tool| tool.as_mut())
+    }
+
+    pub fn selected(&self) -> &'static str {
+        self.selected
+    }
+
+    // returns false for ids that aren't registered
+    pub fn select(&mut self, id: &str) -> bool {
+        match self.tools.iter().find(|tool| tool.info().id == id) {
+            Some(tool) => {
+                self.selected = tool.info().id;
+                true
+            }
+            None => false
+        }
+    }
+
+    // categories without tools are left out
+    pub fn grouped(&self) -> Vec<(ToolCategory, Vec<&dyn PaintTool>)> {
+        ToolCategory::all().into_iter()
+            .map(|category| (category, self.iter().filter(|tool| tool.info().category == category).collect::<Vec<_>>()))
+            .filter(|(_, tools)| !tools.is_empty())
+            .collect()
+    }
+
+    // every tool gets a "tool.<id>" action with its default shortcut
+    pub fn register_actions(&self, actions: &mut ActionRegistry) {
+        for tool in self.iter() {
+            let info = tool.info();
+            let shortcuts = info.shortcut.into_iter().collect::<Vec<_>>();
+            actions.register(&format!("tool.{}", info.id), &format!("Tool: {}", info.name), &shortcuts);
+        }
+    }
+
+    // the tooltips show the shortcut the user has now, not the default one
+    pub fn tool_box(&mut self, ui: &mut egui::Ui, actions: &ActionRegistry) {
+        let mut clicked = None;
+        for (category, tools) in self.grouped() {
+            ui.label(RichText::new(category.name()).small().weak());
+            ui.horizontal_wrapped(|ui| {
+                for tool in tools {
+                    let info = tool.info();
+                    let tooltip = match actions.shortcut_text(ui.ctx(), &format!("tool.{}", info.id)) {
+                        Some(shortcut) => format!("{} ({})", info.name, shortcut),
+                        None => info.name.to_string()
+                    };
+                    let button = egui::SelectableLabel::new(self.selected == info.id, RichText::new(info.icon).size(18.0));
+                    let response = ui.add_sized(Vec2::splat(30.0), button).on_hover_text(tooltip);
+                    if response.clicked() {
+                        clicked = Some(info.id);
+                    }
+                }
+            });
+        }
+        if let Some(id) = clicked {
+            self.selected = id;
+        }
+    }
+
+    // the options of the selected tool and its own settings, returns true when an option changed
+    pub fn settings_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let Some(tool) = self.get_mut(self.selected) else { return false };
+        let changed = options_ui(ui, tool);
+        tool.settings_ui(ui);
+        changed
+    }
+
+    // one line per option of every tool: "line.width = 4"
+    pub fn options_config(&self) -> String {
+        let mut result = String::new();
+        for tool in self.iter() {
+            for option in tool.options() {
+                result += &format!("{}.{} = {}\n", tool.info().id, option.id, option.value_text());
+            }
+        }
+        result
+    }
+
+    // like the shortcuts, bad lines are reported and unknown tools or options are ignored
+    pub fn load_options_config(&mut self, text: &str) -> Result<(), String> {
+        let mut errors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let parsed = line.split_once('=')
+                .and_then(|(key, value)| key.trim().split_once('.').map(|(tool, option)| (tool, option, value.trim())));
+            let Some((tool_id, option_id, value)) = parsed else {
+                errors.push(format!("line {}: expected tool.option = value", number + 1));
+                continue;
+            };
+            let Some(tool) = self.get_mut(tool_id) else { continue };
+            let Some(option) = tool.options().into_iter().find(|option| option.id == option_id) else { continue };
+            match option.parse_value(value) {
+                Some(value) => tool.set_option(option_id, value),
+                None => errors.push(format!("line {}: bad value {} for {}.{}", number + 1, value, tool_id, option_id))
+            }
+        }
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors.join("\n"))
+        }
+    }
+
+    pub fn load(&mut self) -> Result<(), String> {
+        match read_config(CONFIG_FILE)? {
+            Some(text) => self.load_options_config(&text),
+            None => Ok(())
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        write_config(CONFIG_FILE, &self.options_config())
+    }
+}
+
+impl Default for ToolRegistry {
+    fn default() -> ToolRegistry {
+        ToolRegistry::new()
+    }
+}
+
+// draws the options a tool describes, returns true when one changed
+pub fn options_ui(ui: &mut egui::Ui, tool: &mut dyn PaintTool) -> bool {
+    let mut changed = false;
+    for option in tool.options() {
+        let value = ui.add_enabled_ui(option.enabled, |ui| match (&option.kind, option.value) {
+            (ToolOptionKind::Slider { min, max, integer, logarithmic, suffix }, ToolOptionValue::Number(mut value)) => {
+                let mut slider = Slider::new(&mut value, *min..=*max).text(option.name).suffix(*suffix).logarithmic(*logarithmic);
+                if *integer {
+                    slider = slider.integer();
+                }
+                ui.add(slider).changed().then_some(ToolOptionValue::Number(value))
+            }
+            (ToolOptionKind::Toggle, ToolOptionValue::Toggle(mut value)) => {
+                ui.checkbox(&mut value, option.name).changed().then_some(ToolOptionValue::Toggle(value))
+            }
+            (ToolOptionKind::Choice(choices), ToolOptionValue::Choice(mut selected)) => {
+                let mut clicked = false;
+                ComboBox::new(option.id, option.name)
+                    .selected_text(*choices.get(selected).unwrap_or(&""))
+                    .show_ui(ui, |ui| {
+                        for (i, choice) in choices.iter().enumerate() {
+                            clicked |= ui.selectable_value(&mut selected, i, *choice).clicked();
+                        }
+                    });
+                clicked.then_some(ToolOptionValue::Choice(selected))
+            }
+            _ => None
+        }).inner;
+        if let Some(value) = value {
+            tool.set_option(option.id, value);
+            changed = true;
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas::{LineTool, PixelPencil};
+    use crate::paint_app::color_picker_tool::ColorPickerTool;
+    use crate::paint_app::text_tool::TextTool;
+    use crate::paint_app::gradient_tool::GradientTool;
+
+    #[test]
+    fn test_registry_order() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(PixelPencil::new()));
+        registry.register(Box::new(LineTool::new()));
+        registry.register(Box::new(ColorPickerTool::new()));
+        registry.register(Box::new(GradientTool::new()));
+        registry.register(Box::new(TextTool::new()));
+        registry.register(Box::new(LineTool::new()));
+        assert_eq!(registry.iter().map(|tool| tool.info().id).collect::<Vec<_>>(), ["pencil", "line", "color_picker", "gradient", "text"]);
+        assert_eq!(registry.selected(), "pencil");
+
+        let grouped = registry.grouped().into_iter()
+            .map(|(category, tools)| (category, tools.iter().map(|tool| tool.info().id).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        assert_eq!(grouped, [
+            (ToolCategory::Paint, vec!["pencil", "gradient"]),
+            (ToolCategory::Shapes, vec!["line"]),
+            (ToolCategory::Color, vec!["color_picker"]),
+            (ToolCategory::Text, vec!["text"]),
+        ]);
+
+        assert!(registry.select("gradient"));
+        assert!(!registry.select("brush"));
+        assert_eq!(registry.selected(), "gradient");
+
+        let mut actions = ActionRegistry::new();
+        registry.register_actions(&mut actions);
+        let line = actions.get("tool.line").unwrap();
+        assert_eq!(line.name, "Tool: Line");
+        assert_eq!(line.shortcuts[0].to_string(), "L");
+    }
+
+    #[test]
+    fn test_options_config() {
+        let mut registry = ToolRegistry::new();
+        registry.register(Box::new(LineTool::new()));
+        registry.register(Box::new(ColorPickerTool::new()));
+        let mut line = LineTool::new();
+        line.width = 7;
+        line.anti_alias = true;
+        registry.register(Box::new(line));
+        let config = registry.options_config();
+        assert!(config.contains("line.width = 7\n"));
+        assert!(config.contains("line.anti_alias = true\n"));
+        assert!(config.contains("color_picker.sample_size = Point\n"));
+
+        let mut loaded = ToolRegistry::new();
+        loaded.register(Box::new(LineTool::new()));
+        loaded.register(Box::new(ColorPickerTool::new()));
+        assert!(loaded.load_options_config(&config).is_ok());
+        assert_eq!(loaded.options_config(), config);
+
+        // out of range numbers are clamped, unknown tools are skipped, bad values are reported
+        let result = loaded.load_options_config("line.width = 500\nbrush.size = 3\ncolor_picker.sample_size = Huge");
+        assert!(result.unwrap_err().contains("line 3"));
+        assert!(loaded.options_config().contains("line.width = 64\n"));
+    }
+}
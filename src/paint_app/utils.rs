@@ -2,62 +2,87 @@ use std::collections::hash_map::IntoIter;
 use crate::paint_app::canvas_layer::CanvasLayer;
 use super::data_types::*;
 
-fn blend_color(top: Color, bottom: Color) -> Color {
-    let combined_alpha = top.alpha as u16 + ((255 - top.alpha as u16) * bottom.alpha as u16 + 127) / 255;
+// the space colors are blended in. blending sRGB values is what the app always did, linear light
+// blends the amounts of light the values stand for, which avoids dark fringes between colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendSpace {
+    Srgb,
+    Linear,
+}
 
-    if combined_alpha == 0 {
-        return Color { red: 0, green: 0, blue: 0, alpha: 0 };
-    }
+// straight sRGB rgba to premultiplied rgba in space
+pub fn premultiply(rgba: [f32; 4], space: BlendSpace) -> [f32; 4] {
+    let alpha = rgba[3];
+    let channel = |value: f32| match space {
+        BlendSpace::Srgb => value * alpha,
+        BlendSpace::Linear => srgb_to_linear(value) * alpha,
+    };
+    [channel(rgba[0]), channel(rgba[1]), channel(rgba[2]), alpha]
+}
 
-    Color {
-        red: ((top.red as u16 * top.alpha as u16 + (255 - top.alpha as u16) * bottom.red as u16 * bottom.alpha as u16 / 255 + 127) / combined_alpha) as u8,
-        green: ((top.green as u16 * top.alpha as u16 + (255 - top.alpha as u16) * bottom.green as u16 * bottom.alpha as u16 / 255 + 127) / combined_alpha) as u8,
-        blue: ((top.blue as u16 * top.alpha as u16 + (255 - top.alpha as u16) * bottom.blue as u16 * bottom.alpha as u16 / 255 + 127) / combined_alpha) as u8,
-        alpha: combined_alpha as u8,
+// premultiplied rgba in space back to straight sRGB, fully transparent pixels become zero
+pub fn unpremultiply(premultiplied: [f32; 4], space: BlendSpace) -> [f32; 4] {
+    let alpha = premultiplied[3];
+    if alpha <= 0.0 {
+        return [0.0; 4];
     }
+    let channel = |value: f32| match space {
+        BlendSpace::Srgb => value / alpha,
+        BlendSpace::Linear => linear_to_srgb((value / alpha).max(0.0)),
+    };
+    [channel(premultiplied[0]), channel(premultiplied[1]), channel(premultiplied[2]), alpha]
 }
 
-/// applies color_a over color_b
-pub fn pixel_overlap2(color_a : Color, color_b : Color) -> Color {
-    let color_a_f32 = glam::Vec4::new(color_a.red as f32, color_a.green as f32, color_a.blue as f32, color_a.alpha as f32) * (1.0 / 255.0);
-    let color_b_f32 = glam::Vec4::new(color_b.red as f32, color_b.green as f32, color_b.blue as f32, color_b.alpha as f32) * (1.0 / 255.0);
+// applies top over bottom, both premultiplied
+pub fn over(top: [f32; 4], bottom: [f32; 4]) -> [f32; 4] {
+    let rest = 1.0 - top[3];
+    [0, 1, 2, 3].map(|i| top[i] + bottom[i] * rest)
+}
 
-    let alpha_a = color_a_f32.w;
-    let mut alpha_b = color_b_f32.w;
-    if (alpha_a + alpha_b) >= 1.0 {
-        alpha_b = 1.0 - alpha_a;
+/// Applies color_a over color_b, in 8 bit sRGB without using floats
+pub fn pixel_overlap(color_a: Color, color_b: Color) -> Color {
+    let alpha_a = color_a.alpha as u32;
+    // how much of color_b shows through, alpha and weights are in 0..255*255
+    let alpha_b = color_b.alpha as u32 * (255 - alpha_a);
+    let alpha = alpha_a * 255 + alpha_b;
+    if alpha == 0 {
+        return Color::new(0, 0, 0, 0);
     }
-
-    let x = ((color_a_f32 * alpha_a + color_b_f32 * alpha_b) * 255.0).x;
-    let y = ((color_a_f32 * alpha_a + color_b_f32 * alpha_b) * 255.0).y;
-    let z = ((color_a_f32 * alpha_a + color_b_f32 * alpha_b) * 255.0).z;
-    let w = (alpha_a + alpha_b) * 255.0;
+    let channel = |a: u8, b: u8| ((a as u32 * alpha_a * 255 + b as u32 * alpha_b + alpha / 2) / alpha) as u8;
     Color::new(
-        x as u8,
-        y as u8,
-        z as u8,
-        w as u8
+        channel(color_a.red, color_b.red),
+        channel(color_a.green, color_b.green),
+        channel(color_a.blue, color_b.blue),
+        ((alpha + 127) / 255) as u8
     )
 }
-/// Applies color_a over color_b
-pub fn pixel_overlap(color_a: Color, color_b: Color) -> Color {
-    // Convert colors to integer representation
-    let color_a_int = color_a;
-    let color_b_int = color_b;
-
-    // Calculate weighted sum without using floats
-    let alpha_a = color_a_int.alpha as i32;
-    let mut alpha_b = color_b_int.alpha as i32;
-    if (alpha_a + alpha_b) >= 255 {
-        alpha_b = 255 - alpha_a;
+
+// sRGB transfer function, value in 0..1
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
     }
+}
 
-    let x = ((color_a_int.red as i32 * alpha_a + color_b_int.red as i32 * alpha_b) / 255) as u8;
-    let y = ((color_a_int.green as i32 * alpha_a + color_b_int.green as i32 * alpha_b) / 255) as u8;
-    let z = ((color_a_int.blue as i32 * alpha_a + color_b_int.blue as i32 * alpha_b) / 255) as u8;
-    let w = (alpha_a + alpha_b) as u8;
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
 
-    Color::new(x, y, z, w)
+// 4x4 ordered dither offset in -0.5..0.5
+pub fn bayer_dither(pixel_pos: PixelPos) -> f32 {
+    const BAYER: [[u8; 4]; 4] = [
+        [0, 8, 2, 10],
+        [12, 4, 14, 6],
+        [3, 11, 1, 9],
+        [15, 7, 13, 5],
+    ];
+    (BAYER[(pixel_pos.y % 4) as usize][(pixel_pos.x % 4) as usize] as f32 + 0.5) / 16.0 - 0.5
 }
 
 //pub fn checkers_pattern(pixel_pos : PixelPos, grid_len : usize) -> Color
@@ -93,45 +118,6 @@ pub fn checkers_pattern(pixel_pos : PixelPos, grid_len : usize, square_color_a:
     }
 }
 
-// return iterable of pixelpos, dont return vec
-pub fn rasterize_line(start : PixelPos, end : PixelPos) -> Vec<PixelPos> {
-    let mut result = Vec::new();
-    let mut x0 = start.x as i32;
-    let mut y0 = start.y as i32;
-    let mut x1 = end.x as i32;
-    let mut y1 = end.y as i32;
-
-    let mut steep = false;
-    if (x0-x1).abs() < (y0-y1).abs() {
-        std::mem::swap(&mut x0, &mut y0);
-        std::mem::swap(&mut x1, &mut y1);
-        steep = true;
-    }
-    if x0 > x1 {
-        std::mem::swap(&mut x0, &mut x1);
-        std::mem::swap(&mut y0, &mut y1);
-    }
-    let dx = x1-x0;
-    let dy = y1-y0;
-    let derror2 = dy.abs() * 2;
-    let mut error2 = 0;
-    let mut y = y0;
-    for x in x0..x1+1 {
-        if steep {
-            result.push(PixelPos{x: y as u32, y: x as u32});
-        } else {
-            result.push(PixelPos{x: x as u32, y: y as u32});
-        }
-        error2 += derror2;
-        if error2 > dx {
-            y += if y1 > y0 { 1 } else { -1 };
-            error2 -= dx * 2;
-        }
-    }
-    result
-
-}
-
 fn rasterize_rect(start : PixelPos, end : PixelPos) -> Vec<PixelPos> {
     let mut result = Vec::new();
     let mut x0 = start.x as i32;
@@ -154,8 +140,10 @@ fn rasterize_rect(start : PixelPos, end : PixelPos) -> Vec<PixelPos> {
 }
 
 pub fn draw_rect(canvas: &mut dyn CanvasLayer, start : PixelPos, end : PixelPos, color: Color) {
+    let (width, height) = canvas.get_size();
     let pixels = rasterize_rect(start, end);
-    for pixel in pixels {
+    // the part outside of the canvas is left out
+    for pixel in pixels.into_iter().filter(|pixel| pixel.x < width && pixel.y < height) {
         canvas.set_pixel(pixel, color);
     }
 }
@@ -166,6 +154,51 @@ pub fn draw_rect(canvas: &mut dyn CanvasLayer, start : PixelPos, end : PixelPos,
 mod tests {
     use super::*;
 
+    fn assert_close(result: [f32; 4], expected: [f32; 4], tolerance: f32) {
+        assert!(result.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() <= tolerance), "{:?} != {:?}", result, expected);
+    }
+
+    // top over bottom stored as P, the way the compositor does it
+    fn blend_pixels<P: Pixel>(top: P, bottom: P, space: BlendSpace) -> P {
+        let result = over(premultiply(top.to_rgba(), space), premultiply(bottom.to_rgba(), space));
+        P::from_rgba(unpremultiply(result, space))
+    }
+
+    type Rgba = [f32; 4];
+
+    // top, bottom, the result in sRGB and the result in linear light, all straight rgba
+    const REFERENCE: [(Rgba, Rgba, Rgba, Rgba); 5] = [
+        ([1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0], [1.0, 0.0, 0.0, 1.0]),
+        ([1.0, 0.0, 0.0, 0.5], [0.0, 1.0, 0.0, 1.0], [0.5, 0.5, 0.0, 1.0], [0.73536, 0.73536, 0.0, 1.0]),
+        ([0.0, 0.0, 0.0, 0.5], [1.0, 1.0, 1.0, 1.0], [0.5, 0.5, 0.5, 1.0], [0.73536, 0.73536, 0.73536, 1.0]),
+        // nothing below, the color must not get darker
+        ([1.0, 1.0, 1.0, 0.5], [0.0, 0.0, 0.0, 0.0], [1.0, 1.0, 1.0, 0.5], [1.0, 1.0, 1.0, 0.5]),
+        ([0.0, 0.0, 1.0, 0.25], [1.0, 0.0, 0.0, 0.5], [0.6, 0.0, 0.4, 0.625], [0.79774, 0.0, 0.66519, 0.625]),
+    ];
+
+    fn check_reference<P: Pixel>(tolerance: f32) {
+        for (top, bottom, srgb, linear) in REFERENCE {
+            let (top, bottom) = (P::from_rgba(top), P::from_rgba(bottom));
+            assert_close(blend_pixels(top, bottom, BlendSpace::Srgb).to_rgba(), srgb, tolerance);
+            assert_close(blend_pixels(top, bottom, BlendSpace::Linear).to_rgba(), linear, tolerance);
+        }
+    }
+
+    #[test]
+    fn test_blend_reference() {
+        check_reference::<Color>(1.5 / 255.0);
+        check_reference::<Color16>(1e-4);
+        check_reference::<ColorF32>(1e-5);
+    }
+
+    #[test]
+    fn test_premultiply_round_trip() {
+        let rgba = [0.2, 0.5, 0.9, 0.3];
+        assert_close(unpremultiply(premultiply(rgba, BlendSpace::Srgb), BlendSpace::Srgb), rgba, 1e-6);
+        assert_close(unpremultiply(premultiply(rgba, BlendSpace::Linear), BlendSpace::Linear), rgba, 1e-5);
+        assert_eq!(unpremultiply([0.5, 0.5, 0.5, 0.0], BlendSpace::Srgb), [0.0; 4]);
+    }
+
     #[test]
     fn test_pixel_overlap() {
         //let color_a = Color::new(255, 0, 0, 255);
@@ -177,5 +210,18 @@ mod tests {
         let color_b = Color::new(0, 255, 0, 255);
         let result = pixel_overlap(color_a, color_b);
         assert_eq!(result, Color::new(120, 135, 0, 255));
+
+        // over transparent the color stays the same
+        let result = pixel_overlap(Color::new(200, 100, 50, 128), Color::new(0, 0, 0, 0));
+        assert_eq!(result, Color::new(200, 100, 50, 128));
+
+        // the integer version agrees with the float one
+        for (a, b) in [(0u8, 255u8), (64, 200), (128, 128), (250, 3)] {
+            let color_a = Color::new(a, b, 255 - a, b);
+            let color_b = Color::new(b, a, 40, a);
+            let exact = blend_pixels(color_a, color_b, BlendSpace::Srgb).to_rgba();
+            let result = pixel_overlap(color_a, color_b).to_rgba();
+            assert_close(result, exact, 1.0 / 255.0);
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,219 @@
+use glam::Vec2;
+use crate::paint_app::canvas_layer::CanvasLayer;
+use crate::paint_app::rasterize::{Fill, LineJoin, Stroke, StrokeStyle};
+use crate::paint_app::utils::{over, premultiply, unpremultiply, BlendSpace};
+use crate::paint_app::vector_path::{PathNode, VectorPath};
+use super::data_types::*;
+
+// how far the handles of a quarter circle reach, relative to the radius
+const KAPPA: f32 = 0.552_284_8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShapeKind {
+    Rectangle { min: Vec2, max: Vec2, corner_radius: f32 },
+    Ellipse { min: Vec2, max: Vec2 },
+    // in canvas coordinates
+    Path(VectorPath),
+}
+
+// the stroke is centered on the outline and drawn over the fill
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorShape {
+    pub kind: ShapeKind,
+    pub fill: Option<Color>,
+    pub stroke: Option<Color>,
+    pub stroke_width: f32,
+}
+
+impl VectorShape {
+    pub fn rectangle(min: Vec2, max: Vec2, fill: Color) -> VectorShape {
+        VectorShape { kind: ShapeKind::Rectangle { min, max, corner_radius: 0.0 }, fill: Some(fill), stroke: None, stroke_width: 1.0 }
+    }
+
+    pub fn ellipse(min: Vec2, max: Vec2, fill: Color) -> VectorShape {
+        VectorShape { kind: ShapeKind::Ellipse { min, max }, fill: Some(fill), stroke: None, stroke_width: 1.0 }
+    }
+
+    pub fn path(path: VectorPath, stroke: Color) -> VectorShape {
+        VectorShape { kind: ShapeKind::Path(path), fill: None, stroke: Some(stroke), stroke_width: 1.0 }
+    }
+
+    pub fn name(&self) -> &str {
+        match &self.kind {
+            ShapeKind::Rectangle { .. } => "Rectangle",
+            ShapeKind::Ellipse { .. } => "Ellipse",
+            ShapeKind::Path(path) => &path.name
+        }
+    }
+
+    // paths include their handles
+    pub fn bounds(&self) -> (Vec2, Vec2) {
+        match &self.kind {
+            ShapeKind::Rectangle { min, max, .. } | ShapeKind::Ellipse { min, max } => (min.min(*max), min.max(*max)),
+            ShapeKind::Path(path) => path.nodes.iter()
+                .flat_map(|node| [node.anchor, node.handle_in, node.handle_out])
+                .fold((Vec2::MAX, Vec2::MIN), |(min, max), point| (min.min(point), max.max(point)))
+        }
+    }
+
+    // moves and stretches the shape into the box, it's drawn again at the new size so nothing gets blurry
+    pub fn set_bounds(&mut self, new_min: Vec2, new_max: Vec2) {
+        let (old_min, old_max) = self.bounds();
+        match &mut self.kind {
+            ShapeKind::Rectangle { min, max, .. } | ShapeKind::Ellipse { min, max } => (*min, *max) = (new_min, new_max),
+            ShapeKind::Path(path) => {
+                let old_size = old_max - old_min;
+                // flat paths only move along the side they have no size in
+                let scale = Vec2::select(old_size.cmpgt(Vec2::ZERO), (new_max - new_min) / old_size, Vec2::ONE);
+                let map = |point: &mut Vec2| *point = new_min + (*point - old_min) * scale;
+                for node in path.nodes.iter_mut() {
+                    map(&mut node.anchor);
+                    map(&mut node.handle_in);
+                    map(&mut node.handle_out);
+                }
+            }
+        }
+    }
+
+    pub fn translate(&mut self, offset: Vec2) {
+        let (min, max) = self.bounds();
+        self.set_bounds(min + offset, max + offset);
+    }
+
+    // rectangles and ellipses are made of curves as well, so everything is drawn the same way
+    pub fn outline(&self) -> VectorPath {
+        let mut result = VectorPath::new(self.name().to_string());
+        result.closed = true;
+        match &self.kind {
+            ShapeKind::Rectangle { min, max, corner_radius } => {
+                let (min, max) = (min.min(*max), min.max(*max));
+                let radius = corner_radius.min((max.x - min.x) / 2.0).min((max.y - min.y) / 2.0).max(0.0);
+                // clockwise from the top right, each corner with the way in and out of it
+                let corners = [
+                    (Vec2::new(max.x, min.y), Vec2::X, Vec2::Y),
+                    (max, Vec2::Y, -Vec2::X),
+                    (Vec2::new(min.x, max.y), -Vec2::X, -Vec2::Y),
+                    (min, -Vec2::Y, Vec2::X),
+                ];
+                for (corner, dir_in, dir_out) in corners {
+                    if radius == 0.0 {
+                        result.nodes.push(PathNode::corner(corner));
+                        continue;
+                    }
+                    let (start, end) = (corner - dir_in * radius, corner + dir_out * radius);
+                    result.nodes.push(PathNode { anchor: start, handle_in: start, handle_out: start + dir_in * radius * KAPPA });
+                    result.nodes.push(PathNode { anchor: end, handle_in: end - dir_out * radius * KAPPA, handle_out: end });
+                }
+            }
+            ShapeKind::Ellipse { min, max } => {
+                let center = (*min + *max) / 2.0;
+                let radius = (*max - *min).abs() / 2.0;
+                let handle = radius * KAPPA;
+                // right, bottom, left and top, with handles along the way around
+                for (dir, along) in [(Vec2::X, Vec2::Y), (Vec2::Y, -Vec2::X), (-Vec2::X, -Vec2::Y), (-Vec2::Y, Vec2::X)] {
+                    let anchor = center + dir * radius;
+                    result.nodes.push(PathNode { anchor, handle_in: anchor - along * handle, handle_out: anchor + along * handle });
+                }
+            }
+            ShapeKind::Path(path) => result = path.clone()
+        }
+        result
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorLayer {
+    // bottom first
+    pub shapes: Vec<VectorShape>,
+}
+
+impl VectorLayer {
+    pub fn new() -> VectorLayer {
+        VectorLayer {
+            shapes: Vec::new(),
+        }
+    }
+}
+
+impl Default for VectorLayer {
+    fn default() -> VectorLayer {
+        VectorLayer::new()
+    }
+}
+
+// clears target and draws the shapes anti-aliased at its size
+pub fn render_vector_layer(vector: &VectorLayer, blend_space: BlendSpace, target: &mut dyn CanvasLayer) {
+    target.fill(Color::new(255, 255, 255, 0));
+    let size = target.get_size();
+    let paint = |target: &mut dyn CanvasLayer, pos: PixelPos, color: Color, coverage: f32| {
+        let mut rgba = color.to_rgba();
+        rgba[3] *= coverage;
+        let result = over(premultiply(rgba, blend_space), premultiply(target.get_rgba(pos), blend_space));
+        target.set_rgba(pos, unpremultiply(result, blend_space));
+    };
+    for shape in vector.shapes.iter() {
+        let outline = shape.outline();
+        let points = outline.flatten();
+        if let Some(color) = shape.fill.filter(|_| points.len() > 2) {
+            for (pos, coverage) in Fill::new(&points[..], true, size) {
+                paint(target, pos, color, coverage);
+            }
+        }
+        if let Some(color) = shape.stroke.filter(|_| shape.stroke_width > 0.0) {
+            let style = StrokeStyle { join: LineJoin::Miter, ..StrokeStyle::new(shape.stroke_width) };
+            let mut stroke = Stroke::new(&points[..], style, size);
+            if outline.closed {
+                stroke = stroke.closed();
+            }
+            for (pos, coverage) in stroke {
+                paint(target, pos, color, coverage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paint_app::canvas_layer::FlatCanvasLayer;
+
+    #[test]
+    fn test_render_shapes() {
+        let red = Color::new(255, 0, 0, 255);
+        let mut rounded = VectorShape::rectangle(Vec2::new(2.0, 2.0), Vec2::new(22.0, 12.0), red);
+        rounded.kind = ShapeKind::Rectangle { min: Vec2::new(2.0, 2.0), max: Vec2::new(22.0, 12.0), corner_radius: 4.0 };
+        rounded.stroke = Some(Color::black());
+        rounded.stroke_width = 2.0;
+        assert_eq!(rounded.outline().nodes.len(), 8);
+        let ellipse = VectorShape::ellipse(Vec2::new(2.0, 20.0), Vec2::new(22.0, 30.0), red);
+        let vector = VectorLayer { shapes: vec![rounded, ellipse] };
+
+        let mut layer = FlatCanvasLayer::<Color>::new(32, 32);
+        render_vector_layer(&vector, BlendSpace::Srgb, &mut layer);
+        let pixel = |x, y| layer.get_pixel(PixelPos { x, y });
+        assert_eq!(pixel(12, 7), red);
+        assert_eq!(pixel(12, 2), Color::black());
+        // the rounded corner leaves the corner of the box empty
+        assert_eq!(pixel(2, 2).alpha, 0);
+        assert_eq!(pixel(12, 25), red);
+        assert_eq!(pixel(3, 21).alpha, 0);
+        // anti-aliased edge
+        let edge = pixel(12, 20).alpha;
+        assert!(edge > 0 && edge < 255);
+    }
+
+    #[test]
+    fn test_set_bounds() {
+        let mut path = VectorPath::new("Line".to_string());
+        path.nodes.push(PathNode::corner(Vec2::new(0.0, 4.0)));
+        path.nodes.push(PathNode { anchor: Vec2::new(10.0, 4.0), handle_in: Vec2::new(10.0, 0.0), handle_out: Vec2::new(10.0, 4.0) });
+        let mut shape = VectorShape::path(path, Color::black());
+        assert_eq!(shape.bounds(), (Vec2::new(0.0, 0.0), Vec2::new(10.0, 4.0)));
+        shape.set_bounds(Vec2::new(10.0, 10.0), Vec2::new(30.0, 12.0));
+        let ShapeKind::Path(path) = &shape.kind else { unreachable!() };
+        assert_eq!(path.nodes[0].anchor, Vec2::new(10.0, 12.0));
+        assert_eq!(path.nodes[1].handle_in, Vec2::new(30.0, 10.0));
+        shape.translate(Vec2::new(-10.0, 0.0));
+        assert_eq!(shape.bounds(), (Vec2::new(0.0, 10.0), Vec2::new(20.0, 12.0)));
+    }
+}
@@ -0,0 +1,237 @@
+use glam::Vec2;
+use crate::paint_app::rasterize::cubic_points;
+
+// an anchor of a cubic bezier path, the handles are absolute positions and
+// sit on the anchor for corners
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathNode {
+    pub anchor: Vec2,
+    pub handle_in: Vec2,
+    pub handle_out: Vec2,
+}
+
+impl PathNode {
+    pub fn corner(anchor: Vec2) -> PathNode {
+        PathNode { anchor, handle_in: anchor, handle_out: anchor }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathHandle {
+    Anchor(usize),
+    In(usize),
+    Out(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorPath {
+    pub name: String,
+    pub nodes: Vec<PathNode>,
+    pub closed: bool,
+}
+
+impl VectorPath {
+    pub fn new(name: String) -> VectorPath {
+        VectorPath {
+            name,
+            nodes: Vec::new(),
+            closed: false,
+        }
+    }
+
+    // the control points of every segment, closed paths end with the one back to the start
+    pub fn segments(&self) -> impl Iterator<Item = [Vec2; 4]> + '_ {
+        let count = self.nodes.len();
+        let segments = match self.closed && count > 1 {
+            true => count,
+            false => count.saturating_sub(1)
+        };
+        (0..segments).map(move |i| {
+            let (a, b) = (self.nodes[i], self.nodes[(i + 1) % count]);
+            [a.anchor, a.handle_out, b.handle_in, b.anchor]
+        })
+    }
+
+    // the curves as line segments, closed paths don't repeat the first point
+    pub fn flatten(&self) -> Vec<Vec2> {
+        let mut result = self.nodes.first().map(|node| node.anchor).into_iter().collect::<Vec<_>>();
+        for [p0, p1, p2, p3] in self.segments() {
+            result.extend_from_slice(&cubic_points(p0, p1, p2, p3).as_ref()[1..]);
+        }
+        if self.closed && result.len() > 1 {
+            result.pop();
+        }
+        result
+    }
+
+    pub fn position(&self, handle: PathHandle) -> Option<Vec2> {
+        match handle {
+            PathHandle::Anchor(i) => self.nodes.get(i).map(|node| node.anchor),
+            PathHandle::In(i) => self.nodes.get(i).map(|node| node.handle_in),
+            PathHandle::Out(i) => self.nodes.get(i).map(|node| node.handle_out)
+        }
+    }
+
+    // anchors win over handles, handles lying on their anchor can't be grabbed
+    pub fn hit(&self, pos: Vec2, radius: f32) -> Option<PathHandle> {
+        let near = |point: Vec2| point.distance(pos) <= radius;
+        let anchors = (0..self.nodes.len()).map(PathHandle::Anchor);
+        let handles = (0..self.nodes.len()).flat_map(|i| [PathHandle::In(i), PathHandle::Out(i)]);
+        anchors.chain(handles.filter(|handle| match *handle {
+            PathHandle::In(i) => self.nodes[i].handle_in != self.nodes[i].anchor,
+            PathHandle::Out(i) => self.nodes[i].handle_out != self.nodes[i].anchor,
+            PathHandle::Anchor(_) => true
+        }))
+        .find(|handle| self.position(*handle).is_some_and(near))
+    }
+
+    // anchors take their handles along. with mirror the opposite handle follows,
+    // which keeps the curve smooth through the anchor
+    pub fn move_handle(&mut self, handle: PathHandle, pos: Vec2, mirror: bool) {
+        let Some(node) = self.nodes.get_mut(match handle {
+            PathHandle::Anchor(i) | PathHandle::In(i) | PathHandle::Out(i) => i
+        }) else { return };
+        match handle {
+            PathHandle::Anchor(_) => {
+                let offset = pos - node.anchor;
+                node.anchor += offset;
+                node.handle_in += offset;
+                node.handle_out += offset;
+            }
+            PathHandle::In(_) => {
+                node.handle_in = pos;
+                if mirror {
+                    node.handle_out = node.anchor * 2.0 - pos;
+                }
+            }
+            PathHandle::Out(_) => {
+                node.handle_out = pos;
+                if mirror {
+                    node.handle_in = node.anchor * 2.0 - pos;
+                }
+            }
+        }
+    }
+
+    // svg path data with absolute commands, "M 1 2 C 3 4 5 6 7 8 Z"
+    pub fn to_svg_data(&self) -> String {
+        let mut result = match self.nodes.first() {
+            Some(first) => format!("M {} {}", first.anchor.x, first.anchor.y),
+            None => return String::new()
+        };
+        for [_, p1, p2, p3] in self.segments() {
+            result += &format!(" C {} {} {} {} {} {}", p1.x, p1.y, p2.x, p2.y, p3.x, p3.y);
+        }
+        if self.closed {
+            // the closing curve is written out, Z only marks the path closed
+            result += " Z";
+        }
+        result
+    }
+
+    // reads what to_svg_data writes plus L, one subpath with absolute commands only
+    pub fn parse_svg_data(name: String, data: &str) -> Result<VectorPath, String> {
+        let mut path = VectorPath::new(name);
+        let mut tokens = data.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty());
+        fn numbers<'a>(tokens: &mut impl Iterator<Item = &'a str>, count: usize) -> Result<Vec<Vec2>, String> {
+            let mut values = Vec::new();
+            for _ in 0..count * 2 {
+                let token = tokens.next().ok_or("path data ends early")?;
+                values.push(token.parse::<f32>().map_err(|_| format!("bad number {}", token))?);
+            }
+            Ok(values.chunks(2).map(|pair| Vec2::new(pair[0], pair[1])).collect())
+        }
+        while let Some(command) = tokens.next() {
+            match command {
+                "M" if path.nodes.is_empty() => path.nodes.push(PathNode::corner(numbers(&mut tokens, 1)?[0])),
+                "L" | "C" if !path.nodes.is_empty() && !path.closed => {
+                    let points = numbers(&mut tokens, if command == "L" { 1 } else { 3 })?;
+                    let (handle_out, handle_in, anchor) = match points[..] {
+                        [a, b, c] => (a, b, c),
+                        _ => (path.nodes[path.nodes.len() - 1].anchor, points[0], points[0])
+                    };
+                    path.nodes.last_mut().unwrap().handle_out = handle_out;
+                    path.nodes.push(PathNode { anchor, handle_in, handle_out: anchor });
+                }
+                "Z" if !path.nodes.is_empty() => {
+                    // a closing curve back to the start becomes the first anchor's in handle
+                    let last = path.nodes.len() - 1;
+                    if last > 0 && path.nodes[last].anchor == path.nodes[0].anchor {
+                        let closing = path.nodes.pop().unwrap();
+                        path.nodes[0].handle_in = closing.handle_in;
+                    }
+                    path.closed = true;
+                }
+                _ => return Err(format!("unsupported path command {}", command))
+            }
+        }
+        Ok(path)
+    }
+}
+
+// one path per line, "name = svg path data", for storing the paths with a document
+pub fn paths_to_text(paths: &[VectorPath]) -> String {
+    paths.iter().map(|path| format!("{} = {}\n", path.name, path.to_svg_data())).collect()
+}
+
+pub fn parse_paths(text: &str) -> Result<Vec<VectorPath>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            let (name, data) = line.rsplit_once('=').ok_or(format!("line {}: expected name = path data", number + 1))?;
+            VectorPath::parse_svg_data(name.trim().to_string(), data).map_err(|err| format!("line {}: {}", number + 1, err))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_path() -> VectorPath {
+        let mut path = VectorPath::new("Outline = 1".to_string());
+        path.nodes.push(PathNode::corner(Vec2::new(2.0, 2.0)));
+        path.nodes.push(PathNode { anchor: Vec2::new(10.0, 2.0), handle_in: Vec2::new(8.0, 0.0), handle_out: Vec2::new(12.0, 4.0) });
+        path.nodes.push(PathNode::corner(Vec2::new(6.0, 9.5)));
+        path
+    }
+
+    #[test]
+    fn test_svg_data() {
+        let mut path = test_path();
+        assert_eq!(path.to_svg_data(), "M 2 2 C 2 2 8 0 10 2 C 12 4 6 9.5 6 9.5");
+        path.closed = true;
+        path.nodes[0].handle_in = Vec2::new(1.0, 5.0);
+        let text = paths_to_text(&[path.clone(), VectorPath::new("Empty".to_string())]);
+        assert!(text.ends_with(" C 6 9.5 1 5 2 2 Z\nEmpty = \n"));
+        assert_eq!(parse_paths(&text).unwrap(), [path, VectorPath::new("Empty".to_string())]);
+
+        let lines = VectorPath::parse_svg_data(String::new(), "M 0,0 L 4,0 L 4 4 Z").unwrap();
+        assert_eq!(lines.nodes.len(), 3);
+        assert!(lines.closed);
+        assert!(VectorPath::parse_svg_data(String::new(), "M 0 0 Q 1 1 2 2").is_err());
+        assert!(parse_paths("a = M 0 0 C 1").unwrap_err().starts_with("line 1"));
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut path = test_path();
+        assert_eq!(path.hit(Vec2::new(10.0, 3.0), 2.0), Some(PathHandle::Anchor(1)));
+        assert_eq!(path.hit(Vec2::new(12.0, 5.0), 2.0), Some(PathHandle::Out(1)));
+        // corner handles stay under their anchor
+        assert_eq!(path.hit(Vec2::new(2.0, 2.0), 2.0), Some(PathHandle::Anchor(0)));
+        assert_eq!(path.hit(Vec2::new(20.0, 20.0), 2.0), None);
+
+        path.move_handle(PathHandle::Anchor(1), Vec2::new(11.0, 3.0), false);
+        assert_eq!(path.nodes[1].handle_out, Vec2::new(13.0, 5.0));
+        path.move_handle(PathHandle::Out(1), Vec2::new(15.0, 3.0), true);
+        assert_eq!(path.nodes[1].handle_in, Vec2::new(7.0, 3.0));
+
+        let points = path.flatten();
+        assert_eq!(points[0], Vec2::new(2.0, 2.0));
+        assert_eq!(*points.last().unwrap(), Vec2::new(6.0, 9.5));
+        path.closed = true;
+        assert_ne!(*path.flatten().last().unwrap(), Vec2::new(2.0, 2.0));
+    }
+}
@@ -0,0 +1,1010 @@
+use image::codecs::webp::WebPEncoder;
+use image::ColorType;
+use crate::paint_app::canvas_layer::{CanvasLayer, FlatCanvasLayer};
+use super::data_types::*;
+
+// lossless webp goes through the image crate, which can't write lossy webp, so that is done here
+// and only the encoder is ours, files are read by the image crate. lossy webp is a single vp8 key frame with 16x16 and
+// 8x8 intra prediction only and the default token probabilities, https://datatracker.ietf.org/doc/html/rfc6386
+// alpha is stored losslessly next to it, https://developers.google.com/speed/webp/docs/riff_container
+
+const MAX_SIZE: u32 = 16383;
+
+pub fn encode_webp(layer: &FlatCanvasLayer, lossless: bool, quality: u8) -> Result<Vec<u8>, String> {
+    let (width, height) = layer.get_size();
+    if width > MAX_SIZE || height > MAX_SIZE {
+        return Err(format!("webp images can't be larger than {}x{}", MAX_SIZE, MAX_SIZE));
+    }
+    if lossless {
+        let rgba = layer.get_data().iter().flat_map(|color| [color.red, color.green, color.blue, color.alpha]).collect::<Vec<_>>();
+        let mut bytes = Vec::new();
+        WebPEncoder::new_lossless(&mut bytes).encode(&rgba, width, height, ColorType::Rgba8).map_err(|err| err.to_string())?;
+        return Ok(bytes);
+    }
+
+    let frame = encode_vp8(layer, quality);
+    let mut chunks = Vec::new();
+    if layer.get_data().iter().any(|color| color.alpha < 255) {
+        let mut header = vec![0x10, 0, 0, 0];
+        header.extend_from_slice(&(width - 1).to_le_bytes()[..3]);
+        header.extend_from_slice(&(height - 1).to_le_bytes()[..3]);
+        chunks.extend(chunk(b"VP8X", &header));
+        chunks.extend(chunk(b"ALPH", &encode_alpha(layer)?));
+    }
+    chunks.extend(chunk(b"VP8 ", &frame));
+    let mut result = b"RIFF".to_vec();
+    result.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+    result.extend_from_slice(b"WEBP");
+    result.extend(chunks);
+    Ok(result)
+}
+
+fn chunk(name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut result = name.to_vec();
+    result.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    result.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        result.push(0);
+    }
+    result
+}
+
+// the alpha channel as the green of a lossless image, without the lossless header
+fn encode_alpha(layer: &FlatCanvasLayer) -> Result<Vec<u8>, String> {
+    let (width, height) = layer.get_size();
+    let alpha = layer.get_data().iter().map(|color| color.alpha).collect::<Vec<_>>();
+    let mut bytes = Vec::new();
+    WebPEncoder::new_lossless(&mut bytes).encode(&alpha, width, height, ColorType::L8).map_err(|err| err.to_string())?;
+    // riff header, chunk header, then the one byte signature and four bytes of size
+    let stream = bytes.get(25..).filter(|_| &bytes[12..16] == b"VP8L").ok_or("the alpha channel couldn't be compressed")?;
+    // no filtering or preprocessing, lossless compression
+    let mut result = vec![1];
+    result.extend_from_slice(stream);
+    Ok(result)
+}
+
+// the boolean entropy coder from section 7, the decoder splits the range the same way
+struct BoolWriter {
+    output: Vec<u8>,
+    range: u32,
+    low: u32,
+    count: i32,
+}
+
+impl BoolWriter {
+    fn new() -> BoolWriter {
+        BoolWriter { output: Vec::new(), range: 255, low: 0, count: -24 }
+    }
+
+    fn put(&mut self, bit: bool, probability: u8) {
+        let split = 1 + (((self.range - 1) * probability as u32) >> 8);
+        self.range = match bit {
+            true => {
+                self.low += split;
+                self.range - split
+            }
+            false => split
+        };
+        let mut shift = self.range.leading_zeros() as i32 - 24;
+        self.range <<= shift;
+        self.count += shift;
+        if self.count >= 0 {
+            let offset = shift - self.count;
+            if (self.low << (offset - 1)) & 0x80000000 != 0 {
+                // carry into the bytes already written
+                let mut i = self.output.len() - 1;
+                while self.output[i] == 0xff {
+                    self.output[i] = 0;
+                    i -= 1;
+                }
+                self.output[i] += 1;
+            }
+            self.output.push((self.low >> (24 - offset)) as u8);
+            self.low = (self.low << offset) & 0xffffff;
+            shift = self.count;
+            self.count -= 8;
+        }
+        self.low <<= shift;
+    }
+
+    fn put_literal(&mut self, value: u32, bits: u32) {
+        (0..bits).rev().for_each(|bit| self.put((value >> bit) & 1 == 1, 128));
+    }
+
+    // walks the tree down to the leaf holding -value, the probabilities are per pair of entries
+    fn put_tree(&mut self, tree: &[i8], probabilities: &[u8], value: i8, start: usize) {
+        fn path(tree: &[i8], node: usize, value: i8, result: &mut Vec<(usize, bool)>) -> bool {
+            for branch in [false, true] {
+                let next = tree[node + branch as usize];
+                let found = match next <= 0 {
+                    true => -next == value,
+                    false => path(tree, next as usize, value, result)
+                };
+                if found {
+                    result.push((node, branch));
+                    return true;
+                }
+            }
+            false
+        }
+        let mut steps = Vec::new();
+        path(tree, start, value, &mut steps);
+        steps.iter().rev().for_each(|(node, branch)| self.put(*branch, probabilities[node >> 1]));
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        (0..32).for_each(|_| self.put(false, 128));
+        self.output
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Dc = 0,
+    Vertical = 1,
+    Horizontal = 2,
+    TrueMotion = 3,
+}
+
+const MODES: [Mode; 4] = [Mode::Dc, Mode::Vertical, Mode::Horizontal, Mode::TrueMotion];
+
+// a plane padded to whole macroblocks, the source and then what the decoder will see
+struct Plane {
+    data: Vec<u8>,
+    stride: usize,
+}
+
+impl Plane {
+    fn get(&self, x: usize, y: usize) -> u8 {
+        self.data[y * self.stride + x]
+    }
+
+    fn set(&mut self, x: usize, y: usize, value: u8) {
+        self.data[y * self.stride + x] = value;
+    }
+}
+
+// the pixels around a block the decoder predicts from, section 12.2
+struct Edges {
+    above: Vec<u8>,
+    left: Vec<u8>,
+    corner: u8,
+    has_above: bool,
+    has_left: bool,
+}
+
+impl Edges {
+    fn new(plane: &Plane, x: usize, y: usize, size: usize) -> Edges {
+        Edges {
+            above: (0..size).map(|i| if y == 0 { 127 } else { plane.get(x + i, y - 1) }).collect(),
+            left: (0..size).map(|i| if x == 0 { 129 } else { plane.get(x - 1, y + i) }).collect(),
+            corner: match (x, y) {
+                (_, 0) => 127,
+                (0, _) => 129,
+                _ => plane.get(x - 1, y - 1)
+            },
+            has_above: y > 0,
+            has_left: x > 0,
+        }
+    }
+
+    fn predict(&self, mode: Mode) -> Vec<u8> {
+        let size = self.above.len();
+        match mode {
+            Mode::Dc => {
+                let mut sum = 0u32;
+                let mut shift = size.trailing_zeros() - 1;
+                if self.has_above {
+                    sum += self.above.iter().map(|value| *value as u32).sum::<u32>();
+                    shift += 1;
+                }
+                if self.has_left {
+                    sum += self.left.iter().map(|value| *value as u32).sum::<u32>();
+                    shift += 1;
+                }
+                let value = match self.has_above || self.has_left {
+                    true => ((sum + (1 << (shift - 1))) >> shift) as u8,
+                    false => 128
+                };
+                vec![value; size * size]
+            }
+            Mode::Vertical => (0..size * size).map(|i| self.above[i % size]).collect(),
+            Mode::Horizontal => (0..size * size).map(|i| self.left[i / size]).collect(),
+            Mode::TrueMotion => (0..size * size).map(|i| {
+                (self.left[i / size] as i32 + self.above[i % size] as i32 - self.corner as i32).clamp(0, 255) as u8
+            }).collect(),
+        }
+    }
+}
+
+// picks the prediction closest to the source
+fn best_prediction(edges: &[Edges], sources: &[Vec<u8>]) -> (Mode, Vec<Vec<u8>>) {
+    MODES.iter().map(|mode| {
+        let predictions = edges.iter().map(|edges| edges.predict(*mode)).collect::<Vec<_>>();
+        let error = predictions.iter().zip(sources).map(|(prediction, source)| {
+            prediction.iter().zip(source).map(|(a, b)| (*a as i32 - *b as i32).pow(2) as u64).sum::<u64>()
+        }).sum::<u64>();
+        (error, *mode, predictions)
+    }).min_by_key(|(error, _, _)| *error).map(|(_, mode, predictions)| (mode, predictions)).unwrap()
+}
+
+// the 4x4 transforms from libvpx, the decoder inverts them with idct and iwht below
+fn forward_dct(input: &[i32; 16]) -> [i32; 16] {
+    let mut temp = [0; 16];
+    for row in 0..4 {
+        let i = &input[row * 4..row * 4 + 4];
+        let a = (i[0] + i[3]) * 8;
+        let b = (i[1] + i[2]) * 8;
+        let c = (i[1] - i[2]) * 8;
+        let d = (i[0] - i[3]) * 8;
+        temp[row * 4] = a + b;
+        temp[row * 4 + 2] = a - b;
+        temp[row * 4 + 1] = (c * 2217 + d * 5352 + 14500) >> 12;
+        temp[row * 4 + 3] = (d * 2217 - c * 5352 + 7500) >> 12;
+    }
+    let mut output = [0; 16];
+    for column in 0..4 {
+        let a = temp[column] + temp[column + 12];
+        let b = temp[column + 4] + temp[column + 8];
+        let c = temp[column + 4] - temp[column + 8];
+        let d = temp[column] - temp[column + 12];
+        output[column] = (a + b + 7) >> 4;
+        output[column + 8] = (a - b + 7) >> 4;
+        output[column + 4] = ((c * 2217 + d * 5352 + 12000) >> 16) + (d != 0) as i32;
+        output[column + 12] = (d * 2217 - c * 5352 + 51000) >> 16;
+    }
+    output
+}
+
+fn inverse_dct(block: &mut [i32; 16]) {
+    const C1: i64 = 20091;
+    const C2: i64 = 35468;
+    let mut temp = [0i64; 16];
+    for i in 0..4 {
+        let value = |row: usize| block[row * 4 + i] as i64;
+        let a = value(0) + value(2);
+        let b = value(0) - value(2);
+        let c = ((value(1) * C2) >> 16) - (value(3) + ((value(3) * C1) >> 16));
+        let d = (value(1) + ((value(1) * C1) >> 16)) + ((value(3) * C2) >> 16);
+        temp[i] = a + d;
+        temp[4 + i] = b + c;
+        temp[8 + i] = b - c;
+        temp[12 + i] = a - d;
+    }
+    for i in 0..4 {
+        let value = |column: usize| temp[i * 4 + column];
+        let a = value(0) + value(2);
+        let b = value(0) - value(2);
+        let c = ((value(1) * C2) >> 16) - (value(3) + ((value(3) * C1) >> 16));
+        let d = (value(1) + ((value(1) * C1) >> 16)) + ((value(3) * C2) >> 16);
+        block[i * 4] = ((a + d + 4) >> 3) as i32;
+        block[i * 4 + 1] = ((b + c + 4) >> 3) as i32;
+        block[i * 4 + 2] = ((b - c + 4) >> 3) as i32;
+        block[i * 4 + 3] = ((a - d + 4) >> 3) as i32;
+    }
+}
+
+fn forward_wht(input: &[i32; 16]) -> [i32; 16] {
+    let mut temp = [0; 16];
+    for row in 0..4 {
+        let i = &input[row * 4..row * 4 + 4];
+        let a = (i[0] + i[2]) * 4;
+        let d = (i[1] + i[3]) * 4;
+        let c = (i[1] - i[3]) * 4;
+        let b = (i[0] - i[2]) * 4;
+        temp[row * 4] = a + d + (a != 0) as i32;
+        temp[row * 4 + 1] = b + c;
+        temp[row * 4 + 2] = b - c;
+        temp[row * 4 + 3] = a - d;
+    }
+    let mut output = [0; 16];
+    for column in 0..4 {
+        let a = temp[column] + temp[column + 8];
+        let d = temp[column + 4] + temp[column + 12];
+        let c = temp[column + 4] - temp[column + 12];
+        let b = temp[column] - temp[column + 8];
+        let results = [a + d, b + c, b - c, a - d];
+        for (row, value) in results.into_iter().enumerate() {
+            output[column + row * 4] = (value + (value < 0) as i32 + 3) >> 3;
+        }
+    }
+    output
+}
+
+fn inverse_wht(block: &mut [i32; 16]) {
+    for i in 0..4 {
+        let a = block[i] + block[12 + i];
+        let b = block[4 + i] + block[8 + i];
+        let c = block[4 + i] - block[8 + i];
+        let d = block[i] - block[12 + i];
+        block[i] = a + b;
+        block[4 + i] = c + d;
+        block[8 + i] = a - b;
+        block[12 + i] = d - c;
+    }
+    for i in 0..4 {
+        let row = &mut block[i * 4..i * 4 + 4];
+        let a = row[0] + row[3];
+        let b = row[1] + row[2];
+        let c = row[1] - row[2];
+        let d = row[0] - row[3];
+        row[0] = (a + b + 3) >> 3;
+        row[1] = (c + d + 3) >> 3;
+        row[2] = (a - b + 3) >> 3;
+        row[3] = (d - c + 3) >> 3;
+    }
+}
+
+// the dc and ac step sizes for one kind of block
+#[derive(Clone, Copy)]
+struct Steps {
+    dc: i32,
+    ac: i32,
+}
+
+impl Steps {
+    fn step(&self, position: usize) -> i32 {
+        match position {
+            0 => self.dc,
+            _ => self.ac
+        }
+    }
+
+    // rounds the coefficients in zigzag order, returns the levels and the coefficients the decoder will see
+    fn quantize(&self, coefficients: &[i32; 16], first: usize) -> ([i32; 16], [i32; 16]) {
+        let mut levels = [0; 16];
+        let mut result = [0; 16];
+        for i in first..16 {
+            let position = ZIGZAG[i];
+            let step = self.step(position);
+            // rounding ac a little towards zero saves more than it costs
+            let bias = if position == 0 { step / 2 } else { step * 3 / 8 };
+            let level = ((coefficients[position].abs() + bias) / step).min(MAX_LEVEL) * coefficients[position].signum();
+            levels[i] = level;
+            result[position] = level * step;
+        }
+        (levels, result)
+    }
+}
+
+// what the tokens can hold, the last category has 11 extra bits
+const MAX_LEVEL: i32 = 67 + 2047;
+
+// the coefficient tokens of the blocks in one macroblock, the planes are numbered like the probability tables
+struct Tokens {
+    writer: BoolWriter,
+    // whether the last block above and to the left had coefficients, y2 then 4 luma, 2 u and 2 v
+    above: Vec<[u8; 9]>,
+    left: [u8; 9],
+}
+
+impl Tokens {
+    fn put_block(&mut self, levels: &[i32; 16], plane: usize, column: usize, context: (usize, usize)) {
+        let first = if plane == 0 { 1 } else { 0 };
+        let last = (first..16).rev().find(|i| levels[*i] != 0);
+        let mut complexity = (self.above[column][context.0] + self.left[context.1]) as usize;
+        let mut after_zero = false;
+        for (i, level) in levels.iter().enumerate().skip(first) {
+            let probabilities = &COEFF_PROBS[plane][COEFF_BANDS[i]][complexity];
+            if last.is_none_or(|last| i > last) {
+                self.writer.put_tree(&DCT_TOKEN_TREE, probabilities, DCT_EOB, 0);
+                break;
+            }
+            let value = level.abs();
+            let (token, extra) = match value {
+                0..=4 => (value as i8, None),
+                _ => {
+                    let category = DCT_CAT_BASE.iter().rposition(|base| value >= *base).unwrap();
+                    (DCT_CAT1 + category as i8, Some((category, value - DCT_CAT_BASE[category])))
+                }
+            };
+            self.writer.put_tree(&DCT_TOKEN_TREE, probabilities, token, if after_zero { 2 } else { 0 });
+            if let Some((category, extra)) = extra {
+                let bits = PROB_DCT_CAT[category].iter().take_while(|probability| **probability > 0).collect::<Vec<_>>();
+                for (bit, probability) in bits.iter().enumerate() {
+                    self.writer.put((extra >> (bits.len() - 1 - bit)) & 1 == 1, **probability);
+                }
+            }
+            if value != 0 {
+                self.writer.put(*level < 0, 128);
+            }
+            complexity = value.min(2) as usize;
+            after_zero = value == 0;
+        }
+        let has_coefficients = last.is_some() as u8;
+        self.above[column][context.0] = has_coefficients;
+        self.left[context.1] = has_coefficients;
+    }
+}
+
+fn to_yuv(color: Color) -> [i32; 3] {
+    let (red, green, blue) = (color.red as i32, color.green as i32, color.blue as i32);
+    [
+        16 + ((66 * red + 129 * green + 25 * blue + 128) >> 8),
+        128 + ((-38 * red - 74 * green + 112 * blue + 128) >> 8),
+        128 + ((112 * red - 94 * green - 18 * blue + 128) >> 8),
+    ]
+}
+
+// the source planes, the borders repeat the edge pixels
+fn source_planes(layer: &FlatCanvasLayer, columns: usize, rows: usize) -> [Plane; 3] {
+    let (width, height) = layer.get_size();
+    let data = layer.get_data();
+    let yuv = |x: usize, y: usize| to_yuv(data[y.min(height as usize - 1) * width as usize + x.min(width as usize - 1)]);
+    let luma = Plane {
+        data: (0..rows * 16).flat_map(|y| (0..columns * 16).map(move |x| (x, y))).map(|(x, y)| yuv(x, y)[0] as u8).collect(),
+        stride: columns * 16,
+    };
+    let (chroma_width, chroma_height) = (width.div_ceil(2) as usize, height.div_ceil(2) as usize);
+    let chroma = |channel: usize| Plane {
+        data: (0..rows * 8).flat_map(|y| (0..columns * 8).map(move |x| (x, y))).map(|(x, y)| {
+            // some decoders predict from 127 above and 129 to the left of the image instead of the
+            // padding, aiming for those keeps both close
+            if x >= chroma_width && y % 8 == 7 && y < chroma_height {
+                return 127;
+            }
+            if y >= chroma_height && x % 8 == 7 && x < chroma_width {
+                return 129;
+            }
+            let sum = [(0, 0), (1, 0), (0, 1), (1, 1)].iter().map(|(dx, dy)| yuv(x * 2 + dx, y * 2 + dy)[channel]).sum::<i32>();
+            ((sum + 2) / 4) as u8
+        }).collect(),
+        stride: columns * 8,
+    };
+    [luma, chroma(1), chroma(2)]
+}
+
+fn encode_vp8(layer: &FlatCanvasLayer, quality: u8) -> Vec<u8> {
+    let (width, height) = layer.get_size();
+    let (columns, rows) = (width.div_ceil(16) as usize, height.div_ceil(16) as usize);
+    let sources = source_planes(layer, columns, rows);
+    let mut planes = sources.iter().map(|plane| Plane { data: vec![0; plane.data.len()], stride: plane.stride }).collect::<Vec<_>>();
+
+    let index = (100 - quality.min(100) as usize) * 127 / 100;
+    let luma = Steps { dc: DC_QUANT[index], ac: AC_QUANT[index] };
+    let y2 = Steps { dc: DC_QUANT[index] * 2, ac: (AC_QUANT[index] * 155 / 100).max(8) };
+    let chroma = Steps { dc: DC_QUANT[index].min(132), ac: AC_QUANT[index] };
+
+    let mut header = BoolWriter::new();
+    // color space and clamping
+    header.put_literal(0, 2);
+    let filter_level = (index as u32 * 3 / 8).min(63);
+    // one segment for every macroblock with absolute values. some decoders ignore the base
+    // quantizer without segments, so it is given here as well
+    header.put_literal(0b1011, 4);
+    for segment in 0..4 {
+        header.put(segment == 0, 128);
+        if segment == 0 {
+            header.put_literal(index as u32, 7);
+            header.put(false, 128);
+        }
+    }
+    for segment in 0..4 {
+        header.put(segment == 0, 128);
+        if segment == 0 {
+            header.put_literal(filter_level, 6);
+            header.put(false, 128);
+        }
+    }
+    // normal loop filter, stronger for coarser steps, no sharpness
+    header.put(false, 128);
+    header.put_literal(filter_level, 6);
+    header.put_literal(0, 3);
+    // no filter adjustments and one token partition
+    header.put(false, 128);
+    header.put_literal(0, 2);
+    // the base quantizer without deltas
+    header.put_literal(index as u32, 7);
+    (0..5).for_each(|_| header.put(false, 128));
+    // refresh entropy probabilities
+    header.put(false, 128);
+    // keep the default token probabilities
+    COEFF_UPDATE_PROBS.iter().flatten().flatten().flatten().for_each(|probability| header.put(false, *probability));
+    // every macroblock has coefficients
+    header.put(false, 128);
+
+    let mut tokens = Tokens { writer: BoolWriter::new(), above: vec![[0; 9]; columns], left: [0; 9] };
+    for row in 0..rows {
+        tokens.left = [0; 9];
+        for column in 0..columns {
+            let (x, y) = (column * 16, row * 16);
+            let block_source = |plane: &Plane, x: usize, y: usize, size: usize| {
+                (0..size * size).map(|i| plane.get(x + i % size, y + i / size)).collect::<Vec<_>>()
+            };
+
+            // luma, 16 blocks predicted together with their dc in the y2 block
+            let luma_edges = [Edges::new(&planes[0], x, y, 16)];
+            let (luma_mode, luma_prediction) = best_prediction(&luma_edges, &[block_source(&sources[0], x, y, 16)]);
+            let mut coefficients = [[0; 16]; 16];
+            for (block, coefficients) in coefficients.iter_mut().enumerate() {
+                let mut residue = [0; 16];
+                for (i, residue) in residue.iter_mut().enumerate() {
+                    let (px, py) = ((block % 4) * 4 + i % 4, (block / 4) * 4 + i / 4);
+                    *residue = sources[0].get(x + px, y + py) as i32 - luma_prediction[0][py * 16 + px] as i32;
+                }
+                *coefficients = forward_dct(&residue);
+            }
+            let dc = forward_wht(&std::array::from_fn(|block| coefficients[block][0]));
+            let (dc_levels, mut dc) = y2.quantize(&dc, 0);
+            inverse_wht(&mut dc);
+            tokens.put_block(&dc_levels, 1, column, (0, 0));
+            for (block, coefficients) in coefficients.iter().enumerate() {
+                let (levels, mut residue) = luma.quantize(coefficients, 1);
+                tokens.put_block(&levels, 0, column, (1 + block % 4, 1 + block / 4));
+                residue[0] = dc[block];
+                inverse_dct(&mut residue);
+                for (i, residue) in residue.iter().enumerate() {
+                    let (px, py) = ((block % 4) * 4 + i % 4, (block / 4) * 4 + i / 4);
+                    let value = (luma_prediction[0][py * 16 + px] as i32 + residue).clamp(0, 255) as u8;
+                    planes[0].set(x + px, y + py, value);
+                }
+            }
+
+            // both chroma planes share a prediction mode
+            let (x, y) = (column * 8, row * 8);
+            let chroma_edges = [Edges::new(&planes[1], x, y, 8), Edges::new(&planes[2], x, y, 8)];
+            let chroma_sources = [block_source(&sources[1], x, y, 8), block_source(&sources[2], x, y, 8)];
+            let (chroma_mode, chroma_prediction) = best_prediction(&chroma_edges, &chroma_sources);
+            for plane in 0..2 {
+                for block in 0..4 {
+                    let position = |i: usize| ((block % 2) * 4 + i % 4, (block / 2) * 4 + i / 4);
+                    let residue = std::array::from_fn(|i| {
+                        let (px, py) = position(i);
+                        chroma_sources[plane][py * 8 + px] as i32 - chroma_prediction[plane][py * 8 + px] as i32
+                    });
+                    let (levels, mut residue) = chroma.quantize(&forward_dct(&residue), 0);
+                    tokens.put_block(&levels, 2, column, (5 + plane * 2 + block % 2, 5 + plane * 2 + block / 2));
+                    inverse_dct(&mut residue);
+                    for (i, residue) in residue.iter().enumerate() {
+                        let (px, py) = position(i);
+                        let value = (chroma_prediction[plane][py * 8 + px] as i32 + residue).clamp(0, 255) as u8;
+                        planes[plane + 1].set(x + px, y + py, value);
+                    }
+                }
+            }
+
+            header.put_tree(&KEYFRAME_YMODE_TREE, &KEYFRAME_YMODE_PROBS, luma_mode as i8, 0);
+            header.put_tree(&KEYFRAME_UV_MODE_TREE, &KEYFRAME_UV_MODE_PROBS, chroma_mode as i8, 0);
+        }
+    }
+
+    let header = header.finish();
+    let mut result = Vec::new();
+    // a shown key frame followed by the size of the first partition
+    let tag = (1 << 4) | (header.len() as u32) << 5;
+    result.extend_from_slice(&tag.to_le_bytes()[..3]);
+    result.extend_from_slice(&[0x9d, 0x01, 0x2a]);
+    result.extend_from_slice(&(width as u16).to_le_bytes());
+    result.extend_from_slice(&(height as u16).to_le_bytes());
+    result.extend(header);
+    result.extend(tokens.writer.finish());
+    result
+}
+
+// the tables below are from the spec
+const ZIGZAG: [usize; 16] = [0, 1, 4, 8, 5, 2, 3, 6, 9, 12, 13, 10, 7, 11, 14, 15];
+const COEFF_BANDS: [usize; 16] = [0, 1, 2, 3, 6, 4, 5, 6, 6, 6, 6, 6, 6, 6, 6, 7];
+
+const KEYFRAME_YMODE_TREE: [i8; 8] = [-4, 2, 4, 6, -(Mode::Dc as i8), -(Mode::Vertical as i8), -(Mode::Horizontal as i8), -(Mode::TrueMotion as i8)];
+const KEYFRAME_YMODE_PROBS: [u8; 4] = [145, 156, 163, 128];
+const KEYFRAME_UV_MODE_TREE: [i8; 6] = [-(Mode::Dc as i8), 2, -(Mode::Vertical as i8), 4, -(Mode::Horizontal as i8), -(Mode::TrueMotion as i8)];
+const KEYFRAME_UV_MODE_PROBS: [u8; 3] = [142, 114, 183];
+
+const DCT_CAT1: i8 = 5;
+const DCT_EOB: i8 = 11;
+const DCT_TOKEN_TREE: [i8; 22] = [-DCT_EOB, 2, 0, 4, -1, 6, 8, 12, -2, 10, -3, -4, 14, 16, -5, -6, 18, 20, -7, -8, -9, -10];
+const PROB_DCT_CAT: [[u8; 12]; 6] = [
+    [159, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [165, 145, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [173, 148, 140, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+    [176, 155, 140, 135, 0, 0, 0, 0, 0, 0, 0, 0],
+    [180, 157, 141, 134, 130, 0, 0, 0, 0, 0, 0, 0],
+    [254, 254, 243, 230, 196, 177, 153, 140, 133, 130, 129, 0],
+];
+const DCT_CAT_BASE: [i32; 6] = [5, 7, 11, 19, 35, 67];
+
+const DC_QUANT: [i32; 128] = [
+    4, 5, 6, 7, 8, 9, 10, 10, 11, 12, 13, 14, 15, 16, 17, 17,
+    18, 19, 20, 20, 21, 21, 22, 22, 23, 23, 24, 25, 25, 26, 27, 28,
+    29, 30, 31, 32, 33, 34, 35, 36, 37, 37, 38, 39, 40, 41, 42, 43,
+    44, 45, 46, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58,
+    59, 60, 61, 62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74,
+    75, 76, 76, 77, 78, 79, 80, 81, 82, 83, 84, 85, 86, 87, 88, 89,
+    91, 93, 95, 96, 98, 100, 101, 102, 104, 106, 108, 110, 112, 114, 116, 118,
+    122, 124, 126, 128, 130, 132, 134, 136, 138, 140, 143, 145, 148, 151, 154, 157,
+];
+
+const AC_QUANT: [i32; 128] = [
+    4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19,
+    20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35,
+    36, 37, 38, 39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51,
+    52, 53, 54, 55, 56, 57, 58, 60, 62, 64, 66, 68, 70, 72, 74, 76,
+    78, 80, 82, 84, 86, 88, 90, 92, 94, 96, 98, 100, 102, 104, 106, 108,
+    110, 112, 114, 116, 119, 122, 125, 128, 131, 134, 137, 140, 143, 146, 149, 152,
+    155, 158, 161, 164, 167, 170, 173, 177, 181, 185, 189, 193, 197, 201, 205, 209,
+    213, 217, 221, 225, 229, 234, 239, 245, 249, 254, 259, 264, 269, 274, 279, 284,
+];
+
+type TokenProbabilities = [[[[u8; 11]; 3]; 8]; 4];
+const COEFF_UPDATE_PROBS: TokenProbabilities = [
+    [
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [176, 246, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 241, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 244, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 246, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [239, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 254, 255, 255, 255, 255, 255, 255],
+            [250, 255, 254, 255, 254, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [217, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [225, 252, 241, 253, 255, 255, 254, 255, 255, 255, 255],
+            [234, 250, 241, 250, 253, 255, 253, 254, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [223, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [238, 253, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 248, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [247, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [186, 251, 250, 255, 255, 255, 255, 255, 255, 255, 255],
+            [234, 251, 244, 254, 255, 255, 255, 255, 255, 255, 255],
+            [251, 251, 243, 253, 254, 255, 254, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [236, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [251, 253, 253, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+    [
+        [
+            [248, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 254, 252, 254, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 249, 253, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [246, 253, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 254, 251, 254, 254, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 254, 252, 255, 255, 255, 255, 255, 255, 255, 255],
+            [248, 254, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 255, 254, 254, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [245, 251, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [253, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 251, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [252, 253, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 254, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 252, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [249, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 254, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 253, 255, 255, 255, 255, 255, 255, 255, 255],
+            [250, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+        [
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [254, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+            [255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255],
+        ],
+    ],
+];
+
+const COEFF_PROBS: TokenProbabilities = [
+    [
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [253, 136, 254, 255, 228, 219, 128, 128, 128, 128, 128],
+            [189, 129, 242, 255, 227, 213, 255, 219, 128, 128, 128],
+            [106, 126, 227, 252, 214, 209, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 98, 248, 255, 236, 226, 255, 255, 128, 128, 128],
+            [181, 133, 238, 254, 221, 234, 255, 154, 128, 128, 128],
+            [78, 134, 202, 247, 198, 180, 255, 219, 128, 128, 128],
+        ],
+        [
+            [1, 185, 249, 255, 243, 255, 128, 128, 128, 128, 128],
+            [184, 150, 247, 255, 236, 224, 128, 128, 128, 128, 128],
+            [77, 110, 216, 255, 236, 230, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 101, 251, 255, 241, 255, 128, 128, 128, 128, 128],
+            [170, 139, 241, 252, 236, 209, 255, 255, 128, 128, 128],
+            [37, 116, 196, 243, 228, 255, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 204, 254, 255, 245, 255, 128, 128, 128, 128, 128],
+            [207, 160, 250, 255, 238, 128, 128, 128, 128, 128, 128],
+            [102, 103, 231, 255, 211, 171, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 152, 252, 255, 240, 255, 128, 128, 128, 128, 128],
+            [177, 135, 243, 255, 234, 225, 128, 128, 128, 128, 128],
+            [80, 129, 211, 255, 194, 224, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [246, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [255, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [198, 35, 237, 223, 193, 187, 162, 160, 145, 155, 62],
+            [131, 45, 198, 221, 172, 176, 220, 157, 252, 221, 1],
+            [68, 47, 146, 208, 149, 167, 221, 162, 255, 223, 128],
+        ],
+        [
+            [1, 149, 241, 255, 221, 224, 255, 255, 128, 128, 128],
+            [184, 141, 234, 253, 222, 220, 255, 199, 128, 128, 128],
+            [81, 99, 181, 242, 176, 190, 249, 202, 255, 255, 128],
+        ],
+        [
+            [1, 129, 232, 253, 214, 197, 242, 196, 255, 255, 128],
+            [99, 121, 210, 250, 201, 198, 255, 202, 128, 128, 128],
+            [23, 91, 163, 242, 170, 187, 247, 210, 255, 255, 128],
+        ],
+        [
+            [1, 200, 246, 255, 234, 255, 128, 128, 128, 128, 128],
+            [109, 178, 241, 255, 231, 245, 255, 255, 128, 128, 128],
+            [44, 130, 201, 253, 205, 192, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 132, 239, 251, 219, 209, 255, 165, 128, 128, 128],
+            [94, 136, 225, 251, 218, 190, 255, 255, 128, 128, 128],
+            [22, 100, 174, 245, 186, 161, 255, 199, 128, 128, 128],
+        ],
+        [
+            [1, 182, 249, 255, 232, 235, 128, 128, 128, 128, 128],
+            [124, 143, 241, 255, 227, 234, 128, 128, 128, 128, 128],
+            [35, 77, 181, 251, 193, 211, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 157, 247, 255, 236, 231, 255, 255, 128, 128, 128],
+            [121, 141, 235, 255, 225, 227, 255, 255, 128, 128, 128],
+            [45, 99, 188, 251, 195, 217, 255, 224, 128, 128, 128],
+        ],
+        [
+            [1, 1, 251, 255, 213, 255, 128, 128, 128, 128, 128],
+            [203, 1, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [137, 1, 177, 255, 224, 255, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [253, 9, 248, 251, 207, 208, 255, 192, 128, 128, 128],
+            [175, 13, 224, 243, 193, 185, 249, 198, 255, 255, 128],
+            [73, 17, 171, 221, 161, 179, 236, 167, 255, 234, 128],
+        ],
+        [
+            [1, 95, 247, 253, 212, 183, 255, 255, 128, 128, 128],
+            [239, 90, 244, 250, 211, 209, 255, 255, 128, 128, 128],
+            [155, 77, 195, 248, 188, 195, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 24, 239, 251, 218, 219, 255, 205, 128, 128, 128],
+            [201, 51, 219, 255, 196, 186, 128, 128, 128, 128, 128],
+            [69, 46, 190, 239, 201, 218, 255, 228, 128, 128, 128],
+        ],
+        [
+            [1, 191, 251, 255, 255, 128, 128, 128, 128, 128, 128],
+            [223, 165, 249, 255, 213, 255, 128, 128, 128, 128, 128],
+            [141, 124, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 16, 248, 255, 255, 128, 128, 128, 128, 128, 128],
+            [190, 36, 230, 255, 236, 255, 128, 128, 128, 128, 128],
+            [149, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 226, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [247, 192, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [240, 128, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [1, 134, 252, 255, 255, 128, 128, 128, 128, 128, 128],
+            [213, 62, 250, 255, 255, 128, 128, 128, 128, 128, 128],
+            [55, 93, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+        [
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+            [128, 128, 128, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+    [
+        [
+            [202, 24, 213, 235, 186, 191, 220, 160, 240, 175, 255],
+            [126, 38, 182, 232, 169, 184, 228, 174, 255, 187, 128],
+            [61, 46, 138, 219, 151, 178, 240, 170, 255, 216, 128],
+        ],
+        [
+            [1, 112, 230, 250, 199, 191, 247, 159, 255, 255, 128],
+            [166, 109, 228, 252, 211, 215, 255, 174, 128, 128, 128],
+            [39, 77, 162, 232, 172, 180, 245, 178, 255, 255, 128],
+        ],
+        [
+            [1, 52, 220, 246, 198, 199, 249, 220, 255, 255, 128],
+            [124, 74, 191, 243, 183, 193, 250, 221, 255, 255, 128],
+            [24, 71, 130, 219, 154, 170, 243, 182, 255, 255, 128],
+        ],
+        [
+            [1, 182, 225, 249, 219, 240, 255, 224, 128, 128, 128],
+            [149, 150, 226, 252, 216, 205, 255, 171, 128, 128, 128],
+            [28, 108, 170, 242, 183, 194, 254, 223, 255, 255, 128],
+        ],
+        [
+            [1, 81, 230, 252, 204, 203, 255, 192, 128, 128, 128],
+            [123, 102, 209, 247, 188, 196, 255, 233, 128, 128, 128],
+            [20, 95, 153, 243, 164, 173, 255, 203, 128, 128, 128],
+        ],
+        [
+            [1, 222, 248, 255, 216, 213, 128, 128, 128, 128, 128],
+            [168, 175, 246, 252, 235, 205, 255, 255, 128, 128, 128],
+            [47, 116, 215, 255, 211, 212, 255, 255, 128, 128, 128],
+        ],
+        [
+            [1, 121, 236, 253, 212, 214, 255, 255, 128, 128, 128],
+            [141, 84, 213, 252, 201, 202, 255, 219, 128, 128, 128],
+            [42, 80, 160, 240, 162, 185, 255, 205, 128, 128, 128],
+        ],
+        [
+            [1, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [244, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+            [238, 1, 255, 128, 128, 128, 128, 128, 128, 128, 128],
+        ],
+    ],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<u8> {
+        image::load_from_memory_with_format(bytes, image::ImageFormat::WebP).unwrap().to_rgba8().into_raw()
+    }
+
+    #[test]
+    fn test_webp_round_trip() {
+        // smooth color with a hard edge and an odd size so the macroblocks hang over
+        let colors = (0..37 * 21).map(|i| {
+            let (x, y) = (i % 37, i / 37);
+            match x > 20 {
+                true => Color::new(20, 200, 90, 255),
+                false => Color::new((x * 6) as u8, (y * 10) as u8, 255 - (x * 3 + y * 4) as u8, 255)
+            }
+        }).collect();
+        let layer = FlatCanvasLayer::from_data(37, 21, colors);
+        let expected = layer.get_data().iter().flat_map(|color| [color.red, color.green, color.blue, color.alpha]).collect::<Vec<_>>();
+        let error = |bytes: &[u8]| {
+            let decoded = decode(bytes);
+            decoded.iter().zip(&expected).map(|(a, b)| (*a as i32 - *b as i32).abs()).sum::<i32>() as f32 / expected.len() as f32
+        };
+
+        let lossless = encode_webp(&layer, true, 0).unwrap();
+        assert_eq!(decode(&lossless), expected);
+        let high = encode_webp(&layer, false, 95).unwrap();
+        let low = encode_webp(&layer, false, 10).unwrap();
+        assert!(error(&high) < 5.0, "{}", error(&high));
+        assert!(error(&low) < 14.0, "{}", error(&low));
+        assert!(low.len() < high.len());
+
+        let mut transparent = layer.clone();
+        transparent.set_pixel(PixelPos { x: 3, y: 4 }, Color::new(0, 0, 0, 0));
+        let decoded = decode(&encode_webp(&transparent, false, 80).unwrap());
+        assert_eq!(decoded[(4 * 37 + 3) * 4 + 3], 0);
+        assert_eq!(decoded[(4 * 37 + 4) * 4 + 3], 255);
+    }
+}
@@ -0,0 +1,191 @@
+// just enough of the zip format for openraster files. files are written stored, since
+// the pngs inside are compressed already, and read stored or deflated
+
+const LOCAL_HEADER: u32 = 0x04034b50;
+const CENTRAL_HEADER: u32 = 0x02014b50;
+const END_OF_DIRECTORY: u32 = 0x06054b50;
+// names are utf-8
+const UTF8_FLAG: u16 = 1 << 11;
+// 1980-01-01, the earliest date zip can store
+const DOS_DATE: u16 = 0x21;
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let table = (0..256u32).map(|mut value| {
+        for _ in 0..8 {
+            value = match value & 1 {
+                1 => 0xedb88320 ^ (value >> 1),
+                _ => value >> 1
+            };
+        }
+        value
+    }).collect::<Vec<_>>();
+    !bytes.iter().fold(!0u32, |crc, byte| table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+pub struct ZipWriter {
+    data: Vec<u8>,
+    // name, crc, size and where the local header starts
+    files: Vec<(String, u32, u32, u32)>,
+}
+
+impl ZipWriter {
+    pub fn new() -> ZipWriter {
+        ZipWriter {
+            data: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+
+    // files are stored in the order they are added
+    pub fn add(&mut self, name: &str, bytes: &[u8]) {
+        let (crc, size, offset) = (crc32(bytes), bytes.len() as u32, self.data.len() as u32);
+        self.data.extend_from_slice(&LOCAL_HEADER.to_le_bytes());
+        for value in [20, UTF8_FLAG, 0, 0, DOS_DATE] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [crc, size, size] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [name.len() as u16, 0] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(bytes);
+        self.files.push((name.to_string(), crc, size, offset));
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let directory = self.data.len() as u32;
+        for (name, crc, size, offset) in self.files.iter() {
+            self.data.extend_from_slice(&CENTRAL_HEADER.to_le_bytes());
+            for value in [20, 20, UTF8_FLAG, 0, 0, DOS_DATE] {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [*crc, *size, *size] {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [name.len() as u16, 0, 0, 0, 0] {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+            for value in [0, *offset] {
+                self.data.extend_from_slice(&value.to_le_bytes());
+            }
+            self.data.extend_from_slice(name.as_bytes());
+        }
+        let directory_size = self.data.len() as u32 - directory;
+        let count = self.files.len() as u16;
+        self.data.extend_from_slice(&END_OF_DIRECTORY.to_le_bytes());
+        for value in [0, 0, count, count] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [directory_size, directory] {
+            self.data.extend_from_slice(&value.to_le_bytes());
+        }
+        self.data.extend_from_slice(&0u16.to_le_bytes());
+        self.data
+    }
+}
+
+impl Default for ZipWriter {
+    fn default() -> ZipWriter {
+        ZipWriter::new()
+    }
+}
+
+// offsets and sizes come from the file, so they are checked against it and can't overflow
+fn bytes_at(bytes: &[u8], pos: usize, length: usize) -> Result<&[u8], String> {
+    pos.checked_add(length).and_then(|end| bytes.get(pos..end)).ok_or("zip file ends early".to_string())
+}
+
+fn u16_at(bytes: &[u8], pos: usize) -> Result<u16, String> {
+    bytes_at(bytes, pos, 2).map(|value| u16::from_le_bytes([value[0], value[1]]))
+}
+
+fn u32_at(bytes: &[u8], pos: usize) -> Result<u32, String> {
+    bytes_at(bytes, pos, 4).map(|value| u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+// the names and contents of every file, in the order of the directory
+pub fn read_zip(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    // the directory end is followed by a comment of up to 64k
+    let end = (0..bytes.len().saturating_sub(21)).rev()
+        .take(65536 + 22)
+        .find(|pos| u32_at(bytes, *pos) == Ok(END_OF_DIRECTORY))
+        .ok_or("not a zip file")?;
+    let count = u16_at(bytes, end + 10)? as usize;
+    let mut pos = u32_at(bytes, end + 16)? as usize;
+    let mut result = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(bytes, pos)? != CENTRAL_HEADER {
+            return Err("broken zip directory".to_string());
+        }
+        let method = u16_at(bytes, pos + 10)?;
+        let crc = u32_at(bytes, pos + 16)?;
+        let compressed_size = u32_at(bytes, pos + 20)? as usize;
+        let size = u32_at(bytes, pos + 24)? as usize;
+        let name_length = u16_at(bytes, pos + 28)? as usize;
+        let extra_length = u16_at(bytes, pos + 30)? as usize;
+        let comment_length = u16_at(bytes, pos + 32)? as usize;
+        let offset = u32_at(bytes, pos + 42)? as usize;
+        let name = bytes_at(bytes, pos + 46, name_length)?;
+        let name = String::from_utf8_lossy(name).to_string();
+        pos += 46 + name_length + extra_length + comment_length;
+
+        // the local header can have other extra fields than the directory
+        let header = bytes_at(bytes, offset, 30)?;
+        if u32::from_le_bytes([header[0], header[1], header[2], header[3]]) != LOCAL_HEADER {
+            return Err(format!("{}: broken zip entry", name));
+        }
+        let start = offset + 30 + u16::from_le_bytes([header[26], header[27]]) as usize + u16::from_le_bytes([header[28], header[29]]) as usize;
+        let data = bytes_at(bytes, start, compressed_size)?;
+        let data = match method {
+            0 => data.to_vec(),
+            // no more than the directory says, a broken size can't make it grow without end
+            8 => miniz_oxide::inflate::decompress_to_vec_with_limit(data, size).map_err(|err| format!("{}: broken deflate data {:?}", name, err.status))?,
+            _ => return Err(format!("{}: unsupported zip compression {}", name, method))
+        };
+        if data.len() != size || crc32(&data) != crc {
+            return Err(format!("{}: checksum mismatch", name));
+        }
+        result.push((name, data));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zip_round_trip() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        let mut writer = ZipWriter::new();
+        writer.add("mimetype", b"image/openraster");
+        writer.add("data/läyer.png", &[0, 1, 2, 255]);
+        let bytes = writer.finish();
+        // openraster wants the mime type readable at a fixed place
+        assert_eq!(&bytes[30..38], b"mimetype");
+        assert_eq!(&bytes[38..54], b"image/openraster");
+
+        let files = read_zip(&bytes).unwrap();
+        assert_eq!(files, vec![("mimetype".to_string(), b"image/openraster".to_vec()), ("data/läyer.png".to_string(), vec![0, 1, 2, 255])]);
+        assert!(read_zip(&bytes[..bytes.len() - 30]).is_err());
+
+        // the offset of the second entry pointing past the end, then at the wrong place
+        let directory = bytes.len() - 22 - (46 + "data/läyer.png".len());
+        let mut broken = bytes.clone();
+        broken[directory + 42..directory + 46].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_zip(&broken).is_err());
+        broken[directory + 42..directory + 46].copy_from_slice(&1u32.to_le_bytes());
+        assert!(read_zip(&broken).is_err());
+        // sizes larger than the file
+        let mut broken = bytes.clone();
+        broken[directory + 20..directory + 28].copy_from_slice(&[0xff; 8]);
+        assert!(read_zip(&broken).is_err());
+        for i in 0..bytes.len() {
+            let mut broken = bytes.clone();
+            broken[i] ^= 0xff;
+            let _ = read_zip(&broken);
+        }
+    }
+}